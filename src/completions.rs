@@ -1,8 +1,11 @@
 //! Shell completion generation module.
 
+use crate::mark::Mark;
 use clap::CommandFactory;
-use clap_complete::Shell;
+use prompter::CompletionShell as Shell;
+use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
 
 use crate::Cli;
 
@@ -33,6 +36,14 @@ pub fn generate_completions(shell: Shell) {
             println!("# For fish (~/.config/fish/config.fish):");
             println!("#   {bin_name} completions fish | source");
         }
+        Shell::Nushell => {
+            println!("# For nushell:");
+            println!(
+                "#   {bin_name} completions nushell | save -f ~/.config/nushell/{bin_name}-completions.nu"
+            );
+            println!("#   # Then add to config.nu:");
+            println!("#   source ~/.config/nushell/{bin_name}-completions.nu");
+        }
         _ => {
             println!("# For {shell}:");
             println!("#   {bin_name} completions {shell} > /path/to/completions/_{bin_name}");
@@ -45,10 +56,234 @@ pub fn generate_completions(shell: Shell) {
     clap_complete::generate(shell, &mut cmd, bin_name, &mut io::stdout());
 }
 
+/// The completion script for `shell`, without the instructions header.
+fn completion_script(shell: Shell) -> Vec<u8> {
+    let mut cmd = Cli::command();
+    let bin_name = cmd.get_name().to_string();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut cmd, bin_name, &mut script);
+    script
+}
+
+/// Where a completion script was installed, plus any step left to the user.
+#[derive(Debug, PartialEq, Eq)]
+struct Installed {
+    path: PathBuf,
+    unchanged: bool,
+    manual_step: Option<String>,
+}
+
+/// Install the completion script for `shell` into its per-user location.
+///
+/// Prints the path written and any remaining manual step, with emoji
+/// markers when `styled`.
+pub fn install_completions(shell: Shell, force: bool, styled: bool) -> Result<(), String> {
+    let home = std::env::var("HOME").map_err(|_| "HOME is not set".to_string())?;
+    let fpath = std::env::var("FPATH").ok();
+    let installed = install_completions_in(
+        shell,
+        Path::new(&home),
+        fpath.as_deref(),
+        &completion_script(shell),
+        force,
+    )?;
+    if installed.unchanged {
+        println!(
+            "{}Completions already installed: {}",
+            Mark::Ok.text(styled),
+            installed.path.display()
+        );
+    } else {
+        println!(
+            "{}Installed completions: {}",
+            Mark::Ok.text(styled),
+            installed.path.display()
+        );
+    }
+    if let Some(step) = installed.manual_step {
+        println!("{}{step}", Mark::Hint.text(styled));
+    }
+    Ok(())
+}
+
+/// Install `script` for `shell` under `home`.
+///
+/// A differing existing file is only replaced with `force`.
+fn install_completions_in(
+    shell: Shell,
+    home: &Path,
+    fpath: Option<&str>,
+    script: &[u8],
+    force: bool,
+) -> Result<Installed, String> {
+    let (path, manual_step) = install_location(shell, home, fpath)?;
+    let unchanged = write_script(&path, script, force)?;
+    Ok(Installed {
+        path,
+        unchanged,
+        manual_step,
+    })
+}
+
+/// The per-user location `--install` writes to for `shell`, plus any manual
+/// step needed before the shell picks it up.
+///
+/// Zsh uses the first writable entry of `fpath` (the colon-separated
+/// `$FPATH`) when there is one, otherwise `~/.zsh/completions`.
+fn install_location(
+    shell: Shell,
+    home: &Path,
+    fpath: Option<&str>,
+) -> Result<(PathBuf, Option<String>), String> {
+    match shell {
+        Shell::Bash => Ok((
+            home.join(".local/share/bash-completion/completions/prompter"),
+            Some("Restart bash (requires the bash-completion package)".to_string()),
+        )),
+        Shell::Zsh => {
+            let writable = fpath_dirs(fpath).find(|dir| is_writable_dir(dir));
+            Ok(writable.map_or_else(
+                || {
+                    (
+                        home.join(".zsh/completions/_prompter"),
+                        Some(
+                            "Add 'fpath=(~/.zsh/completions $fpath)' to ~/.zshrc before compinit, \
+                             then restart zsh"
+                                .to_string(),
+                        ),
+                    )
+                },
+                |dir| (dir.join("_prompter"), None),
+            ))
+        }
+        Shell::Fish => Ok((home.join(".config/fish/completions/prompter.fish"), None)),
+        other => Err(format!(
+            "--install supports bash, zsh, and fish; for {other}, redirect \
+             'prompter completions {other}' to a file instead"
+        )),
+    }
+}
+
+/// The non-empty entries of a colon-separated `$FPATH`.
+fn fpath_dirs(fpath: Option<&str>) -> impl Iterator<Item = PathBuf> + '_ {
+    fpath
+        .unwrap_or_default()
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Whether the installed completion script matches the current binary.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompletionStatus {
+    /// The script at this path is what the binary generates
+    UpToDate(PathBuf),
+    /// The script at this path differs from what the binary generates
+    Stale(PathBuf),
+    /// No script was found in any location the shell loads from
+    Missing,
+    /// `--install` has no per-user location for this shell
+    Unsupported,
+}
+
+/// The shell named by `$SHELL`, matched on the executable name.
+pub fn detect_shell() -> Option<Shell> {
+    shell_from_path(&std::env::var("SHELL").ok()?)
+}
+
+/// Map a shell executable path such as `/bin/zsh` to a [`Shell`].
+fn shell_from_path(path: &str) -> Option<Shell> {
+    let name = Path::new(path).file_name()?.to_str()?;
+    <Shell as clap::ValueEnum>::from_str(name, true).ok()
+}
+
+/// Check the installed completion script for `shell` against the one the
+/// current binary generates.
+pub fn completion_status(shell: Shell) -> Result<CompletionStatus, String> {
+    let home = std::env::var("HOME").map_err(|_| "HOME is not set".to_string())?;
+    let fpath = std::env::var("FPATH").ok();
+    Ok(completion_status_in(
+        shell,
+        Path::new(&home),
+        fpath.as_deref(),
+        &completion_script(shell),
+    ))
+}
+
+/// Compare the first installed script for `shell` under `home` with `script`.
+///
+/// Zsh looks for `_prompter` in every `fpath` entry before falling back to
+/// `~/.zsh/completions`.
+fn completion_status_in(
+    shell: Shell,
+    home: &Path,
+    fpath: Option<&str>,
+    script: &[u8],
+) -> CompletionStatus {
+    let Ok((default, _)) = install_location(shell, home, None) else {
+        return CompletionStatus::Unsupported;
+    };
+    let mut candidates: Vec<PathBuf> = if shell == Shell::Zsh {
+        fpath_dirs(fpath).map(|dir| dir.join("_prompter")).collect()
+    } else {
+        Vec::new()
+    };
+    candidates.push(default);
+
+    candidates
+        .into_iter()
+        .find_map(|path| {
+            let existing = fs::read(&path).ok()?;
+            Some(if existing == script {
+                CompletionStatus::UpToDate(path)
+            } else {
+                CompletionStatus::Stale(path)
+            })
+        })
+        .unwrap_or(CompletionStatus::Missing)
+}
+
+/// Write the completion script for `shell` to `path`, creating its directory.
+///
+/// Prints the path written.
+pub fn write_completions_to(shell: Shell, path: &Path, force: bool) -> Result<(), String> {
+    write_script(path, &completion_script(shell), force)?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// Write `script` to `path`, creating parent directories as needed.
+///
+/// Returns `true` when the file already held `script`. A differing existing
+/// file is only replaced with `force`.
+fn write_script(path: &Path, script: &[u8], force: bool) -> Result<bool, String> {
+    if let Ok(existing) = fs::read(path) {
+        if existing == script {
+            return Ok(true);
+        }
+        if !force {
+            return Err(format!(
+                "{} already exists with different content (use --force to overwrite)",
+                path.display()
+            ));
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+    }
+    fs::write(path, script).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(false)
+}
+
+/// Whether files can be created in `dir`.
+fn is_writable_dir(dir: &Path) -> bool {
+    dir.is_dir() && tempfile::tempfile_in(dir).is_ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use clap_complete::Shell;
 
     #[test]
     fn test_generate_completions_bash() {
@@ -75,6 +310,148 @@ mod tests {
         generate_completions(Shell::PowerShell);
     }
 
+    #[test]
+    fn test_generate_completions_nushell() {
+        generate_completions(Shell::Nushell);
+        let script = String::from_utf8(completion_script(Shell::Nushell)).unwrap();
+        assert!(script.contains("export extern prompter"), "{script}");
+    }
+
+    #[test]
+    fn test_install_completions_locations() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+
+        let bash = install_completions_in(Shell::Bash, home, None, b"bash", false).unwrap();
+        assert_eq!(
+            bash.path,
+            home.join(".local/share/bash-completion/completions/prompter")
+        );
+        assert_eq!(fs::read(&bash.path).unwrap(), b"bash");
+
+        let fish = install_completions_in(Shell::Fish, home, None, b"fish", false).unwrap();
+        assert_eq!(
+            fish.path,
+            home.join(".config/fish/completions/prompter.fish")
+        );
+        assert_eq!(fish.manual_step, None);
+
+        let zsh = install_completions_in(Shell::Zsh, home, None, b"zsh", false).unwrap();
+        assert_eq!(zsh.path, home.join(".zsh/completions/_prompter"));
+        assert!(zsh.manual_step.unwrap().contains("fpath"));
+
+        assert!(install_completions_in(Shell::Elvish, home, None, b"", false).is_err());
+    }
+
+    #[test]
+    fn test_install_completions_zsh_uses_writable_fpath() {
+        let home = tempfile::tempdir().unwrap();
+        let site = home.path().join("site-functions");
+        fs::create_dir_all(&site).unwrap();
+        let fpath = format!(
+            "{}:{}",
+            home.path().join("missing").display(),
+            site.display()
+        );
+
+        let zsh =
+            install_completions_in(Shell::Zsh, home.path(), Some(&fpath), b"zsh", false).unwrap();
+        assert_eq!(zsh.path, site.join("_prompter"));
+        assert_eq!(zsh.manual_step, None);
+    }
+
+    #[test]
+    fn test_install_completions_refuses_to_overwrite() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        let first = install_completions_in(Shell::Fish, home, None, b"v1", false).unwrap();
+        assert!(!first.unchanged);
+
+        // Same content is a no-op, even without --force
+        let again = install_completions_in(Shell::Fish, home, None, b"v1", false).unwrap();
+        assert!(again.unchanged);
+
+        let err = install_completions_in(Shell::Fish, home, None, b"v2", false).unwrap_err();
+        assert!(err.contains("--force"));
+        assert_eq!(fs::read(&first.path).unwrap(), b"v1");
+
+        install_completions_in(Shell::Fish, home, None, b"v2", true).unwrap();
+        assert_eq!(fs::read(&first.path).unwrap(), b"v2");
+    }
+
+    #[test]
+    fn test_completion_status_detects_missing_stale_and_current() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path();
+        assert_eq!(
+            completion_status_in(Shell::Fish, home, None, b"v1"),
+            CompletionStatus::Missing
+        );
+
+        let installed = install_completions_in(Shell::Fish, home, None, b"v1", false).unwrap();
+        assert_eq!(
+            completion_status_in(Shell::Fish, home, None, b"v1"),
+            CompletionStatus::UpToDate(installed.path.clone())
+        );
+        assert_eq!(
+            completion_status_in(Shell::Fish, home, None, b"v2"),
+            CompletionStatus::Stale(installed.path)
+        );
+
+        assert_eq!(
+            completion_status_in(Shell::Nushell, home, None, b"v1"),
+            CompletionStatus::Unsupported
+        );
+    }
+
+    #[test]
+    fn test_completion_status_searches_zsh_fpath() {
+        let home = tempfile::tempdir().unwrap();
+        let site = home.path().join("site-functions");
+        fs::create_dir_all(&site).unwrap();
+        fs::write(site.join("_prompter"), b"zsh").unwrap();
+        let fpath = format!(
+            "{}:{}",
+            home.path().join("missing").display(),
+            site.display()
+        );
+
+        assert_eq!(
+            completion_status_in(Shell::Zsh, home.path(), Some(&fpath), b"zsh"),
+            CompletionStatus::UpToDate(site.join("_prompter"))
+        );
+        assert_eq!(
+            completion_status_in(Shell::Zsh, home.path(), None, b"zsh"),
+            CompletionStatus::Missing
+        );
+    }
+
+    #[test]
+    fn test_shell_from_path() {
+        assert_eq!(shell_from_path("/bin/zsh"), Some(Shell::Zsh));
+        assert_eq!(shell_from_path("/usr/local/bin/fish"), Some(Shell::Fish));
+        assert_eq!(shell_from_path("/usr/bin/nu"), Some(Shell::Nushell));
+        assert_eq!(shell_from_path("/bin/sh"), None);
+        assert_eq!(shell_from_path(""), None);
+    }
+
+    #[test]
+    fn test_write_script_creates_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/dir/_prompter");
+        assert!(!write_script(&path, b"v1", false).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), b"v1");
+        assert!(write_script(&path, b"v1", false).unwrap());
+        assert!(write_script(&path, b"v2", false).is_err());
+    }
+
+    #[test]
+    fn test_completion_script_has_no_header() {
+        let script = String::from_utf8(completion_script(Shell::Bash)).unwrap();
+        assert!(!script.starts_with("# Shell completion"));
+        assert!(script.contains("prompter"));
+    }
+
     #[test]
     fn test_cli_command_factory() {
         use clap::CommandFactory;
@@ -1,140 +1,723 @@
 //! Health check and diagnostics module.
 
-use std::path::Path;
+use crate::mark::Mark;
+use is_terminal::IsTerminal;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Options controlling a doctor run.
+#[derive(Debug, Default, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct DoctorOptions {
+    /// Skip the network update check
+    pub offline: bool,
+    /// Repair fixable findings instead of only reporting them
+    pub fix: bool,
+    /// Apply fixes without asking for confirmation
+    pub yes: bool,
+    /// Delete orphaned library files without asking for confirmation
+    pub force: bool,
+    /// Treat warnings as failures
+    pub strict: bool,
+    /// Also scan library file contents
+    pub deep: bool,
+    /// Per-file size limit in KB; `None` falls back to
+    /// `PROMPTER_MAX_FILE_SIZE_KB`, then 100
+    pub max_file_size_kb: Option<u64>,
+    /// Follow symlinks when looking for orphaned library files
+    pub follow_symlinks: bool,
+    /// Config file to check instead of the default, from `--config`,
+    /// `--config-dir` or a local `.prompterrc`
+    pub config: Option<PathBuf>,
+    /// Reject unknown config keys (`--strict-config`)
+    pub strict_config: bool,
+    /// Print emoji markers instead of plain labels
+    pub styled: bool,
+}
+
+/// A finding that `doctor --fix` knows how to repair without destroying data.
+#[derive(Debug, PartialEq, Eq)]
+enum Fix {
+    /// Config is missing: scaffold the default config and library at these
+    /// paths
+    InitScaffold { config: PathBuf, library: PathBuf },
+    /// Library directory is missing
+    CreateLibraryDir(PathBuf),
+    /// A `.md` file referenced by a profile is missing
+    CreateStub(PathBuf),
+}
+
+/// Content written into stub files created for missing references.
+const STUB_CONTENT: &str =
+    "<!-- prompter stub: created by `prompter doctor --fix`. Replace with real content. -->\n";
+
+/// Findings accumulated while the doctor checks run.
+#[derive(Debug, Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+    /// Errors that `--fix` can repair
+    repairs: Vec<Fix>,
+    /// Library files no profile uses, which `--fix` offers to delete
+    orphans: Vec<PathBuf>,
+    /// Profiles that fail to resolve, which keep `--fix` from deleting orphans
+    unresolved: Vec<String>,
+    /// Print emoji markers instead of plain labels
+    styled: bool,
+}
+
+impl Report {
+    const fn error(&mut self) {
+        self.errors += 1;
+    }
+
+    fn fixable(&mut self, fix: Fix) {
+        self.errors += 1;
+        self.repairs.push(fix);
+    }
+
+    const fn warning(&mut self) {
+        self.warnings += 1;
+    }
+}
 
 /// Run doctor command to check health and configuration.
 ///
-/// Returns exit code: 0 if healthy, 1 if issues found.
-pub fn run_doctor() -> i32 {
-    println!("🏥 prompter health check");
+/// When `opts.offline` is set, the network update check is skipped. When
+/// `opts.fix` is set, fixable findings are repaired after the checks run.
+///
+/// Returns exit code: 0 if healthy, 1 if errors were found (or warnings, when
+/// `opts.strict` is set).
+pub fn run_doctor(opts: &DoctorOptions) -> i32 {
+    let styled = opts.styled;
+    println!("{}prompter health check", Mark::Health.text(styled));
     println!("========================");
     println!();
 
-    let mut has_errors = false;
-    let mut has_warnings = false;
+    let mut report = Report {
+        styled,
+        ..Report::default()
+    };
 
-    // Check configuration
-    println!("Configuration:");
     let home = std::env::var("HOME").unwrap_or_else(|_| "~".to_string());
-    let config_path = Path::new(&home).join(".config/prompter/config.toml");
+    let (config_path, library_path) = match prompter::ResolvedPaths::resolve(opts.config.as_deref())
+    {
+        Ok(paths) if paths.config_is_stdin() => {
+            println!(
+                "{}Cannot check a config read from stdin; pass a file path",
+                Mark::Error.text(styled)
+            );
+            return 1;
+        }
+        Ok(paths) => (paths.config, paths.library),
+        Err(_) => (
+            Path::new(&home).join(".config/prompter/config.toml"),
+            Path::new(&home).join(".local/share/prompter/library"),
+        ),
+    };
+    // The old location only stands in for the default library
+    let legacy_path = opts.config.is_none().then(|| {
+        prompter::legacy_library_dir()
+            .unwrap_or_else(|_| Path::new(&home).join(".local/prompter/library"))
+    });
+
+    let cfg = check_configuration(&mut report, &config_path, &library_path, opts.strict_config);
+    check_library_dir(&mut report, &library_path, legacy_path.as_deref());
+    println!();
+
+    if let Some(cfg) = &cfg {
+        let limit_kb = opts
+            .max_file_size_kb
+            .or_else(prompter::max_file_size_kb_from_env)
+            .unwrap_or(prompter::DEFAULT_MAX_FILE_SIZE_KB);
+        check_file_sizes(&mut report, cfg, &library_path, limit_kb);
+        println!();
+        check_orphans(&mut report, cfg, &library_path, opts.follow_symlinks);
+        println!();
+    }
+
+    if opts.deep {
+        check_library_files(&mut report, &library_path);
+        println!();
+    }
+
+    if let Some(shell) = crate::completions::detect_shell() {
+        check_completions(&mut report, shell);
+        println!();
+    }
+
+    check_updates(&mut report, opts.offline);
+    println!();
+
+    if opts.fix && !report.repairs.is_empty() {
+        run_fixes(&mut report, opts.yes);
+        println!();
+    }
+
+    if opts.fix && !report.orphans.is_empty() {
+        remove_orphans(&mut report, opts.force);
+        println!();
+    }
+
+    println!("{}", summary_line(report.errors, report.warnings, styled));
+    exit_code(report.errors, report.warnings, opts.strict)
+}
+
+/// Format the final summary line for the given finding counts, with a
+/// leading emoji when `styled`.
+fn summary_line(errors: usize, warnings: usize, styled: bool) -> String {
+    let (mark, counts) = match (errors, warnings) {
+        (0, 0) => (Mark::Highlight, "Everything looks healthy!".to_string()),
+        (0, w) => (Mark::Warning, format!("{} found", plural(w, "warning"))),
+        (e, 0) => (Mark::Error, format!("{} found", plural(e, "error"))),
+        (e, w) => (
+            Mark::Error,
+            format!("{}, {} found", plural(e, "error"), plural(w, "warning")),
+        ),
+    };
+    if styled {
+        format!("{}{counts}", mark.text(true))
+    } else {
+        counts
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// Errors always fail; warnings only fail in strict mode.
+const fn exit_code(errors: usize, warnings: usize, strict: bool) -> i32 {
+    if errors > 0 || (strict && warnings > 0) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Check the config and library, returning the parsed config when it loads.
+fn check_configuration(
+    report: &mut Report,
+    config_path: &Path,
+    library_path: &Path,
+    strict_config: bool,
+) -> Option<prompter::Config> {
+    let styled = report.styled;
+    println!("Configuration:");
+    let mut loaded = None;
 
     if config_path.exists() {
-        println!("  ✅ Config file: {}", config_path.display());
+        println!(
+            "  {}Config file: {}",
+            Mark::Ok.text(styled),
+            config_path.display()
+        );
 
         // Try to parse it
-        match std::fs::read_to_string(&config_path) {
+        match std::fs::read_to_string(config_path) {
             Ok(content) => {
                 if toml::from_str::<toml::Value>(&content).is_ok() {
-                    println!("  ✅ Config is valid TOML");
+                    println!("  {}Config is valid TOML", Mark::Ok.text(styled));
                 } else {
-                    println!("  ❌ Config is invalid TOML");
-                    has_errors = true;
+                    println!("  {}Config is invalid TOML", Mark::Error.text(styled));
+                    report.error();
+                }
+                // Load it the way every other command does, so --strict-config applies
+                let cfg = if strict_config {
+                    prompter::Config::from_path_strict(config_path)
+                } else {
+                    prompter::Config::from_path(config_path)
+                };
+                match cfg {
+                    Ok(cfg) => {
+                        for missing in prompter::missing_files(&cfg, library_path) {
+                            println!(
+                                "  {}Referenced snippet not found: {}",
+                                Mark::Error.text(styled),
+                                missing.display()
+                            );
+                            report.fixable(Fix::CreateStub(missing));
+                        }
+                        check_profiles(report, &cfg, library_path);
+                        loaded = Some(cfg);
+                    }
+                    Err(e) => {
+                        println!("  {}Config does not load: {e}", Mark::Error.text(styled));
+                        report.error();
+                    }
                 }
             }
             Err(e) => {
-                println!("  ❌ Failed to read config: {e}");
-                has_errors = true;
+                println!("  {}Failed to read config: {e}", Mark::Error.text(styled));
+                report.error();
             }
         }
     } else {
-        println!("  ❌ Config file not found: {}", config_path.display());
-        println!("  ℹ️  Run 'prompter init' to create default configuration");
-        has_errors = true;
+        println!(
+            "  {}Config file not found: {}",
+            Mark::Error.text(styled),
+            config_path.display()
+        );
+        println!(
+            "  {}Run 'prompter init' to create default configuration",
+            Mark::Info.text(styled)
+        );
+        report.fixable(Fix::InitScaffold {
+            config: config_path.to_path_buf(),
+            library: library_path.to_path_buf(),
+        });
     }
+    loaded
+}
 
-    // Check library directory
-    let library_path = Path::new(&home).join(".local/prompter/library");
-
+/// Check the library directory, suggesting a move when only the pre-XDG
+/// location (`legacy_path`) exists.
+fn check_library_dir(report: &mut Report, library_path: &Path, legacy_path: Option<&Path>) {
+    let styled = report.styled;
     if library_path.exists() {
-        println!("  ✅ Library directory: {}", library_path.display());
+        println!(
+            "  {}Library directory: {}",
+            Mark::Ok.text(styled),
+            library_path.display()
+        );
+    } else if let Some(legacy_path) = legacy_path.filter(|path| path.is_dir()) {
+        println!(
+            "  {}Library directory not found: {} (found the old location {})",
+            Mark::Error.text(styled),
+            library_path.display(),
+            legacy_path.display()
+        );
+        println!(
+            "  {}Move it: mkdir -p {} && mv {} {}",
+            Mark::Hint.text(styled),
+            library_path.parent().unwrap_or(library_path).display(),
+            legacy_path.display(),
+            library_path.display()
+        );
+        report.error();
     } else {
         println!(
-            "  ❌ Library directory not found: {}",
+            "  {}Library directory not found: {}",
+            Mark::Error.text(styled),
             library_path.display()
         );
-        println!("  ℹ️  Run 'prompter init' to create default library");
-        has_errors = true;
+        println!(
+            "  {}Run 'prompter init' to create default library",
+            Mark::Info.text(styled)
+        );
+        report.fixable(Fix::CreateLibraryDir(library_path.to_path_buf()));
     }
+}
 
-    println!();
+/// Warn about resolved files larger than `limit_kb`, naming the profiles
+/// that include each one.
+fn check_file_sizes(
+    report: &mut Report,
+    cfg: &prompter::Config,
+    library_path: &Path,
+    limit_kb: u64,
+) {
+    let styled = report.styled;
+    println!("File sizes:");
+    let limit = limit_kb.saturating_mul(1024);
+    let mut oversized = 0;
+    for (path, profiles) in prompter::file_profiles(cfg, library_path) {
+        let Ok(meta) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if meta.len() <= limit {
+            continue;
+        }
+        let shown = path.strip_prefix(library_path).unwrap_or(&path);
+        let used_by: Vec<String> = profiles.iter().map(|p| format!("[{p}]")).collect();
+        println!(
+            "  {}{} is {} KB (limit {limit_kb} KB), used by {}",
+            Mark::Warning.text(styled),
+            shown.display(),
+            meta.len().div_ceil(1024),
+            used_by.join(", ")
+        );
+        report.warning();
+        oversized += 1;
+    }
+    if oversized == 0 {
+        println!(
+            "  {}No resolved file exceeds {limit_kb} KB",
+            Mark::Ok.text(styled)
+        );
+    }
+}
+
+/// Warn about library files that no profile resolves to.
+fn check_orphans(
+    report: &mut Report,
+    cfg: &prompter::Config,
+    library_path: &Path,
+    follow_symlinks: bool,
+) {
+    let styled = report.styled;
+    println!("Orphaned files:");
+    report.unresolved = prompter::unresolved_profiles(cfg, library_path);
+    match prompter::orphaned_files(cfg, library_path, follow_symlinks) {
+        Ok(orphans) if orphans.is_empty() => {
+            println!(
+                "  {}Every library file is used by a profile",
+                Mark::Ok.text(styled)
+            );
+        }
+        Ok(orphans) => {
+            for orphan in orphans {
+                println!(
+                    "  {}Not used by any profile: {}",
+                    Mark::Warning.text(styled),
+                    orphan.display()
+                );
+                report.warning();
+                report.orphans.push(library_path.join(orphan));
+            }
+        }
+        Err(e) => {
+            println!("  {}Failed to scan library: {e}", Mark::Error.text(styled));
+            report.error();
+        }
+    }
+}
+
+/// Run `validate` and the profile lints, with a suggested fix for each finding.
+///
+/// Missing files and self-dependencies are left out of the `validate`
+/// output because they are reported more specifically elsewhere.
+fn check_profiles(report: &mut Report, cfg: &prompter::Config, library_path: &Path) {
+    let styled = report.styled;
+    let lints = prompter::profile_lints(cfg);
+    let self_cycles: Vec<String> = lints
+        .iter()
+        .filter_map(|lint| match lint {
+            prompter::ProfileLint::SelfDependency(name) => {
+                Some(format!("Cycle detected: {name} -> {name}"))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if let Err(prompter::PrompterError::Invalid(errors)) = prompter::validate(cfg, library_path) {
+        let mut errors: Vec<&String> = errors
+            .iter()
+            .filter(|e| !e.starts_with("Missing file:") && !self_cycles.contains(e))
+            .collect();
+        errors.sort_unstable();
+        for error in errors {
+            println!("  {}{error}", Mark::Error.text(styled));
+            println!(
+                "  {}Run 'prompter validate' and fix the reference in config.toml",
+                Mark::Hint.text(styled)
+            );
+            report.error();
+        }
+    }
+
+    for lint in lints {
+        match lint {
+            prompter::ProfileLint::Empty(name) => {
+                println!(
+                    "  {}Profile [{name}] has no dependencies",
+                    Mark::Warning.text(styled)
+                );
+                println!(
+                    "  {}Add files or profiles to its depends_on, or remove the section",
+                    Mark::Hint.text(styled)
+                );
+                report.warning();
+            }
+            prompter::ProfileLint::SelfDependency(name) => {
+                println!(
+                    "  {}Profile [{name}] depends on itself",
+                    Mark::Error.text(styled)
+                );
+                println!(
+                    "  {}Remove \"{name}\" from the depends_on of [{name}]",
+                    Mark::Hint.text(styled)
+                );
+                report.error();
+            }
+            prompter::ProfileLint::Duplicate { profile, dep } => {
+                println!(
+                    "  {}Profile [{profile}] lists \"{dep}\" more than once",
+                    Mark::Warning.text(styled)
+                );
+                println!(
+                    "  {}Remove the repeats; only the first occurrence is rendered",
+                    Mark::Hint.text(styled)
+                );
+                report.warning();
+            }
+        }
+    }
+}
+
+/// Report library files that are not valid UTF-8 or lack a trailing newline.
+fn check_library_files(report: &mut Report, library_path: &Path) {
+    let styled = report.styled;
+    println!("Library files:");
+    match prompter::check_library(library_path) {
+        Ok(issues) if issues.is_empty() => {
+            println!("  {}All library files OK", Mark::Ok.text(styled));
+        }
+        Ok(issues) => {
+            for issue in issues {
+                println!("  {}{issue}", Mark::Warning.text(styled));
+                report.warning();
+            }
+        }
+        Err(e) => {
+            println!("  {}Failed to scan library: {e}", Mark::Error.text(styled));
+            report.error();
+        }
+    }
+}
+
+/// Check that the completion script for `shell` is installed and matches
+/// what this binary generates. Completions are optional, so only a stale
+/// script is a warning.
+fn check_completions(report: &mut Report, shell: prompter::CompletionShell) {
+    use crate::completions::CompletionStatus;
+    let styled = report.styled;
+
+    println!("Shell completions:");
+    match crate::completions::completion_status(shell) {
+        Ok(CompletionStatus::UpToDate(path)) => {
+            println!(
+                "  {}{shell} completions up to date: {}",
+                Mark::Ok.text(styled),
+                path.display()
+            );
+        }
+        Ok(CompletionStatus::Stale(path)) => {
+            println!(
+                "  {}{shell} completions are out of date: {}",
+                Mark::Warning.text(styled),
+                path.display()
+            );
+            println!(
+                "  {}Run 'prompter completions {shell} --write --force'",
+                Mark::Hint.text(styled)
+            );
+            report.warning();
+        }
+        Ok(CompletionStatus::Missing) => {
+            println!(
+                "  {}{shell} completions not installed",
+                Mark::Info.text(styled)
+            );
+            println!(
+                "  {}Run 'prompter completions {shell} --write'",
+                Mark::Hint.text(styled)
+            );
+        }
+        Ok(CompletionStatus::Unsupported) => {
+            println!(
+                "  {}Completion check skipped ({shell} has no per-user install location)",
+                Mark::Info.text(styled)
+            );
+        }
+        Err(e) => println!("  {}Completion check skipped: {e}", Mark::Info.text(styled)),
+    }
+}
 
-    // Check for updates
+fn check_updates(report: &mut Report, offline: bool) {
+    let styled = report.styled;
     println!("Updates:");
+    if offline {
+        println!(
+            "  {}Update check skipped (offline)",
+            Mark::Info.text(styled)
+        );
+        return;
+    }
     match check_for_updates() {
         Ok(Some(latest)) => {
             let current = env!("CARGO_PKG_VERSION");
-            println!("  ⚠️  Update available: v{latest} (current: v{current})");
-            println!("  💡 Run 'prompter update' to install the latest version");
-            has_warnings = true;
+            println!(
+                "  {}Update available: v{latest} (current: v{current})",
+                Mark::Warning.text(styled)
+            );
+            println!(
+                "  {}Run 'prompter update' to install the latest version",
+                Mark::Hint.text(styled)
+            );
+            report.warning();
         }
         Ok(None) => {
             println!(
-                "  ✅ Running latest version (v{})",
+                "  {}Running latest version (v{})",
+                Mark::Ok.text(styled),
                 env!("CARGO_PKG_VERSION")
             );
         }
         Err(e) => {
-            println!("  ⚠️  Failed to check for updates: {e}");
-            has_warnings = true;
+            println!(
+                "  {}Failed to check for updates: {e}",
+                Mark::Warning.text(styled)
+            );
+            report.warning();
         }
     }
+}
 
-    println!();
+/// Apply the report's repairs, confirming first on an interactive terminal.
+fn run_fixes(report: &mut Report, yes: bool) {
+    let styled = report.styled;
+    let found = report.errors;
+    let applied = if yes || !io::stdin().is_terminal() || confirm_fixes(report.repairs.len()) {
+        println!("Fixes:");
+        apply_fixes(&report.repairs, styled)
+    } else {
+        0
+    };
+    let remaining = found - applied;
+    println!(
+        "{}Fixed {applied} of {found} issue(s), {remaining} remaining",
+        Mark::Fixed.text(styled)
+    );
+    report.errors = remaining;
+}
 
-    // Summary
-    if has_errors {
+/// Delete orphaned library files, after confirmation unless `force` is set.
+///
+/// Without a terminal to ask on, nothing is deleted unless `force` is set.
+/// Nothing is deleted at all while a profile fails to resolve, since the
+/// files it would use are unknown.
+fn remove_orphans(report: &mut Report, force: bool) {
+    let styled = report.styled;
+    let count = report.orphans.len();
+    if !report.unresolved.is_empty() {
         println!(
-            "❌ {} found",
-            if has_warnings {
-                format!(
-                    "{} error{}, {} warning{}",
-                    if has_errors { "1" } else { "0" },
-                    if has_errors { "" } else { "s" },
-                    if has_warnings { "1" } else { "0" },
-                    if has_warnings { "" } else { "s" }
-                )
-            } else {
-                "1 error".to_string()
-            }
+            "{}Kept {count} orphaned file(s); fix the profiles that fail to resolve first: {}",
+            Mark::Deleted.text(styled),
+            report.unresolved.join(", ")
         );
-        1
-    } else if has_warnings {
-        println!("⚠️  1 warning found");
-        0 // Warnings don't cause failure
-    } else {
-        println!("✨ Everything looks healthy!");
-        0
+        return;
+    }
+    let confirmed = force
+        || (io::stdin().is_terminal()
+            && confirm(&format!("Delete {count} orphaned library file(s)?")));
+    if !confirmed {
+        println!(
+            "{}Kept {count} orphaned file(s); pass --force to delete them",
+            Mark::Deleted.text(styled)
+        );
+        return;
+    }
+    println!("Orphaned files:");
+    let removed = delete_files(&report.orphans, styled);
+    report.warnings -= removed;
+    println!(
+        "{}Deleted {removed} of {count} orphaned file(s)",
+        Mark::Deleted.text(styled)
+    );
+}
+
+/// Delete each file, printing the outcome. Returns how many were deleted.
+fn delete_files(paths: &[PathBuf], styled: bool) -> usize {
+    let mut removed = 0;
+    for path in paths {
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                println!("  {}Deleted {}", Mark::Deleted.text(styled), path.display());
+                removed += 1;
+            }
+            Err(e) => println!(
+                "  {}Failed to delete {}: {e}",
+                Mark::Error.text(styled),
+                path.display()
+            ),
+        }
+    }
+    removed
+}
+
+/// Ask once whether the collected fixes should be applied.
+fn confirm_fixes(count: usize) -> bool {
+    confirm(&format!("Apply {count} fix(es)?"))
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no.
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N]: ");
+    io::stdout().flush().ok();
+
+    let mut response = String::new();
+    if io::stdin().read_line(&mut response).is_err() {
+        return false;
+    }
+    matches!(response.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Apply fixes in order, printing each one. Never overwrites or deletes.
+///
+/// Returns the number of fixes that succeeded.
+fn apply_fixes(repairs: &[Fix], styled: bool) -> usize {
+    let mut applied = 0;
+    for fix in repairs {
+        match apply_fix(fix, styled) {
+            Ok(msg) => {
+                println!("  {}{msg}", Mark::Fixed.text(styled));
+                applied += 1;
+            }
+            Err(e) => println!("  {}{e}", Mark::Error.text(styled)),
+        }
+    }
+    applied
+}
+
+fn apply_fix(fix: &Fix, styled: bool) -> Result<String, String> {
+    match fix {
+        Fix::InitScaffold { config, library } => {
+            let opts = prompter::InitOptions {
+                styled,
+                ..prompter::InitOptions::default()
+            };
+            prompter::init_scaffold(config, library, opts)?;
+            Ok(format!(
+                "Created default config and library: {}",
+                config.display()
+            ))
+        }
+        Fix::CreateLibraryDir(dir) => {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+            Ok(format!("Created library directory: {}", dir.display()))
+        }
+        Fix::CreateStub(path) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+            }
+            // create_new refuses to touch a file that appeared in the meantime
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .and_then(|mut f| f.write_all(STUB_CONTENT.as_bytes()))
+                .map_err(|e| format!("Failed to create stub {}: {e}", path.display()))?;
+            Ok(format!("Created stub: {}", path.display()))
+        }
     }
 }
 
 fn check_for_updates() -> Result<Option<String>, String> {
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("prompter-doctor")
-        .timeout(std::time::Duration::from_secs(5))
-        .build()
-        .map_err(|e| e.to_string())?;
-
-    let url = "https://api.github.com/repos/workhelix/prompter/releases/latest";
-    let response: serde_json::Value = client
-        .get(url)
-        .send()
-        .map_err(|e| e.to_string())?
-        .json()
-        .map_err(|e| e.to_string())?;
-
-    let tag_name = response["tag_name"]
-        .as_str()
-        .ok_or_else(|| "No tag_name in response".to_string())?;
-
-    let latest = tag_name
-        .trim_start_matches("prompter-v")
-        .trim_start_matches('v');
+    let latest = crate::release::latest_version("prompter-doctor", Duration::from_secs(5))?;
     let current = env!("CARGO_PKG_VERSION");
 
-    if latest == current {
-        Ok(None)
+    if crate::release::is_newer(&latest, current) {
+        Ok(Some(latest))
     } else {
-        Ok(Some(latest.to_string()))
+        Ok(None)
     }
 }
 
@@ -161,9 +744,162 @@ mod tests {
     }
 
     #[test]
-    fn test_run_doctor_returns_valid_exit_code() {
-        let exit_code = run_doctor();
-        // Should return 0 or 1
-        assert!(exit_code == 0 || exit_code == 1);
+    fn test_summary_line_pluralization() {
+        assert_eq!(summary_line(0, 0, true), "✨ Everything looks healthy!");
+        assert_eq!(summary_line(0, 1, true), "⚠️  1 warning found");
+        assert_eq!(summary_line(0, 2, true), "⚠️  2 warnings found");
+        assert_eq!(summary_line(1, 0, true), "❌ 1 error found");
+        assert_eq!(summary_line(3, 0, true), "❌ 3 errors found");
+        assert_eq!(summary_line(3, 1, true), "❌ 3 errors, 1 warning found");
+        assert_eq!(summary_line(1, 4, true), "❌ 1 error, 4 warnings found");
+        assert_eq!(summary_line(0, 0, false), "Everything looks healthy!");
+        assert_eq!(summary_line(3, 1, false), "3 errors, 1 warning found");
+    }
+
+    #[test]
+    fn test_exit_code_strict_mode() {
+        assert_eq!(exit_code(0, 0, false), 0);
+        assert_eq!(exit_code(0, 2, false), 0);
+        assert_eq!(exit_code(0, 2, true), 1);
+        assert_eq!(exit_code(1, 0, false), 1);
+        assert_eq!(exit_code(0, 0, true), 0);
+    }
+
+    #[test]
+    fn test_apply_fix_creates_stub_without_overwriting() {
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("nested/dir/missing.md");
+        assert_eq!(apply_fixes(&[Fix::CreateStub(stub.clone())], false), 1);
+        assert_eq!(std::fs::read_to_string(&stub).unwrap(), STUB_CONTENT);
+
+        std::fs::write(&stub, "real content").unwrap();
+        assert_eq!(apply_fixes(&[Fix::CreateStub(stub.clone())], false), 0);
+        assert_eq!(std::fs::read_to_string(&stub).unwrap(), "real content");
+    }
+
+    #[test]
+    fn test_check_profiles_counts_findings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.md"), "A\n").unwrap();
+        let cfg = prompter::parse_config_toml(
+            "[empty]\ndepends_on = []\n\
+             [loop]\ndepends_on = [\"loop\"]\n\
+             [dup]\ndepends_on = [\"a.md\", \"a.md\", \"nope\"]\n",
+        )
+        .unwrap();
+
+        let mut report = Report::default();
+        check_profiles(&mut report, &cfg, dir.path());
+        // unknown profile "nope" and the self-dependency; the trivial cycle is
+        // not counted twice
+        assert_eq!(report.errors, 2);
+        // empty profile and duplicate entry
+        assert_eq!(report.warnings, 2);
+        assert!(report.repairs.is_empty());
+    }
+
+    #[test]
+    fn test_check_file_sizes_warns_over_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("big.md"), vec![b'x'; 3 * 1024]).unwrap();
+        std::fs::write(dir.path().join("small.md"), "small\n").unwrap();
+        let cfg = prompter::parse_config_toml(
+            "[a]\ndepends_on = [\"big.md\", \"small.md\"]\n[b]\ndepends_on = [\"a\"]\n",
+        )
+        .unwrap();
+
+        let mut report = Report::default();
+        check_file_sizes(&mut report, &cfg, dir.path(), 2);
+        assert_eq!(report.warnings, 1);
+
+        let mut report = Report::default();
+        check_file_sizes(&mut report, &cfg, dir.path(), 3);
+        assert_eq!(report.warnings, 0);
+        assert_eq!(report.errors, 0);
+    }
+
+    #[test]
+    fn test_check_orphans_counts_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("used.md"), "u\n").unwrap();
+        std::fs::write(dir.path().join("old.md"), "o\n").unwrap();
+        std::fs::write(dir.path().join("older.md"), "o\n").unwrap();
+        let cfg = prompter::parse_config_toml("[a]\ndepends_on = [\"used.md\"]\n").unwrap();
+
+        let mut report = Report::default();
+        check_orphans(&mut report, &cfg, dir.path(), false);
+        assert_eq!(report.warnings, 2);
+        assert_eq!(report.errors, 0);
+    }
+
+    #[test]
+    fn test_remove_orphans_keeps_files_while_a_profile_is_broken() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("old.md"), "o\n").unwrap();
+        let cfg =
+            prompter::parse_config_toml("[a]\ndepends_on = [\"gone.md\"]\n[b]\ndepends_on = []\n")
+                .unwrap();
+
+        let mut report = Report::default();
+        check_orphans(&mut report, &cfg, dir.path(), false);
+        assert_eq!(report.orphans, vec![dir.path().join("old.md")]);
+        assert_eq!(report.unresolved, vec!["a".to_string()]);
+        remove_orphans(&mut report, true);
+        assert!(dir.path().join("old.md").exists());
+        assert_eq!(report.warnings, 1);
+    }
+
+    #[test]
+    fn test_delete_files_counts_removals() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.md");
+        std::fs::write(&old, "o\n").unwrap();
+        let gone = dir.path().join("gone.md");
+        assert_eq!(delete_files(&[old.clone(), gone], false), 1);
+        assert!(!old.exists());
+    }
+
+    #[test]
+    fn test_check_library_files_counts_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("ok.md"), "fine\n").unwrap();
+        std::fs::write(dir.path().join("bad.md"), b"\xff").unwrap();
+
+        let mut report = Report::default();
+        check_library_files(&mut report, dir.path());
+        // invalid UTF-8 and no trailing newline
+        assert_eq!(report.warnings, 2);
+        assert_eq!(report.errors, 0);
+    }
+
+    #[test]
+    fn test_check_library_dir_hints_at_legacy_location() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = dir.path().join("share/prompter/library");
+        let legacy = dir.path().join("prompter/library");
+
+        let mut report = Report::default();
+        check_library_dir(&mut report, &library, Some(&legacy));
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.repairs.len(), 1);
+
+        std::fs::create_dir_all(&legacy).unwrap();
+        let mut report = Report::default();
+        check_library_dir(&mut report, &library, Some(&legacy));
+        assert_eq!(report.errors, 1);
+        assert!(report.repairs.is_empty(), "moving is left to the user");
+
+        std::fs::create_dir_all(&library).unwrap();
+        let mut report = Report::default();
+        check_library_dir(&mut report, &library, Some(&legacy));
+        assert_eq!(report.errors, 0);
+    }
+
+    #[test]
+    fn test_apply_fix_creates_library_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("library");
+        assert_eq!(apply_fixes(&[Fix::CreateLibraryDir(lib.clone())], false), 1);
+        assert!(lib.is_dir());
     }
 }
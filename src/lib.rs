@@ -4,37 +4,822 @@
 //! from a structured library using TOML configuration files. It supports recursive
 //! profile dependencies, file deduplication, and customizable output formatting.
 
-use chrono::Local;
-use clap::{Parser, Subcommand};
-use colored::Colorize;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use colored::{Color, Colorize};
 use indicatif::{ProgressBar, ProgressStyle};
 use is_terminal::IsTerminal;
-use std::collections::{HashMap, HashSet};
+use serde::de::IgnoredAny;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::num::NonZeroUsize;
+use std::panic;
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::thread;
+use std::time::SystemTime;
 
 /// Configuration structure holding profile definitions and their dependencies.
 ///
 /// Profiles map names to lists of dependencies, where dependencies can be either
-/// markdown files (ending in .md) or references to other profiles.
-#[derive(Debug)]
+/// markdown files (ending in .md) or references to other profiles. A profile may
+/// also extend a parent profile, inheriting its files ahead of its own.
+///
+/// `Config` implements serde's `Serialize` and `Deserialize` with one table per
+/// profile keyed by its full name; [`Config::to_toml_string`] writes the
+/// canonical config file text.
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     /// Map of profile names to their dependency lists
     pub(crate) profiles: HashMap<String, Vec<String>>,
     /// Optional post-prompt text to append at the end of output
     pub(crate) post_prompt: Option<String>,
+    /// Library file whose text replaces the default pre-prompt
+    pub(crate) pre_prompt_file: Option<String>,
+    /// Library file whose text replaces `post_prompt`
+    pub(crate) post_prompt_file: Option<String>,
+    /// Profile rendered by `prompter run` when no name is given
+    pub(crate) default_profile: Option<String>,
+    /// chrono format for the date in the system prefix line
+    pub(crate) timestamp_format: Option<String>,
+    /// Clock the system prefix date is read from
+    pub(crate) timezone: Option<Timezone>,
+    /// Map of profile names to the parent profile they extend
+    pub(crate) extends: HashMap<String, String>,
+    /// Per-profile metadata such as tags, keyed by profile name
+    pub(crate) meta: HashMap<String, ProfileMeta>,
+    /// Extra library directories searched, in order, for files missing from
+    /// the primary library
+    pub(crate) library_roots: Vec<String>,
+    /// `prompter lint` rules turned off in the `[lint]` table
+    pub(crate) disabled_lints: BTreeSet<LintRule>,
+}
+
+impl Config {
+    /// An empty config with no profiles, to be filled with
+    /// [`Config::add_profile`].
+    ///
+    /// # Examples
+    /// ```
+    /// use prompter::Config;
+    /// let mut cfg = Config::new();
+    /// cfg.add_profile("python.api", ["python/api.md", "general"])
+    ///     .add_profile("general", ["style.md"]);
+    /// assert_eq!(cfg.profile("general"), Some(&["style.md".to_string()][..]));
+    /// ```
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read and parse the config at `path` (`-` reads stdin).
+    ///
+    /// # Examples
+    /// ```
+    /// use prompter::Config;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("config.toml");
+    /// std::fs::write(&path, "post_prompt = \"Done.\"\n[rust]\ndepends_on = [\"rust.md\"]\n").unwrap();
+    /// let cfg = Config::from_path(&path).unwrap();
+    /// assert_eq!(cfg.profile_names(), ["rust"]);
+    /// assert_eq!(cfg.post_prompt(), Some("Done."));
+    /// ```
+    ///
+    /// Unknown keys are ignored, as by [`parse_config_toml`]; see
+    /// [`Config::from_path_strict`] to reject them.
+    ///
+    /// # Errors
+    /// Returns [`PrompterError::ConfigRead`] if the file cannot be read and
+    /// [`PrompterError::ConfigParse`] if it is malformed.
+    pub fn from_path(path: &Path) -> Result<Self, PrompterError> {
+        parse_config_toml(&read_config_with_path(path)?)
+    }
+
+    /// Read and parse the config at `path` like [`Config::from_path`], but
+    /// reject unknown keys as [`parse_config_toml_strict`] does.
+    ///
+    /// # Examples
+    /// ```
+    /// use prompter::Config;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let path = dir.path().join("config.toml");
+    /// std::fs::write(&path, "[rust]\ndepnds_on = [\"rust.md\"]\n").unwrap();
+    /// assert!(Config::from_path(&path).is_ok());
+    /// assert!(Config::from_path_strict(&path).is_err());
+    /// ```
+    ///
+    /// # Errors
+    /// As [`Config::from_path`], and [`PrompterError::ConfigParse`] for any
+    /// unknown key.
+    pub fn from_path_strict(path: &Path) -> Result<Self, PrompterError> {
+        parse_config_toml_strict(&read_config_with_path(path)?)
+    }
+
+    /// [`Config::from_path_strict`] when `strict`, else [`Config::from_path`].
+    fn load(path: &Path, strict: bool) -> Result<Self, PrompterError> {
+        parse_config(&read_config_with_path(path)?, strict)
+    }
+
+    /// Define `name` with the given dependencies, replacing any previous
+    /// definition. Entries ending in `.md` are library files; anything else
+    /// names another profile.
+    pub fn add_profile<I, S>(&mut self, name: impl Into<String>, deps: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.profiles
+            .insert(name.into(), deps.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the text appended after the rendered files instead of the default.
+    pub fn set_post_prompt(&mut self, post_prompt: Option<String>) -> &mut Self {
+        self.post_prompt = post_prompt;
+        self
+    }
+
+    /// Set the library file read for the pre-prompt.
+    pub fn set_pre_prompt_file(&mut self, file: Option<String>) -> &mut Self {
+        self.pre_prompt_file = file;
+        self
+    }
+
+    /// Set the library file read for the post-prompt, which wins over
+    /// [`Config::set_post_prompt`].
+    pub fn set_post_prompt_file(&mut self, file: Option<String>) -> &mut Self {
+        self.post_prompt_file = file;
+        self
+    }
+
+    /// Turn a `prompter lint` rule on or off; every rule is on by default.
+    ///
+    /// The `[lint]` table is read as a profile when it sets `depends_on`,
+    /// `extends` or `tags`, so rules are not written out while a profile is
+    /// named `lint`.
+    pub fn set_lint_rule(&mut self, rule: LintRule, enabled: bool) -> &mut Self {
+        if enabled {
+            self.disabled_lints.remove(&rule);
+        } else {
+            self.disabled_lints.insert(rule);
+        }
+        self
+    }
+
+    /// Set the profile `prompter run` renders when no name is given.
+    pub fn set_default_profile(&mut self, default_profile: Option<String>) -> &mut Self {
+        self.default_profile = default_profile;
+        self
+    }
+
+    /// Set the chrono format of the banner date; `None` uses
+    /// [`DEFAULT_TIMESTAMP_FORMAT`].
+    pub fn set_timestamp_format(&mut self, timestamp_format: Option<String>) -> &mut Self {
+        self.timestamp_format = timestamp_format;
+        self
+    }
+
+    /// Set the clock the banner date is read from; `None` uses local time.
+    pub const fn set_timezone(&mut self, timezone: Option<Timezone>) -> &mut Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Every profile with its direct dependencies, in no particular order.
+    #[must_use]
+    pub const fn profiles(&self) -> &HashMap<String, Vec<String>> {
+        &self.profiles
+    }
+
+    /// The names of every profile, sorted.
+    #[must_use]
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// The direct dependencies of `name` as written in `depends_on`, or
+    /// `None` if it is not defined.
+    ///
+    /// # Examples
+    /// ```
+    /// use prompter::Config;
+    /// let cfg: Config = "[a]\ndepends_on = [\"x.md\", \"b\"]\n[b]\ndepends_on = []\n"
+    ///     .parse()
+    ///     .unwrap();
+    /// assert_eq!(cfg.dependencies("a"), Some(&["x.md".to_string(), "b".to_string()][..]));
+    /// assert_eq!(cfg.dependencies("b"), Some(&[][..]));
+    /// assert_eq!(cfg.dependencies("c"), None);
+    /// ```
+    #[must_use]
+    pub fn dependencies(&self, name: &str) -> Option<&[String]> {
+        self.profiles.get(name).map(Vec::as_slice)
+    }
+
+    /// The direct dependencies of `name`; same as [`Config::dependencies`].
+    #[must_use]
+    pub fn profile(&self, name: &str) -> Option<&[String]> {
+        self.dependencies(name)
+    }
+
+    /// The configured post-prompt, if any.
+    #[must_use]
+    pub fn post_prompt(&self) -> Option<&str> {
+        self.post_prompt.as_deref()
+    }
+
+    /// The configured `pre_prompt_file`, as written.
+    #[must_use]
+    pub fn pre_prompt_file(&self) -> Option<&str> {
+        self.pre_prompt_file.as_deref()
+    }
+
+    /// The configured `post_prompt_file`, as written.
+    #[must_use]
+    pub fn post_prompt_file(&self) -> Option<&str> {
+        self.post_prompt_file.as_deref()
+    }
+
+    /// The configured `default_profile`, if any.
+    #[must_use]
+    pub fn default_profile(&self) -> Option<&str> {
+        self.default_profile.as_deref()
+    }
+
+    /// The configured `timestamp_format`, if any.
+    #[must_use]
+    pub fn timestamp_format(&self) -> Option<&str> {
+        self.timestamp_format.as_deref()
+    }
+
+    /// The configured `timezone`, if any.
+    #[must_use]
+    pub const fn timezone(&self) -> Option<Timezone> {
+        self.timezone
+    }
+
+    /// The prompt file keys that are set, with their values.
+    fn prompt_files(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("pre_prompt_file", self.pre_prompt_file.as_deref()),
+            ("post_prompt_file", self.post_prompt_file.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, file)| file.map(|file| (key, file)))
+    }
+
+    /// Whether `prompter lint` checks `rule`.
+    #[must_use]
+    pub fn lint_enabled(&self, rule: LintRule) -> bool {
+        !self.disabled_lints.contains(&rule)
+    }
+
+    /// The `library_roots` entries as written in the config.
+    #[must_use]
+    pub fn library_roots(&self) -> &[String] {
+        &self.library_roots
+    }
+
+    /// The canonical config file text, which [`parse_config_toml`] reads back
+    /// into an equal `Config`.
+    ///
+    /// Top-level keys come first, then one section per profile in name order
+    /// with `extends`, `depends_on` (optional entries as inline tables) and
+    /// `tags`. Names that are not plain letters, digits, `_` and `-` get a
+    /// quoted header such as `["python.api"]`, which TOML reads as one key.
+    ///
+    /// # Examples
+    /// ```
+    /// use prompter::Config;
+    /// let mut cfg = Config::new();
+    /// cfg.add_profile("python.api", ["python/api.md", "general"])
+    ///     .add_profile("general", ["style.md"]);
+    /// let text = cfg.to_toml_string();
+    /// assert_eq!(
+    ///     text,
+    ///     "[general]\ndepends_on = [\"style.md\"]\n\n[\"python.api\"]\ndepends_on = [\"python/api.md\", \"general\"]\n"
+    /// );
+    /// assert_eq!(text.parse::<Config>().unwrap(), cfg);
+    /// ```
+    #[must_use]
+    pub fn to_toml_string(&self) -> String {
+        self.toml_text(None, &ConfigComments::default())
+    }
+
+    /// Whether the `[lint]` table is written with the disabled rules: there
+    /// are some, and no profile is named `lint`.
+    fn writes_lint_table(&self) -> bool {
+        !self.disabled_lints.is_empty() && !self.profiles.contains_key(LINT_SECTION)
+    }
+
+    /// [`Config::to_toml_string`] with arrays longer than `wrap_at`
+    /// characters split one item per line, and `comments` written above the
+    /// top-level keys and their sections.
+    fn toml_text(&self, wrap_at: Option<usize>, comments: &ConfigComments) -> String {
+        let mut top = Vec::new();
+        if let Some(post_prompt) = &self.post_prompt {
+            top.push(format!("post_prompt = {}", toml_string(post_prompt)));
+        }
+        if let Some(file) = &self.pre_prompt_file {
+            top.push(format!("pre_prompt_file = {}", toml_string(file)));
+        }
+        if let Some(file) = &self.post_prompt_file {
+            top.push(format!("post_prompt_file = {}", toml_string(file)));
+        }
+        if let Some(default_profile) = &self.default_profile {
+            top.push(format!(
+                "default_profile = {}",
+                toml_string(default_profile)
+            ));
+        }
+        if let Some(format) = &self.timestamp_format {
+            top.push(format!("timestamp_format = {}", toml_string(format)));
+        }
+        if let Some(timezone) = self.timezone {
+            top.push(format!("timezone = {}", toml_string(timezone.name())));
+        }
+        if !self.library_roots.is_empty() {
+            let roots = self.library_roots.iter().map(|root| toml_string(root));
+            top.push(toml_array_entry("library_roots", roots, wrap_at));
+        }
+        let mut sections = Vec::new();
+        if !comments.header.is_empty() {
+            sections.push(comments.header.join("\n") + "\n");
+        }
+        if !top.is_empty() {
+            sections.push(top.join("\n") + "\n");
+        }
+        if self.writes_lint_table() {
+            let mut lines = comments
+                .sections
+                .get(LINT_SECTION)
+                .cloned()
+                .unwrap_or_default();
+            lines.push(format!("[{LINT_SECTION}]"));
+            lines.extend(
+                self.disabled_lints
+                    .iter()
+                    .map(|rule| format!("{rule} = false")),
+            );
+            sections.push(lines.join("\n") + "\n");
+        }
+        for name in self.profile_names() {
+            let mut lines = comments.sections.get(name).cloned().unwrap_or_default();
+            lines.extend(self.profile_section(name, wrap_at));
+            sections.push(lines.join("\n") + "\n");
+        }
+        sections.join("\n")
+    }
+
+    /// The lines of the `[name]` section for the profile `name`, as written by
+    /// [`Config::toml_text`].
+    fn profile_section(&self, name: &str, wrap_at: Option<usize>) -> Vec<String> {
+        let meta = self.meta.get(name);
+        let mut lines = vec![format!("[{}]", toml_key(name))];
+        if let Some(parent) = self.extends.get(name) {
+            lines.push(format!("extends = {}", toml_string(parent)));
+        }
+        let deps = self.profiles[name].iter().map(|dep| {
+            if meta.is_some_and(|meta| meta.optional.contains(dep)) {
+                format!("{{ path = {}, optional = true }}", toml_string(dep))
+            } else {
+                toml_string(dep)
+            }
+        });
+        lines.push(toml_array_entry("depends_on", deps, wrap_at));
+        if let Some(meta) = meta.filter(|meta| !meta.tags.is_empty()) {
+            let tags = meta.tags.iter().map(|tag| toml_string(tag));
+            lines.push(toml_array_entry("tags", tags, wrap_at));
+        }
+        lines
+    }
+
+    /// Every directory searched for library files: `lib` first, then each of
+    /// `library_roots` in order. Relative roots are taken relative to `lib`,
+    /// and `~/` and `$VAR` are expanded as in `depends_on`.
+    #[must_use]
+    pub fn search_roots(&self, lib: &Path) -> Vec<PathBuf> {
+        std::iter::once(lib.to_path_buf())
+            .chain(
+                self.library_roots
+                    .iter()
+                    .map(|root| dep_path(lib, &expand_env_lenient(root))),
+            )
+            .collect()
+    }
+}
+
+impl PartialEq for Config {
+    /// Configs are equal when they define the same profiles, prompts and
+    /// roots; profile metadata left at its defaults counts as absent.
+    fn eq(&self, other: &Self) -> bool {
+        fn set_meta(cfg: &Config) -> HashMap<&String, &ProfileMeta> {
+            cfg.meta
+                .iter()
+                .filter(|(_, meta)| **meta != ProfileMeta::default())
+                .collect()
+        }
+        self.profiles == other.profiles
+            && self.post_prompt == other.post_prompt
+            && self.pre_prompt_file == other.pre_prompt_file
+            && self.post_prompt_file == other.post_prompt_file
+            && self.default_profile == other.default_profile
+            && self.timestamp_format == other.timestamp_format
+            && self.timezone == other.timezone
+            && self.extends == other.extends
+            && self.library_roots == other.library_roots
+            && self.disabled_lints == other.disabled_lints
+            && set_meta(self) == set_meta(other)
+    }
+}
+
+impl Eq for Config {}
+
+impl std::str::FromStr for Config {
+    type Err = PrompterError;
+
+    /// Parse config text; see [`parse_config_toml`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_config_toml(s)
+    }
+}
+
+/// A quoted config string that both TOML and [`parse_config_toml`] read back
+/// as `s`.
+fn toml_string(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// A section name, quoted unless it is a bare TOML key.
+fn toml_key(name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        name.to_string()
+    } else {
+        toml_string(name)
+    }
+}
+
+/// `key = [items]` for already formatted items, on one line unless that is
+/// longer than `wrap_at` characters; then one item per line.
+fn toml_array_entry(
+    key: &str,
+    items: impl Iterator<Item = String>,
+    wrap_at: Option<usize>,
+) -> String {
+    let items: Vec<String> = items.collect();
+    let line = format!("{key} = [{}]", items.join(", "));
+    if items.len() > 1 && wrap_at.is_some_and(|width| line.chars().count() > width) {
+        format!("{key} = [\n  {}\n]", items.join(",\n  "))
+    } else {
+        line
+    }
+}
+
+/// Line width past which `prompter fmt` splits an array one item per line.
+pub const FMT_WIDTH: usize = 80;
+
+/// Full-line comments that [`format_config`] keeps, as written.
+#[derive(Debug, Default)]
+struct ConfigComments {
+    /// Comments before the first section that are not directly above it
+    header: Vec<String>,
+    /// Comments to write above each profile's section header
+    sections: HashMap<String, Vec<String>>,
+}
+
+impl ConfigComments {
+    /// Move `pending` to `section`, or to the header outside any section.
+    fn keep(&mut self, section: Option<&str>, pending: &mut Vec<String>) {
+        match section {
+            Some(name) => self
+                .sections
+                .entry(name.to_string())
+                .or_default()
+                .append(pending),
+            None => self.header.append(pending),
+        }
+    }
+}
+
+/// Collect the full-line comments of config text.
+///
+/// A comment block belongs to the next section header when only blank lines
+/// and comments separate them; otherwise to the section it sits in, or to
+/// the file header before the first section. A block separated from the
+/// first section header by a blank line stays in the file header.
+fn config_comments(input: &str) -> ConfigComments {
+    let mut comments = ConfigComments::default();
+    let mut current: Option<String> = None;
+    let mut pending: Vec<String> = Vec::new();
+    let mut in_array = false;
+    for (_, raw_line) in logical_lines(input) {
+        let line = raw_line.trim();
+        if line.starts_with('#') {
+            pending.push(line.to_string());
+            continue;
+        }
+        let code = strip_comments(line);
+        let code = code.trim();
+        if code.is_empty() {
+            if current.is_none() {
+                comments.header.append(&mut pending);
+            }
+            continue;
+        }
+        if in_array {
+            in_array = !contains_closing_bracket_outside_quotes(code);
+        } else if code.starts_with('[') && code.ends_with(']') {
+            current = Some(
+                dependency_table_name(code)
+                    .unwrap_or_else(|| unquote_key(code[1..code.len() - 1].trim())),
+            );
+        } else if let Some((_, value)) = code.split_once('=') {
+            let value = value.trim();
+            in_array = value.starts_with('[') && !contains_closing_bracket_outside_quotes(value);
+        }
+        comments.keep(current.as_deref(), &mut pending);
+    }
+    comments.keep(current.as_deref(), &mut pending);
+    comments
+}
+
+/// Rewrite config text in the canonical form written by `prompter fmt`.
+///
+/// Top-level keys come first, then one section per profile sorted by name,
+/// every string double-quoted, and arrays longer than [`FMT_WIDTH`]
+/// characters split one item per line. Full-line comments are kept: those
+/// directly above a section header (blank lines allowed) stay above it,
+/// those inside a section move above its header, and those before the
+/// first section stay at the top. Trailing comments after a value, blank
+/// line layout, and keys prompter does not read are dropped.
+///
+/// # Examples
+/// ```
+/// let text = "[b]\ndepends_on = [ \"x.md\" ]  # inline\n\n# About a\n[a]\ndepends_on=[\"y.md\"]\n";
+/// assert_eq!(
+///     prompter::format_config(text).unwrap(),
+///     "# About a\n[a]\ndepends_on = [\"y.md\"]\n\n[b]\ndepends_on = [\"x.md\"]\n"
+/// );
+/// ```
+///
+/// # Errors
+/// Returns [`PrompterError::ConfigParse`] if `input` is not a valid config.
+pub fn format_config(input: &str) -> Result<String, PrompterError> {
+    let cfg = parse_config_toml(input)?;
+    Ok(cfg.toml_text(Some(FMT_WIDTH), &config_comments(input)))
+}
+
+/// One profile as serialized: the fields of its config section.
+#[derive(Serialize)]
+struct ProfileSection<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extends: Option<&'a str>,
+    depends_on: Vec<SectionDependency<'a>>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+}
+
+/// A serialized `depends_on` entry.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum SectionDependency<'a> {
+    Plain(&'a str),
+    Optional { path: &'a str, optional: bool },
+}
+
+impl Serialize for Config {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(post_prompt) = &self.post_prompt {
+            map.serialize_entry("post_prompt", post_prompt)?;
+        }
+        if let Some(file) = &self.pre_prompt_file {
+            map.serialize_entry("pre_prompt_file", file)?;
+        }
+        if let Some(file) = &self.post_prompt_file {
+            map.serialize_entry("post_prompt_file", file)?;
+        }
+        if let Some(default_profile) = &self.default_profile {
+            map.serialize_entry("default_profile", default_profile)?;
+        }
+        if let Some(format) = &self.timestamp_format {
+            map.serialize_entry("timestamp_format", format)?;
+        }
+        if let Some(timezone) = self.timezone {
+            map.serialize_entry("timezone", timezone.name())?;
+        }
+        if !self.library_roots.is_empty() {
+            map.serialize_entry("library_roots", &self.library_roots)?;
+        }
+        if self.writes_lint_table() {
+            let rules: BTreeMap<&str, bool> = self
+                .disabled_lints
+                .iter()
+                .map(|rule| (rule.name(), false))
+                .collect();
+            map.serialize_entry(LINT_SECTION, &rules)?;
+        }
+        for name in self.profile_names() {
+            let meta = self.meta.get(name);
+            let depends_on = self.profiles[name]
+                .iter()
+                .map(|dep| {
+                    if meta.is_some_and(|meta| meta.optional.contains(dep)) {
+                        SectionDependency::Optional {
+                            path: dep,
+                            optional: true,
+                        }
+                    } else {
+                        SectionDependency::Plain(dep)
+                    }
+                })
+                .collect();
+            let section = ProfileSection {
+                extends: self.extends.get(name).map(String::as_str),
+                depends_on,
+                tags: meta.map_or(&[], |meta| &meta.tags),
+            };
+            map.serialize_entry(name, &section)?;
+        }
+        map.end()
+    }
+}
+
+/// A config document as deserialized: top-level keys plus sections.
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    post_prompt: Option<String>,
+    #[serde(default)]
+    pre_prompt_file: Option<String>,
+    #[serde(default)]
+    post_prompt_file: Option<String>,
+    #[serde(default)]
+    default_profile: Option<String>,
+    #[serde(default)]
+    timestamp_format: Option<String>,
+    #[serde(default)]
+    timezone: Option<Timezone>,
+    #[serde(default)]
+    library_roots: Vec<String>,
+    #[serde(default)]
+    lint: Option<RawSection>,
+    #[serde(flatten)]
+    entries: BTreeMap<String, RawEntry>,
+}
+
+/// A value inside a config table: a nested section, a `[lint]` rule switch,
+/// or a key prompter ignores.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEntry {
+    Section(RawSection),
+    Switch(bool),
+    Other(IgnoredAny),
+}
+
+/// A table that is a profile when it has any profile key, and may hold
+/// nested tables for dotted names (TOML reads `[python.api]` as
+/// `python` → `api`).
+#[derive(Deserialize)]
+struct RawSection {
+    #[serde(default)]
+    extends: Option<String>,
+    #[serde(default)]
+    depends_on: Option<Vec<RawDependency>>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(flatten)]
+    children: BTreeMap<String, RawEntry>,
+}
+
+/// A deserialized `depends_on` entry.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDependency {
+    Plain(String),
+    Table {
+        path: String,
+        #[serde(default)]
+        optional: bool,
+    },
+}
+
+impl RawSection {
+    /// Whether this section sets any profile key.
+    const fn is_profile(&self) -> bool {
+        self.extends.is_some() || self.depends_on.is_some() || self.tags.is_some()
+    }
+
+    /// Add this section as `name`, and its nested sections as `name.child`.
+    fn collect_into(self, name: &str, cfg: &mut Config) {
+        if self.is_profile() {
+            let mut deps = Vec::new();
+            let mut meta = ProfileMeta::default();
+            for dep in self.depends_on.unwrap_or_default() {
+                match dep {
+                    RawDependency::Plain(path) => deps.push(path),
+                    RawDependency::Table { path, optional } => {
+                        if optional {
+                            meta.optional.insert(path.clone());
+                        }
+                        deps.push(path);
+                    }
+                }
+            }
+            meta.tags = self.tags.unwrap_or_default();
+            if let Some(parent) = self.extends {
+                cfg.extends.insert(name.to_string(), parent);
+            }
+            cfg.profiles.insert(name.to_string(), deps);
+            cfg.meta.insert(name.to_string(), meta);
+        }
+        for (child, entry) in self.children {
+            if let RawEntry::Section(section) = entry {
+                section.collect_into(&format!("{name}.{child}"), cfg);
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Config {
+    /// Read profiles keyed by their full name or nested by dotted segment,
+    /// so both `toml::to_string` output and hand-written configs load.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawConfig::deserialize(deserializer)?;
+        let mut cfg = Self {
+            post_prompt: raw.post_prompt,
+            pre_prompt_file: raw.pre_prompt_file,
+            post_prompt_file: raw.post_prompt_file,
+            default_profile: raw.default_profile,
+            timestamp_format: raw.timestamp_format,
+            timezone: raw.timezone,
+            library_roots: raw.library_roots,
+            ..Self::default()
+        };
+        for (name, entry) in raw.entries {
+            if let RawEntry::Section(section) = entry {
+                section.collect_into(&name, &mut cfg);
+            }
+        }
+        match raw.lint {
+            // A profile named `lint` from before the table held rules
+            Some(lint) if lint.is_profile() => lint.collect_into(LINT_SECTION, &mut cfg),
+            Some(lint) => {
+                for (key, entry) in lint.children {
+                    match entry {
+                        RawEntry::Switch(enabled) => {
+                            let rule = LintRule::from_name(&key).ok_or_else(|| {
+                                serde::de::Error::custom(format!("Unknown lint rule: {key}"))
+                            })?;
+                            cfg.set_lint_rule(rule, enabled);
+                        }
+                        RawEntry::Section(section) => {
+                            section.collect_into(&format!("{LINT_SECTION}.{key}"), &mut cfg);
+                        }
+                        RawEntry::Other(_) => {}
+                    }
+                }
+            }
+            None => {}
+        }
+        Ok(cfg)
+    }
+}
+
+/// Descriptive metadata attached to a profile section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileMeta {
+    /// Free-form tags used to filter `list` output
+    pub tags: Vec<String>,
+    /// Dependencies declared with `optional = true`, skipped when missing
+    pub optional: HashSet<String>,
 }
 
 /// Command-line interface structure for the prompter tool.
 ///
 /// This structure defines the main CLI interface using clap's derive API,
 /// supporting both subcommands and direct profile rendering.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Serialize, Deserialize)]
 #[command(name = "prompter")]
 #[command(about = "A CLI tool for composing reusable prompt snippets")]
 #[command(version)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Cli {
     /// Optional subcommand to execute
     #[command(subcommand)]
@@ -44,6 +829,95 @@ pub struct Cli {
     #[arg(value_name = "PROFILE")]
     pub profile: Option<String>,
 
+    /// Rendering options for the profile shorthand
+    #[command(flatten)]
+    pub render: RenderArgs,
+
+    /// Override configuration file path ('-' reads the config from stdin)
+    #[arg(short = 'c', long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Directory holding `config.toml` and `library/` (instead of --config)
+    #[arg(long, value_name = "DIR", global = true, conflicts_with = "config")]
+    pub config_dir: Option<PathBuf>,
+
+    /// When to use colors and emoji: auto uses them on a terminal unless
+    /// `NO_COLOR` is set; always and never override both
+    #[arg(long, value_name = "WHEN", global = true)]
+    pub color: Option<ColorChoice>,
+
+    /// Same as --color never
+    #[arg(long, global = true, conflicts_with = "color")]
+    pub no_color: bool,
+
+    /// Skip `.prompterrc`/`prompter.toml` discovery and use the global config
+    #[arg(long, global = true)]
+    pub no_local_config: bool,
+
+    /// Reject unknown config keys (e.g. a misspelled `depnds_on`) instead of
+    /// ignoring them
+    #[arg(long, global = true)]
+    pub strict_config: bool,
+
+    /// Print the parsed mode as JSON instead of running it, for scripts
+    #[arg(long, global = true, hide = true)]
+    pub emit_mode_json: bool,
+}
+
+impl Cli {
+    /// The config file to use instead of the default, if any.
+    ///
+    /// `--config` is used as given and `--config-dir` maps to
+    /// `<dir>/config.toml`; clap rejects passing both. The library is then
+    /// found next to the config file as usual.
+    #[must_use]
+    pub fn config_override(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(|| {
+            self.config_dir
+                .as_ref()
+                .map(|dir| dir.join(CONFIG_FILE_NAME))
+        })
+    }
+
+    /// What chose the config returned by [`Cli::effective_config`]:
+    /// `--config`, `--config-dir`, local discovery, or nothing
+    /// ([`PathSource::Default`]).
+    #[must_use]
+    pub fn config_source(&self) -> PathSource {
+        if self.config.is_some() {
+            PathSource::Flag("--config")
+        } else if self.config_dir.is_some() {
+            PathSource::Flag("--config-dir")
+        } else if self.effective_config().is_some() {
+            PathSource::LocalConfig
+        } else {
+            PathSource::Default
+        }
+    }
+
+    /// Like [`Cli::config_override`], but falling back to a project-local
+    /// config found by walking up from the working directory, unless
+    /// `--no-local-config` is given.
+    #[must_use]
+    pub fn effective_config(&self) -> Option<PathBuf> {
+        self.config_override().or_else(|| {
+            if self.no_local_config {
+                return None;
+            }
+            env::current_dir()
+                .ok()
+                .and_then(|cwd| find_local_config(&cwd))
+        })
+    }
+}
+
+/// File name of the config inside a config directory.
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Rendering flags shared by the profile shorthand and the `run` subcommand.
+#[derive(Args, Debug, Clone, Default, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RenderArgs {
     /// Separator between files
     #[arg(short, long, value_name = "STRING")]
     pub separator: Option<String>,
@@ -56,102 +930,718 @@ pub struct Cli {
     #[arg(short = 'P', long, value_name = "TEXT")]
     pub post_prompt: Option<String>,
 
-    /// Override configuration file path
-    #[arg(short = 'c', long, value_name = "FILE", global = true)]
-    pub config: Option<PathBuf>,
+    /// Replace `{{KEY}}` in snippet files with VALUE (repeatable). Defaults
+    /// for every render can be set as `PROMPTER_VARS=KEY1=VAL1,KEY2=VAL2`;
+    /// `--var` wins when both set the same key
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+
+    /// How duplicate files are detected [default: path]
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub dedup: Option<DedupMode>,
+
+    /// Order in which files are written [default: config]
+    #[arg(long, value_enum, value_name = "ORDER")]
+    pub order: Option<FileOrder>,
+
+    /// Append a file (optionally with a `#L<start>-L<end>` range) or profile
+    /// to the profile's dependencies (repeatable)
+    #[arg(long = "add", value_name = "ENTRY")]
+    pub add: Vec<String>,
+
+    /// Leave out a dependency entry wherever it appears (repeatable)
+    #[arg(long = "drop", value_name = "ENTRY")]
+    pub drop: Vec<String>,
+
+    /// Fail on undefined environment variables in paths and prompts
+    #[arg(long)]
+    pub strict_env: bool,
+
+    /// Warn about and skip missing snippet files instead of failing
+    #[arg(long)]
+    pub skip_missing: bool,
+
+    /// Drop leading `---` (YAML) or `+++` (TOML) frontmatter from each file
+    #[arg(long)]
+    pub strip_frontmatter: bool,
+
+    /// Strip trailing spaces and tabs from every line of each file
+    #[arg(long)]
+    pub trim_trailing_whitespace: bool,
+
+    /// Convert `\r\n` line endings in each file to `\n`
+    #[arg(long)]
+    pub normalize_line_endings: bool,
+
+    /// Write the separator only between files, not after the last one
+    #[arg(long)]
+    pub between_only: bool,
+
+    /// Allow at most one blank line where the prompts, files, and
+    /// separators meet
+    #[arg(long)]
+    pub collapse_blank_lines: bool,
+
+    /// Allow absolute and `~/` paths that point outside the library
+    #[arg(long)]
+    pub allow_external: bool,
+
+    /// Fail on snippet files that are not valid UTF-8 instead of copying
+    /// their bytes
+    #[arg(long)]
+    pub require_utf8: bool,
+
+    /// Abort once the rendered output would exceed this many bytes
+    #[arg(long, value_name = "N")]
+    pub limit_bytes: Option<u64>,
+
+    /// Only include files modified after this RFC 3339 time (e.g.
+    /// 2025-06-01T12:00:00Z)
+    #[arg(long, value_name = "TIME", value_parser = parse_since)]
+    pub since: Option<SystemTime>,
+
+    /// chrono format of the date in the banner (e.g. "%Y-%m-%d %H:%M")
+    /// [default: the config's `timestamp_format`, or %Y-%m-%d]
+    #[arg(long, value_name = "FORMAT", value_parser = parse_timestamp_format)]
+    pub timestamp_format: Option<String>,
+
+    /// Write the banner date in UTC instead of local time (overrides the
+    /// config's `timezone`)
+    #[arg(long)]
+    pub utc: bool,
+
+    /// Also write a JSON manifest of the included files and their SHA-256
+    #[arg(long, value_name = "FILE")]
+    pub manifest: Option<PathBuf>,
+}
+
+impl RenderArgs {
+    /// Combine with `fallback`, preferring values set on `self`.
+    fn or(&self, fallback: &Self) -> Self {
+        Self {
+            separator: self
+                .separator
+                .clone()
+                .or_else(|| fallback.separator.clone()),
+            pre_prompt: self
+                .pre_prompt
+                .clone()
+                .or_else(|| fallback.pre_prompt.clone()),
+            post_prompt: self
+                .post_prompt
+                .clone()
+                .or_else(|| fallback.post_prompt.clone()),
+            vars: [fallback.vars.as_slice(), &self.vars].concat(),
+            dedup: self.dedup.or(fallback.dedup),
+            order: self.order.or(fallback.order),
+            add: [fallback.add.as_slice(), &self.add].concat(),
+            drop: [fallback.drop.as_slice(), &self.drop].concat(),
+            strict_env: self.strict_env || fallback.strict_env,
+            skip_missing: self.skip_missing || fallback.skip_missing,
+            strip_frontmatter: self.strip_frontmatter || fallback.strip_frontmatter,
+            trim_trailing_whitespace: self.trim_trailing_whitespace
+                || fallback.trim_trailing_whitespace,
+            normalize_line_endings: self.normalize_line_endings || fallback.normalize_line_endings,
+            between_only: self.between_only || fallback.between_only,
+            collapse_blank_lines: self.collapse_blank_lines || fallback.collapse_blank_lines,
+            allow_external: self.allow_external || fallback.allow_external,
+            require_utf8: self.require_utf8 || fallback.require_utf8,
+            limit_bytes: self.limit_bytes.or(fallback.limit_bytes),
+            since: self.since.or(fallback.since),
+            timestamp_format: self
+                .timestamp_format
+                .clone()
+                .or_else(|| fallback.timestamp_format.clone()),
+            utc: self.utc || fallback.utc,
+            manifest: self.manifest.clone().or_else(|| fallback.manifest.clone()),
+        }
+    }
 }
 
 /// Available subcommands for the prompter CLI.
 ///
 /// Each variant represents a different operation mode of the tool.
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Serialize, Deserialize)]
 pub enum Commands {
     /// Show version information
     Version,
-    /// Initialize default config and library
-    Init,
+    /// Initialize default config and library (`--config` picks another location)
+    Init {
+        /// Back up and rewrite default files that already exist
+        #[arg(long)]
+        force: bool,
+        /// Only create the config (with an empty `[example]` profile) and an
+        /// empty library directory
+        #[arg(long)]
+        minimal: bool,
+        /// Print a single `initialized`/`already initialized` line instead of
+        /// a spinner and messages (implied when stdout is not a terminal)
+        #[arg(short, long)]
+        quiet: bool,
+        /// Start from an embedded template instead of the samples ('list'
+        /// shows them)
+        #[arg(long, value_name = "NAME", conflicts_with = "minimal")]
+        template: Option<String>,
+        /// Library directory to create [default: `library/` next to --config,
+        /// or `$XDG_DATA_HOME/prompter/library`]
+        #[arg(long, value_name = "DIR")]
+        library: Option<PathBuf>,
+    },
     /// List available profiles
-    List,
+    List {
+        /// Only show profiles that have this tag (repeatable; all must match)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Print every distinct tag instead of profiles
+        #[arg(long = "tags", conflicts_with_all = ["tags", "long"])]
+        all_tags: bool,
+        /// Show dependency and resolved file counts and tags in a table
+        #[arg(short, long)]
+        long: bool,
+        /// Group profiles under headers by their first namespace segment
+        #[arg(short, long, conflicts_with_all = ["all_tags", "long"])]
+        grouped: bool,
+        /// Show each profile's direct dependencies and what it resolves to
+        #[arg(short, long, conflicts_with_all = ["all_tags", "long", "grouped"])]
+        verbose: bool,
+        /// Print profiles and their dependencies as a JSON array
+        #[arg(long, conflicts_with_all = ["all_tags", "long", "grouped", "verbose"])]
+        json: bool,
+        /// Show profiles no other profile uses as roots, with the profiles
+        /// they include nested beneath them
+        #[arg(
+            long,
+            conflicts_with_all = ["all_tags", "long", "grouped", "verbose", "json"]
+        )]
+        tree: bool,
+        /// Also show library files as leaves of the tree
+        #[arg(long, requires = "tree")]
+        files: bool,
+        /// Only show names containing PATTERN, or matching it as a glob when
+        /// it has `*` or `?` (case-insensitive; exit 1 if nothing matches)
+        #[arg(conflicts_with = "all_tags")]
+        pattern: Option<String>,
+    },
     /// Validate configuration and library references
     Validate,
+    /// Check library files for trailing whitespace, missing final newlines,
+    /// CRLF line endings, broken links, and empty headings (exit 1 on findings)
+    Lint {
+        /// Only lint the files this profile resolves to
+        profile: Option<String>,
+        /// Fix trailing whitespace, final newlines, and line endings in place
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Rewrite the config in canonical form: sorted sections, double quotes,
+    /// long arrays one item per line (keeps full-line comments only)
+    Fmt {
+        /// Write nothing; print a diff and exit 1 if the config would change
+        #[arg(long)]
+        check: bool,
+    },
     /// Render a profile (concatenated file contents)
     Run {
-        /// Profile name to render
+        /// Profile name to render [default: the config's `default_profile`]
+        profile: Option<String>,
+        /// Rendering options
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Summarize a profile's files: count, bytes, lines, and the largest file
+    Stats {
+        /// Profile name to summarize
         profile: String,
-        /// Separator between files
+        /// How duplicate files are detected [default: path]
+        #[arg(long, value_enum, value_name = "MODE")]
+        dedup: Option<DedupMode>,
+    },
+    /// Add a bundle's library files and profiles to the library and config,
+    /// then validate
+    Import {
+        /// Bundle to import: a `.tar.gz` holding `config.toml` and `library/`
+        #[arg(value_name = "BUNDLE")]
+        bundle: PathBuf,
+        /// Replace files and profiles that exist with different content
         #[arg(short, long)]
-        separator: Option<String>,
-        /// Pre-prompt text to inject at the beginning
-        #[arg(short = 'p', long)]
-        pre_prompt: Option<String>,
-        /// Post-prompt text to inject at the end
-        #[arg(short = 'P', long)]
-        post_prompt: Option<String>,
+        force: bool,
+        /// Import profiles as `<PREFIX><name>` and files under `<PREFIX>/`
+        /// (without its trailing dot)
+        #[arg(long, value_name = "PREFIX")]
+        prefix: Option<String>,
     },
-    /// Generate shell completion scripts
-    Completions {
-        /// Shell to generate completions for
-        #[arg(value_enum)]
-        shell: clap_complete::Shell,
+    /// Compare two profiles' resolved files, and optionally their rendered
+    /// output (exit 0 if identical, 1 if they differ)
+    Diff {
+        /// First profile
+        a: String,
+        /// Second profile
+        b: String,
+        /// Also render both (without the system prefix) and show a unified
+        /// diff of the output
+        #[arg(long)]
+        content: bool,
     },
-    /// Check health and configuration status
-    Doctor,
-    /// Update to the latest version
-    Update {
-        /// Install specific version instead of latest
+    /// Compare rendered profiles with stored snapshots (exit 1 on any mismatch)
+    Test {
+        /// Profile to check [default: every profile]
+        profile: Option<String>,
+        /// Write the current output as the new snapshots
         #[arg(long)]
-        version: Option<String>,
-        /// Skip confirmation prompt
+        update: bool,
+        /// Directory holding `<profile>.snap` files
+        #[arg(long, value_name = "DIR", default_value = "snapshots")]
+        snapshot_dir: PathBuf,
+    },
+    /// Inspect the snippet library
+    Library {
+        /// Library operation
+        #[command(subcommand)]
+        command: LibraryCommands,
+    },
+    /// Open the config (optionally at a profile's section) or a library file in $EDITOR
+    #[command(args_conflicts_with_subcommands = true)]
+    Edit {
+        /// Profile whose section to open the config at
+        profile: Option<String>,
+        /// What to open
+        #[command(subcommand)]
+        target: Option<EditTarget>,
+    },
+    /// Create a new snippet file in the library
+    New {
+        /// Path of the snippet relative to the library (e.g. python/style.md)
+        path: String,
+        /// Initial content for the file
+        #[arg(long, value_name = "TEXT", allow_hyphen_values = true)]
+        content: Option<String>,
+        /// Open the file in $EDITOR (or $VISUAL) after creating it
+        #[arg(short, long)]
+        edit: bool,
+        /// Overwrite the file if it already exists
         #[arg(short, long)]
         force: bool,
-        /// Custom installation directory
+    },
+    /// Read stdin and print it with newlines, tabs, quotes and backslashes escaped
+    Escape,
+    /// Show which config and library are used, whether they exist, and why
+    #[command(visible_alias = "paths")]
+    Which {
+        /// Print the paths as JSON
         #[arg(long)]
-        install_dir: Option<PathBuf>,
+        json: bool,
     },
-}
-
+    /// Generate shell completion scripts (bash, zsh, fish, elvish, powershell, nushell)
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: CompletionShell,
+        /// Write the script to the shell's per-user completions directory
+        /// and print the path written
+        #[arg(long, visible_alias = "write", group = "destination")]
+        install: bool,
+        /// Write the script to this file instead and print its path
+        #[arg(long, value_name = "PATH", group = "destination")]
+        write_to: Option<PathBuf>,
+        /// Replace an existing, different completions file
+        #[arg(short, long, requires = "destination")]
+        force: bool,
+    },
+    /// Check health and configuration status
+    Doctor {
+        /// Skip the network update check (also enabled by `PROMPTER_OFFLINE=1`)
+        #[arg(long)]
+        offline: bool,
+        /// Repair fixable problems (missing config, library, or referenced files)
+        /// and offer to delete library files no profile uses
+        #[arg(long)]
+        fix: bool,
+        /// Apply fixes without asking for confirmation
+        #[arg(short, long, requires = "fix")]
+        yes: bool,
+        /// Delete orphaned library files without asking for confirmation
+        #[arg(short, long, requires = "fix")]
+        force: bool,
+        /// Exit with status 1 when any warning is found
+        #[arg(long)]
+        strict: bool,
+        /// Also scan library files for invalid UTF-8 and missing trailing newlines
+        #[arg(long)]
+        deep: bool,
+        /// Warn about resolved files larger than this many KB [default: 100,
+        /// or `PROMPTER_MAX_FILE_SIZE_KB`]
+        #[arg(long, value_name = "N")]
+        max_file_size_kb: Option<u64>,
+        /// Follow symlinks when looking for library files no profile uses
+        #[arg(long)]
+        follow_symlinks: bool,
+    },
+    /// Update to the latest version
+    Update {
+        /// Install specific version instead of latest
+        #[arg(long)]
+        version: Option<String>,
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+        /// Custom installation directory [default: the running binary's
+        /// directory, or the first writable one on `$PATH` if it is not
+        /// writable]
+        #[arg(long)]
+        install_dir: Option<PathBuf>,
+        /// Restore the binary saved before the last update
+        #[arg(long, conflicts_with = "version")]
+        rollback: bool,
+        /// Only report whether an update exists (exit 0 if up to date, 10 if
+        /// an update is available); never downloads or prompts
+        #[arg(long, conflicts_with_all = ["version", "rollback"])]
+        check: bool,
+        /// Print the `--check` result as JSON
+        #[arg(long, requires = "check")]
+        json: bool,
+        /// Release channel to follow
+        #[arg(long, value_enum, default_value_t = UpdateChannel::Stable)]
+        channel: UpdateChannel,
+        /// Install even if the release has no reachable `.sha256` checksum
+        #[arg(long, conflicts_with_all = ["rollback", "check"])]
+        skip_checksum: bool,
+        /// Install without fetching or checking any checksum (not
+        /// recommended)
+        #[arg(long, conflicts_with_all = ["rollback", "check", "skip_checksum"])]
+        skip_verify: bool,
+        /// Where to save the previous binary (or restore it from with
+        /// `--rollback`) [default: `<binary>.bak`]
+        #[arg(long, value_name = "PATH", conflicts_with = "check")]
+        backup_path: Option<PathBuf>,
+        /// Resolve the target version and print the steps an update would
+        /// take, stopping before anything is downloaded
+        #[arg(long, conflicts_with_all = ["rollback", "check"])]
+        dry_run: bool,
+        /// Hide the download progress bar (implied when stdout is not a
+        /// terminal)
+        #[arg(short, long)]
+        quiet: bool,
+        /// Print published versions with their release dates (exit 1 if
+        /// GitHub cannot be reached); never downloads or prompts
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "version",
+                "rollback",
+                "check",
+                "skip_checksum",
+                "skip_verify",
+                "backup_path",
+                "dry_run"
+            ]
+        )]
+        list_versions: bool,
+        /// How many versions `--list-versions` shows
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 10,
+            requires = "list_versions"
+        )]
+        limit: usize,
+    },
+}
+
+/// Operations under the `library` subcommand.
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LibraryCommands {
+    /// List every markdown file in the library
+    List {
+        /// Show size, modification date, and whether a profile references the file
+        #[arg(short, long)]
+        long: bool,
+    },
+    /// Search library files for lines matching a query (exit 1 if nothing matches)
+    Search {
+        /// Text (or regular expression with --regex) to look for
+        query: String,
+        /// Match case exactly
+        #[arg(short = 's', long)]
+        case_sensitive: bool,
+        /// Treat the query as a regular expression
+        #[arg(short = 'e', long)]
+        regex: bool,
+    },
+    /// Check library files for invalid UTF-8 and missing trailing newlines (exit 1 on issues)
+    Check,
+    /// Summarize library size, estimated tokens, and unreferenced files
+    Stats,
+}
+
+/// Shells that `completions` can generate scripts for.
+///
+/// Wraps [`clap_complete::Shell`] and adds Nushell, which lives in the
+/// separate `clap_complete_nushell` crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum CompletionShell {
+    /// Bourne Again `SHell`
+    Bash,
+    /// Elvish shell
+    Elvish,
+    /// Friendly Interactive `SHell`
+    Fish,
+    /// `PowerShell`
+    #[value(name = "powershell")]
+    PowerShell,
+    /// Z `SHell`
+    Zsh,
+    /// Nushell
+    #[value(alias = "nu")]
+    Nushell,
+}
+
+impl std::fmt::Display for CompletionShell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect("no values are skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+impl clap_complete::Generator for CompletionShell {
+    fn file_name(&self, name: &str) -> String {
+        match self {
+            Self::Bash => clap_complete::Shell::Bash.file_name(name),
+            Self::Elvish => clap_complete::Shell::Elvish.file_name(name),
+            Self::Fish => clap_complete::Shell::Fish.file_name(name),
+            Self::PowerShell => clap_complete::Shell::PowerShell.file_name(name),
+            Self::Zsh => clap_complete::Shell::Zsh.file_name(name),
+            Self::Nushell => clap_complete_nushell::Nushell.file_name(name),
+        }
+    }
+
+    fn generate(&self, cmd: &clap::Command, buf: &mut dyn Write) {
+        match self {
+            Self::Bash => clap_complete::Shell::Bash.generate(cmd, buf),
+            Self::Elvish => clap_complete::Shell::Elvish.generate(cmd, buf),
+            Self::Fish => clap_complete::Shell::Fish.generate(cmd, buf),
+            Self::PowerShell => clap_complete::Shell::PowerShell.generate(cmd, buf),
+            Self::Zsh => clap_complete::Shell::Zsh.generate(cmd, buf),
+            Self::Nushell => clap_complete_nushell::Nushell.generate(cmd, buf),
+        }
+    }
+}
+
+/// When output uses colors and emoji, set by the global `--color` flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum ColorChoice {
+    /// On a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    /// Even when piped or with `NO_COLOR` set
+    Always,
+    /// Never, e.g. for text copied into docs
+    Never,
+}
+
+/// Which releases `update` considers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum UpdateChannel {
+    /// Only full releases
+    #[default]
+    Stable,
+    /// Full releases and release candidates
+    Prerelease,
+}
+
+/// What the `edit` subcommand opens.
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditTarget {
+    /// Open the config file at a profile's section
+    Profile {
+        /// Profile name
+        name: String,
+    },
+    /// Open a file from the library
+    File {
+        /// Path relative to the library
+        path: String,
+    },
+}
+
 /// Application execution modes after parsing command-line arguments.
 ///
 /// This enum represents the resolved execution mode after processing
 /// both subcommands and direct profile arguments.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum AppMode {
     /// Render a profile with optional separator and pre-prompt
     Run {
-        /// Profile name to render
-        profile: String,
-        /// Optional separator between concatenated files
-        separator: Option<String>,
-        /// Optional custom pre-prompt text
-        pre_prompt: Option<String>,
-        /// Optional custom post-prompt text
-        post_prompt: Option<String>,
+        /// Profile name to render; `None` uses the config's `default_profile`
+        profile: Option<String>,
+        /// Rendering options built from the CLI flags
+        opts: RenderOptions,
         /// Optional configuration file override
         config: Option<PathBuf>,
+        /// Where to write a manifest of the included files
+        manifest: Option<PathBuf>,
     },
     /// List all available profiles using an optional config override
     List {
         /// Optional configuration file override
         config: Option<PathBuf>,
+        /// Filtering and formatting options
+        opts: ListOptions,
     },
     /// Validate configuration and library references with an optional config override
     Validate {
         /// Optional configuration file override
         config: Option<PathBuf>,
     },
+    /// Lint library files
+    Lint {
+        /// Profile whose files to lint; `None` lints the whole library
+        profile: Option<String>,
+        /// Fix mechanical findings in place
+        fix: bool,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Reformat the config in place
+    Fmt {
+        /// Only report whether the config would change
+        check: bool,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
     /// Initialize default configuration and library
-    Init,
+    Init {
+        /// Back up and rewrite existing default files
+        force: bool,
+        /// Skip the sample library files
+        minimal: bool,
+        /// Embedded template to write, or `list`
+        template: Option<String>,
+        /// Print a single result line
+        quiet: bool,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+        /// Optional library directory override
+        library: Option<PathBuf>,
+    },
+    /// Summarize a profile's resolved files
+    Stats {
+        /// Profile name to summarize
+        profile: String,
+        /// How duplicate files are detected
+        dedup: DedupMode,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Import a bundle into the library and config
+    Import {
+        /// Bundle archive to read
+        bundle: PathBuf,
+        /// Replace differing files and profiles
+        force: bool,
+        /// Rename the bundle's profiles and files with this prefix
+        prefix: Option<String>,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Compare two profiles
+    Diff {
+        /// First profile
+        a: String,
+        /// Second profile
+        b: String,
+        /// Also diff the rendered output
+        content: bool,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Compare rendered profiles with their snapshots
+    Test {
+        /// Profile to check; `None` checks every profile
+        profile: Option<String>,
+        /// Rewrite the snapshots instead of comparing
+        update: bool,
+        /// Directory holding the snapshots
+        snapshot_dir: PathBuf,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Inspect the snippet library
+    Library {
+        /// Library operation
+        command: LibraryCommands,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Open the config, a profile, or a library file in an editor
+    Edit {
+        /// What to open; `None` opens the config file at the top
+        target: Option<EditTarget>,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Create a new snippet file in the library
+    New {
+        /// Path of the snippet relative to the library
+        path: String,
+        /// Optional initial content
+        content: Option<String>,
+        /// Open the file in an editor after creating it
+        edit: bool,
+        /// Overwrite an existing file
+        force: bool,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
+    /// Escape stdin so it can be pasted into a config string or `--separator`
+    Escape,
+    /// Print the resolved config and library paths
+    Which {
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+        /// What chose `config`
+        config_source: PathSource,
+        /// Emit JSON instead of text
+        json: bool,
+    },
     /// Show version information
     Version,
     /// Show help information
     Help,
+    /// Print the wrapped invocation as JSON instead of running it
+    /// (`--emit-mode-json`)
+    EmitModeJson(Box<Invocation>),
     /// Generate shell completion scripts
     Completions {
         /// Shell to generate completions for
-        shell: clap_complete::Shell,
+        shell: CompletionShell,
+        /// Install the script instead of printing it
+        install: bool,
+        /// Write the script to this file instead of printing it
+        write_to: Option<PathBuf>,
+        /// Overwrite a differing installed script
+        force: bool,
     },
     /// Check health and configuration status
-    Doctor,
+    Doctor {
+        /// Skip checks that require network access
+        offline: bool,
+        /// Repair fixable problems
+        fix: bool,
+        /// Apply fixes without asking for confirmation
+        yes: bool,
+        /// Delete orphaned library files without asking
+        force: bool,
+        /// Treat warnings as failures
+        strict: bool,
+        /// Scan library file contents as well
+        deep: bool,
+        /// Per-file size limit in KB, if given on the command line
+        max_file_size_kb: Option<u64>,
+        /// Follow symlinks in the orphaned-file scan
+        follow_symlinks: bool,
+        /// Optional configuration file override
+        config: Option<PathBuf>,
+    },
     /// Update to the latest version
     Update {
         /// Optional specific version to install
@@ -160,20 +1650,57 @@ pub enum AppMode {
         force: bool,
         /// Custom installation directory
         install_dir: Option<PathBuf>,
+        /// Restore the previous binary instead of updating
+        rollback: bool,
+        /// Only report whether an update exists
+        check: bool,
+        /// Emit the check result as JSON
+        json: bool,
+        /// Release channel to follow
+        channel: UpdateChannel,
+        /// Allow installing without a checksum
+        skip_checksum: bool,
+        /// Skip checksum verification entirely
+        skip_verify: bool,
+        /// Custom location for the previous binary
+        backup_path: Option<PathBuf>,
+        /// Print the update steps without downloading
+        dry_run: bool,
+        /// Hide the download progress bar
+        quiet: bool,
+        /// List published versions instead of updating
+        list_versions: bool,
+        /// Number of versions to list
+        limit: usize,
     },
 }
 
+/// A parsed command line: the mode to run and the global flags that apply
+/// to whichever mode it is.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invocation {
+    /// What to run
+    pub mode: AppMode,
+    /// Reject unknown config keys when loading the config
+    /// (`--strict-config`)
+    pub strict_config: bool,
+    /// When to use colors and emoji (`--color`, `--no-color`); see
+    /// [`use_styling`]
+    pub color: ColorChoice,
+}
+
 /// Parse command-line arguments and return the resolved application mode.
 ///
 /// This function takes raw command-line arguments and uses clap to parse them
 /// into a structured `AppMode` enum, handling both subcommands and direct
-/// profile arguments for backward compatibility.
+/// profile arguments for backward compatibility, together with the global
+/// flags in an [`Invocation`].
 ///
 /// # Arguments
 /// * `args` - Vector of command-line arguments including program name
 ///
 /// # Returns
-/// * `Ok(AppMode)` - Successfully parsed application mode
+/// * `Ok(Invocation)` - Successfully parsed application mode
 /// * `Err(String)` - Error message if parsing fails
 ///
 /// # Errors
@@ -181,81 +1708,321 @@ pub enum AppMode {
 /// - Invalid command-line syntax is provided
 /// - Required arguments are missing
 /// - Conflicting options are specified
-pub fn parse_args_from(args: Vec<String>) -> Result<AppMode, String> {
+#[allow(clippy::too_many_lines)] // one arm per subcommand
+pub fn parse_args_from(args: Vec<String>) -> Result<Invocation, String> {
     let cli = Cli::try_parse_from(args).map_err(|e| e.to_string())?;
+    let config = cli.effective_config();
 
-    match (&cli.command, &cli.profile) {
+    let mode: Result<AppMode, String> = match (&cli.command, &cli.profile) {
         (Some(Commands::Version), _) => Ok(AppMode::Version),
-        (Some(Commands::Init), _) => Ok(AppMode::Init),
-        (Some(Commands::List), _) => Ok(AppMode::List {
-            config: cli.config.clone(),
+        (Some(Commands::Escape), _) => Ok(AppMode::Escape),
+        (Some(Commands::Which { json }), _) => Ok(AppMode::Which {
+            config,
+            config_source: cli.config_source(),
+            json: *json,
+        }),
+        (
+            Some(Commands::Init {
+                force,
+                minimal,
+                quiet,
+                template,
+                library,
+            }),
+            _,
+        ) => Ok(AppMode::Init {
+            force: *force,
+            minimal: *minimal,
+            template: template.clone(),
+            quiet: *quiet,
+            // Init only writes where it is told to, never into a discovered config
+            config: cli.config_override(),
+            library: library.clone(),
+        }),
+        (
+            Some(Commands::List {
+                tags,
+                all_tags,
+                long,
+                grouped,
+                verbose,
+                json,
+                tree,
+                files,
+                pattern,
+            }),
+            _,
+        ) => Ok(AppMode::List {
+            config,
+            opts: ListOptions {
+                tags: tags.clone(),
+                all_tags: *all_tags,
+                long: *long,
+                grouped: *grouped,
+                verbose: *verbose,
+                json: *json,
+                tree: *tree,
+                files: *files,
+                pattern: pattern.clone(),
+                ..ListOptions::default()
+            },
+        }),
+        (Some(Commands::Validate), _) => Ok(AppMode::Validate { config }),
+        (Some(Commands::Lint { profile, fix }), _) => Ok(AppMode::Lint {
+            profile: profile.clone(),
+            fix: *fix,
+            config,
+        }),
+        (Some(Commands::Fmt { check }), _) => Ok(AppMode::Fmt {
+            check: *check,
+            config,
+        }),
+        (Some(Commands::Library { command }), _) => Ok(AppMode::Library {
+            command: command.clone(),
+            config,
+        }),
+        (Some(Commands::Edit { profile, target }), _) => Ok(AppMode::Edit {
+            target: target
+                .clone()
+                .or_else(|| profile.clone().map(|name| EditTarget::Profile { name })),
+            config,
+        }),
+        (Some(Commands::Stats { profile, dedup }), _) => Ok(AppMode::Stats {
+            profile: profile.clone(),
+            dedup: dedup.unwrap_or_default(),
+            config,
+        }),
+        (
+            Some(Commands::Import {
+                bundle,
+                force,
+                prefix,
+            }),
+            _,
+        ) => Ok(AppMode::Import {
+            bundle: bundle.clone(),
+            force: *force,
+            prefix: prefix.clone(),
+            config,
+        }),
+        (Some(Commands::Diff { a, b, content }), _) => Ok(AppMode::Diff {
+            a: a.clone(),
+            b: b.clone(),
+            content: *content,
+            config,
+        }),
+        (
+            Some(Commands::Test {
+                profile,
+                update,
+                snapshot_dir,
+            }),
+            _,
+        ) => Ok(AppMode::Test {
+            profile: profile.clone(),
+            update: *update,
+            snapshot_dir: snapshot_dir.clone(),
+            config,
+        }),
+        (
+            Some(Commands::New {
+                path,
+                content,
+                edit,
+                force,
+            }),
+            _,
+        ) => Ok(AppMode::New {
+            path: path.clone(),
+            content: content.as_deref().map(unescape),
+            edit: *edit,
+            force: *force,
+            config,
+        }),
+        (
+            Some(Commands::Completions {
+                shell,
+                install,
+                write_to,
+                force,
+            }),
+            _,
+        ) => Ok(AppMode::Completions {
+            shell: *shell,
+            install: *install,
+            write_to: write_to.clone(),
+            force: *force,
         }),
-        (Some(Commands::Validate), _) => Ok(AppMode::Validate {
-            config: cli.config.clone(),
+        (
+            Some(Commands::Doctor {
+                offline,
+                fix,
+                yes,
+                force,
+                strict,
+                deep,
+                max_file_size_kb,
+                follow_symlinks,
+            }),
+            _,
+        ) => Ok(AppMode::Doctor {
+            offline: *offline,
+            fix: *fix,
+            yes: *yes,
+            force: *force,
+            strict: *strict,
+            deep: *deep,
+            max_file_size_kb: *max_file_size_kb,
+            follow_symlinks: *follow_symlinks,
+            config,
         }),
-        (Some(Commands::Completions { shell }), _) => Ok(AppMode::Completions { shell: *shell }),
-        (Some(Commands::Doctor), _) => Ok(AppMode::Doctor),
         (
             Some(Commands::Update {
                 version,
                 force,
                 install_dir,
+                rollback,
+                check,
+                json,
+                channel,
+                skip_checksum,
+                skip_verify,
+                backup_path,
+                dry_run,
+                quiet,
+                list_versions,
+                limit,
             }),
             _,
         ) => Ok(AppMode::Update {
             version: version.clone(),
             force: *force,
             install_dir: install_dir.clone(),
+            rollback: *rollback,
+            check: *check,
+            json: *json,
+            channel: *channel,
+            skip_checksum: *skip_checksum,
+            skip_verify: *skip_verify,
+            backup_path: backup_path.clone(),
+            dry_run: *dry_run,
+            quiet: *quiet,
+            list_versions: *list_versions,
+            limit: *limit,
         }),
-        (
-            Some(Commands::Run {
-                profile,
-                separator,
-                pre_prompt,
-                post_prompt,
-            }),
-            _,
-        ) => {
-            let sep = separator
-                .as_ref()
-                .or(cli.separator.as_ref())
-                .map(|s| unescape(s));
-            let pre = pre_prompt
-                .as_ref()
-                .or(cli.pre_prompt.as_ref())
-                .map(|s| unescape(s));
-            let post = post_prompt
-                .as_ref()
-                .or(cli.post_prompt.as_ref())
-                .map(|s| unescape(s));
-            Ok(AppMode::Run {
-                profile: profile.clone(),
-                separator: sep,
-                pre_prompt: pre,
-                post_prompt: post,
-                config: cli.config.clone(),
-            })
-        }
-        (None, Some(profile)) => {
-            let sep = cli.separator.as_ref().map(|s| unescape(s));
-            let pre = cli.pre_prompt.as_ref().map(|s| unescape(s));
-            let post = cli.post_prompt.as_ref().map(|s| unescape(s));
-            Ok(AppMode::Run {
-                profile: profile.clone(),
-                separator: sep,
-                pre_prompt: pre,
-                post_prompt: post,
-                config: cli.config.clone(),
-            })
-        }
+        (Some(Commands::Run { profile, render }), _) => Ok(run_mode(
+            profile.as_deref(),
+            &render.or(&cli.render),
+            config.as_ref(),
+        )),
+        (None, Some(profile)) => Ok(run_mode(Some(profile), &cli.render, config.as_ref())),
         (None, None) => Ok(AppMode::Help),
+    };
+    let color = if cli.no_color {
+        ColorChoice::Never
+    } else {
+        cli.color.unwrap_or_default()
+    };
+    let invocation = |mode| Invocation {
+        mode,
+        strict_config: cli.strict_config,
+        color,
+    };
+    let mode = mode?;
+    if cli.emit_mode_json {
+        return Ok(invocation(AppMode::EmitModeJson(Box::new(invocation(
+            mode,
+        )))));
+    }
+    Ok(invocation(mode))
+}
+
+/// Parse a `--since` value.
+fn parse_since(value: &str) -> Result<SystemTime, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(SystemTime::from)
+        .map_err(|e| format!("expected an RFC 3339 time such as 2025-06-01T12:00:00Z ({e})"))
+}
+
+fn run_mode(profile: Option<&str>, render: &RenderArgs, config: Option<&PathBuf>) -> AppMode {
+    let separator = render.separator.as_deref().map(unescape);
+    let pre_prompt = render.pre_prompt.as_deref().map(unescape);
+    let post_prompt = render.post_prompt.as_deref().map(unescape);
+    let opts = RenderOptions::new()
+        .separator(separator.as_deref())
+        .pre_prompt(pre_prompt.as_deref())
+        .post_prompt(post_prompt.as_deref())
+        .vars(render.vars.iter().cloned())
+        .dedup(render.dedup.unwrap_or_default())
+        .order(render.order.unwrap_or_default())
+        .add_dependencies(&render.add)
+        .drop_dependencies(&render.drop)
+        .strict_env(render.strict_env)
+        .skip_missing(render.skip_missing)
+        .strip_frontmatter(render.strip_frontmatter)
+        .trim_trailing_whitespace(render.trim_trailing_whitespace)
+        .normalize_line_endings(render.normalize_line_endings)
+        .between_only(render.between_only)
+        .collapse_blank_lines(render.collapse_blank_lines)
+        .allow_external(render.allow_external)
+        .require_utf8(render.require_utf8)
+        .limit_bytes(render.limit_bytes)
+        .since(render.since)
+        .timestamp_format(render.timestamp_format.as_deref())
+        .timezone(render.utc.then_some(Timezone::Utc));
+    AppMode::Run {
+        profile: profile.map(str::to_string),
+        opts,
+        config: config.cloned(),
+        manifest: render.manifest.clone(),
+    }
+}
+
+/// Escape special characters so that [`unescape`] restores the input.
+///
+/// Backslashes, double quotes, newlines, tabs and carriage returns become
+/// `\\`, `\"`, `\n`, `\t` and `\r`; everything else is kept as is.
+///
+/// # Examples
+/// ```
+/// use prompter::{escape, unescape};
+/// assert_eq!(escape("a\tb\n"), "a\\tb\\n");
+/// assert_eq!(unescape(&escape("say \"hi\"\n")), "say \"hi\"\n");
+/// ```
+#[must_use]
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            other => out.push(other),
+        }
     }
+    out
+}
+
+/// Escape all of stdin and print it on a single line.
+///
+/// # Errors
+/// Returns an error if stdin cannot be read or stdout cannot be written.
+pub fn run_escape_stdout() -> Result<(), PrompterError> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    writeln!(io::stdout(), "{}", escape(&input))?;
+    Ok(())
 }
 
 /// Unescape special characters in strings.
 ///
-/// Processes escape sequences like `\n`, `\t`, `\"`, and `\\` in input strings,
-/// converting them to their literal character equivalents.
+/// Processes escape sequences like `\n`, `\t`, `\"`, `\\`, and `\u00e9` in
+/// input strings, converting them to their literal character equivalents.
+/// Characters outside the Basic Multilingual Plane are written as a UTF-16
+/// surrogate pair, as in JSON (`\ud83d\ude00`). A `\u` that is not followed
+/// by four hex digits naming a character is kept as written.
 ///
 /// # Arguments
 /// * `s` - Input string that may contain escape sequences
@@ -267,6 +2034,7 @@ pub fn parse_args_from(args: Vec<String>) -> Result<AppMode, String> {
 /// ```
 /// use prompter::unescape;
 /// assert_eq!(unescape("line1\\nline2"), "line1\nline2");
+/// assert_eq!(unescape("caf\\u00e9"), "café");
 /// ```
 #[must_use]
 #[allow(clippy::while_let_on_iterator)]
@@ -281,6 +2049,15 @@ pub fn unescape(s: &str) -> String {
                 Some('r') => out.push('\r'),
                 Some('"') => out.push('"'),
                 Some('\\') | None => out.push('\\'),
+                Some('u') => {
+                    let rest = chars.as_str();
+                    if let Some((c, len)) = unicode_escape(rest) {
+                        out.push(c);
+                        chars = rest[len..].chars();
+                    } else {
+                        out.push_str("\\u");
+                    }
+                }
                 Some(other) => {
                     out.push('\\');
                     out.push(other);
@@ -293,100 +2070,716 @@ pub fn unescape(s: &str) -> String {
     out
 }
 
-fn home_dir() -> Result<PathBuf, String> {
-    env::var("HOME")
-        .map(PathBuf::from)
-        .map_err(|_| "$HOME not set".into())
+/// The character named by the hex digits after a `\u`, and how many bytes of
+/// `s` it used: four, or ten for a `\uXXXX` surrogate pair.
+fn unicode_escape(s: &str) -> Option<(char, usize)> {
+    let hex4 = |s: &str| {
+        s.get(..4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+    };
+    let unit = hex4(s)?;
+    if let Some(c) = char::from_u32(unit) {
+        return Some((c, 4));
+    }
+    // A high surrogate must be followed by an escaped low surrogate
+    if !(0xD800..0xDC00).contains(&unit) {
+        return None;
+    }
+    let low = s[4..].strip_prefix("\\u").and_then(hex4)?;
+    if !(0xDC00..0xE000).contains(&low) {
+        return None;
+    }
+    char::from_u32(0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)).map(|c| (c, 10))
 }
 
-fn config_path() -> Result<PathBuf, String> {
-    Ok(home_dir()?.join(".config/prompter/config.toml"))
-}
+/// Environment variable that disables all network access when set (e.g. `PROMPTER_OFFLINE=1`).
+pub const OFFLINE_ENV: &str = "PROMPTER_OFFLINE";
 
-fn library_dir() -> Result<PathBuf, String> {
-    Ok(home_dir()?.join(".local/prompter/library"))
-}
+/// Environment variable overriding the doctor's per-file size limit, in KB.
+pub const MAX_FILE_SIZE_ENV: &str = "PROMPTER_MAX_FILE_SIZE_KB";
 
-fn config_path_override(path: &Path) -> Result<PathBuf, String> {
-    let resolved = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        env::current_dir()
-            .map_err(|e| format!("Failed to resolve working directory: {e}"))?
-            .join(path)
-    };
-    Ok(resolved)
-}
+/// Per-file size limit used by `doctor` when none is configured, in KB.
+pub const DEFAULT_MAX_FILE_SIZE_KB: u64 = 100;
 
-fn library_dir_for_config(config: &Path) -> Result<PathBuf, String> {
-    let parent = config
-        .parent()
-        .ok_or_else(|| format!("Config path {} has no parent directory", config.display()))?;
-    Ok(parent.join("library"))
+/// Read the per-file size limit from [`MAX_FILE_SIZE_ENV`], if set to a number.
+#[must_use]
+pub fn max_file_size_kb_from_env() -> Option<u64> {
+    env::var(MAX_FILE_SIZE_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
 }
 
-fn is_terminal() -> bool {
-    std::io::stdout().is_terminal()
+/// Report whether network access has been disabled through [`OFFLINE_ENV`].
+///
+/// Any non-empty value other than `0` or `false` (case-insensitive) enables
+/// offline mode.
+#[must_use]
+pub fn offline_from_env() -> bool {
+    env::var(OFFLINE_ENV).is_ok_and(|v| is_truthy(&v))
 }
 
-fn default_pre_prompt() -> String {
-    "You are an LLM coding agent. Here are invariants that you must adhere to. Please respond with 'Got it' when you have studied these and understand them. At that point, the operator will give you further instructions. You are *not* to do anything to the contents of this directory until you have been explicitly asked to, by the operator.\n\n".to_string()
-}
+/// Environment variable holding template variables for every render, as
+/// comma-separated `KEY=VALUE` pairs (e.g. `PROMPTER_VARS=team=core,lang=rust`).
+pub const VARS_ENV: &str = "PROMPTER_VARS";
 
-fn default_post_prompt() -> String {
-    "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist.".to_string()
+/// Parse one `KEY=VALUE` template variable, as given to `--var`.
+///
+/// # Errors
+/// Returns an error if there is no `=` or the key is empty.
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got '{s}'")),
+    }
 }
 
-fn format_system_prefix() -> String {
-    let date = Local::now().format("%Y-%m-%d").to_string();
-    let os = env::consts::OS;
-    let arch = env::consts::ARCH;
-
-    if is_terminal() {
-        format!(
-            "🗓️  Today is {}, and you are running on a {}/{} system.\n\n",
-            date.bright_cyan(),
-            arch.bright_green(),
-            os.bright_green()
-        )
-    } else {
-        format!("Today is {date}, and you are running on a {arch}/{os} system.\n\n")
+/// The template variables for a render: the pairs in `env` (the value of
+/// [`VARS_ENV`]) overlaid with `cli`, so `--var` wins when both set a key.
+///
+/// # Examples
+/// ```
+/// use std::collections::BTreeMap;
+/// use prompter::template_vars;
+///
+/// let cli = BTreeMap::from([("team".to_string(), "web".to_string())]);
+/// let vars = template_vars(Some("team=core,lang=rust"), &cli).unwrap();
+/// assert_eq!(vars["team"], "web");
+/// assert_eq!(vars["lang"], "rust");
+/// ```
+///
+/// # Errors
+/// Returns an error naming the first entry of `env` that is not `KEY=VALUE`.
+pub fn template_vars(
+    env: Option<&str>,
+    cli: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, String> {
+    let mut vars = BTreeMap::new();
+    for entry in env.unwrap_or_default().split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = parse_var(entry).map_err(|e| format!("Invalid {VARS_ENV}: {e}"))?;
+        vars.insert(key, value);
     }
+    vars.extend(cli.iter().map(|(key, value)| (key.clone(), value.clone())));
+    Ok(vars)
 }
 
-fn success_message(msg: &str) -> String {
-    if is_terminal() {
-        format!("✅ {}", msg.bright_green())
-    } else {
-        msg.to_string()
+/// Replace every `{{KEY}}` in `text` with its value in `vars`.
+///
+/// Placeholders for keys that are not set are left as written. The text is
+/// handled as bytes, so files need not be UTF-8.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use prompter::substitute_vars;
+///
+/// let vars = BTreeMap::from([("name".to_string(), "Ada".to_string())]);
+/// assert_eq!(substitute_vars(b"Hi {{name}}, {{other}}", &vars), b"Hi Ada, {{other}}");
+/// ```
+#[must_use]
+pub fn substitute_vars(text: &[u8], vars: &BTreeMap<String, String>) -> Vec<u8> {
+    let find = |hay: &[u8], needle: &[u8]| hay.windows(2).position(|w| w == needle);
+    let mut out = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = find(rest, b"{{") {
+        out.extend_from_slice(&rest[..start]);
+        rest = &rest[start + 2..];
+        let value = find(rest, b"}}").and_then(|end| {
+            let key = std::str::from_utf8(&rest[..end]).ok()?;
+            vars.get(key).map(|value| (end, value))
+        });
+        if let Some((end, value)) = value {
+            out.extend_from_slice(value.as_bytes());
+            rest = &rest[end + 2..];
+        } else {
+            out.extend_from_slice(b"{{");
+        }
     }
+    out.extend_from_slice(rest);
+    out
 }
 
-fn info_message(msg: &str) -> String {
-    if is_terminal() {
-        format!("ℹ️  {}", msg.bright_blue())
+fn is_truthy(value: &str) -> bool {
+    let value = value.trim();
+    !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false")
+}
+
+/// Expand `$VAR` and `${VAR}` references to environment variables.
+///
+/// Undefined variables are left untouched unless `strict` is set. A `$` that
+/// does not start a variable name is kept literally.
+///
+/// # Errors
+/// Returns an error naming the first undefined variable when `strict` is set.
+///
+/// # Examples
+/// ```
+/// use prompter::expand_env;
+/// assert_eq!(expand_env("cost: $5", true).unwrap(), "cost: $5");
+/// assert_eq!(
+///     expand_env("${PROMPTER_SURELY_UNSET}/a.md", false).unwrap(),
+///     "${PROMPTER_SURELY_UNSET}/a.md"
+/// );
+/// assert!(expand_env("$PROMPTER_SURELY_UNSET", true).is_err());
+/// ```
+pub fn expand_env(input: &str, strict: bool) -> Result<String, String> {
+    match expand_vars(input, |name| env::var(name).ok()) {
+        (_, Some(name)) if strict => Err(format!("Undefined environment variable: {name}")),
+        (expanded, _) => Ok(expanded),
+    }
+}
+
+/// Expand variables without failing on undefined ones.
+fn expand_env_lenient(input: &str) -> String {
+    expand_vars(input, |name| env::var(name).ok()).0
+}
+
+/// Expand variables using `lookup`, returning the expanded text and the first
+/// variable that `lookup` could not resolve.
+fn expand_vars(input: &str, lookup: impl Fn(&str) -> Option<String>) -> (String, Option<String>) {
+    let is_name = |s: &str| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let mut out = String::with_capacity(input.len());
+    let mut undefined = None;
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let (name, len) = after.strip_prefix('{').map_or_else(
+            || {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            },
+            |braced| {
+                braced
+                    .find('}')
+                    .map_or(("", 0), |end| (&braced[..end], end + 2))
+            },
+        );
+        if !is_name(name) {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        if let Some(value) = lookup(name) {
+            out.push_str(&value);
+        } else {
+            undefined.get_or_insert_with(|| name.to_string());
+            out.push_str(&rest[pos..=pos + len]);
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    (out, undefined)
+}
+
+fn home_dir() -> Result<PathBuf, String> {
+    env::var("HOME")
+        .map(PathBuf::from)
+        .map_err(|_| "$HOME not set".into())
+}
+
+/// Pick an XDG base directory: `value` when it is an absolute path (the spec
+/// says relative values are ignored), otherwise `fallback` under `home`.
+fn xdg_base_from(
+    value: Option<PathBuf>,
+    home: impl FnOnce() -> Result<PathBuf, String>,
+    fallback: &str,
+) -> Result<PathBuf, String> {
+    match value {
+        Some(dir) if dir.is_absolute() => Ok(dir),
+        _ => Ok(home()?.join(fallback)),
+    }
+}
+
+fn xdg_base(var: &str, fallback: &str) -> Result<PathBuf, String> {
+    xdg_base_from(env::var_os(var).map(PathBuf::from), home_dir, fallback)
+}
+
+/// The global config file: `$XDG_CONFIG_HOME/prompter/config.toml`, where
+/// `XDG_CONFIG_HOME` defaults to `~/.config`.
+///
+/// # Errors
+/// Returns an error when `HOME` is needed but not set.
+pub fn config_path() -> Result<PathBuf, String> {
+    Ok(xdg_base("XDG_CONFIG_HOME", ".config")?.join("prompter/config.toml"))
+}
+
+/// The global library: `$XDG_DATA_HOME/prompter/library`, where
+/// `XDG_DATA_HOME` defaults to `~/.local/share`.
+///
+/// # Errors
+/// Returns an error when `HOME` is needed but not set.
+pub fn library_dir() -> Result<PathBuf, String> {
+    Ok(xdg_base("XDG_DATA_HOME", ".local/share")?.join("prompter/library"))
+}
+
+/// Where the library lived before prompter followed the XDG spec
+/// (`~/.local/prompter/library`); `doctor` suggests moving it.
+///
+/// # Errors
+/// Returns an error when `HOME` is not set.
+pub fn legacy_library_dir() -> Result<PathBuf, String> {
+    Ok(home_dir()?.join(".local/prompter/library"))
+}
+
+fn config_path_override(path: &Path) -> Result<PathBuf, String> {
+    let resolved = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .map_err(|e| format!("Failed to resolve working directory: {e}"))?
+            .join(path)
+    };
+    Ok(resolved)
+}
+
+fn library_dir_for_config(config: &Path) -> Result<PathBuf, String> {
+    let parent = config
+        .parent()
+        .ok_or_else(|| format!("Config path {} has no parent directory", config.display()))?;
+    Ok(parent.join("library"))
+}
+
+fn is_terminal() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+/// Environment variable that disables colors and emoji when set to any
+/// non-empty value (see <https://no-color.org>).
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// Whether output should use colors and emoji under `choice`: `always` and
+/// `never` decide alone, `auto` styles a terminal unless `NO_COLOR` is set.
+///
+/// The CLI decides this once and passes the result to each output function
+/// as `styled`, including the banner written into rendered prompts.
+#[must_use]
+pub fn use_styling(choice: ColorChoice) -> bool {
+    styling_enabled(
+        choice,
+        env::var(NO_COLOR_ENV).ok().as_deref(),
+        is_terminal(),
+    )
+}
+
+const fn styling_enabled(choice: ColorChoice, no_color: Option<&str>, tty: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => tty && !matches!(no_color, Some(v) if !v.is_empty()),
+    }
+}
+
+fn default_pre_prompt() -> String {
+    "You are an LLM coding agent. Here are invariants that you must adhere to. Please respond with 'Got it' when you have studied these and understand them. At that point, the operator will give you further instructions. You are *not* to do anything to the contents of this directory until you have been explicitly asked to, by the operator.\n\n".to_string()
+}
+
+fn default_post_prompt() -> String {
+    "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist.".to_string()
+}
+
+/// The chrono format of the banner date when neither `--timestamp-format`
+/// nor the config's `timestamp_format` is set.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d";
+
+/// Check that `format` is a chrono `strftime` format string.
+///
+/// # Errors
+/// Names the format when it contains an unknown or incomplete specifier.
+///
+/// # Examples
+/// ```
+/// use prompter::check_timestamp_format;
+///
+/// assert!(check_timestamp_format("%Y-%m-%d %H:%M").is_ok());
+/// assert!(check_timestamp_format("%Q").is_err());
+/// ```
+pub fn check_timestamp_format(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        Err(format!(
+            "Invalid timestamp format '{format}': unknown or incomplete % specifier"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_timestamp_format(value: &str) -> Result<String, String> {
+    check_timestamp_format(value).map(|()| value.to_string())
+}
+
+/// The clock the banner date is read from, set by the config's `timezone`
+/// or `--utc`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Timezone {
+    /// The machine's local time zone
+    #[default]
+    Local,
+    /// Coordinated Universal Time, the same on every machine
+    Utc,
+}
+
+impl Timezone {
+    /// The value's spelling in the config.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Utc => "utc",
+        }
+    }
+
+    /// The time zone whose config spelling is `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Local, Self::Utc]
+            .into_iter()
+            .find(|timezone| timezone.name() == name)
+    }
+}
+
+/// The system prefix line, with the current date in `timezone` written with
+/// `timestamp_format`.
+///
+/// The format must already have passed [`check_timestamp_format`]. `styled`
+/// adds color and emoji.
+fn format_system_prefix(timestamp_format: &str, timezone: Timezone, styled: bool) -> String {
+    let date = match timezone {
+        Timezone::Local => Local::now().format(timestamp_format).to_string(),
+        Timezone::Utc => Utc::now().format(timestamp_format).to_string(),
+    };
+    let os = env::consts::OS;
+    let arch = env::consts::ARCH;
+
+    if styled {
+        format!(
+            "🗓️  Today is {}, and you are running on a {}/{} system.\n\n",
+            date.bright_cyan(),
+            arch.bright_green(),
+            os.bright_green()
+        )
+    } else {
+        format!("Today is {date}, and you are running on a {arch}/{os} system.\n\n")
+    }
+}
+
+fn success_text(msg: &str, styled: bool) -> String {
+    if styled {
+        format!("✅ {}", msg.bright_green())
+    } else {
+        msg.to_string()
+    }
+}
+
+fn warning_text(msg: &str, styled: bool) -> String {
+    if styled {
+        format!("⚠️  {}", msg.bright_yellow())
+    } else {
+        format!("Warning: {msg}")
+    }
+}
+
+fn info_text(msg: &str, styled: bool) -> String {
+    if styled {
+        format!("ℹ️  {}", msg.bright_blue())
     } else {
         msg.to_string()
     }
 }
 
-fn read_config_with_path(path: &Path) -> Result<String, String> {
-    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+/// Config override value that reads the configuration from standard input.
+const STDIN_CONFIG: &str = "-";
+
+fn is_stdin_config(path: &Path) -> bool {
+    path == Path::new(STDIN_CONFIG)
+}
+
+fn read_config_with_path(path: &Path) -> Result<String, PrompterError> {
+    let read = if is_stdin_config(path) {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).map(|_| text)
+    } else {
+        fs::read_to_string(path)
+    };
+    read.map_err(|source| PrompterError::ConfigRead {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// File names recognised as a project-local config, in order of preference.
+pub const LOCAL_CONFIG_NAMES: [&str; 2] = [".prompterrc", "prompter.toml"];
+
+/// Find the nearest project-local config, checking `start` and then each of
+/// its ancestors up to the filesystem root.
+///
+/// The library for a local config is `library/` next to the file, as with
+/// `--config`.
+#[must_use]
+pub fn find_local_config(start: &Path) -> Option<PathBuf> {
+    start.ancestors().find_map(|dir| {
+        LOCAL_CONFIG_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// What determined a path reported by [`ResolvedPaths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PathSource {
+    /// The built-in location under `$HOME`
+    Default,
+    /// An environment variable such as `XDG_CONFIG_HOME`
+    Env(&'static str),
+    /// A command-line flag such as `--config`
+    Flag(&'static str),
+    /// A `.prompterrc` or `prompter.toml` found from the working directory
+    LocalConfig,
+    /// `library/` next to a config that was not the global one
+    NextToConfig,
+    /// `./library`, because the config is read from stdin
+    WorkingDirectory,
+    /// A config file key such as `library_roots`
+    ConfigKey(&'static str),
+}
+
+impl<'de> Deserialize<'de> for PathSource {
+    /// Reads what `Serialize` writes, mapping each name back to the
+    /// `'static` one prompter reports.
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        const NAMES: [&str; 5] = [
+            "XDG_CONFIG_HOME",
+            "XDG_DATA_HOME",
+            "--config",
+            "--config-dir",
+            "library_roots",
+        ];
+        #[derive(Deserialize)]
+        enum Owned {
+            Default,
+            Env(String),
+            Flag(String),
+            LocalConfig,
+            NextToConfig,
+            WorkingDirectory,
+            ConfigKey(String),
+        }
+        let name = |name: String| {
+            NAMES
+                .into_iter()
+                .find(|known| *known == name)
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown path source: {name}")))
+        };
+        Ok(match Owned::deserialize(d)? {
+            Owned::Default => Self::Default,
+            Owned::Env(var) => Self::Env(name(var)?),
+            Owned::Flag(flag) => Self::Flag(name(flag)?),
+            Owned::LocalConfig => Self::LocalConfig,
+            Owned::NextToConfig => Self::NextToConfig,
+            Owned::WorkingDirectory => Self::WorkingDirectory,
+            Owned::ConfigKey(key) => Self::ConfigKey(name(key)?),
+        })
+    }
+}
+
+impl std::fmt::Display for PathSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::Env(var) => write!(f, "${var}"),
+            Self::Flag(flag) => write!(f, "{flag} flag"),
+            Self::LocalConfig => f.write_str("local config"),
+            Self::NextToConfig => f.write_str("next to config"),
+            Self::WorkingDirectory => f.write_str("working directory"),
+            Self::ConfigKey(key) => write!(f, "config key {key}"),
+        }
+    }
+}
+
+/// The config file and library root a command reads, and what chose each.
+///
+/// Every command that loads the config goes through
+/// [`ResolvedPaths::resolve`], so `prompter which` reports exactly what the
+/// others use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPaths {
+    /// Config file (`-` for stdin)
+    pub config: PathBuf,
+    /// What chose `config`
+    pub config_source: PathSource,
+    /// Primary library directory
+    pub library: PathBuf,
+    /// What chose `library`
+    pub library_source: PathSource,
+}
+
+impl ResolvedPaths {
+    /// Resolve the paths for `config_override` (from `--config` or local
+    /// discovery), falling back to the XDG locations.
+    ///
+    /// # Errors
+    /// Returns an error when `HOME` is needed but unset or the working
+    /// directory cannot be determined.
+    pub fn resolve(config_override: Option<&Path>) -> Result<Self, String> {
+        Self::resolve_as(config_override, PathSource::Flag("--config"))
+    }
+
+    /// Like [`ResolvedPaths::resolve`], reporting `source` as what chose
+    /// `config_override`.
+    ///
+    /// # Errors
+    /// Returns an error when `HOME` is needed but unset or the working
+    /// directory cannot be determined.
+    pub fn resolve_as(config_override: Option<&Path>, source: PathSource) -> Result<Self, String> {
+        match config_override {
+            Some(path) if is_stdin_config(path) => Ok(Self {
+                config: path.to_path_buf(),
+                config_source: source,
+                // A piped config has no directory of its own; use ./library instead.
+                library: env::current_dir()
+                    .map(|cwd| cwd.join("library"))
+                    .map_err(|e| format!("Failed to resolve working directory: {e}"))?,
+                library_source: PathSource::WorkingDirectory,
+            }),
+            Some(path) => {
+                let config = config_path_override(path)?;
+                Ok(Self {
+                    library: library_dir_for_config(&config)?,
+                    library_source: PathSource::NextToConfig,
+                    config,
+                    config_source: source,
+                })
+            }
+            None => Ok(Self {
+                config: config_path()?,
+                config_source: xdg_source("XDG_CONFIG_HOME"),
+                library: library_dir()?,
+                library_source: xdg_source("XDG_DATA_HOME"),
+            }),
+        }
+    }
+
+    /// Whether the config is read from stdin.
+    #[must_use]
+    pub fn config_is_stdin(&self) -> bool {
+        is_stdin_config(&self.config)
+    }
+}
+
+/// [`PathSource::Env`] when `var` holds an absolute path (as [`xdg_base`]
+/// requires), else [`PathSource::Default`].
+fn xdg_source(var: &'static str) -> PathSource {
+    if env::var_os(var).is_some_and(|value| Path::new(&value).is_absolute()) {
+        PathSource::Env(var)
+    } else {
+        PathSource::Default
+    }
 }
 
-fn resolve_config_path(config_override: Option<&Path>) -> Result<PathBuf, String> {
-    config_override.map_or_else(config_path, config_path_override)
+/// Load the config chosen by `config_override` together with its library root,
+/// rejecting unknown keys when `strict`.
+fn load_config(
+    config_override: Option<&Path>,
+    strict: bool,
+) -> Result<(Config, PathBuf), PrompterError> {
+    let paths = ResolvedPaths::resolve(config_override)?;
+    let cfg = Config::load(&paths.config, strict)?;
+    Ok((cfg, paths.library))
 }
 
-fn library_path_for_config_override(
+/// Print the config and library `prompter` would use, whether each exists,
+/// and what chose it; with `json`, as `{config, library, library_roots}`
+/// objects of `{path, exists, source}`.
+///
+/// Extra `library_roots` are listed when the config file can be read; a
+/// config on stdin is not read.
+///
+/// # Errors
+/// Returns an error if the paths cannot be resolved or writing fails.
+pub fn run_which_stdout(
     config_override: Option<&Path>,
-    resolved_config: &Path,
-) -> Result<PathBuf, String> {
-    if config_override.is_some() {
-        library_dir_for_config(resolved_config)
+    source: PathSource,
+    json: bool,
+) -> Result<(), PrompterError> {
+    let paths = ResolvedPaths::resolve_as(config_override, source)?;
+    write_which(&paths, json, io::stdout())
+}
+
+fn write_which(paths: &ResolvedPaths, json: bool, mut w: impl Write) -> Result<(), PrompterError> {
+    let config_exists = paths.config_is_stdin() || paths.config.is_file();
+    let roots: Vec<PathBuf> = if paths.config_is_stdin() {
+        Vec::new()
     } else {
-        library_dir()
+        Config::from_path(&paths.config)
+            .map(|cfg| cfg.search_roots(&paths.library).split_off(1))
+            .unwrap_or_default()
+    };
+    let entry = |path: &Path, exists: bool, source: PathSource| {
+        serde_json::json!({
+            "path": path.display().to_string(),
+            "exists": exists,
+            "source": source.to_string(),
+        })
+    };
+
+    if json {
+        let value = serde_json::json!({
+            "config": entry(&paths.config, config_exists, paths.config_source),
+            "library": entry(&paths.library, paths.library.is_dir(), paths.library_source),
+            "library_roots": roots
+                .iter()
+                .map(|root| entry(root, root.is_dir(), PathSource::ConfigKey("library_roots")))
+                .collect::<Vec<_>>(),
+        });
+        let text = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+        writeln!(w, "{text}")?;
+        return Ok(());
+    }
+
+    let line = |label: &str, path: &Path, exists: bool, source: PathSource| {
+        let state = if exists { "exists" } else { "missing" };
+        format!("{label:<9}{} ({state}; {source})", path.display())
+    };
+    writeln!(
+        w,
+        "{}",
+        line("config:", &paths.config, config_exists, paths.config_source)
+    )?;
+    writeln!(
+        w,
+        "{}",
+        line(
+            "library:",
+            &paths.library,
+            paths.library.is_dir(),
+            paths.library_source
+        )
+    )?;
+    for root in roots {
+        let exists = root.is_dir();
+        writeln!(
+            w,
+            "{}",
+            line(
+                "root:",
+                &root,
+                exists,
+                PathSource::ConfigKey("library_roots")
+            )
+        )?;
     }
+    Ok(())
 }
 
 /// Parse TOML configuration into a Config structure.
@@ -399,644 +2792,5689 @@ fn library_path_for_config_override(
 ///
 /// # Returns
 /// * `Ok(Config)` - Successfully parsed configuration
-/// * `Err(String)` - Error message describing parsing failure
+/// * `Err(PrompterError::ConfigParse)` - Parsing failure with its line number
 ///
 /// # Errors
 /// Returns an error if:
 /// - TOML syntax is invalid
 /// - Profile sections are malformed
 /// - `depends_on` arrays have invalid syntax
-pub fn parse_config_toml(input: &str) -> Result<Config, String> {
+pub fn parse_config_toml(input: &str) -> Result<Config, PrompterError> {
+    parse_config(input, false)
+}
+
+/// Parse a config like [`parse_config_toml`], but reject keys it would
+/// silently ignore, such as a misspelled `depnds_on`.
+///
+/// Profile sections may set `depends_on`, `tags`, `extends`, and
+/// `description`; everything else must be one of the top-level keys, which
+/// come before the first section.
+///
+/// # Examples
+/// ```
+/// use prompter::parse_config_toml_strict;
+///
+/// assert!(parse_config_toml_strict("[a]\ndepends_on = [\"a.md\"]\n").is_ok());
+/// let err = parse_config_toml_strict("[a]\ndepnds_on = [\"a.md\"]\n").unwrap_err();
+/// assert!(err.to_string().contains("Unknown key 'depnds_on' in [a]"));
+/// ```
+///
+/// # Errors
+/// Returns [`PrompterError::ConfigParse`] for everything
+/// [`parse_config_toml`] rejects and for any unknown key.
+pub fn parse_config_toml_strict(input: &str) -> Result<Config, PrompterError> {
+    parse_config(input, true)
+}
+
+/// Keys allowed before the first section.
+const TOP_LEVEL_KEYS: [&str; 7] = [
+    "post_prompt",
+    "pre_prompt_file",
+    "post_prompt_file",
+    "default_profile",
+    "timestamp_format",
+    "timezone",
+    "library_roots",
+];
+
+/// Keys allowed in a profile section.
+const PROFILE_KEYS: [&str; 4] = ["depends_on", "tags", "extends", "description"];
+
+/// Why strict parsing rejects `key` in `section` (`None` before the first
+/// section), or `None` if the key is known there. `lint_rules` is set inside
+/// a `[lint]` table that holds rules rather than a profile.
+fn unknown_key_error(key: &str, section: Option<&str>, lint_rules: bool) -> Option<String> {
+    match section {
+        None if TOP_LEVEL_KEYS.contains(&key) => None,
+        None => Some(format!(
+            "Unknown top-level key '{key}' (expected one of: {})",
+            TOP_LEVEL_KEYS.join(", ")
+        )),
+        Some(_) if TOP_LEVEL_KEYS.contains(&key) => {
+            Some(format!("{key} must come before the first profile section"))
+        }
+        // Rule names are checked while reading the table
+        Some(_) if lint_rules => None,
+        Some(_) if PROFILE_KEYS.contains(&key) => None,
+        Some(section) => Some(format!(
+            "Unknown key '{key}' in [{section}] (expected one of: {})",
+            PROFILE_KEYS.join(", ")
+        )),
+    }
+}
+
+#[allow(clippy::too_many_lines)] // one branch per kind of line
+fn parse_config(input: &str, strict: bool) -> Result<Config, PrompterError> {
     let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    let mut extends: HashMap<String, String> = HashMap::new();
+    let mut meta: HashMap<String, ProfileMeta> = HashMap::new();
     let mut current: Option<String> = None;
-    let mut post_prompt: Option<String> = None;
+    // Top-level keys and lint settings
+    let mut top = Config::default();
 
-    let mut collecting = false;
+    // Key and starting line of the multi-line array being collected, if any
+    let mut collecting: Option<(String, usize)> = None;
     let mut buffer = String::new();
+    // Header line and `key = value` fields of the `[[<profile>.depends_on]]`
+    // table being read, if any
+    let mut dep_table: Option<(usize, Vec<String>)> = None;
+    let lines = logical_lines(input);
+    // Configs written before `[lint]` held rules may have a profile by that name
+    let lint_is_profile = section_is_profile(&lines, LINT_SECTION);
 
-    for raw_line in input.lines() {
-        let line = strip_comments(raw_line).trim().to_string();
+    for (line_no, raw_line) in lines {
+        if in_multiline_string(&raw_line) {
+            return Err(PrompterError::parse(
+                line_no,
+                "Unterminated multi-line string",
+            ));
+        }
+        let line = strip_comments(&raw_line).trim().to_string();
         if line.is_empty() {
             continue;
         }
 
-        if collecting {
+        if let Some((key, start)) = &collecting {
             buffer.push(' ');
             buffer.push_str(&line);
             if contains_closing_bracket_outside_quotes(&buffer) {
-                let items = parse_array_items(&buffer).map_err(|e| {
-                    format!(
-                        "Invalid depends_on array for [{}]: {}",
-                        current.clone().unwrap_or_default(),
-                        e
-                    )
-                })?;
-                let name = current
-                    .clone()
-                    .ok_or_else(|| "depends_on outside of a profile section".to_string())?;
-                profiles.insert(name, items);
-                collecting = false;
+                store_array(
+                    key,
+                    &buffer,
+                    current.as_ref(),
+                    &mut profiles,
+                    &mut meta,
+                    &mut top.library_roots,
+                )
+                .map_err(|e| PrompterError::parse(*start, e))?;
+                collecting = None;
                 buffer.clear();
             }
             continue;
         }
 
         if line.starts_with('[') && line.ends_with(']') {
-            let name = line[1..line.len() - 1].trim().to_string();
+            if let (Some((start, fields)), Some(name)) = (dep_table.take(), &current) {
+                store_dependency_table(&fields, name, &mut profiles, &mut meta)
+                    .map_err(|e| PrompterError::parse(start, e))?;
+            }
+            if line.starts_with("[[") {
+                let name = dependency_table_name(&line).ok_or_else(|| {
+                    PrompterError::parse(
+                        line_no,
+                        format!("Only [[<profile>.depends_on]] tables are supported, not {line}"),
+                    )
+                })?;
+                current = Some(name);
+                dep_table = Some((line_no, Vec::new()));
+                continue;
+            }
+            let name = unquote_key(line[1..line.len() - 1].trim());
             if name.is_empty() {
-                return Err("Empty section name []".into());
+                return Err(PrompterError::parse(line_no, "Empty section name []"));
             }
             current = Some(name);
             continue;
         }
 
+        if let Some((_, fields)) = dep_table.as_mut() {
+            fields.push(line);
+            continue;
+        }
+
         if let Some(eq_pos) = line.find('=') {
             let key = line[..eq_pos].trim();
             let value = line[eq_pos + 1..].trim();
-
-            if key == "post_prompt" {
-                if !value.starts_with('"') || !value.ends_with('"') {
-                    return Err("post_prompt must be a string".into());
+            let lint_rules = current.as_deref() == Some(LINT_SECTION) && !lint_is_profile;
+            if strict {
+                if let Some(e) = unknown_key_error(key, current.as_deref(), lint_rules) {
+                    return Err(PrompterError::parse(line_no, e));
                 }
-                let unquoted = &value[1..value.len() - 1];
-                post_prompt = Some(unescape(unquoted));
+            }
+
+            let top_level = match key {
+                "post_prompt" => Some(&mut top.post_prompt),
+                "pre_prompt_file" => Some(&mut top.pre_prompt_file),
+                "post_prompt_file" => Some(&mut top.post_prompt_file),
+                "default_profile" => Some(&mut top.default_profile),
+                "timestamp_format" => Some(&mut top.timestamp_format),
+                _ => None,
+            };
+            if let Some(slot) = top_level {
+                *slot = Some(string_value(key, value, line_no)?);
+                continue;
+            }
+            if key == "timezone" {
+                top.timezone = Some(timezone_value(value, line_no)?);
+                continue;
+            }
+
+            if lint_rules {
+                set_lint_rule(&mut top, key, value, line_no)?;
+                continue;
+            }
+
+            if key == "extends" {
+                let parent = string_value(key, value, line_no)?;
+                let name = current.clone().ok_or_else(|| {
+                    PrompterError::parse(line_no, "extends outside of a profile section")
+                })?;
+                // A profile may consist of nothing but its parent
+                profiles.entry(name.clone()).or_default();
+                extends.insert(name, parent);
                 continue;
             }
 
-            if key != "depends_on" {
+            if key != "depends_on" && key != "tags" && key != "library_roots" {
                 continue;
             }
             if !value.starts_with('[') {
-                return Err("depends_on must be an array".into());
+                return Err(PrompterError::parse(
+                    line_no,
+                    format!("{key} must be an array"),
+                ));
             }
             buffer.clear();
             buffer.push_str(value);
             if contains_closing_bracket_outside_quotes(&buffer) {
-                let items = parse_array_items(&buffer).map_err(|e| {
-                    format!(
-                        "Invalid depends_on array for [{}]: {}",
-                        current.clone().unwrap_or_default(),
-                        e
-                    )
-                })?;
-                let name = current
-                    .clone()
-                    .ok_or_else(|| "depends_on outside of a profile section".to_string())?;
-                profiles.insert(name, items);
+                store_array(
+                    key,
+                    &buffer,
+                    current.as_ref(),
+                    &mut profiles,
+                    &mut meta,
+                    &mut top.library_roots,
+                )
+                .map_err(|e| PrompterError::parse(line_no, e))?;
                 buffer.clear();
             } else {
-                collecting = true;
+                collecting = Some((key.to_string(), line_no));
             }
         }
     }
+    if let (Some((start, fields)), Some(name)) = (dep_table, &current) {
+        store_dependency_table(&fields, name, &mut profiles, &mut meta)
+            .map_err(|e| PrompterError::parse(start, e))?;
+    }
 
     Ok(Config {
         profiles,
-        post_prompt,
+        extends,
+        meta,
+        ..top
     })
 }
 
-fn strip_comments(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    let mut in_str = false;
-    for c in s.chars() {
-        if c == '"' {
-            out.push(c);
-            in_str = !in_str;
-            continue;
-        }
-        if !in_str && c == '#' {
-            break;
-        }
-        out.push(c);
+/// The contents of the quoted string `value` for `key`; see
+/// [`decode_string_literal`].
+fn string_value(key: &str, value: &str, line_no: usize) -> Result<String, PrompterError> {
+    if string_literal_len(value) != Some(value.len()) {
+        return Err(PrompterError::parse(
+            line_no,
+            format!("{key} must be a string"),
+        ));
     }
-    out
+    decode_string_literal(value).map_err(|e| PrompterError::parse(line_no, format!("{key}: {e}")))
 }
 
-fn contains_closing_bracket_outside_quotes(s: &str) -> bool {
-    let mut in_str = false;
-    for c in s.chars() {
-        if c == '"' {
-            in_str = !in_str;
-        }
-        if !in_str && c == ']' {
-            return true;
-        }
+/// The value of one complete string literal. A `"basic"` string is
+/// unescaped with [`unescape`] and a `'literal'` one is taken as written.
+/// Multi-line `"""` and `'''` strings are decoded by the TOML parser, which
+/// also drops a newline right after the opening quotes.
+fn decode_string_literal(literal: &str) -> Result<String, String> {
+    if literal.starts_with("\"\"\"") || literal.starts_with("'''") {
+        let parsed: toml::Table = toml::from_str(&format!("s = {literal}"))
+            .map_err(|e| format!("Invalid multi-line string: {}", e.message()))?;
+        return match parsed.get("s") {
+            Some(toml::Value::String(s)) => Ok(s.clone()),
+            _ => Err("Invalid multi-line string".into()),
+        };
     }
-    false
+    let inner = &literal[1..literal.len() - 1];
+    Ok(if literal.starts_with('"') {
+        unescape(inner)
+    } else {
+        inner.to_string()
+    })
 }
 
-fn parse_array_items(s: &str) -> Result<Vec<String>, String> {
-    let mut items = Vec::new();
-    let mut in_str = false;
-    let mut buf = String::new();
+/// Length in bytes of the string literal `s` starts with, quotes included,
+/// or `None` if it does not start with one or it is unterminated.
+///
+/// As in TOML, a backslash escapes the next character in `"` and `"""`
+/// strings, `'` and `'''` strings have no escapes, and up to two quotes
+/// right before a closing `"""` or `'''` belong to the string.
+fn string_literal_len(s: &str) -> Option<usize> {
+    let quote = s.chars().next().filter(|&c| is_quote(c))?;
+    let bytes = s.as_bytes();
+    let q = bytes[0];
+    let basic = quote == '"';
+    let triple = [q; 3];
+    if bytes.starts_with(&triple) {
+        let mut i = 3;
+        while i < bytes.len() {
+            if basic && bytes[i] == b'\\' {
+                i += 2;
+            } else if bytes[i..].starts_with(&triple) {
+                let run = bytes[i..].iter().take_while(|&&b| b == q).count();
+                return Some(i + run.min(5));
+            } else {
+                i += 1;
+            }
+        }
+        return None;
+    }
     let mut escaped = false;
-    let mut started = false;
+    for (i, &b) in bytes.iter().enumerate().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if basic && b == b'\\' {
+            escaped = true;
+        } else if b == q {
+            return Some(i + 1);
+        }
+    }
+    None
+}
 
-    for c in s.chars() {
-        if !started {
-            if c == '[' {
-                started = true;
-            }
+/// Whether `line` ends inside a `"""` or `'''` string that continues on the
+/// next line.
+fn in_multiline_string(line: &str) -> bool {
+    let mut skip_to = 0;
+    for (i, c) in line.char_indices() {
+        if i < skip_to {
             continue;
         }
-        if c == ']' && !in_str {
-            break;
+        if c == '#' {
+            return false;
         }
-        if in_str {
-            if escaped {
-                buf.push(c);
-                escaped = false;
-                continue;
-            }
-            if c == '\\' {
-                escaped = true;
-                continue;
-            }
-            if c == '"' {
-                in_str = false;
-                items.push(buf.clone());
-                buf.clear();
-                continue;
-            }
-            buf.push(c);
-        } else if c == '"' {
-            in_str = true;
+        if is_quote(c) {
+            let rest = &line[i..];
+            let Some(len) = string_literal_len(rest) else {
+                return rest.starts_with("\"\"\"") || rest.starts_with("'''");
+            };
+            skip_to = i + len;
         }
     }
+    false
+}
 
-    if in_str {
-        return Err("Unterminated string in array".into());
+/// Config lines with their 1-based starting line numbers, where a multi-line
+/// string joins the lines it spans (separated by `\n`) into one.
+fn logical_lines(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut open = false;
+    for (idx, raw_line) in input.lines().enumerate() {
+        match lines.last_mut() {
+            Some((_, text)) if open => {
+                text.push('\n');
+                text.push_str(raw_line);
+            }
+            _ => lines.push((idx + 1, raw_line.to_string())),
+        }
+        open = lines
+            .last()
+            .is_some_and(|(_, text)| in_multiline_string(text));
     }
-    Ok(items)
+    lines
 }
 
-/// Errors that can occur during profile resolution.
-///
-/// These errors represent various failure modes when resolving
-/// profile dependencies and validating file references.
-#[derive(Debug, PartialEq, Eq)]
-pub enum ResolveError {
-    /// Referenced profile name does not exist in configuration
-    UnknownProfile(String),
-    /// Circular dependency detected in profile references
-    Cycle(Vec<String>),
-    /// Referenced markdown file does not exist
-    MissingFile(PathBuf, String), // (path, referenced_by)
+/// The time zone named by the quoted string `value`.
+fn timezone_value(value: &str, line_no: usize) -> Result<Timezone, PrompterError> {
+    let name = string_value("timezone", value, line_no)?;
+    Timezone::from_name(&name).ok_or_else(|| {
+        PrompterError::parse(
+            line_no,
+            format!("timezone must be \"utc\" or \"local\", not \"{name}\""),
+        )
+    })
 }
 
-/// Recursively resolve a profile's dependencies into a list of file paths.
-///
-/// Performs depth-first traversal of profile dependencies, handling both
-/// direct file references and recursive profile dependencies. Implements
-/// cycle detection and file deduplication.
-///
-/// # Arguments
-/// * `name` - Profile name to resolve
-/// * `cfg` - Configuration containing profile definitions
-/// * `lib` - Library root directory for resolving file paths
-/// * `seen_files` - Set tracking already included files for deduplication
-/// * `stack` - Stack for cycle detection during recursion
-/// * `out` - Output vector to collect resolved file paths
-///
-/// # Returns
-/// * `Ok(())` - Profile successfully resolved
-/// * `Err(ResolveError)` - Resolution failed due to missing files, cycles, or unknown profiles
-///
-/// # Errors
-/// Returns an error if:
-/// - Profile name is not found in configuration
-/// - Circular dependency is detected
-/// - Referenced markdown file does not exist
-#[allow(clippy::implicit_hasher)]
-pub fn resolve_profile(
-    name: &str,
-    cfg: &Config,
-    lib: &Path,
-    seen_files: &mut HashSet<PathBuf>,
-    stack: &mut Vec<String>,
-    out: &mut Vec<PathBuf>,
-) -> Result<(), ResolveError> {
-    if stack.contains(&name.to_string()) {
-        let mut cycle = stack.clone();
-        cycle.push(name.to_string());
-        return Err(ResolveError::Cycle(cycle));
-    }
-    let deps = cfg
-        .profiles
-        .get(name)
-        .ok_or_else(|| ResolveError::UnknownProfile(name.to_string()))?;
-    stack.push(name.to_string());
-    for dep in deps {
-        if std::path::Path::new(dep)
-            .extension()
-            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-        {
-            let path = lib.join(dep);
-            if !path.exists() {
-                return Err(ResolveError::MissingFile(path, name.to_string()));
-            }
-            if seen_files.insert(path.clone()) {
-                out.push(path);
-            }
-        } else {
-            resolve_profile(dep, cfg, lib, seen_files, stack, out)?;
+/// Apply a `rule = true|false` line from the `[lint]` table.
+fn set_lint_rule(
+    cfg: &mut Config,
+    key: &str,
+    value: &str,
+    line_no: usize,
+) -> Result<(), PrompterError> {
+    let rule = LintRule::from_name(key).ok_or_else(|| {
+        PrompterError::parse(
+            line_no,
+            format!("Unknown lint rule in [{LINT_SECTION}]: {key}"),
+        )
+    })?;
+    let enabled = match value {
+        "true" => true,
+        "false" => false,
+        _ => {
+            return Err(PrompterError::parse(
+                line_no,
+                format!("{key} must be true or false"),
+            ));
         }
-    }
-    stack.pop();
+    };
+    cfg.set_lint_rule(rule, enabled);
     Ok(())
 }
 
-/// List all available profiles to a writer.
-///
-/// Outputs all profile names from the configuration in alphabetical order,
-/// one per line.
-///
-/// # Arguments
-/// * `cfg` - Configuration containing profile definitions
-/// * `w` - Writer to output profile names to
-///
-/// # Returns
-/// * `Ok(())` - All profiles listed successfully
-/// * `Err(io::Error)` - Write operation failed
+/// Parse a complete `depends_on` or `tags` array and store it on the current
+/// profile, or a top-level `library_roots` array.
 ///
-/// # Errors
-/// Returns an error if writing to the output fails.
-pub fn list_profiles(cfg: &Config, mut w: impl Write) -> io::Result<()> {
-    let mut names: Vec<_> = cfg.profiles.keys().cloned().collect();
-    names.sort();
-    for n in names {
-        writeln!(&mut w, "{n}")?;
+/// `depends_on` entries may be inline tables; their `optional` flags are kept
+/// in the profile's [`ProfileMeta`].
+fn store_array(
+    key: &str,
+    buffer: &str,
+    current: Option<&String>,
+    profiles: &mut HashMap<String, Vec<String>>,
+    meta: &mut HashMap<String, ProfileMeta>,
+    library_roots: &mut Vec<String>,
+) -> Result<(), String> {
+    if key == "library_roots" {
+        if current.is_some() {
+            return Err("library_roots must come before the first profile section".into());
+        }
+        *library_roots =
+            parse_array_items(buffer).map_err(|e| format!("Invalid library_roots array: {e}"))?;
+        return Ok(());
+    }
+    let section = current.cloned().unwrap_or_default();
+    let invalid = |e: String| format!("Invalid {key} array for [{section}]: {e}");
+    let name = current
+        .cloned()
+        .ok_or_else(|| format!("{key} outside of a profile section"))?;
+    if key == "tags" {
+        let items = parse_array_items(buffer).map_err(invalid)?;
+        profiles.entry(name.clone()).or_default();
+        meta.entry(name).or_default().tags = items;
+    } else {
+        let entries = parse_array_entries(buffer).map_err(invalid)?;
+        let optional = entries
+            .iter()
+            .filter(|e| matches!(e, ArrayEntry::Dependency { optional: true, .. }))
+            .map(|e| e.value().to_string())
+            .collect();
+        meta.entry(name.clone()).or_default().optional = optional;
+        profiles.insert(
+            name,
+            entries.iter().map(|e| e.value().to_string()).collect(),
+        );
     }
     Ok(())
 }
 
-/// Validate configuration and library file references.
-///
-/// Checks that all profile dependencies are valid, including:
-/// - Referenced profiles exist in configuration
-/// - Referenced markdown files exist in library
-/// - No circular dependencies exist
-///
-/// # Arguments
-/// * `cfg` - Configuration to validate
-/// * `lib` - Library root directory for file validation
-///
-/// # Returns
-/// * `Ok(())` - Configuration is valid
-/// * `Err(String)` - Validation errors found
-///
-/// # Errors
-/// Returns an error if:
-/// - Referenced profiles don't exist
-/// - Referenced files don't exist
-/// - Circular dependencies are detected
-pub fn validate(cfg: &Config, lib: &Path) -> Result<(), String> {
-    let mut errors: Vec<String> = Vec::new();
+/// Keys that make a table a profile, even the `[lint]` table.
+const PROFILE_MARKER_KEYS: [&str; 3] = ["depends_on", "tags", "extends"];
 
-    for (profile, deps) in &cfg.profiles {
-        for dep in deps {
-            if std::path::Path::new(dep)
-                .extension()
-                .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-            {
-                let path = lib.join(dep);
-                if !path.exists() {
-                    errors.push(format!(
-                        "Missing file: {} (referenced by [{}])",
-                        path.display(),
-                        profile
-                    ));
+/// Whether the table `section` sets one of [`PROFILE_MARKER_KEYS`] or has
+/// `[[<section>.depends_on]]` entries, scanning the logical `lines` of a
+/// config.
+fn section_is_profile(lines: &[(usize, String)], section: &str) -> bool {
+    let mut current: Option<String> = None;
+    for (_, raw_line) in lines {
+        let line = strip_comments(raw_line).trim().to_string();
+        if line.starts_with("[[") {
+            if dependency_table_name(&line).as_deref() == Some(section) {
+                return true;
+            }
+            current = None;
+        } else if line.starts_with('[') && line.ends_with(']') {
+            current = Some(unquote_key(line[1..line.len() - 1].trim()));
+        } else if current.as_deref() == Some(section) {
+            if let Some((key, _)) = line.split_once('=') {
+                if PROFILE_MARKER_KEYS.contains(&key.trim()) {
+                    return true;
                 }
-            } else if !cfg.profiles.contains_key(dep) {
-                errors.push(format!(
-                    "Unknown profile: {dep} (referenced by [{profile}])"
-                ));
             }
         }
     }
+    false
+}
 
-    for name in cfg.profiles.keys() {
-        let mut seen_files = HashSet::new();
-        let mut stack = Vec::new();
-        let mut out = Vec::new();
-        if let Err(ResolveError::Cycle(cycle)) =
-            resolve_profile(name, cfg, lib, &mut seen_files, &mut stack, &mut out)
-        {
-            let chain = cycle.join(" -> ");
-            errors.push(format!("Cycle detected: {chain}"));
-        }
+/// The profile named by a `[[<profile>.depends_on]]` header, as written by
+/// the `toml` crate for a `depends_on` array made only of inline tables.
+fn dependency_table_name(header: &str) -> Option<String> {
+    let inner = header.strip_prefix("[[")?.strip_suffix("]]")?.trim();
+    let name = inner
+        .strip_suffix("depends_on")?
+        .trim_end()
+        .strip_suffix('.')?
+        .trim_end();
+    (!name.is_empty()).then(|| unquote_key(name))
+}
+
+/// Append the dependency described by the `key = value` `fields` of a
+/// `[[<profile>.depends_on]]` table to the profile `name`.
+fn store_dependency_table(
+    fields: &[String],
+    name: &str,
+    profiles: &mut HashMap<String, Vec<String>>,
+    meta: &mut HashMap<String, ProfileMeta>,
+) -> Result<(), String> {
+    let entry = parse_dependency_table(&format!("{{ {} }}", fields.join(", ")))
+        .map_err(|e| format!("Invalid depends_on table for [{name}]: {e}"))?;
+    if let ArrayEntry::Dependency {
+        path,
+        optional: true,
+    } = &entry
+    {
+        meta.entry(name.to_string())
+            .or_default()
+            .optional
+            .insert(path.clone());
     }
+    profiles
+        .entry(name.to_string())
+        .or_default()
+        .push(entry.value().to_string());
+    Ok(())
+}
 
-    if errors.is_empty() {
-        Ok(())
+/// A section name without its quotes: `"a.b"` (with escapes) and `'a.b'`
+/// both name the profile `a.b`, as in TOML.
+fn unquote_key(name: &str) -> String {
+    let quoted = |q: char| name.len() >= 2 && name.starts_with(q) && name.ends_with(q);
+    if quoted('"') {
+        unescape(&name[1..name.len() - 1])
+    } else if quoted('\'') {
+        name[1..name.len() - 1].to_string()
     } else {
-        Err(errors.join("\n"))
+        name.to_string()
     }
 }
 
-/// Initialize default configuration and library structure.
-///
-/// Creates the default directory structure and configuration files
-/// for prompter, including sample profiles and library files.
-/// Only creates files that don't already exist (non-destructive).
-///
-/// # Returns
-/// * `Ok(())` - Initialization completed successfully
-/// * `Err(String)` - Initialization failed
-///
-/// # Errors
-/// Returns an error if:
-/// - Directory creation fails
-/// - File writing fails
-/// - HOME environment variable is not set
-///
-/// # Panics
-/// Panics if the progress bar template is invalid (should not happen with the
-/// hardcoded template string).
-pub fn init_scaffold() -> Result<(), String> {
-    let pb = if is_terminal() {
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(
-            ProgressStyle::default_spinner()
-                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
-                .template("{spinner:.green} {msg}")
-                .unwrap(),
-        );
-        pb.set_message("Initializing prompter...");
-        pb.enable_steady_tick(std::time::Duration::from_millis(120));
-        Some(pb)
-    } else {
-        None
-    };
+/// Yield each character of `s` with whether it is outside a string (see
+/// [`string_literal_len`]). Quotes count as inside, and an unterminated
+/// string runs to the end of `s`.
+fn chars_outside_quotes(s: &str) -> impl Iterator<Item = (char, bool)> + '_ {
+    let mut string_end = 0;
+    s.char_indices().map(move |(i, c)| {
+        if i < string_end {
+            return (c, false);
+        }
+        if is_quote(c) {
+            string_end = i + string_literal_len(&s[i..]).unwrap_or(s.len() - i);
+            return (c, false);
+        }
+        (c, true)
+    })
+}
 
-    let cfg_path = config_path()?;
-    let cfg_dir = cfg_path
-        .parent()
-        .ok_or_else(|| "Invalid config path".to_string())?;
+const fn is_quote(c: char) -> bool {
+    matches!(c, '"' | '\'')
+}
 
-    if let Some(ref pb) = pb {
-        pb.set_message("Creating config directory...");
-    }
-    fs::create_dir_all(cfg_dir)
-        .map_err(|e| format!("Failed to create {}: {}", cfg_dir.display(), e))?;
+fn strip_comments(s: &str) -> String {
+    chars_outside_quotes(s)
+        .take_while(|&(c, outside)| !(outside && c == '#'))
+        .map(|(c, _)| c)
+        .collect()
+}
 
-    let lib = library_dir()?;
-    if let Some(ref pb) = pb {
-        pb.set_message("Creating library directory...");
-    }
-    fs::create_dir_all(&lib).map_err(|e| format!("Failed to create {}: {}", lib.display(), e))?;
+fn contains_closing_bracket_outside_quotes(s: &str) -> bool {
+    chars_outside_quotes(s).any(|(c, outside)| outside && c == ']')
+}
 
-    if !cfg_path.exists() {
-        if let Some(ref pb) = pb {
-            pb.set_message("Writing default config...");
+/// One entry of a config array: a plain string, or an inline dependency table
+/// such as `{ path = "a.md", optional = true }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ArrayEntry {
+    Plain(String),
+    Dependency { path: String, optional: bool },
+}
+
+impl ArrayEntry {
+    fn value(&self) -> &str {
+        match self {
+            Self::Plain(value) | Self::Dependency { path: value, .. } => value,
         }
-        let default_cfg = r#"# Prompter configuration
-# Profiles map to sets of markdown files and/or other profiles.
-# Files are relative to $HOME/.local/prompter/library
+    }
+}
 
-[python.api]
-depends_on = ["a/b/c.md", "f/g/h.md"]
+/// Parse an array of strings, rejecting inline tables.
+fn parse_array_items(s: &str) -> Result<Vec<String>, String> {
+    parse_array_entries(s)?
+        .into_iter()
+        .map(|entry| match entry {
+            ArrayEntry::Plain(value) => Ok(value),
+            ArrayEntry::Dependency { .. } => Err("inline tables are not allowed here".to_string()),
+        })
+        .collect()
+}
 
-[general.testing]
-depends_on = ["python.api", "a/b/d.md"]
-"#;
-        fs::write(&cfg_path, default_cfg)
-            .map_err(|e| format!("Failed to write {}: {}", cfg_path.display(), e))?;
+/// Parse an array whose entries are strings or inline dependency tables.
+///
+/// Strings may be `"basic"`, `'literal'` (no escapes, as in TOML), or
+/// multi-line, and a trailing comma is allowed.
+fn parse_array_entries(s: &str) -> Result<Vec<ArrayEntry>, String> {
+    let mut items = Vec::new();
+    let Some(open) = s.find('[') else {
+        return Ok(items);
+    };
+    let mut rest = &s[open + 1..];
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            ']' => break,
+            '{' => {
+                let len = inline_table_len(rest)
+                    .ok_or_else(|| "Unterminated inline table in array".to_string())?;
+                items.push(parse_dependency_table(&rest[..len])?);
+                len
+            }
+            c if is_quote(c) => {
+                let len = string_literal_len(rest)
+                    .ok_or_else(|| "Unterminated string in array".to_string())?;
+                items.push(ArrayEntry::Plain(decode_string_literal(&rest[..len])?));
+                len
+            }
+            c => c.len_utf8(),
+        };
+        rest = &rest[len..];
     }
+    Ok(items)
+}
 
-    let paths_and_contents: Vec<(PathBuf, &str)> = vec![
-        (
-            lib.join("a/b/c.md"),
-            "# a/b/c.md\nExample snippet for python.api.\n",
-        ),
-        (lib.join("a/b.md"), "# a/b.md\nFolder-level notes.\n"),
-        (
-            lib.join("a/b/d.md"),
-            "# a/b/d.md\nGeneral testing snippet.\n",
-        ),
-        (lib.join("f/g/h.md"), "# f/g/h.md\nShared helper snippet.\n"),
-    ];
+/// Length in bytes of the inline table `s` starts with, up to and including
+/// its closing `}`.
+fn inline_table_len(s: &str) -> Option<usize> {
+    s.char_indices()
+        .zip(chars_outside_quotes(s))
+        .find(|&(_, (c, outside))| outside && c == '}')
+        .map(|((i, _), _)| i + 1)
+}
 
-    for (path, contents) in paths_and_contents {
-        if let Some(ref pb) = pb {
-            pb.set_message(format!(
-                "Creating {}",
-                path.file_name().unwrap_or_default().to_string_lossy()
-            ));
-        }
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
-        }
-        if !path.exists() {
-            fs::write(&path, contents)
-                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+/// Parse `{ path = "...", optional = true }` into a dependency entry.
+fn parse_dependency_table(text: &str) -> Result<ArrayEntry, String> {
+    let parsed: toml::Table = toml::from_str(&format!("dep = {text}"))
+        .map_err(|e| format!("Invalid inline table {text}: {e}"))?;
+    let Some(toml::Value::Table(fields)) = parsed.get("dep") else {
+        return Err(format!("Invalid inline table {text}"));
+    };
+    let mut path = None;
+    let mut optional = false;
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("path", toml::Value::String(p)) => path = Some(p.clone()),
+            ("optional", toml::Value::Boolean(b)) => optional = *b,
+            ("path", _) => return Err("path must be a string".into()),
+            ("optional", _) => return Err("optional must be a boolean".into()),
+            (other, _) => return Err(format!("Unknown key '{other}' in {text}")),
         }
     }
+    let path = path.ok_or_else(|| format!("Missing path in {text}"))?;
+    Ok(ArrayEntry::Dependency { path, optional })
+}
 
-    if let Some(pb) = pb {
-        pb.finish_with_message("Initialization complete!");
-        std::thread::sleep(std::time::Duration::from_millis(200)); // Brief pause to show completion
-    }
+fn is_markdown_dep(dep: &str) -> bool {
+    has_markdown_extension(dep_base(dep))
+}
 
-    println!(
-        "{}",
-        success_message(&format!("Initialized config at {}", cfg_path.display()))
-    );
-    println!(
-        "{}",
-        info_message(&format!("Library root at {}", lib.display()))
-    );
-    Ok(())
+fn has_markdown_extension(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
 }
 
-/// List profiles to stdout.
+/// `path` with `.` components dropped and each `..` folded into the
+/// component before it, without touching the file system.
 ///
-/// Convenience function that reads configuration and lists all profiles
-/// to standard output.
+/// Used to compare paths that name the same file differently, such as
+/// `sub/../a.md` and `a.md`.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => match out.components().next_back() {
+                Some(std::path::Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(std::path::Component::RootDir | std::path::Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Whether an existing `file` lies outside `lib` once `..` components and
+/// symlinks are resolved.
+fn escapes(lib: &Path, file: &Path) -> bool {
+    let root = fs::canonicalize(lib).unwrap_or_else(|_| lib.to_path_buf());
+    fs::canonicalize(file).is_ok_and(|real| !real.starts_with(root))
+}
+
+/// Whether a file dependency points outside the library: an absolute path or
+/// one starting with `~/`.
+fn is_external_dep(dep: &str) -> bool {
+    dep.starts_with("~/") || Path::new(dep).is_absolute()
+}
+
+/// Where a file dependency lives on disk: `~/` is expanded against `$HOME`,
+/// absolute paths are kept, and anything else is joined onto `lib`.
+fn dep_path(lib: &Path, dep: &str) -> PathBuf {
+    dep.strip_prefix("~/").map_or_else(
+        || lib.join(dep),
+        |rest| home_dir().map_or_else(|_| PathBuf::from(dep), |home| home.join(rest)),
+    )
+}
+
+/// The first of `libs` holding `dep`, with the file's path inside it.
+fn find_in_roots<'a>(libs: &[&'a Path], dep: &str) -> Option<(&'a Path, PathBuf)> {
+    libs.iter()
+        .map(|lib| (*lib, dep_path(lib, dep)))
+        .find(|(_, file)| file.exists())
+}
+
+/// Strip a `#L..` line-range fragment from a markdown dependency, if present.
+fn dep_base(dep: &str) -> &str {
+    match dep.rsplit_once('#') {
+        Some((base, _)) if has_markdown_extension(base) => base,
+        _ => dep,
+    }
+}
+
+/// An inclusive, 1-based range of lines selected from a file dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LineRange {
+    /// First line to include
+    pub start: usize,
+    /// Last line to include
+    pub end: usize,
+}
+
+impl std::fmt::Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "L{}", self.start)
+        } else {
+            write!(f, "L{}-L{}", self.start, self.end)
+        }
+    }
+}
+
+/// Split a dependency such as `file.md#L10-L40` or `file.md#L10` into its
+/// base path and optional line range.
 ///
-/// # Returns
-/// * `Ok(())` - Profiles listed successfully
-/// * `Err(String)` - Operation failed
+/// Dependencies without a fragment are returned unchanged.
 ///
 /// # Errors
-/// Returns an error if:
-/// - Configuration file cannot be read or parsed
-/// - Writing to stdout fails
-pub fn run_list_stdout(config_override: Option<&Path>) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    list_profiles(&cfg, io::stdout()).map_err(|e| e.to_string())
+/// Returns an error if the fragment is not of the form `L<n>` or `L<n>-L<m>`
+/// with `1 <= n <= m`.
+pub fn split_line_range(dep: &str) -> Result<(&str, Option<LineRange>), String> {
+    let base = dep_base(dep);
+    if base.len() == dep.len() {
+        return Ok((dep, None));
+    }
+    let fragment = &dep[base.len() + 1..];
+    let invalid =
+        || format!("Invalid line range '#{fragment}' in {dep} (expected #L<start>-L<end>)");
+    let line = |s: &str| {
+        s.strip_prefix('L')
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+    };
+    let (start, end) = match fragment.split_once('-') {
+        Some((a, b)) => (line(a), line(b)),
+        None => (line(fragment), line(fragment)),
+    };
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => Ok((base, Some(LineRange { start, end }))),
+        _ => Err(invalid()),
+    }
 }
 
-/// Validate configuration and output results to stdout.
-///
-/// Convenience function that reads configuration and validates it,
-/// outputting any errors found.
+/// A resolved file as shown to people: its path, with the `#L..` fragment
+/// back on the file name when only a range of its lines is used.
+fn labeled_path(path: &Path, range: Option<LineRange>) -> PathBuf {
+    match (range, path.file_name()) {
+        (Some(range), Some(name)) => {
+            let mut name = name.to_os_string();
+            name.push(format!("#{range}"));
+            path.with_file_name(name)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Read a resolved file, keeping only the lines in `range` if given.
+fn read_snippet(path: &Path, range: Option<LineRange>) -> Result<Vec<u8>, String> {
+    Ok(SnippetFile::read(path, range)?.snippet()?.into_owned())
+}
+
+/// A resolved file read whole into memory, before its line range is applied.
+struct SnippetFile {
+    path: PathBuf,
+    range: Option<LineRange>,
+    /// The file's bytes as read, including any byte order mark
+    raw: Vec<u8>,
+}
+
+impl SnippetFile {
+    fn read(path: &Path, range: Option<LineRange>) -> Result<Self, String> {
+        let raw =
+            fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            range,
+            raw,
+        })
+    }
+
+    /// The bytes [`read_snippet`] returns: without a byte order mark, and
+    /// only the selected lines.
+    fn snippet(&self) -> Result<Cow<'_, [u8]>, String> {
+        let bytes = strip_bom(&self.raw);
+        self.range.map_or(Ok(Cow::Borrowed(bytes)), |range| {
+            select_lines(bytes, range, &self.path).map(Cow::Owned)
+        })
+    }
+
+    /// The same hash as [`snippet_digest`].
+    fn digest(&self) -> Result<Vec<u8>, String> {
+        if self.range.is_some() {
+            Ok(Sha256::digest(self.snippet()?).to_vec())
+        } else {
+            Ok(Sha256::digest(&self.raw).to_vec())
+        }
+    }
+}
+
+/// Profiles with at least this many files have them read on several threads.
+const PARALLEL_READ_MIN_FILES: usize = 32;
+
+/// Most threads used to read a profile's files.
+const MAX_READERS: usize = 8;
+
+/// Read every file in `files` on up to [`MAX_READERS`] threads, returning
+/// the results in `files` order.
+fn read_snippets_parallel(
+    files: &[(PathBuf, Option<LineRange>)],
+) -> Vec<Result<SnippetFile, String>> {
+    let readers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(MAX_READERS);
+    let chunk_len = files.len().div_ceil(readers).max(1);
+    thread::scope(|scope| {
+        // Spawn every reader before joining the first
+        #[allow(clippy::needless_collect)]
+        let readers: Vec<_> = files
+            .chunks(chunk_len)
+            .map(|chunk| {
+                scope.spawn(|| {
+                    chunk
+                        .iter()
+                        .map(|(path, range)| SnippetFile::read(path, *range))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        readers
+            .into_iter()
+            .flat_map(|reader| reader.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+/// Stream a resolved file into `w`, substituting `vars`.
 ///
-/// # Returns
-/// * `Ok(())` - Configuration is valid
-/// * `Err(String)` - Validation errors found
+/// Whole files are copied through a buffered reader so memory stays bounded
+/// regardless of file size; only `#L..` line ranges are read into memory.
+/// With variables set the file is copied a line at a time, since a
+/// placeholder never spans lines.
+fn copy_snippet(
+    path: &Path,
+    range: Option<LineRange>,
+    vars: &BTreeMap<String, String>,
+    w: &mut impl Write,
+) -> Result<(), String> {
+    if range.is_some() {
+        let bytes = substitute_vars(&read_snippet(path, range)?, vars);
+        return w.write_all(&bytes).map_err(|e| format!("Write error: {e}"));
+    }
+    let file =
+        fs::File::open(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let mut reader = io::BufReader::new(file);
+    // Skip a byte order mark so it never lands in the middle of the prompt
+    let head = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    if head.starts_with(UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+    }
+    let copied = if vars.is_empty() {
+        io::copy(&mut reader, w).map(|_| ())
+    } else {
+        copy_substituted(&mut reader, vars, w)
+    };
+    copied.map_err(|e| format!("Failed to copy {}: {e}", path.display()))
+}
+
+/// Copy `reader` into `w` a line at a time, substituting `vars` in each line.
+fn copy_substituted(
+    reader: &mut impl BufRead,
+    vars: &BTreeMap<String, String>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        w.write_all(&substitute_vars(&line, vars))?;
+        line.clear();
+    }
+    Ok(())
+}
+
+/// The UTF-8 byte order mark some editors put at the start of a file.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Remove a leading UTF-8 byte order mark from `bytes`.
 ///
-/// # Errors
-/// Returns an error if:
-/// - Configuration file cannot be read or parsed
-/// - Validation finds missing files or circular dependencies
-pub fn run_validate_stdout(config_override: Option<&Path>) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    let lib = library_path_for_config_override(config_override, &cfg_path)?;
-    validate(&cfg, &lib)
+/// ```
+/// use prompter::strip_bom;
+/// assert_eq!(strip_bom(b"\xEF\xBB\xBFHello"), b"Hello");
+/// assert_eq!(strip_bom(b"Hello"), b"Hello");
+/// ```
+#[must_use]
+pub fn strip_bom(bytes: &[u8]) -> &[u8] {
+    bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes)
 }
 
-/// Render a profile's content to a writer.
+/// Remove a leading frontmatter block from `bytes`.
 ///
-/// Resolves profile dependencies and writes the concatenated content
-/// to the provided writer, including pre-prompt, system info, file
-/// contents with optional separators, and post-prompt.
+/// A block opens with a `---` (YAML) or `+++` (TOML) line at the very start
+/// and closes at the next line holding the same delimiter. Content without an
+/// opening delimiter, or whose block never closes, is returned unchanged.
 ///
-/// # Arguments
-/// * `cfg` - Configuration containing profile definitions
-/// * `lib` - Library root directory for file resolution
-/// * `w` - Writer to output rendered content to
-/// * `profile` - Profile name to render
-/// * `separator` - Optional separator between files
-/// * `pre_prompt` - Optional custom pre-prompt (defaults to LLM instructions)
-/// * `post_prompt` - Optional custom post-prompt (defaults to @AGENTS/@CLAUDE instructions)
+/// ```
+/// use prompter::strip_frontmatter;
+/// assert_eq!(strip_frontmatter(b"---\ntitle: x\n---\nBody\n"), b"Body\n");
+/// assert_eq!(strip_frontmatter(b"Body\n---\n"), b"Body\n---\n");
+/// ```
+#[must_use]
+pub fn strip_frontmatter(bytes: &[u8]) -> &[u8] {
+    fn trim(line: &[u8]) -> &[u8] {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        line.strip_suffix(b"\r").unwrap_or(line)
+    }
+
+    let mut lines = bytes.split_inclusive(|&b| b == b'\n');
+    let Some(first) = lines.next() else {
+        return bytes;
+    };
+    let delimiter = trim(first);
+    if delimiter != b"---" && delimiter != b"+++" {
+        return bytes;
+    }
+    let mut offset = first.len();
+    for line in lines {
+        offset += line.len();
+        if trim(line) == delimiter {
+            return &bytes[offset..];
+        }
+    }
+    bytes
+}
+
+/// Strip trailing whitespace from every line, keeping each line ending as is.
 ///
-/// # Returns
-/// * `Ok(())` - Profile rendered successfully
-/// * `Err(String)` - Rendering failed
+/// ```
+/// use prompter::trim_trailing_whitespace;
+/// assert_eq!(trim_trailing_whitespace(b"a  \r\nb\t\nc "), b"a\r\nb\nc");
+/// ```
+#[must_use]
+pub fn trim_trailing_whitespace(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        let ending: &[u8] = if line.ends_with(b"\r\n") {
+            b"\r\n"
+        } else if line.ends_with(b"\n") {
+            b"\n"
+        } else {
+            b""
+        };
+        out.extend_from_slice(line[..line.len() - ending.len()].trim_ascii_end());
+        out.extend_from_slice(ending);
+    }
+    out
+}
+
+/// Convert `\r\n` line endings to `\n`; lone `\r` bytes are kept.
 ///
-/// # Errors
-/// Returns an error if:
-/// - Profile resolution fails (missing files, cycles, unknown profiles)
-/// - Writing to output fails
-/// - File reading fails
-pub fn render_to_writer(
-    cfg: &Config,
-    lib: &Path,
-    mut w: impl Write,
-    profile: &str,
-    separator: Option<&str>,
-    pre_prompt: Option<&str>,
-    post_prompt: Option<&str>,
-) -> Result<(), String> {
-    let mut seen_files = HashSet::new();
-    let mut stack = Vec::new();
-    let mut files = Vec::new();
-    resolve_profile(profile, cfg, lib, &mut seen_files, &mut stack, &mut files).map_err(
-        |e| match e {
-            ResolveError::UnknownProfile(p) => format!("Unknown profile: {p}"),
-            ResolveError::Cycle(c) => format!("Cycle detected: {}", c.join(" -> ")),
-            ResolveError::MissingFile(path, prof) => format!(
+/// ```
+/// use prompter::normalize_line_endings;
+/// assert_eq!(normalize_line_endings(b"a\r\nb\rc\n"), b"a\nb\rc\n");
+/// ```
+#[must_use]
+pub fn normalize_line_endings(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        match line.strip_suffix(b"\r\n") {
+            Some(body) => {
+                out.extend_from_slice(body);
+                out.push(b'\n');
+            }
+            None => out.extend_from_slice(line),
+        }
+    }
+    out
+}
+
+/// SHA-256 of a resolved file's content, streamed like [`copy_snippet`].
+fn snippet_digest(path: &Path, range: Option<LineRange>) -> Result<Vec<u8>, String> {
+    if range.is_some() {
+        let bytes = read_snippet(path, range)?;
+        return Ok(Sha256::digest(&bytes).to_vec());
+    }
+    let file =
+        fs::File::open(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut io::BufReader::new(file), &mut hasher)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Keep only the lines of `bytes` that fall inside `range`.
+fn select_lines(bytes: &[u8], range: LineRange, path: &Path) -> Result<Vec<u8>, String> {
+    let lines: Vec<&[u8]> = bytes.split_inclusive(|&b| b == b'\n').collect();
+    if range.end > lines.len() {
+        return Err(format!(
+            "Line range {range} out of range for {} ({} lines)",
+            path.display(),
+            lines.len()
+        ));
+    }
+    Ok(lines[range.start - 1..range.end].concat())
+}
+
+/// Errors that can occur during profile resolution.
+///
+/// These errors represent various failure modes when resolving
+/// profile dependencies and validating file references.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// Referenced profile name does not exist in configuration
+    UnknownProfile(String),
+    /// Circular dependency detected in profile references
+    Cycle(Vec<String>),
+    /// Referenced markdown file does not exist
+    MissingFile(PathBuf, String), // (path, referenced_by)
+    /// Profile nesting went deeper than the configured maximum depth
+    DepthExceeded(usize),
+    /// A `#L..` line-range fragment could not be parsed
+    InvalidLineRange(String, String), // (dependency, referenced_by)
+    /// A path referenced an undefined environment variable under `strict_env`
+    UndefinedVariable(String, String), // (variable, referenced_by)
+    /// A path outside the library was used without `allow_external`
+    ExternalPath(String, String), // (dependency, referenced_by)
+    /// A library-relative path escaped the library through `..` or a symlink
+    OutsideLibrary(PathBuf),
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownProfile(p) => write!(f, "Unknown profile: {p}"),
+            Self::Cycle(c) => write!(f, "Cycle detected: {}", c.join(" -> ")),
+            Self::DepthExceeded(max) => {
+                write!(
+                    f,
+                    "Profile nesting too deep: exceeds maximum depth of {max}"
+                )
+            }
+            Self::MissingFile(path, prof) => write!(
+                f,
                 "Missing file: {} (referenced by [{}])",
                 path.display(),
                 prof
             ),
-        },
-    )?;
+            Self::InvalidLineRange(dep, prof) => {
+                write!(f, "Invalid line range in {dep} (referenced by [{prof}])")
+            }
+            Self::UndefinedVariable(var, prof) => write!(
+                f,
+                "Undefined environment variable: {var} (referenced by [{prof}])"
+            ),
+            Self::ExternalPath(dep, prof) => write!(
+                f,
+                "File outside the library: {dep} (referenced by [{prof}]); \
+                 pass --allow-external to include it"
+            ),
+            Self::OutsideLibrary(path) => write!(
+                f,
+                "Path escapes the library: {}; pass --allow-external to include it",
+                path.display()
+            ),
+        }
+    }
+}
 
-    // Write pre-prompt (defaults if not provided)
-    let default_pre = default_pre_prompt();
-    let pre_prompt_text = pre_prompt.unwrap_or(&default_pre);
-    w.write_all(pre_prompt_text.as_bytes())
-        .map_err(|e| format!("Write error: {e}"))?;
+impl std::error::Error for ResolveError {}
 
-    // Write system prefix with two newlines before
-    w.write_all(b"\n")
-        .map_err(|e| format!("Write error: {e}"))?;
-    let prefix = format_system_prefix();
-    w.write_all(prefix.as_bytes())
-        .map_err(|e| format!("Write error: {e}"))?;
-
-    let sep = separator.unwrap_or("");
-    for path in files {
-        // Two newlines before each file
-        w.write_all(b"\n")
-            .map_err(|e| format!("Write error: {e}"))?;
+/// Errors returned by the library's config, validation and render API.
+///
+/// `Display` gives the same messages the CLI prints, so callers that only
+/// report errors can keep formatting them; callers that need to react to a
+/// particular failure can match on the variant instead.
+#[derive(Debug)]
+pub enum PrompterError {
+    /// The config file (or stdin, shown as `-`) could not be read
+    ConfigRead {
+        /// Path of the config that failed to read
+        path: PathBuf,
+        /// Underlying I/O error
+        source: io::Error,
+    },
+    /// The config text is malformed
+    ConfigParse {
+        /// 1-based line where the problem was found, when known
+        line: Option<usize>,
+        /// Description of the problem
+        message: String,
+    },
+    /// A profile failed to resolve
+    Resolve(ResolveError),
+    /// `validate` found problems, one message per finding
+    Invalid(Vec<String>),
+    /// Reading or writing failed outside of rendering
+    Io(io::Error),
+    /// Rendering failed after resolution, e.g. a write error or `--limit-bytes`
+    Render(String),
+    /// Any other failure, described by its message
+    Other(String),
+}
+
+impl PrompterError {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        Self::ConfigParse {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+}
 
-        match fs::read(&path) {
-            Ok(bytes) => w
-                .write_all(&bytes)
-                .map_err(|e| format!("Write error: {e}"))?,
-            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+impl std::fmt::Display for PrompterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConfigRead { path, source } if is_stdin_config(path) => {
+                write!(f, "Failed to read config from stdin: {source}")
+            }
+            Self::ConfigRead { path, source } => {
+                write!(f, "Failed to read {}: {source}", path.display())
+            }
+            Self::ConfigParse { message, .. } | Self::Render(message) | Self::Other(message) => {
+                f.write_str(message)
+            }
+            Self::Resolve(e) => e.fmt(f),
+            Self::Invalid(errors) => f.write_str(&errors.join("\n")),
+            Self::Io(e) => e.fmt(f),
         }
+    }
+}
 
-        // Write separator after each file if provided
-        if !sep.is_empty() {
-            w.write_all(sep.as_bytes())
-                .map_err(|e| format!("Write error: {e}"))?;
+impl std::error::Error for PrompterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ConfigRead { source, .. } => Some(source),
+            Self::Resolve(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
         }
     }
+}
 
-    // Write post-prompt (defaults if not provided)
-    let default_post = default_post_prompt();
-    let post_prompt_text = post_prompt
-        .or(cfg.post_prompt.as_deref())
-        .unwrap_or(&default_post);
+impl From<ResolveError> for PrompterError {
+    fn from(e: ResolveError) -> Self {
+        Self::Resolve(e)
+    }
+}
 
-    // Two newlines before post-prompt
-    w.write_all(b"\n\n")
-        .map_err(|e| format!("Write error: {e}"))?;
-    w.write_all(post_prompt_text.as_bytes())
-        .map_err(|e| format!("Write error: {e}"))?;
+impl From<io::Error> for PrompterError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
 
-    Ok(())
+impl From<String> for PrompterError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
 }
 
-/// Render a profile to stdout.
+impl From<PrompterError> for String {
+    fn from(e: PrompterError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Default maximum profile nesting depth used during resolution.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Options controlling how profiles are resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveOptions {
+    /// Maximum profile nesting depth; deeper chains fail with
+    /// [`ResolveError::DepthExceeded`] instead of recursing further
+    pub max_depth: usize,
+    /// Fail with [`ResolveError::UndefinedVariable`] instead of leaving
+    /// undefined `$VAR` references in file paths untouched
+    pub strict_env: bool,
+    /// Warn on stderr and leave out missing files instead of failing with
+    /// [`ResolveError::MissingFile`]
+    pub skip_missing: bool,
+    /// Accept absolute and `~/` file paths, and relative ones that leave the
+    /// library through `..` or a symlink, instead of failing with
+    /// [`ResolveError::ExternalPath`] or [`ResolveError::OutsideLibrary`]
+    pub allow_external: bool,
+    /// Files or profiles appended to the resolved profile's own `depends_on`
+    pub add: Vec<String>,
+    /// `depends_on` entries (files or profiles) skipped wherever they appear,
+    /// matched exactly before any recursion, as are `extends` parents
+    pub drop: Vec<String>,
+}
+
+impl Default for ResolveOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            strict_env: false,
+            skip_missing: false,
+            allow_external: false,
+            add: Vec::new(),
+            drop: Vec::new(),
+        }
+    }
+}
+
+/// Recursively resolve a profile's dependencies into a list of file paths.
 ///
-/// Convenience function that reads configuration and renders the specified
-/// profile to standard output with optional separator, pre-prompt, and post-prompt.
+/// Performs depth-first traversal of profile dependencies, handling both
+/// direct file references and recursive profile dependencies. Implements
+/// cycle detection and file deduplication. Uses the default
+/// [`ResolveOptions`]; see [`resolve_profile_with`] to customize them.
+///
+/// Each file is collected as its on-disk path and the [`LineRange`] selected
+/// by a fragment such as `style.md#L10-L40`, if any; the same file with
+/// different ranges is collected once per range.
 ///
 /// # Arguments
-/// * `profile` - Profile name to render
-/// * `separator` - Optional separator between files
-/// * `pre_prompt` - Optional custom pre-prompt text
-/// * `post_prompt` - Optional custom post-prompt text
+/// * `name` - Profile name to resolve
+/// * `cfg` - Configuration containing profile definitions
+/// * `libs` - Library roots searched in order for each file; the first one
+///   that has the file wins (see [`Config::search_roots`])
+/// * `seen_files` - Set tracking already included files for deduplication
+/// * `stack` - Stack for cycle detection during recursion
+/// * `out` - Output vector to collect resolved files and their line ranges
 ///
 /// # Returns
-/// * `Ok(())` - Profile rendered successfully
-/// * `Err(String)` - Rendering failed
+/// * `Ok(())` - Profile successfully resolved
+/// * `Err(ResolveError)` - Resolution failed due to missing files, cycles, or unknown profiles
 ///
 /// # Errors
 /// Returns an error if:
-/// - Configuration file cannot be read or parsed
-/// - Profile resolution fails
-/// - Writing to stdout fails
-pub fn run_render_stdout(
-    profile: &str,
-    separator: Option<&str>,
-    pre_prompt: Option<&str>,
-    post_prompt: Option<&str>,
-    config_override: Option<&Path>,
-) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    let lib = library_path_for_config_override(config_override, &cfg_path)?;
-    let stdout = io::stdout();
-    let handle = stdout.lock();
-    render_to_writer(
-        &cfg,
-        &lib,
-        handle,
-        profile,
-        separator,
-        pre_prompt,
-        post_prompt,
-    )
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn mk_tmp(prefix: &str) -> PathBuf {
+/// - Profile name is not found in configuration
+/// - Circular dependency is detected
+/// - Referenced markdown file does not exist
+/// - Profile nesting exceeds [`DEFAULT_MAX_DEPTH`]
+#[allow(clippy::implicit_hasher)]
+pub fn resolve_profile(
+    name: &str,
+    cfg: &Config,
+    libs: &[&Path],
+    seen_files: &mut HashSet<(PathBuf, Option<LineRange>)>,
+    stack: &mut Vec<String>,
+    out: &mut Vec<(PathBuf, Option<LineRange>)>,
+) -> Result<(), ResolveError> {
+    resolve_profile_with(
+        name,
+        cfg,
+        libs,
+        &ResolveOptions::default(),
+        seen_files,
+        stack,
+        out,
+    )
+}
+
+/// Recursively resolve a profile's dependencies using explicit options.
+///
+/// Behaves like [`resolve_profile`] but honors the supplied [`ResolveOptions`].
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile name is not found in configuration
+/// - Circular dependency is detected
+/// - Referenced markdown file does not exist
+/// - Profile nesting exceeds `opts.max_depth`
+#[allow(clippy::implicit_hasher)]
+pub fn resolve_profile_with(
+    name: &str,
+    cfg: &Config,
+    libs: &[&Path],
+    opts: &ResolveOptions,
+    seen_files: &mut HashSet<(PathBuf, Option<LineRange>)>,
+    stack: &mut Vec<String>,
+    out: &mut Vec<(PathBuf, Option<LineRange>)>,
+) -> Result<(), ResolveError> {
+    if stack.contains(&name.to_string()) {
+        let mut cycle = stack.clone();
+        cycle.push(name.to_string());
+        return Err(ResolveError::Cycle(cycle));
+    }
+    if stack.len() >= opts.max_depth {
+        return Err(ResolveError::DepthExceeded(opts.max_depth));
+    }
+    let deps = cfg
+        .profiles
+        .get(name)
+        .ok_or_else(|| ResolveError::UnknownProfile(name.to_string()))?;
+    stack.push(name.to_string());
+    if let Some(parent) = cfg.extends.get(name).filter(|p| !opts.drop.contains(p)) {
+        resolve_profile_with(parent, cfg, libs, opts, seen_files, stack, out)?;
+    }
+    for raw_dep in deps {
+        if opts.drop.contains(raw_dep) {
+            continue;
+        }
+        if is_markdown_dep(raw_dep) {
+            let dep = match expand_vars(raw_dep, |var| env::var(var).ok()) {
+                (_, Some(var)) if opts.strict_env => {
+                    return Err(ResolveError::UndefinedVariable(var, name.to_string()));
+                }
+                (expanded, _) => expanded,
+            };
+            if is_external_dep(&dep) && !opts.allow_external {
+                return Err(ResolveError::ExternalPath(dep, name.to_string()));
+            }
+            let (base, range) = split_line_range(&dep)
+                .map_err(|_| ResolveError::InvalidLineRange(dep.clone(), name.to_string()))?;
+            let Some((lib, file)) = find_in_roots(libs, base) else {
+                let file = dep_path(libs.first().copied().unwrap_or_else(|| Path::new("")), base);
+                if cfg.is_optional(name, raw_dep) {
+                    continue;
+                }
+                if opts.skip_missing {
+                    eprintln!(
+                        "Warning: skipping missing file {} (referenced by [{name}])",
+                        file.display()
+                    );
+                    continue;
+                }
+                return Err(ResolveError::MissingFile(file, name.to_string()));
+            };
+            if !opts.allow_external && escapes(lib, &file) {
+                return Err(ResolveError::OutsideLibrary(file));
+            }
+            if seen_files.insert((file.clone(), range)) {
+                out.push((file, range));
+            }
+        } else {
+            resolve_profile_with(raw_dep, cfg, libs, opts, seen_files, stack, out)?;
+        }
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Resolve a profile into its ordered, deduplicated list of file paths.
+///
+/// This is the high-level entry point for profile resolution. It owns the
+/// scratch collections needed by [`resolve_profile`] and returns the files in
+/// depth-first `depends_on` order, keeping only the first occurrence of each path.
+/// Line ranges are dropped; see [`resolve_ranges`] to keep them.
+///
+/// # Arguments
+/// * `cfg` - Configuration containing profile definitions
+/// * `lib` - Library root directory for resolving file paths, searched
+///   before the config's `library_roots`
+/// * `profile` - Profile name to resolve
+///
+/// # Returns
+/// * `Ok(Vec<PathBuf>)` - Resolved file paths in render order
+/// * `Err(ResolveError)` - Resolution failed due to missing files, cycles, or unknown profiles
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile name is not found in configuration
+/// - Circular dependency is detected
+/// - Referenced markdown file does not exist
+///
+/// # Examples
+/// ```
+/// use prompter::{parse_config_toml, resolve};
+///
+/// let lib = tempfile::tempdir().unwrap();
+/// std::fs::write(lib.path().join("a.md"), "A").unwrap();
+/// std::fs::write(lib.path().join("b.md"), "B").unwrap();
+///
+/// let cfg = parse_config_toml(
+///     "[base]\ndepends_on = [\"a.md\"]\n[app]\ndepends_on = [\"base\", \"b.md\", \"a.md\"]\n",
+/// )
+/// .unwrap();
+/// let files = resolve(&cfg, lib.path(), "app").unwrap();
+/// assert_eq!(files, vec![lib.path().join("a.md"), lib.path().join("b.md")]);
+/// ```
+pub fn resolve(cfg: &Config, lib: &Path, profile: &str) -> Result<Vec<PathBuf>, ResolveError> {
+    resolve_with(cfg, lib, profile, &ResolveOptions::default())
+}
+
+/// Resolve a profile like [`resolve_with`], keeping the [`LineRange`] each
+/// file's `#L..` fragment selects, if any.
+///
+/// A file used with several ranges appears once per range.
+///
+/// # Errors
+/// Returns the same errors as [`resolve_with`].
+///
+/// # Examples
+/// ```
+/// use prompter::{LineRange, ResolveOptions, parse_config_toml, resolve_ranges};
+///
+/// let lib = tempfile::tempdir().unwrap();
+/// std::fs::write(lib.path().join("a.md"), "1\n2\n3\n").unwrap();
+/// let cfg = parse_config_toml("[p]\ndepends_on = [\"a.md#L2-L3\"]\n").unwrap();
+/// let files = resolve_ranges(&cfg, lib.path(), "p", &ResolveOptions::default()).unwrap();
+/// assert_eq!(
+///     files,
+///     vec![(lib.path().join("a.md"), Some(LineRange { start: 2, end: 3 }))]
+/// );
+/// ```
+pub fn resolve_ranges(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    opts: &ResolveOptions,
+) -> Result<Vec<(PathBuf, Option<LineRange>)>, ResolveError> {
+    let roots = cfg.search_roots(lib);
+    let libs: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    // Added entries resolve as if the profile listed them last
+    let extended;
+    let cfg = if opts.add.is_empty() || !cfg.profiles.contains_key(profile) {
+        cfg
+    } else {
+        let mut copy = cfg.clone();
+        if let Some(deps) = copy.profiles.get_mut(profile) {
+            deps.extend(opts.add.iter().cloned());
+        }
+        extended = copy;
+        &extended
+    };
+    let mut seen_files = HashSet::new();
+    let mut stack = Vec::new();
+    let mut files = Vec::new();
+    resolve_profile_with(
+        profile,
+        cfg,
+        &libs,
+        opts,
+        &mut seen_files,
+        &mut stack,
+        &mut files,
+    )?;
+    Ok(files)
+}
+
+/// Resolve a profile into its ordered file list using explicit options.
+///
+/// Behaves like [`resolve`] but honors the supplied [`ResolveOptions`].
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile name is not found in configuration
+/// - Circular dependency is detected
+/// - Referenced markdown file does not exist
+/// - Profile nesting exceeds `opts.max_depth`
+pub fn resolve_with(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    opts: &ResolveOptions,
+) -> Result<Vec<PathBuf>, ResolveError> {
+    let mut seen = HashSet::new();
+    Ok(resolve_ranges(cfg, lib, profile, opts)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .filter(|path| seen.insert(path.clone()))
+        .collect())
+}
+
+/// List all available profiles to a writer.
+///
+/// Outputs all profile names from the configuration in alphabetical order,
+/// one per line.
+///
+/// # Arguments
+/// * `cfg` - Configuration containing profile definitions
+/// * `w` - Writer to output profile names to
+///
+/// # Returns
+/// * `Ok(())` - All profiles listed successfully
+/// * `Err(io::Error)` - Write operation failed
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn list_profiles(cfg: &Config, w: impl Write) -> io::Result<()> {
+    // The library is only consulted by the long format
+    list_profiles_with(cfg, Path::new(""), &ListOptions::default(), w)
+}
+
+/// Options controlling which profiles `list` shows and how.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ListOptions {
+    /// Only include profiles carrying every one of these tags
+    pub tags: Vec<String>,
+    /// Print the distinct tags used across the config instead of profiles
+    pub all_tags: bool,
+    /// Show a table of dependency counts, resolved file counts, and tags
+    /// (see [`list_profiles_long`])
+    pub long: bool,
+    /// Group profiles under a `<namespace>:` header per first dotted segment
+    pub grouped: bool,
+    /// Show direct dependencies and a resolved size summary per profile
+    /// (see [`list_profiles_verbose`])
+    pub verbose: bool,
+    /// Print a JSON array of names and dependencies (see [`list_profiles_json`])
+    pub json: bool,
+    /// Print profiles as a tree of what they include (see [`profile_tree`])
+    pub tree: bool,
+    /// Show library files as leaves in the tree format
+    pub files: bool,
+    /// Only include names matching this pattern (see [`profile_matches`])
+    pub pattern: Option<String>,
+    /// Decorate the verbose and tree formats with colors and emoji; decided
+    /// when the list is printed, so not part of the parsed mode
+    #[serde(skip)]
+    pub styled: bool,
+}
+
+impl Config {
+    /// Tags attached to `profile`, empty when it has none.
+    fn tags(&self, profile: &str) -> &[String] {
+        self.meta.get(profile).map_or(&[], |m| m.tags.as_slice())
+    }
+
+    /// Whether `profile` declared `dep` with `optional = true`.
+    fn is_optional(&self, profile: &str, dep: &str) -> bool {
+        self.meta
+            .get(profile)
+            .is_some_and(|m| m.optional.contains(dep))
+    }
+}
+
+/// List profiles to a writer, filtered and formatted by [`ListOptions`].
+///
+/// `lib` is only read in the long format, to count resolved files.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn list_profiles_with(
+    cfg: &Config,
+    lib: &Path,
+    opts: &ListOptions,
+    mut w: impl Write,
+) -> io::Result<()> {
+    if opts.all_tags {
+        let tags: BTreeSet<&String> = cfg.meta.values().flat_map(|m| &m.tags).collect();
+        for tag in tags {
+            writeln!(&mut w, "{tag}")?;
+        }
+        return Ok(());
+    }
+
+    let names = listed_names(cfg, opts);
+    if opts.long {
+        return write_profile_table(cfg, lib, &names, w);
+    }
+    if opts.grouped {
+        return write_profile_groups(&names, w);
+    }
+    if opts.verbose {
+        return write_profile_details(cfg, lib, &names, opts.styled, w);
+    }
+    if opts.json {
+        return write_profile_json(cfg, &names, w);
+    }
+    if opts.tree {
+        let nodes = build_tree(cfg, &names, opts.files);
+        return write_tree(&nodes, opts.styled, w);
+    }
+    for n in names {
+        writeln!(&mut w, "{n}")?;
+    }
+    Ok(())
+}
+
+/// Sorted names of the profiles that pass the tag and pattern filters.
+fn listed_names<'a>(cfg: &'a Config, opts: &ListOptions) -> Vec<&'a String> {
+    let mut names: Vec<&String> = cfg
+        .profiles
+        .keys()
+        .filter(|name| {
+            let tags = cfg.tags(name);
+            opts.tags.iter().all(|t| tags.contains(t))
+        })
+        .filter(|name| {
+            opts.pattern
+                .as_deref()
+                .is_none_or(|pattern| profile_matches(name, pattern))
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Whether profile `name` matches a `list` filter `pattern`, ignoring case.
+///
+/// A pattern containing `*` (any run of characters) or `?` (one character)
+/// is a glob that must match the whole name; anything else matches as a
+/// substring.
+///
+/// ```
+/// use prompter::profile_matches;
+///
+/// assert!(profile_matches("python.api", "PY"));
+/// assert!(profile_matches("python.api", "python.*"));
+/// assert!(!profile_matches("rust.python", "python.*"));
+/// ```
+#[must_use]
+pub fn profile_matches(name: &str, pattern: &str) -> bool {
+    let name = name.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    if pattern.contains(['*', '?']) {
+        let name: Vec<char> = name.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        glob_matches(&pattern, &name)
+    } else {
+        name.contains(&pattern)
+    }
+}
+
+/// Match `text` against a glob of `*` and `?` wildcards, backtracking to
+/// the most recent `*` on a mismatch.
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// List the profiles whose names match `pattern` (see [`profile_matches`]),
+/// one per line, and return how many were listed.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn list_profiles_matching(cfg: &Config, pattern: &str, w: impl Write) -> io::Result<usize> {
+    let opts = ListOptions {
+        pattern: Some(pattern.to_string()),
+        ..ListOptions::default()
+    };
+    let count = listed_names(cfg, &opts).len();
+    list_profiles_with(cfg, Path::new(""), &opts, w)?;
+    Ok(count)
+}
+
+/// Write sorted profile names grouped by the segment before their first
+/// dot, one `<namespace>:` header per group and a blank line between groups.
+///
+/// A profile without a dot belongs to the group of its own name, so `python`
+/// is listed alongside `python.api`.
+fn write_profile_groups(names: &[&String], mut w: impl Write) -> io::Result<()> {
+    let mut groups: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for name in names {
+        let namespace = name.split_once('.').map_or(name.as_str(), |(ns, _)| ns);
+        groups.entry(namespace).or_default().push(name);
+    }
+    for (i, (namespace, members)) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(&mut w)?;
+        }
+        writeln!(&mut w, "{namespace}:")?;
+        for name in members {
+            writeln!(&mut w, "  {name}")?;
+        }
+    }
+    Ok(())
+}
+
+/// What a [`TreeNode`] stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeNodeKind {
+    /// A defined profile, with what it includes as children
+    Profile,
+    /// A library file entry
+    File,
+    /// A profile already on the path from the root; not expanded again
+    Cycle,
+    /// A profile name that is not defined in the config
+    Unknown,
+}
+
+/// A node in the profile tree shown by `prompter list --tree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    /// Profile name or `depends_on` entry
+    pub name: String,
+    /// What the entry refers to
+    pub kind: TreeNodeKind,
+    /// Included profiles (and files, when requested) in config order, with
+    /// an `extends` parent first
+    pub children: Vec<Self>,
+}
+
+/// Build the profile tree: every profile that no other profile includes
+/// becomes a root, sorted by name, with the profiles it extends or depends
+/// on nested beneath it.
+///
+/// A profile shared by several roots appears under each of them. A profile
+/// that includes one of its own ancestors gets a [`TreeNodeKind::Cycle`]
+/// leaf instead of recursing, and profiles only reachable through a cycle
+/// become roots themselves so nothing is left out. With `files`, library
+/// file entries are kept as leaves.
+#[must_use]
+pub fn profile_tree(cfg: &Config, files: bool) -> Vec<TreeNode> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    build_tree(cfg, &names, files)
+}
+
+/// [`profile_tree`] over the sorted `names`, picking roots among them only.
+fn build_tree(cfg: &Config, names: &[&String], files: bool) -> Vec<TreeNode> {
+    let included: HashSet<&str> = names
+        .iter()
+        .flat_map(|name| profile_children(cfg, name))
+        .collect();
+    let mut roots: Vec<&str> = names
+        .iter()
+        .map(|name| name.as_str())
+        .filter(|name| !included.contains(name))
+        .collect();
+
+    // Profiles that only appear inside cycles have no unreferenced ancestor
+    let mut reached: HashSet<&str> = HashSet::new();
+    for root in &roots {
+        mark_reachable(cfg, root, &mut reached);
+    }
+    for name in names {
+        if !reached.contains(name.as_str()) {
+            roots.push(name);
+            mark_reachable(cfg, name, &mut reached);
+        }
+    }
+    roots.sort_unstable();
+
+    roots
+        .into_iter()
+        .map(|root| tree_node(cfg, root, files, &mut Vec::new()))
+        .collect()
+}
+
+/// The profiles `name` includes: its `extends` parent, then the non-file
+/// entries of `depends_on`.
+fn profile_children<'a>(cfg: &'a Config, name: &str) -> impl Iterator<Item = &'a str> {
+    cfg.extends.get(name).map(String::as_str).into_iter().chain(
+        cfg.dependencies(name)
+            .unwrap_or_default()
+            .iter()
+            .map(String::as_str)
+            .filter(|dep| !is_markdown_dep(dep)),
+    )
+}
+
+fn mark_reachable<'a>(cfg: &'a Config, name: &'a str, reached: &mut HashSet<&'a str>) {
+    if !reached.insert(name) {
+        return;
+    }
+    for child in profile_children(cfg, name) {
+        mark_reachable(cfg, child, reached);
+    }
+}
+
+fn tree_node<'a>(cfg: &'a Config, name: &'a str, files: bool, path: &mut Vec<&'a str>) -> TreeNode {
+    let leaf = |kind| TreeNode {
+        name: name.to_string(),
+        kind,
+        children: Vec::new(),
+    };
+    if path.contains(&name) {
+        return leaf(TreeNodeKind::Cycle);
+    }
+    let Some(deps) = cfg.dependencies(name) else {
+        return leaf(TreeNodeKind::Unknown);
+    };
+
+    path.push(name);
+    let parent = cfg.extends.get(name).map(String::as_str);
+    let children = parent
+        .into_iter()
+        .chain(deps.iter().map(String::as_str))
+        .filter_map(|dep| {
+            if !is_markdown_dep(dep) {
+                Some(tree_node(cfg, dep, files, path))
+            } else if files {
+                Some(TreeNode {
+                    name: dep.to_string(),
+                    kind: TreeNodeKind::File,
+                    children: Vec::new(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+    path.pop();
+
+    TreeNode {
+        name: name.to_string(),
+        kind: TreeNodeKind::Profile,
+        children,
+    }
+}
+
+/// Draw `nodes` with box-drawing characters when `unicode` is set, plain
+/// ASCII otherwise.
+fn write_tree(nodes: &[TreeNode], unicode: bool, mut w: impl Write) -> io::Result<()> {
+    fn write_children(
+        children: &[TreeNode],
+        prefix: &str,
+        unicode: bool,
+        w: &mut impl Write,
+    ) -> io::Result<()> {
+        let (tee, elbow, pipe) = if unicode {
+            ("├── ", "└── ", "│   ")
+        } else {
+            ("|-- ", "`-- ", "|   ")
+        };
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            writeln!(
+                w,
+                "{prefix}{}{}",
+                if last { elbow } else { tee },
+                tree_label(child, unicode)
+            )?;
+            let nested = format!("{prefix}{}", if last { "    " } else { pipe });
+            write_children(&child.children, &nested, unicode, w)?;
+        }
+        Ok(())
+    }
+
+    for node in nodes {
+        writeln!(&mut w, "{}", tree_label(node, unicode))?;
+        write_children(&node.children, "", unicode, &mut w)?;
+    }
+    Ok(())
+}
+
+fn tree_label(node: &TreeNode, unicode: bool) -> String {
+    let ellipsis = if unicode { "…" } else { "..." };
+    match node.kind {
+        TreeNodeKind::Profile | TreeNodeKind::File => node.name.clone(),
+        TreeNodeKind::Cycle => format!("{} {ellipsis} (cycle)", node.name),
+        TreeNodeKind::Unknown => format!("{} (unknown profile)", node.name),
+    }
+}
+
+/// List every profile as JSON for editor integrations, sorted by name:
+/// `[{"name": "...", "depends_on": ["..."]}]`.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn list_profiles_json(cfg: &Config, w: impl Write) -> io::Result<()> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    write_profile_json(cfg, &names, w)
+}
+
+fn write_profile_json(cfg: &Config, names: &[&String], mut w: impl Write) -> io::Result<()> {
+    let profiles: Vec<serde_json::Value> = names
+        .iter()
+        .map(|name| {
+            serde_json::json!({
+                "name": name,
+                "depends_on": cfg.profiles[*name],
+            })
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut w, &profiles)?;
+    writeln!(&mut w)
+}
+
+/// List every profile with its direct `depends_on` entries indented beneath
+/// it and a summary such as `(resolves to 7 files, 12.3 KiB)`.
+///
+/// A profile that fails to resolve shows the error in place of the summary.
+/// When `lib` does not exist, summaries are left out and a hint to run
+/// `prompter init` follows the list.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn list_profiles_verbose(cfg: &Config, lib: &Path, w: impl Write) -> io::Result<()> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    write_profile_details(cfg, lib, &names, false, w)
+}
+
+fn write_profile_details(
+    cfg: &Config,
+    lib: &Path,
+    names: &[&String],
+    styled: bool,
+    mut w: impl Write,
+) -> io::Result<()> {
+    let have_lib = lib.is_dir();
+    for name in names {
+        let summary = if have_lib {
+            match resolved_size(cfg, lib, name) {
+                Ok((files, bytes)) => format!(
+                    " (resolves to {files} file{}, {})",
+                    if files == 1 { "" } else { "s" },
+                    format_size(bytes)
+                ),
+                Err(e) if styled => format!(" ({})", format!("error: {e}").bright_red()),
+                Err(e) => format!(" (error: {e})"),
+            }
+        } else {
+            String::new()
+        };
+        if styled {
+            writeln!(w, "{}{}", name.bold(), summary.dimmed())?;
+        } else {
+            writeln!(w, "{name}{summary}")?;
+        }
+        if let Some(parent) = cfg.extends.get(*name) {
+            writeln!(w, "  extends {parent}")?;
+        }
+        for dep in cfg.profiles.get(*name).into_iter().flatten() {
+            writeln!(w, "  {dep}")?;
+        }
+    }
+    if !have_lib {
+        writeln!(
+            w,
+            "{}",
+            info_text(
+                &format!(
+                    "Library {} not found; run 'prompter init' to create it",
+                    lib.display()
+                ),
+                styled
+            )
+        )?;
+    }
+    Ok(())
+}
+
+/// The number of files `profile` resolves to and their total size in bytes,
+/// honoring line ranges.
+fn resolved_size(cfg: &Config, lib: &Path, profile: &str) -> Result<(usize, u64), String> {
+    let files =
+        resolve_ranges(cfg, lib, profile, &ResolveOptions::default()).map_err(|e| e.to_string())?;
+    let mut bytes = 0;
+    for (path, range) in &files {
+        bytes += read_snippet(path, *range)?.len() as u64;
+    }
+    Ok((files.len(), bytes))
+}
+
+/// A byte count for people: `512 B`, `12.3 KiB`, `4.0 MiB`.
+fn format_size(bytes: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    // One decimal place without going through floats
+    let tenths = |unit: u64| {
+        let t = (bytes * 10 + unit / 2) / unit;
+        format!("{}.{}", t / 10, t % 10)
+    };
+    if bytes < KIB {
+        format!("{bytes} B")
+    } else if bytes < MIB {
+        format!("{} KiB", tenths(KIB))
+    } else {
+        format!("{} MiB", tenths(MIB))
+    }
+}
+
+/// List every profile as an aligned table of direct dependency count,
+/// resolved file count (after dedup), and tags.
+///
+/// Profiles that fail to resolve show `?` as their file count.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn list_profiles_long(cfg: &Config, lib: &Path, w: impl Write) -> io::Result<()> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    write_profile_table(cfg, lib, &names, w)
+}
+
+fn write_profile_table(
+    cfg: &Config,
+    lib: &Path,
+    names: &[&String],
+    mut w: impl Write,
+) -> io::Result<()> {
+    let width = names
+        .iter()
+        .map(|n| n.len())
+        .chain(std::iter::once("PROFILE".len()))
+        .max()
+        .unwrap_or(0);
+    writeln!(w, "{:<width$}  DEPS  FILES  TAGS", "PROFILE")?;
+    for name in names {
+        let deps = cfg.profiles.get(*name).map_or(0, Vec::len);
+        let files =
+            resolve(cfg, lib, name).map_or_else(|_| "?".to_string(), |f| f.len().to_string());
+        let line = format!(
+            "{name:<width$}  {deps:>4}  {files:>5}  {}",
+            cfg.tags(name).join(", ")
+        );
+        writeln!(w, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Validate configuration and library file references.
+///
+/// Checks that all profile dependencies are valid, including:
+/// - Referenced profiles exist in configuration
+/// - Referenced markdown files exist in library
+/// - No circular dependencies exist
+///
+/// # Arguments
+/// * `cfg` - Configuration to validate
+/// * `lib` - Library root directory for file validation
+///
+/// # Returns
+/// * `Ok(())` - Configuration is valid
+/// * `Err(PrompterError::Invalid)` - Validation errors found, one per entry
+///
+/// # Errors
+/// Returns an error if:
+/// - Referenced profiles don't exist
+/// - Referenced files don't exist
+/// - Circular dependencies are detected
+pub fn validate(cfg: &Config, lib: &Path) -> Result<(), PrompterError> {
+    let mut errors: Vec<String> = Vec::new();
+    let roots = cfg.search_roots(lib);
+    let libs: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+
+    for (profile, deps) in &cfg.profiles {
+        for raw_dep in deps {
+            if is_markdown_dep(raw_dep) {
+                let dep = expand_env_lenient(raw_dep);
+                if let Err(e) = split_line_range(&dep) {
+                    errors.push(format!("{e} (referenced by [{profile}])"));
+                }
+                let found = find_in_roots(&libs, dep_base(&dep)).is_some();
+                if !found && !cfg.is_optional(profile, raw_dep) {
+                    let path = dep_path(lib, dep_base(&dep));
+                    errors.push(format!(
+                        "Missing file: {} (referenced by [{}])",
+                        path.display(),
+                        profile
+                    ));
+                }
+            } else if !cfg.profiles.contains_key(raw_dep) {
+                errors.push(format!(
+                    "Unknown profile: {raw_dep} (referenced by [{profile}])"
+                ));
+            }
+        }
+    }
+
+    for (profile, parent) in &cfg.extends {
+        if !cfg.profiles.contains_key(parent) {
+            errors.push(format!(
+                "Unknown profile: {parent} (extended by [{profile}])"
+            ));
+        }
+    }
+
+    if let Some(default) = cfg.default_profile() {
+        if !cfg.profiles.contains_key(default) {
+            errors.push(format!("Unknown profile: {default} (default_profile)"));
+        }
+    }
+
+    if let Some(format) = cfg.timestamp_format() {
+        if let Err(e) = check_timestamp_format(format) {
+            errors.push(format!("{e} (timestamp_format)"));
+        }
+    }
+
+    for (key, file) in cfg.prompt_files() {
+        if let Err(e) = prompt_file_path(cfg, lib, key, file) {
+            errors.push(e.to_string());
+        }
+    }
+
+    for name in cfg.profiles.keys() {
+        match resolve(cfg, lib, name) {
+            Err(ResolveError::Cycle(cycle)) => {
+                let chain = cycle.join(" -> ");
+                errors.push(format!("Cycle detected: {chain}"));
+            }
+            Err(ResolveError::DepthExceeded(max)) => {
+                errors.push(format!(
+                    "Profile nesting too deep: [{name}] exceeds maximum depth of {max}"
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PrompterError::Invalid(errors))
+    }
+}
+
+/// A suspicious profile definition that `validate` does not reject outright.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileLint {
+    /// The profile has no dependencies and no parent
+    Empty(String),
+    /// The profile lists itself in `depends_on`
+    SelfDependency(String),
+    /// The profile lists the same dependency more than once
+    Duplicate {
+        /// Profile whose `depends_on` has the repeat
+        profile: String,
+        /// The repeated entry
+        dep: String,
+    },
+}
+
+/// Find empty profiles, self-dependencies, and duplicate `depends_on` entries.
+///
+/// Results are sorted by profile name, then in `depends_on` order.
+#[must_use]
+pub fn profile_lints(cfg: &Config) -> Vec<ProfileLint> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    let mut lints = Vec::new();
+    for name in names {
+        let deps = &cfg.profiles[name];
+        if deps.is_empty() && !cfg.extends.contains_key(name) {
+            lints.push(ProfileLint::Empty(name.clone()));
+        }
+        if deps.contains(name) {
+            lints.push(ProfileLint::SelfDependency(name.clone()));
+        }
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        for dep in deps {
+            if !seen.insert(dep) && reported.insert(dep) {
+                lints.push(ProfileLint::Duplicate {
+                    profile: name.clone(),
+                    dep: dep.clone(),
+                });
+            }
+        }
+    }
+    lints
+}
+
+/// Map every file reached by resolving each profile to the profiles using it.
+///
+/// Paths are on disk (`.` and `..` folded away) and profile names are
+/// sorted. Files outside the library are included; profiles that fail to
+/// resolve are left out (see [`unresolved_profiles`]). An existing
+/// `pre_prompt_file` or `post_prompt_file` is used by every profile that
+/// resolves, since each render includes it.
+#[must_use]
+pub fn file_profiles(cfg: &Config, lib: &Path) -> BTreeMap<PathBuf, Vec<String>> {
+    let opts = ResolveOptions {
+        allow_external: true,
+        ..ResolveOptions::default()
+    };
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    let mut usage: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    let mut rendered = Vec::new();
+    for name in names {
+        let Ok(files) = resolve_with(cfg, lib, name, &opts) else {
+            continue;
+        };
+        rendered.push(name.clone());
+        for file in files {
+            let users = usage.entry(normalize_lexically(&file)).or_default();
+            if users.last() != Some(name) {
+                users.push(name.clone());
+            }
+        }
+    }
+    if !rendered.is_empty() {
+        for (key, file) in cfg.prompt_files() {
+            let Ok(path) = prompt_file_path(cfg, lib, key, file) else {
+                continue;
+            };
+            let users = usage.entry(normalize_lexically(&path)).or_default();
+            users.extend(rendered.iter().cloned());
+            users.sort();
+            users.dedup();
+        }
+    }
+    usage
+}
+
+/// Names of the profiles that fail to resolve, sorted.
+///
+/// Files such profiles would reach are unknown, so callers deleting
+/// "unused" files should stop when this is not empty.
+#[must_use]
+pub fn unresolved_profiles(cfg: &Config, lib: &Path) -> Vec<String> {
+    let opts = ResolveOptions {
+        allow_external: true,
+        ..ResolveOptions::default()
+    };
+    let mut names: Vec<String> = cfg
+        .profiles
+        .keys()
+        .filter(|name| resolve_with(cfg, lib, name, &opts).is_err())
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+/// Map each library file referenced by a profile to the search root it was
+/// found in (see [`Config::search_roots`]).
+///
+/// Keys are `depends_on` entries after `$VAR` expansion and without any `#L`
+/// range. Missing files and paths outside the library are left out.
+#[must_use]
+pub fn file_roots(cfg: &Config, lib: &Path) -> BTreeMap<String, PathBuf> {
+    let roots = cfg.search_roots(lib);
+    let libs: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    cfg.profiles
+        .values()
+        .flatten()
+        .filter(|dep| is_markdown_dep(dep))
+        .map(|dep| dep_base(&expand_env_lenient(dep)).to_string())
+        .filter(|dep| !is_external_dep(dep))
+        .filter_map(|dep| {
+            let (root, _) = find_in_roots(&libs, &dep)?;
+            let root = root.to_path_buf();
+            Some((dep, root))
+        })
+        .collect()
+}
+
+/// Collect library files referenced by any profile that do not exist on disk.
+///
+/// Dependencies declared `optional = true` and paths outside the library are
+/// not reported. Each missing path is reported once, joined onto `lib`, in
+/// sorted order.
+#[must_use]
+pub fn missing_files(cfg: &Config, lib: &Path) -> Vec<PathBuf> {
+    let roots = cfg.search_roots(lib);
+    let libs: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    let mut missing: Vec<PathBuf> = cfg
+        .profiles
+        .iter()
+        .flat_map(|(profile, deps)| deps.iter().map(move |dep| (profile, dep)))
+        .filter(|(profile, dep)| is_markdown_dep(dep) && !cfg.is_optional(profile, dep))
+        .map(|(_, dep)| expand_env_lenient(dep))
+        .filter(|dep| !is_external_dep(dep))
+        .filter(|dep| find_in_roots(&libs, dep_base(dep)).is_none())
+        .map(|dep| lib.join(dep_base(&dep)))
+        .collect();
+    missing.sort();
+    missing.dedup();
+    missing
+}
+
+/// Options controlling what `init` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct InitOptions {
+    /// Back up and rewrite default files that already exist
+    pub force: bool,
+    /// Write only the config, without sample library files
+    pub minimal: bool,
+    /// Write this template instead of the built-in samples
+    pub template: Option<&'static Template>,
+    /// No spinner or decoration: print a single `initialized <config>` or
+    /// `already initialized <config>` line, with backups on stderr
+    pub quiet: bool,
+    /// Show a spinner and decorate messages with colors and emoji
+    pub styled: bool,
+}
+
+/// A starter config and library that `init --template` can write.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Template {
+    /// Name passed to `--template`
+    pub name: &'static str,
+    /// One-line summary shown by `--template list`
+    pub description: &'static str,
+    /// Contents of `config.toml`
+    config: &'static str,
+    /// Library files as `(relative path, contents)`
+    files: &'static [(&'static str, &'static str)],
+}
+
+/// Templates embedded in the binary, in `--template list` order.
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "coding-agent",
+        description: "Review, implement, and debug profiles for a coding agent",
+        config: include_str!("../templates/coding-agent/config.toml"),
+        files: &[
+            (
+                "core/conduct.md",
+                include_str!("../templates/coding-agent/library/core/conduct.md"),
+            ),
+            (
+                "core/environment.md",
+                include_str!("../templates/coding-agent/library/core/environment.md"),
+            ),
+            (
+                "practices/style.md",
+                include_str!("../templates/coding-agent/library/practices/style.md"),
+            ),
+            (
+                "practices/testing.md",
+                include_str!("../templates/coding-agent/library/practices/testing.md"),
+            ),
+            (
+                "tasks/review.md",
+                include_str!("../templates/coding-agent/library/tasks/review.md"),
+            ),
+            (
+                "tasks/implement.md",
+                include_str!("../templates/coding-agent/library/tasks/implement.md"),
+            ),
+            (
+                "tasks/debug.md",
+                include_str!("../templates/coding-agent/library/tasks/debug.md"),
+            ),
+        ],
+    },
+    Template {
+        name: "docs",
+        description: "Tutorial and reference profiles for technical writing",
+        config: include_str!("../templates/docs/config.toml"),
+        files: &[
+            (
+                "style/voice.md",
+                include_str!("../templates/docs/library/style/voice.md"),
+            ),
+            (
+                "style/formatting.md",
+                include_str!("../templates/docs/library/style/formatting.md"),
+            ),
+            (
+                "docs/tutorial.md",
+                include_str!("../templates/docs/library/docs/tutorial.md"),
+            ),
+            (
+                "docs/reference.md",
+                include_str!("../templates/docs/library/docs/reference.md"),
+            ),
+        ],
+    },
+];
+
+/// Look up an embedded template by name.
+///
+/// # Errors
+/// Returns an error naming the available templates if none matches.
+pub fn find_template(name: &str) -> Result<&'static Template, String> {
+    TEMPLATES.iter().find(|t| t.name == name).ok_or_else(|| {
+        let names: Vec<&str> = TEMPLATES.iter().map(|t| t.name).collect();
+        format!(
+            "Unknown template '{name}' (available: {}); see 'prompter init --template list'",
+            names.join(", ")
+        )
+    })
+}
+
+/// Write each template's name and description, one per line.
+///
+/// # Errors
+/// Returns an error if writing fails.
+pub fn write_templates(mut w: impl Write) -> io::Result<()> {
+    let width = TEMPLATES.iter().map(|t| t.name.len()).max().unwrap_or(0);
+    for template in TEMPLATES {
+        writeln!(w, "{:<width$}  {}", template.name, template.description)?;
+    }
+    Ok(())
+}
+
+/// Initialize default configuration and library structure.
+///
+/// Creates the default directory structure and configuration files
+/// for prompter, including sample profiles and library files.
+/// Only creates files that don't already exist (non-destructive), unless
+/// `opts.force` is set: then each existing default file that differs from
+/// the default is renamed to `<name>.bak-<timestamp>` and rewritten. Other
+/// library files are never touched. With `opts.minimal` only the config and
+/// an empty library directory are created; with `opts.template` the
+/// template's config and files replace the built-in samples.
+///
+/// # Arguments
+/// * `cfg_path` - Config file to write
+/// * `lib` - Library directory to create and fill with samples
+/// * `opts` - Whether to overwrite existing files and include samples
+///
+/// # Returns
+/// * `Ok(())` - Initialization completed successfully
+/// * `Err(String)` - Initialization failed
+///
+/// # Errors
+/// Returns an error if:
+/// - Directory creation fails
+/// - File writing or backing up fails
+///
+/// # Panics
+/// Panics if the progress bar template is invalid (should not happen with the
+/// hardcoded template string).
+pub fn init_scaffold(cfg_path: &Path, lib: &Path, opts: InitOptions) -> Result<(), String> {
+    let defaults = opts.template.map_or_else(
+        || default_files(cfg_path, lib, opts.minimal),
+        |template| template_files(template, cfg_path, lib),
+    );
+
+    if opts.force {
+        let changed: Vec<&PathBuf> = defaults
+            .iter()
+            .filter(|(path, contents)| differs_from_default(path, contents))
+            .map(|(path, _)| path)
+            .collect();
+        if !changed.is_empty() {
+            eprintln!(
+                "{}",
+                warning_text(
+                    &format!(
+                        "Overwriting {} existing file(s); each is backed up first:",
+                        changed.len()
+                    ),
+                    opts.styled
+                )
+            );
+            for path in changed {
+                eprintln!("  {}", path.display());
+            }
+        }
+    }
+
+    let pb = if opts.styled && !opts.quiet {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+                .template("{spinner:.green} {msg}")
+                .unwrap(),
+        );
+        pb.set_message("Initializing prompter...");
+        pb.enable_steady_tick(std::time::Duration::from_millis(120));
+        Some(pb)
+    } else {
+        None
+    };
+
+    let cfg_dir = cfg_path
+        .parent()
+        .ok_or_else(|| "Invalid config path".to_string())?;
+
+    let mut changed = !cfg_dir.is_dir() || !lib.is_dir();
+
+    if let Some(ref pb) = pb {
+        pb.set_message("Creating config directory...");
+    }
+    fs::create_dir_all(cfg_dir)
+        .map_err(|e| format!("Failed to create {}: {}", cfg_dir.display(), e))?;
+
+    if let Some(ref pb) = pb {
+        pb.set_message("Creating library directory...");
+    }
+    fs::create_dir_all(lib).map_err(|e| format!("Failed to create {}: {}", lib.display(), e))?;
+
+    let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut backups = Vec::new();
+
+    for (path, contents) in defaults {
+        if let Some(ref pb) = pb {
+            pb.set_message(format!(
+                "Creating {}",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        match write_default(&path, &contents, opts.force, &stamp)? {
+            DefaultWrite::Kept => {}
+            DefaultWrite::Created => changed = true,
+            DefaultWrite::Replaced { backup } => {
+                changed = true;
+                backups.push((path, backup));
+            }
+        }
+    }
+
+    if let Some(pb) = pb {
+        pb.finish_with_message("Initialization complete!");
+    }
+
+    report_init(cfg_path, lib, &backups, changed, opts.quiet, opts.styled);
+    Ok(())
+}
+
+/// Print the backups `init` made and its one-line (quiet) or decorated result.
+fn report_init(
+    cfg_path: &Path,
+    lib: &Path,
+    backups: &[(PathBuf, PathBuf)],
+    changed: bool,
+    quiet: bool,
+    styled: bool,
+) {
+    for (original, backup) in backups {
+        let msg = format!("Backed up {} to {}", original.display(), backup.display());
+        if quiet {
+            eprintln!("{msg}");
+        } else {
+            println!("{}", info_text(&msg, styled));
+        }
+    }
+    match (quiet, changed) {
+        (true, true) => println!("initialized {}", cfg_path.display()),
+        (true, false) => println!("already initialized {}", cfg_path.display()),
+        (false, true) => println!(
+            "{}",
+            success_text(
+                &format!("Initialized config at {}", cfg_path.display()),
+                styled
+            )
+        ),
+        (false, false) => println!(
+            "{}",
+            info_text(
+                &format!("Already initialized at {}", cfg_path.display()),
+                styled
+            )
+        ),
+    }
+    if !quiet {
+        println!(
+            "{}",
+            info_text(&format!("Library root at {}", lib.display()), styled)
+        );
+    }
+}
+
+/// Where `init` should write the config and library.
+///
+/// Without overrides these are the usual `$HOME` locations. A config
+/// override gets a `library/` directory next to it, as at runtime, unless
+/// `library` names one explicitly. Relative paths are taken from the current
+/// directory.
+///
+/// # Errors
+/// Returns an error if `HOME` is needed but unset, the working directory
+/// cannot be determined, or the config is to be read from stdin.
+pub fn init_paths(
+    config_override: Option<&Path>,
+    library: Option<&Path>,
+) -> Result<(PathBuf, PathBuf), String> {
+    if config_override.is_some_and(is_stdin_config) {
+        return Err("Cannot initialize a config read from stdin; pass a file path".to_string());
+    }
+    let paths = ResolvedPaths::resolve(config_override)?;
+    let lib = match library {
+        Some(dir) => config_path_override(dir)?,
+        None => paths.library,
+    };
+    Ok((paths.config, lib))
+}
+
+/// The files a template writes, config first, at their destination paths.
+fn template_files(template: &Template, cfg_path: &Path, lib: &Path) -> Vec<(PathBuf, String)> {
+    std::iter::once((cfg_path.to_path_buf(), template.config.to_string()))
+        .chain(
+            template
+                .files
+                .iter()
+                .map(|(rel, contents)| (lib.join(rel), (*contents).to_string())),
+        )
+        .collect()
+}
+
+/// The files `init` writes, config first, with their default contents.
+///
+/// A `minimal` scaffold is just a config with an empty `[example]` profile.
+fn default_files(cfg_path: &Path, lib: &Path, minimal: bool) -> Vec<(PathBuf, String)> {
+    let lib_display =
+        if home_dir().is_ok_and(|home| home.join(".local/share/prompter/library") == lib) {
+            "$HOME/.local/share/prompter/library".to_string()
+        } else {
+            lib.display().to_string()
+        };
+    let header = format!(
+        "# Prompter configuration
+# Profiles map to sets of markdown files and/or other profiles.
+# Files are relative to {lib_display}
+"
+    );
+    if minimal {
+        let cfg = format!("{header}\n[example]\ndepends_on = []\n");
+        return vec![(cfg_path.to_path_buf(), cfg)];
+    }
+    let default_cfg = format!(
+        r#"{header}
+[python.api]
+depends_on = ["a/b/c.md", "f/g/h.md"]
+
+[general.testing]
+depends_on = ["python.api", "a/b/d.md"]
+"#
+    );
+    vec![
+        (cfg_path.to_path_buf(), default_cfg),
+        (
+            lib.join("a/b/c.md"),
+            "# a/b/c.md\nExample snippet for python.api.\n".to_string(),
+        ),
+        (
+            lib.join("a/b.md"),
+            "# a/b.md\nFolder-level notes.\n".to_string(),
+        ),
+        (
+            lib.join("a/b/d.md"),
+            "# a/b/d.md\nGeneral testing snippet.\n".to_string(),
+        ),
+        (
+            lib.join("f/g/h.md"),
+            "# f/g/h.md\nShared helper snippet.\n".to_string(),
+        ),
+    ]
+}
+
+/// Whether `path` exists with content other than `contents`.
+fn differs_from_default(path: &Path, contents: &str) -> bool {
+    path.exists() && !fs::read(path).is_ok_and(|current| current == contents.as_bytes())
+}
+
+/// What [`write_default`] did with one file.
+#[derive(Debug, PartialEq, Eq)]
+enum DefaultWrite {
+    /// The existing file was left as it was
+    Kept,
+    /// The file did not exist and was written
+    Created,
+    /// The existing file was moved to `backup` and rewritten
+    Replaced { backup: PathBuf },
+}
+
+/// Write a default file for `init`.
+///
+/// A missing file is created. An existing one is left alone unless `force`
+/// is set and its content differs, in which case it is first renamed to a
+/// fresh [`backup_path`].
+fn write_default(
+    path: &Path,
+    contents: &str,
+    force: bool,
+    stamp: &str,
+) -> Result<DefaultWrite, String> {
+    let outcome = if path.exists() {
+        if !force || !differs_from_default(path, contents) {
+            return Ok(DefaultWrite::Kept);
+        }
+        let backup = backup_path(path, stamp);
+        fs::rename(path, &backup).map_err(|e| {
+            format!(
+                "Failed to back up {} to {}: {e}",
+                path.display(),
+                backup.display()
+            )
+        })?;
+        DefaultWrite::Replaced { backup }
+    } else {
+        DefaultWrite::Created
+    };
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(outcome)
+}
+
+/// First unused `<name>.bak-<stamp>` path next to `path`, adding `-1`, `-2`,
+/// ... so an earlier backup is never overwritten.
+fn backup_path(path: &Path, stamp: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut candidate = path.with_file_name(format!("{name}.bak-{stamp}"));
+    let mut n = 0;
+    while candidate.exists() {
+        n += 1;
+        candidate = path.with_file_name(format!("{name}.bak-{stamp}-{n}"));
+    }
+    candidate
+}
+
+/// List profiles to stdout.
+///
+/// Convenience function that reads configuration and lists all profiles
+/// to standard output.
+///
+/// # Returns
+/// * `Ok(())` - Profiles listed successfully
+/// * `Err(PrompterError)` - Operation failed
+///
+/// # Errors
+/// Returns an error if:
+/// - Configuration file cannot be read or parsed
+/// - Writing to stdout fails
+pub fn run_list_stdout(
+    opts: &ListOptions,
+    config_override: Option<&Path>,
+    strict_config: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    if let Some(pattern) = &opts.pattern {
+        if listed_names(&cfg, opts).is_empty() {
+            return Err(format!("No profiles match '{pattern}'").into());
+        }
+    }
+    Ok(list_profiles_with(&cfg, &lib, opts, io::stdout())?)
+}
+
+/// Validate configuration and output results to stdout.
+///
+/// Convenience function that reads configuration and validates it,
+/// outputting any errors found.
+///
+/// # Returns
+/// * `Ok(())` - Configuration is valid
+/// * `Err(PrompterError)` - Validation errors found
+///
+/// # Errors
+/// Returns an error if:
+/// - Configuration file cannot be read or parsed
+/// - Validation finds missing files or circular dependencies
+pub fn run_validate_stdout(
+    config_override: Option<&Path>,
+    strict_config: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    if !cfg.library_roots.is_empty() {
+        for (dep, root) in file_roots(&cfg, &lib) {
+            println!("{dep}: {}", root.display());
+        }
+    }
+    validate(&cfg, &lib)
+}
+
+/// Rewrite the config in canonical form with [`format_config`], printing
+/// `formatted <path>` when it changed. A config on stdin is printed
+/// instead.
+///
+/// With `check`, nothing is written: a diff of the changes is printed and
+/// an error returned if the config is not already formatted.
+///
+/// # Errors
+/// Returns an error if the config cannot be read, parsed or written, or
+/// with `check` if it would change.
+pub fn run_fmt_stdout(
+    check: bool,
+    config_override: Option<&Path>,
+    strict_config: bool,
+) -> Result<(), PrompterError> {
+    let paths = ResolvedPaths::resolve(config_override)?;
+    let input = read_config_with_path(&paths.config)?;
+    // Formatting drops unknown keys, so --strict-config must see them first
+    parse_config(&input, strict_config)?;
+    let formatted = format_config(&input)?;
+    let mut out = io::stdout().lock();
+    if check {
+        if formatted == input {
+            return Ok(());
+        }
+        out.write_all(line_diff(&input, &formatted).as_bytes())?;
+        return Err(format!(
+            "{} is not formatted; run `prompter fmt` to fix it",
+            paths.config.display()
+        )
+        .into());
+    }
+    if paths.config_is_stdin() {
+        out.write_all(formatted.as_bytes())?;
+    } else if formatted != input {
+        fs::write(&paths.config, &formatted)
+            .map_err(|e| format!("Failed to write {}: {e}", paths.config.display()))?;
+        writeln!(out, "formatted {}", paths.config.display())?;
+    }
+    Ok(())
+}
+
+/// Directory holding a bundle's library files.
+const BUNDLE_LIBRARY_DIR: &str = "library";
+
+/// A prompt bundle: the profiles of its `config.toml` and the library files
+/// under `library/`, as read from a `.tar.gz` by [`Bundle::read`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bundle {
+    /// The bundled profiles; other config keys are not imported
+    pub config: Config,
+    /// File contents keyed by path relative to the library
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl Bundle {
+    /// Read a gzip-compressed tar holding `config.toml` and `library/`.
+    ///
+    /// # Errors
+    /// Returns an error if the archive cannot be read or has no valid
+    /// `config.toml`, or an entry is not a regular file in one of those
+    /// places, has an absolute path, or uses `..`.
+    pub fn read(reader: impl Read) -> Result<Self, String> {
+        let failed = |e: io::Error| format!("Failed to read bundle: {e}");
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(reader));
+        let mut config = None;
+        let mut files = BTreeMap::new();
+        for entry in archive.entries().map_err(failed)? {
+            let mut entry = entry.map_err(failed)?;
+            let kind = entry.header().entry_type();
+            if kind.is_dir() {
+                continue;
+            }
+            let path = entry.path().map_err(failed)?.into_owned();
+            let mut parts = Vec::new();
+            for component in path.components() {
+                match component {
+                    std::path::Component::CurDir => {}
+                    std::path::Component::Normal(part) => parts.push(part.to_os_string()),
+                    _ => return Err(format!("Unsafe path in bundle: {}", path.display())),
+                }
+            }
+            if !kind.is_file() {
+                return Err(format!("Not a regular file in bundle: {}", path.display()));
+            }
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).map_err(failed)?;
+            match parts.as_slice() {
+                [name] if name == CONFIG_FILE_NAME => config = Some(bytes),
+                [dir, rest @ ..] if dir == BUNDLE_LIBRARY_DIR && !rest.is_empty() => {
+                    files.insert(rest.iter().collect(), bytes);
+                }
+                _ => return Err(format!("Unexpected entry in bundle: {}", path.display())),
+            }
+        }
+        let config = config.ok_or_else(|| format!("Bundle has no {CONFIG_FILE_NAME}"))?;
+        let text = String::from_utf8(config)
+            .map_err(|_| format!("Bundle {CONFIG_FILE_NAME} is not valid UTF-8"))?;
+        let config =
+            parse_config_toml(&text).map_err(|e| format!("Bundle {CONFIG_FILE_NAME}: {e}"))?;
+        Ok(Self { config, files })
+    }
+
+    /// Rename every profile to `<prefix><name>` and move every file under the
+    /// directory named by `prefix` without its trailing dots (`team.` puts
+    /// files in `team/`), rewriting dependencies on them to match.
+    ///
+    /// # Errors
+    /// Returns an error if `prefix` names no directory or contains a slash.
+    pub fn with_prefix(self, prefix: &str) -> Result<Self, String> {
+        let dir = prefix.trim_end_matches('.');
+        if dir.is_empty() || dir.contains(['/', '\\']) {
+            return Err(format!(
+                "Invalid prefix {prefix:?}: it must name a directory, like `team.`"
+            ));
+        }
+        let rename = |dep: &str| {
+            if self.config.profiles.contains_key(dep) {
+                format!("{prefix}{dep}")
+            } else if is_markdown_dep(dep) && self.files.contains_key(Path::new(dep_base(dep))) {
+                format!("{dir}/{dep}")
+            } else {
+                dep.to_string()
+            }
+        };
+        let config = Config {
+            profiles: self
+                .config
+                .profiles
+                .iter()
+                .map(|(name, deps)| (rename(name), deps.iter().map(|d| rename(d)).collect()))
+                .collect(),
+            extends: self
+                .config
+                .extends
+                .iter()
+                .map(|(name, parent)| (rename(name), rename(parent)))
+                .collect(),
+            meta: self
+                .config
+                .meta
+                .iter()
+                .map(|(name, meta)| {
+                    let meta = ProfileMeta {
+                        tags: meta.tags.clone(),
+                        optional: meta.optional.iter().map(|d| rename(d)).collect(),
+                    };
+                    (rename(name), meta)
+                })
+                .collect(),
+            ..self.config.clone()
+        };
+        let files = self
+            .files
+            .into_iter()
+            .map(|(path, bytes)| (Path::new(dir).join(path), bytes))
+            .collect();
+        Ok(Self { config, files })
+    }
+}
+
+/// What importing one bundled file or profile does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportAction {
+    /// Nothing by that name exists yet
+    Create,
+    /// It exists with the same content or definition
+    Unchanged,
+    /// It exists and differs, and `force` replaces it
+    Overwrite,
+    /// It exists and differs, so the import is refused
+    Conflict,
+}
+
+/// What importing a [`Bundle`] would do, from [`plan_import`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportPlan {
+    /// Each bundled file, relative to the library, with its action
+    pub files: Vec<(PathBuf, ImportAction)>,
+    /// Each bundled profile with its action
+    pub profiles: Vec<(String, ImportAction)>,
+}
+
+impl ImportPlan {
+    /// One line per file or profile that blocks the import.
+    #[must_use]
+    pub fn conflicts(&self) -> Vec<String> {
+        let files = self
+            .files
+            .iter()
+            .filter(|(_, action)| *action == ImportAction::Conflict)
+            .map(|(path, _)| format!("file {}: differs from the library copy", path.display()));
+        let profiles = self
+            .profiles
+            .iter()
+            .filter(|(_, action)| *action == ImportAction::Conflict)
+            .map(|(name, _)| format!("profile {name}: already defined differently"));
+        files.chain(profiles).collect()
+    }
+}
+
+/// Whether `name` is defined the same way, tags and all, in both configs.
+fn same_profile(a: &Config, b: &Config, name: &str) -> bool {
+    a.profiles.get(name) == b.profiles.get(name)
+        && a.extends.get(name) == b.extends.get(name)
+        && a.meta.get(name).cloned().unwrap_or_default()
+            == b.meta.get(name).cloned().unwrap_or_default()
+}
+
+/// Decide, without writing anything, what importing `bundle` into `cfg` and
+/// `lib` does to each file and profile. Existing ones that differ are
+/// conflicts, or overwritten with `force`.
+#[must_use]
+pub fn plan_import(cfg: &Config, lib: &Path, bundle: &Bundle, force: bool) -> ImportPlan {
+    let differs = if force {
+        ImportAction::Overwrite
+    } else {
+        ImportAction::Conflict
+    };
+    let files = bundle
+        .files
+        .iter()
+        .map(|(path, bytes)| {
+            let target = lib.join(path);
+            let action = if !target.exists() && !target.is_symlink() {
+                ImportAction::Create
+            } else if fs::read(&target).is_ok_and(|old| old == *bytes) {
+                ImportAction::Unchanged
+            } else {
+                differs
+            };
+            (path.clone(), action)
+        })
+        .collect();
+    let mut names: Vec<&String> = bundle.config.profiles.keys().collect();
+    names.sort();
+    let profiles = names
+        .into_iter()
+        .map(|name| {
+            let action = if !cfg.profiles.contains_key(name) {
+                ImportAction::Create
+            } else if same_profile(cfg, &bundle.config, name) {
+                ImportAction::Unchanged
+            } else {
+                differs
+            };
+            (name.clone(), action)
+        })
+        .collect();
+    ImportPlan { files, profiles }
+}
+
+/// Carry out `plan`: write the created and overwritten files into `lib` and
+/// merge those profiles into `cfg`. The caller saves `cfg`.
+///
+/// # Errors
+/// Returns an error, before writing anything, if `plan` has conflicts or a
+/// file would be written through a symlink in the library; otherwise if a
+/// file cannot be written.
+pub fn apply_import(
+    cfg: &mut Config,
+    lib: &Path,
+    bundle: &Bundle,
+    plan: &ImportPlan,
+) -> Result<(), String> {
+    let conflicts = plan.conflicts();
+    if !conflicts.is_empty() {
+        return Err(format!("Import has conflicts:\n{}", conflicts.join("\n")));
+    }
+    let changes =
+        |action: &ImportAction| matches!(action, ImportAction::Create | ImportAction::Overwrite);
+    let linked: Vec<String> = plan
+        .files
+        .iter()
+        .filter(|(_, action)| changes(action))
+        .filter_map(|(path, _)| symlink_on_path(lib, path))
+        .map(|link| link.display().to_string())
+        .collect();
+    if !linked.is_empty() {
+        return Err(format!(
+            "Refusing to write through symlinks in the library:\n{}",
+            linked.join("\n")
+        ));
+    }
+    for (path, _) in plan.files.iter().filter(|(_, action)| changes(action)) {
+        let target = lib.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+        }
+        fs::write(&target, &bundle.files[path])
+            .map_err(|e| format!("Failed to write {}: {e}", target.display()))?;
+    }
+    for (name, _) in plan.profiles.iter().filter(|(_, action)| changes(action)) {
+        let source = &bundle.config;
+        cfg.profiles
+            .insert(name.clone(), source.profiles[name].clone());
+        match source.extends.get(name) {
+            Some(parent) => cfg.extends.insert(name.clone(), parent.clone()),
+            None => cfg.extends.remove(name),
+        };
+        match source.meta.get(name) {
+            Some(meta) => cfg.meta.insert(name.clone(), meta.clone()),
+            None => cfg.meta.remove(name),
+        };
+    }
+    Ok(())
+}
+
+/// The first symlink among the entries from `lib` down to `lib/path`, if any.
+fn symlink_on_path(lib: &Path, path: &Path) -> Option<PathBuf> {
+    let mut entry = lib.to_path_buf();
+    path.components().find_map(|component| {
+        entry.push(component);
+        entry.is_symlink().then(|| entry.clone())
+    })
+}
+
+/// Import the bundle at `bundle_path` into the config and library, then
+/// validate the result.
+///
+/// Every collision is reported before anything is written; with `force`
+/// differing files and profiles are replaced, and with `prefix` the bundle
+/// is imported under new names (see [`Bundle::with_prefix`]). New profiles
+/// are appended to the config, leaving the rest of it as written; replacing
+/// existing ones rewrites the whole file in the canonical form of
+/// [`format_config`], with a warning first.
+///
+/// # Errors
+/// Returns an error if the bundle or config cannot be read, there are
+/// conflicts, writing fails, or the imported config does not validate.
+pub fn run_import_stdout(
+    bundle_path: &Path,
+    force: bool,
+    prefix: Option<&str>,
+    config_override: Option<&Path>,
+    strict_config: bool,
+    styled: bool,
+) -> Result<(), PrompterError> {
+    let paths = ResolvedPaths::resolve(config_override)?;
+    if paths.config_is_stdin() {
+        return Err("Cannot import into a config read from stdin"
+            .to_string()
+            .into());
+    }
+    let input = if paths.config.exists() {
+        read_config_with_path(&paths.config)?
+    } else {
+        String::new()
+    };
+    let mut cfg = parse_config(&input, strict_config)?;
+    let file = fs::File::open(bundle_path)
+        .map_err(|e| format!("Failed to open {}: {e}", bundle_path.display()))?;
+    let mut bundle = Bundle::read(file)?;
+    if let Some(prefix) = prefix {
+        bundle = bundle.with_prefix(prefix)?;
+    }
+
+    let plan = plan_import(&cfg, &paths.library, &bundle, force);
+    let conflicts = plan.conflicts();
+    let mut out = io::stdout().lock();
+    if !conflicts.is_empty() {
+        for conflict in &conflicts {
+            writeln!(out, "{conflict}")?;
+        }
+        return Err(format!(
+            "{} conflict(s); nothing was imported (pass --force to overwrite, or --prefix to \
+             import under new names)",
+            conflicts.len()
+        )
+        .into());
+    }
+    apply_import(&mut cfg, &paths.library, &bundle, &plan)?;
+    for (path, action) in &plan.files {
+        match action {
+            ImportAction::Create => writeln!(out, "added {}", path.display())?,
+            ImportAction::Overwrite => writeln!(out, "replaced {}", path.display())?,
+            ImportAction::Unchanged | ImportAction::Conflict => {}
+        }
+    }
+    let mut added = Vec::new();
+    let mut replaced = false;
+    for (name, action) in &plan.profiles {
+        match action {
+            ImportAction::Create => {
+                writeln!(out, "added profile {name}")?;
+                added.push(name);
+            }
+            ImportAction::Overwrite => {
+                writeln!(out, "replaced profile {name}")?;
+                replaced = true;
+            }
+            ImportAction::Unchanged | ImportAction::Conflict => {}
+        }
+    }
+    if replaced || !added.is_empty() {
+        if let Some(parent) = paths.config.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+        }
+        let text = if replaced {
+            eprintln!(
+                "{}",
+                warning_text(
+                    &format!(
+                        "Rewriting {} in canonical form to replace profiles; trailing \
+                         comments and unknown keys are dropped",
+                        paths.config.display()
+                    ),
+                    styled
+                )
+            );
+            cfg.toml_text(Some(FMT_WIDTH), &config_comments(&input))
+        } else {
+            let mut text = input;
+            for name in added {
+                if !text.is_empty() {
+                    text.push_str(if text.ends_with('\n') { "\n" } else { "\n\n" });
+                }
+                text.push_str(&cfg.profile_section(name, Some(FMT_WIDTH)).join("\n"));
+                text.push('\n');
+            }
+            text
+        };
+        fs::write(&paths.config, text)
+            .map_err(|e| format!("Failed to write {}: {e}", paths.config.display()))?;
+    }
+
+    match validate(&cfg, &paths.library) {
+        Ok(()) => {
+            writeln!(
+                out,
+                "{}",
+                success_text("Imported; configuration is valid", styled)
+            )?;
+            Ok(())
+        }
+        Err(e) => Err(format!("Imported, but the configuration is invalid:\n{e}").into()),
+    }
+}
+
+/// Aggregate size information for a rendered profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileStats {
+    /// Size of each included file, in render order
+    pub files: Vec<FileStats>,
+    /// Total bytes across all included files
+    pub bytes: usize,
+    /// Total lines across all included files
+    pub lines: usize,
+}
+
+/// Size information for a single included file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    /// Path of the file on disk
+    pub path: PathBuf,
+    /// Bytes included from the file
+    pub bytes: usize,
+    /// Lines included from the file
+    pub lines: usize,
+}
+
+impl ProfileStats {
+    /// The file contributing the most bytes, if any files were included.
+    #[must_use]
+    pub fn largest(&self) -> Option<&FileStats> {
+        // Earliest file wins ties so the result follows render order
+        self.files.iter().rev().max_by_key(|f| f.bytes)
+    }
+}
+
+/// Compute [`ProfileStats`] for the files `profile` would render.
+///
+/// Files skipped by `dedup` are not counted, and line ranges are honored.
+///
+/// # Errors
+/// Returns an error if resolution fails or a file cannot be read.
+pub fn profile_stats(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    dedup: DedupMode,
+) -> Result<ProfileStats, String> {
+    let files =
+        resolve_ranges(cfg, lib, profile, &ResolveOptions::default()).map_err(|e| e.to_string())?;
+    let mut stats = ProfileStats::default();
+    let mut seen_hashes = HashSet::new();
+    for (path, range) in files {
+        let bytes = read_snippet(&path, range)?;
+        if dedup == DedupMode::Content && !seen_hashes.insert(Sha256::digest(&bytes)) {
+            continue;
+        }
+        let lines = bytes.split_inclusive(|&b| b == b'\n').count();
+        stats.bytes += bytes.len();
+        stats.lines += lines;
+        stats.files.push(FileStats {
+            path,
+            bytes: bytes.len(),
+            lines,
+        });
+    }
+    Ok(stats)
+}
+
+/// Write `stats` as a table, showing paths relative to `lib` where possible.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn write_stats(
+    stats: &ProfileStats,
+    lib: &Path,
+    styled: bool,
+    mut w: impl Write,
+) -> io::Result<()> {
+    let display = |path: &Path| path.strip_prefix(lib).unwrap_or(path).display().to_string();
+    let total = format!("Total ({} files)", stats.files.len());
+    let width = stats
+        .files
+        .iter()
+        .map(|f| display(&f.path).len())
+        .chain([total.len(), "File".len()])
+        .max()
+        .unwrap_or(0);
+
+    let header = format!("{:<width$}  {:>10}  {:>8}", "File", "Bytes", "Lines");
+    if styled {
+        writeln!(w, "{}", header.bold())?;
+    } else {
+        writeln!(w, "{header}")?;
+    }
+    for f in &stats.files {
+        writeln!(
+            w,
+            "{:<width$}  {:>10}  {:>8}",
+            display(&f.path),
+            f.bytes,
+            f.lines
+        )?;
+    }
+    writeln!(
+        w,
+        "{:<width$}  {:>10}  {:>8}",
+        total, stats.bytes, stats.lines
+    )?;
+    if let Some(largest) = stats.largest() {
+        writeln!(
+            w,
+            "Largest: {} ({} bytes)",
+            display(&largest.path),
+            largest.bytes
+        )?;
+    }
+    Ok(())
+}
+
+/// Print stats for a profile to stdout.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, the
+/// profile fails to resolve, or a file cannot be read.
+pub fn run_stats_stdout(
+    profile: &str,
+    dedup: DedupMode,
+    config_override: Option<&Path>,
+    strict_config: bool,
+    styled: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    let stats = profile_stats(&cfg, &lib, profile, dedup)?;
+    Ok(write_stats(&stats, &lib, styled, io::stdout())?)
+}
+
+/// How two profiles differ, from [`diff_profiles`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileDiff {
+    /// Files only the first profile renders
+    pub only_a: BTreeSet<PathBuf>,
+    /// Files only the second profile renders
+    pub only_b: BTreeSet<PathBuf>,
+    /// Files both profiles render
+    pub shared: BTreeSet<PathBuf>,
+    /// The files both render, in each profile's order, when the orders differ
+    pub reordered: Option<(Vec<PathBuf>, Vec<PathBuf>)>,
+    /// Unified diff of the rendered output, when it was compared and differs
+    pub content: Option<String>,
+}
+
+impl ProfileDiff {
+    /// Whether the profiles render the same files in the same order (and the
+    /// same output, if it was compared).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.only_a.is_empty()
+            && self.only_b.is_empty()
+            && self.reordered.is_none()
+            && self.content.is_none()
+    }
+}
+
+/// The files profile `name` resolves to, as a sorted set.
+///
+/// # Errors
+/// Returns the same errors as [`resolve`].
+pub fn profile_file_sets(
+    cfg: &Config,
+    lib: &Path,
+    name: &str,
+) -> Result<BTreeSet<PathBuf>, ResolveError> {
+    resolve(cfg, lib, name).map(|files| file_set(&files))
+}
+
+fn file_set(files: &[PathBuf]) -> BTreeSet<PathBuf> {
+    files.iter().cloned().collect()
+}
+
+/// Compare the files profiles `a` and `b` resolve to, and with `content`
+/// also their rendered output (without the date-bearing system prefix).
+///
+/// # Examples
+/// ```
+/// use prompter::{Config, diff_profiles};
+///
+/// let lib = tempfile::tempdir().unwrap();
+/// for name in ["a.md", "b.md"] {
+///     std::fs::write(lib.path().join(name), name).unwrap();
+/// }
+/// let mut cfg = Config::new();
+/// cfg.add_profile("old", ["a.md", "b.md"]);
+/// cfg.add_profile("new", ["b.md", "a.md"]);
+///
+/// let diff = diff_profiles(&cfg, lib.path(), "old", "new", false).unwrap();
+/// assert!(diff.only_a.is_empty() && diff.only_b.is_empty());
+/// assert!(diff.reordered.is_some());
+/// ```
+///
+/// # Errors
+/// Returns one line per profile that fails to resolve, or an error if a file
+/// cannot be rendered.
+pub fn diff_profiles(
+    cfg: &Config,
+    lib: &Path,
+    a: &str,
+    b: &str,
+    content: bool,
+) -> Result<ProfileDiff, PrompterError> {
+    // A range of a file counts as its own entry, so narrowing one shows up
+    let side = |profile: &str| {
+        resolve_ranges(cfg, lib, profile, &ResolveOptions::default())
+            .map(|files| {
+                files
+                    .iter()
+                    .map(|(path, range)| labeled_path(path, *range))
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|e| format!("{profile}: {e}"))
+    };
+    let (files_a, files_b) = match (side(a), side(b)) {
+        (Ok(files_a), Ok(files_b)) => (files_a, files_b),
+        (res_a, res_b) => {
+            let errors: Vec<String> = [res_a.err(), res_b.err()].into_iter().flatten().collect();
+            return Err(errors.join("\n").into());
+        }
+    };
+
+    let (set_a, set_b) = (file_set(&files_a), file_set(&files_b));
+    let shared: BTreeSet<PathBuf> = set_a.intersection(&set_b).cloned().collect();
+    let in_shared_order = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+        files
+            .into_iter()
+            .filter(|path| shared.contains(path))
+            .collect()
+    };
+    let (order_a, order_b) = (in_shared_order(files_a), in_shared_order(files_b));
+    let mut diff = ProfileDiff {
+        only_a: set_a.difference(&set_b).cloned().collect(),
+        only_b: set_b.difference(&set_a).cloned().collect(),
+        reordered: (order_a != order_b).then_some((order_a, order_b)),
+        shared,
+        content: None,
+    };
+
+    if content {
+        let opts = RenderOptions::default().system_prefix(false);
+        let render_lossy = |profile: &str| -> Result<String, PrompterError> {
+            let mut out = Vec::new();
+            render(cfg, lib, profile, &opts, &mut out)?;
+            Ok(String::from_utf8_lossy(&out).into_owned())
+        };
+        let (text_a, text_b) = (render_lossy(a)?, render_lossy(b)?);
+        if text_a != text_b {
+            let text = similar::TextDiff::from_lines(&text_a, &text_b)
+                .unified_diff()
+                .header(a, b)
+                .to_string();
+            diff.content = Some(text);
+        }
+    }
+    Ok(diff)
+}
+
+/// Write `diff` between profiles `a` and `b` as a set diff.
+///
+/// Paths are shown relative to `lib` where possible. Files only in `a` are
+/// marked `-` and files only in `b` `+`, in red and green when `styled`.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn write_profile_diff(
+    diff: &ProfileDiff,
+    lib: &Path,
+    a: &str,
+    b: &str,
+    styled: bool,
+    mut w: impl Write,
+) -> io::Result<()> {
+    let display = |path: &PathBuf| path.strip_prefix(lib).unwrap_or(path).display().to_string();
+    if diff.is_empty() {
+        return writeln!(w, "{a} and {b} are identical");
+    }
+    let sections = [
+        (format!("Only in {a}"), "-", &diff.only_a, Some(Color::Red)),
+        (
+            format!("Only in {b}"),
+            "+",
+            &diff.only_b,
+            Some(Color::Green),
+        ),
+        ("Shared".to_string(), " ", &diff.shared, None),
+    ];
+    for (title, marker, files, color) in sections {
+        if files.is_empty() {
+            continue;
+        }
+        writeln!(w, "{title} ({}):", files.len())?;
+        for path in files {
+            let line = format!("  {marker} {}", display(path));
+            match color {
+                Some(color) if styled => writeln!(w, "{}", line.color(color))?,
+                _ => writeln!(w, "{line}")?,
+            }
+        }
+    }
+    if let Some((order_a, order_b)) = &diff.reordered {
+        writeln!(w, "Shared files in a different order:")?;
+        for (name, order) in [(a, order_a), (b, order_b)] {
+            let order: Vec<String> = order.iter().map(display).collect();
+            writeln!(w, "  {name}: {}", order.join(", "))?;
+        }
+    }
+    if let Some(content) = &diff.content {
+        w.write_all(content.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Print how profiles `a` and `b` differ to stdout.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, either
+/// profile fails to resolve, a file cannot be rendered, or the profiles
+/// differ.
+pub fn run_diff_stdout(
+    a: &str,
+    b: &str,
+    content: bool,
+    config_override: Option<&Path>,
+    strict_config: bool,
+    styled: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    let diff = diff_profiles(&cfg, &lib, a, b, content)?;
+    write_profile_diff(&diff, &lib, a, b, styled, io::stdout())?;
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{a} and {b} differ").into())
+    }
+}
+
+/// Create a new snippet file at `path` inside the library.
+///
+/// Parent directories are created as needed. Existing files are only replaced
+/// when `force` is set.
+///
+/// # Returns
+/// The canonical path of the created file.
+///
+/// # Errors
+/// Returns an error if:
+/// - `path` is absolute or escapes the library with `..`
+/// - The file exists and `force` is not set
+/// - The file or its directories cannot be written
+pub fn new_snippet(
+    lib: &Path,
+    path: &str,
+    content: Option<&str>,
+    force: bool,
+) -> Result<PathBuf, String> {
+    let rel = Path::new(path);
+    if path.is_empty()
+        || rel.is_absolute()
+        || rel
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(format!(
+            "Snippet path must be relative to the library: {path}"
+        ));
+    }
+    let target = lib.join(rel);
+    if target.exists() && !force {
+        return Err(format!(
+            "{} already exists (use --force to overwrite)",
+            target.display()
+        ));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&target, content.unwrap_or_default())
+        .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+    fs::canonicalize(&target).map_err(|e| format!("Failed to resolve {}: {}", target.display(), e))
+}
+
+/// Editor used when neither `$EDITOR` nor `$VISUAL` is set.
+const FALLBACK_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Open `path` in the user's editor and wait for it to exit.
+///
+/// Uses `$EDITOR`, then `$VISUAL`, then `vi` (`notepad` on Windows). The variable may include
+/// arguments, such as `code --wait`. When `line` is given, editors that can
+/// jump to it are asked to (see [`editor_args`]); others open at the top.
+///
+/// # Errors
+/// Returns an error if no editor can be started or it exits with a failure
+/// status.
+pub fn open_in_editor(path: &Path, line: Option<usize>) -> Result<(), String> {
+    let configured = ["EDITOR", "VISUAL"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|v| !v.trim().is_empty());
+    let editor = configured
+        .clone()
+        .unwrap_or_else(|| FALLBACK_EDITOR.to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or_default();
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .args(editor_args(program, path, line))
+        .status()
+        .map_err(|e| {
+            if configured.is_none() && e.kind() == io::ErrorKind::NotFound {
+                format!(
+                    "No editor found: set $EDITOR or $VISUAL ({FALLBACK_EDITOR} is not installed)"
+                )
+            } else {
+                format!("Failed to start editor '{editor}': {e}")
+            }
+        })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("Editor '{editor}' exited with {status}"))
+    }
+}
+
+/// The arguments that open `path` in the editor `program`, at `line` when
+/// given: `+<line>` for vi, vim, nvim, nano, and emacs, and `-g <path>:<line>`
+/// for VS Code. Other editors, including the `notepad` fallback, have no
+/// common syntax for it, so they only get the path. Editors are recognized
+/// by file name, ignoring case and any extension such as `.exe`.
+fn editor_args(program: &str, path: &Path, line: Option<usize>) -> Vec<OsString> {
+    let name = Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program)
+        .to_ascii_lowercase();
+    match (line, name.as_str()) {
+        (Some(n), "vi" | "vim" | "nvim" | "nano" | "emacs") => {
+            vec![format!("+{n}").into(), path.into()]
+        }
+        (Some(n), "code") => {
+            let mut target = path.as_os_str().to_owned();
+            target.push(format!(":{n}"));
+            vec!["-g".into(), target]
+        }
+        _ => vec![path.into()],
+    }
+}
+
+/// Collect every markdown file under `lib`, relative to it, sorted by path.
+///
+/// A missing library yields an empty list.
+///
+/// # Errors
+/// Returns an error if a directory inside the library cannot be read.
+pub fn library_files(lib: &Path) -> io::Result<Vec<PathBuf>> {
+    fn walk(lib: &Path, dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                walk(lib, &path, out)?;
+            } else if path.to_str().is_some_and(has_markdown_extension) {
+                out.push(path.strip_prefix(lib).unwrap_or(&path).to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if lib.is_dir() {
+        walk(lib, lib, &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Collect markdown files under `lib` that no profile resolves to, relative
+/// to `lib` and sorted.
+///
+/// A file named by any `depends_on` entry, `pre_prompt_file`, or
+/// `post_prompt_file` is never reported, even when its profile fails to
+/// resolve, and paths are compared after folding `.` and `..` away. Hidden
+/// directories are skipped. Symlinks are skipped too unless
+/// `follow_symlinks` is set, in which case each directory is visited once.
+///
+/// # Errors
+/// Returns an error if a directory inside the library cannot be read.
+pub fn orphaned_files(cfg: &Config, lib: &Path, follow_symlinks: bool) -> io::Result<Vec<PathBuf>> {
+    fn walk(
+        dir: &Path,
+        follow_symlinks: bool,
+        seen: &mut HashSet<PathBuf>,
+        out: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+        if !seen.insert(fs::canonicalize(dir)?) {
+            return Ok(());
+        }
+        // Real entries before symlinks, so a linked directory is reported
+        // under its own path when both are reachable.
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            entries.push((file_type.is_symlink(), entry.file_name(), entry, file_type));
+        }
+        entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        for (_, name, entry, mut file_type) in entries {
+            let path = entry.path();
+            if file_type.is_symlink() {
+                if !follow_symlinks {
+                    continue;
+                }
+                let Ok(meta) = fs::metadata(&path) else {
+                    continue;
+                };
+                file_type = meta.file_type();
+            }
+            if file_type.is_dir() {
+                if !name.to_string_lossy().starts_with('.') {
+                    walk(&path, follow_symlinks, seen, out)?;
+                }
+            } else if path.to_str().is_some_and(has_markdown_extension) {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if lib.is_dir() {
+        walk(lib, follow_symlinks, &mut HashSet::new(), &mut files)?;
+    }
+    let used = file_profiles(cfg, lib);
+    let referenced = referenced_files(cfg, lib);
+    let mut orphans: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|path| {
+            let path = normalize_lexically(path);
+            !used.contains_key(&path) && !referenced.contains(&path)
+        })
+        .map(|path| {
+            path.strip_prefix(lib)
+                .map_or_else(|_| path.clone(), Path::to_path_buf)
+        })
+        .collect();
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// Absolute paths of every library file referenced by some profile or by
+/// the prompt file keys, with `.` and `..` folded away.
+fn referenced_files(cfg: &Config, lib: &Path) -> HashSet<PathBuf> {
+    let deps = cfg
+        .profiles
+        .values()
+        .flatten()
+        .filter(|dep| is_markdown_dep(dep))
+        .map(|dep| lib.join(dep_base(&expand_env_lenient(dep))));
+    let prompts = cfg.prompt_files().map(|(key, file)| {
+        prompt_file_path(cfg, lib, key, file).unwrap_or_else(|_| lib.join(expand_env_lenient(file)))
+    });
+    deps.chain(prompts)
+        .map(|path| normalize_lexically(&path))
+        .collect()
+}
+
+/// Write the library's markdown files, one per line.
+///
+/// In `long` mode each line also shows the size in bytes, the modification
+/// date, and whether any profile references the file.
+///
+/// # Errors
+/// Returns an error if the library cannot be read or writing fails.
+pub fn list_library(cfg: &Config, lib: &Path, long: bool, mut w: impl Write) -> io::Result<()> {
+    let files = library_files(lib)?;
+    if !long {
+        for file in files {
+            writeln!(w, "{}", file.display())?;
+        }
+        return Ok(());
+    }
+
+    let referenced = referenced_files(cfg, lib);
+    let width = files
+        .iter()
+        .map(|f| f.display().to_string().len())
+        .max()
+        .unwrap_or(0);
+    for file in files {
+        let meta = fs::metadata(lib.join(&file))?;
+        let modified = meta.modified().map_or_else(
+            |_| "-".to_string(),
+            |t| {
+                DateTime::<Local>::from(t)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            },
+        );
+        let status = if referenced.contains(&normalize_lexically(&lib.join(&file))) {
+            "referenced"
+        } else {
+            "unreferenced"
+        };
+        writeln!(
+            w,
+            "{:<width$}  {:>8}  {modified}  {status}",
+            file.display().to_string(),
+            meta.len()
+        )?;
+    }
+    Ok(())
+}
+
+/// A line in a library file that matched a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// File path relative to the library
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    /// The full line, without its newline
+    pub text: String,
+    /// Byte ranges of each match within `text`
+    pub spans: Vec<(usize, usize)>,
+}
+
+/// Search every markdown file in the library for lines matching `query`.
+///
+/// Results are in path order, then line order. Files that are not valid
+/// UTF-8 are searched lossily.
+///
+/// # Errors
+/// Returns an error if `query` is not a valid regular expression (with
+/// `regex` set) or the library cannot be read.
+pub fn search_library(
+    lib: &Path,
+    query: &str,
+    case_sensitive: bool,
+    regex: bool,
+) -> Result<Vec<SearchMatch>, String> {
+    let pattern = if regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    let re = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))?;
+
+    let mut matches = Vec::new();
+    for file in library_files(lib).map_err(|e| e.to_string())? {
+        let full = lib.join(&file);
+        let bytes =
+            fs::read(&full).map_err(|e| format!("Failed to read {}: {e}", full.display()))?;
+        let text = String::from_utf8_lossy(&bytes);
+        for (idx, line) in text.lines().enumerate() {
+            let spans: Vec<_> = re.find_iter(line).map(|m| (m.start(), m.end())).collect();
+            if !spans.is_empty() {
+                matches.push(SearchMatch {
+                    path: file.clone(),
+                    line: idx + 1,
+                    text: line.to_string(),
+                    spans,
+                });
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Write search results as `path:line: text`, highlighting matches when
+/// `styled`.
+///
+/// # Errors
+/// Returns an error if writing fails.
+pub fn write_search_matches(
+    matches: &[SearchMatch],
+    styled: bool,
+    mut w: impl Write,
+) -> io::Result<()> {
+    for m in matches {
+        let mut text = String::with_capacity(m.text.len());
+        let mut last = 0;
+        for &(start, end) in &m.spans {
+            text.push_str(&m.text[last..start]);
+            let hit = &m.text[start..end];
+            if styled {
+                text.push_str(&hit.bright_red().bold().to_string());
+            } else {
+                text.push_str(hit);
+            }
+            last = end;
+        }
+        text.push_str(&m.text[last..]);
+        writeln!(w, "{}:{}: {text}", m.path.display(), m.line)?;
+    }
+    Ok(())
+}
+
+/// A problem found in a library file by [`check_library`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileIssue {
+    /// The file is not valid UTF-8; `offset` is the byte offset of the first
+    /// invalid sequence
+    InvalidUtf8 {
+        /// Byte offset of the first invalid sequence
+        offset: usize,
+    },
+    /// The file is non-empty and does not end with a newline
+    MissingTrailingNewline,
+}
+
+/// A library file together with a problem found in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryIssue {
+    /// File path relative to the library
+    pub path: PathBuf,
+    /// What is wrong with it
+    pub issue: FileIssue,
+}
+
+impl std::fmt::Display for LibraryIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.issue {
+            FileIssue::InvalidUtf8 { offset } => {
+                write!(f, "{}: invalid UTF-8 at byte {offset}", self.path.display())
+            }
+            FileIssue::MissingTrailingNewline => {
+                write!(f, "{}: no trailing newline", self.path.display())
+            }
+        }
+    }
+}
+
+/// Check every markdown file in the library for invalid UTF-8 and a missing
+/// trailing newline.
+///
+/// Issues are returned in path order; a file can have both.
+///
+/// # Errors
+/// Returns an error if the library or one of its files cannot be read.
+pub fn check_library(lib: &Path) -> Result<Vec<LibraryIssue>, String> {
+    let mut issues = Vec::new();
+    for file in library_files(lib).map_err(|e| e.to_string())? {
+        let full = lib.join(&file);
+        let bytes =
+            fs::read(&full).map_err(|e| format!("Failed to read {}: {e}", full.display()))?;
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            issues.push(LibraryIssue {
+                path: file.clone(),
+                issue: FileIssue::InvalidUtf8 {
+                    offset: e.valid_up_to(),
+                },
+            });
+        }
+        if bytes.last().is_some_and(|&b| b != b'\n') {
+            issues.push(LibraryIssue {
+                path: file,
+                issue: FileIssue::MissingTrailingNewline,
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// Config table that turns `prompter lint` rules on and off.
+const LINT_SECTION: &str = "lint";
+
+/// A `prompter lint` rule. Each can be turned off in the config's `[lint]`
+/// table, e.g. `trailing_whitespace = false`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintRule {
+    /// Spaces or tabs at the end of a line (fixable)
+    TrailingWhitespace,
+    /// A non-empty file that does not end with a newline (fixable)
+    FinalNewline,
+    /// Windows `\r\n` line endings (fixable)
+    Crlf,
+    /// A relative markdown link to a file that does not exist
+    BrokenLinks,
+    /// A `#` heading with no text
+    EmptyHeadings,
+}
+
+impl LintRule {
+    /// Every rule, in the order findings are reported.
+    pub const ALL: [Self; 5] = [
+        Self::TrailingWhitespace,
+        Self::FinalNewline,
+        Self::Crlf,
+        Self::BrokenLinks,
+        Self::EmptyHeadings,
+    ];
+
+    /// The rule's key in the `[lint]` table.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::TrailingWhitespace => "trailing_whitespace",
+            Self::FinalNewline => "final_newline",
+            Self::Crlf => "crlf",
+            Self::BrokenLinks => "broken_links",
+            Self::EmptyHeadings => "empty_headings",
+        }
+    }
+
+    /// The rule whose `[lint]` key is `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+
+    /// Whether `prompter lint --fix` can repair findings of this rule.
+    #[must_use]
+    pub const fn fixable(self) -> bool {
+        matches!(
+            self,
+            Self::TrailingWhitespace | Self::FinalNewline | Self::Crlf
+        )
+    }
+}
+
+impl std::fmt::Display for LintRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A problem `prompter lint` found in a library file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// File path, relative to the library when it is inside it
+    pub path: PathBuf,
+    /// 1-based line the finding is on
+    pub line: usize,
+    /// Rule that reported it
+    pub rule: LintRule,
+    /// What is wrong
+    pub message: String,
+}
+
+impl std::fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.path.display(),
+            self.line,
+            self.rule,
+            self.message
+        )
+    }
+}
+
+/// What [`lint_library`] found and fixed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintReport {
+    /// Findings left after fixing, by file, then line, then rule
+    pub findings: Vec<LintFinding>,
+    /// Files rewritten by `--fix`, in the same form as finding paths
+    pub fixed: Vec<PathBuf>,
+}
+
+/// Lint the text of one markdown file.
+///
+/// `path` is how findings name the file. Relative link targets are looked up
+/// next to `file`, and targets starting with `/` from `lib`. Links and
+/// headings inside fenced code blocks are ignored.
+///
+/// # Examples
+/// ```
+/// use prompter::{Config, LintRule, lint_text};
+/// use std::path::Path;
+///
+/// let cfg = Config::new();
+/// let dir = tempfile::tempdir().unwrap();
+/// let file = dir.path().join("a.md");
+/// let findings = lint_text(&cfg, "# Title \n##\n", Path::new("a.md"), &file, dir.path());
+/// let rules: Vec<_> = findings.iter().map(|f| (f.line, f.rule)).collect();
+/// assert_eq!(rules, [(1, LintRule::TrailingWhitespace), (2, LintRule::EmptyHeadings)]);
+/// ```
+#[must_use]
+pub fn lint_text(
+    cfg: &Config,
+    text: &str,
+    path: &Path,
+    file: &Path,
+    lib: &Path,
+) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    let mut report = |line: usize, rule: LintRule, message: String| {
+        if cfg.lint_enabled(rule) {
+            findings.push(LintFinding {
+                path: path.to_path_buf(),
+                line,
+                rule,
+                message,
+            });
+        }
+    };
+    let dir = file.parent().unwrap_or(lib);
+
+    let crlf: Vec<usize> = text
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(_, line)| line.ends_with("\r\n"))
+        .map(|(idx, _)| idx + 1)
+        .collect();
+    if let Some(&first) = crlf.first() {
+        report(
+            first,
+            LintRule::Crlf,
+            format!("Windows line endings (\\r\\n) on {} line(s)", crlf.len()),
+        );
+    }
+
+    let mut fence: Option<&str> = None;
+    let mut line_count = 0;
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        line_count = line_no;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.ends_with([' ', '\t']) {
+            report(
+                line_no,
+                LintRule::TrailingWhitespace,
+                "trailing whitespace".into(),
+            );
+        }
+        let trimmed = line.trim_start();
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = match fence {
+                None => Some(marker),
+                Some(open) if open == marker => None,
+                open => open,
+            };
+            continue;
+        }
+        if fence.is_some() {
+            continue;
+        }
+        if is_empty_heading(line) {
+            report(
+                line_no,
+                LintRule::EmptyHeadings,
+                "heading has no text".into(),
+            );
+        }
+        for target in MARKDOWN_LINK.captures_iter(line).filter_map(|c| c.get(1)) {
+            let target = target.as_str();
+            if let Some(missing) = broken_link(target, dir, lib) {
+                report(
+                    line_no,
+                    LintRule::BrokenLinks,
+                    format!("link target not found: {target} ({})", missing.display()),
+                );
+            }
+        }
+    }
+
+    if !text.is_empty() && !text.ends_with('\n') {
+        report(
+            line_count,
+            LintRule::FinalNewline,
+            "no newline at end of file".into(),
+        );
+    }
+    findings.sort_by_key(|f| f.line);
+    findings
+}
+
+/// An inline markdown link or image; group 1 is the target.
+static MARKDOWN_LINK: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r#"!?\[[^\]]*\]\(\s*<?([^)\s>]+)>?(?:\s+"[^"]*")?\s*\)"#)
+        .expect("link pattern is valid")
+});
+
+/// Whether `line` is an ATX heading (`#` to `######`) with no text.
+fn is_empty_heading(line: &str) -> bool {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let rest = &line[indent..];
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    if indent > 3 || !(1..=6).contains(&level) {
+        return false;
+    }
+    let text = &rest[level..];
+    (text.is_empty() || text.starts_with([' ', '\t'])) && text.trim().trim_matches('#').is_empty()
+}
+
+/// The path a relative link `target` points to if nothing exists there;
+/// URLs, `mailto:` and in-page `#anchors` are never broken.
+fn broken_link(target: &str, dir: &Path, lib: &Path) -> Option<PathBuf> {
+    let has_scheme = target.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    if has_scheme || target.starts_with('#') || target.starts_with("//") {
+        return None;
+    }
+    let file = target.split(['#', '?']).next().unwrap_or(target);
+    let path = file
+        .strip_prefix('/')
+        .map_or_else(|| dir.join(file), |rooted| lib.join(rooted));
+    (!path.exists()).then_some(path)
+}
+
+/// `text` with the enabled fixable rules applied: `\r\n` becomes `\n`,
+/// trailing spaces and tabs are removed, and a final newline is added.
+#[must_use]
+pub fn fix_lint(cfg: &Config, text: &str) -> String {
+    let crlf = cfg.lint_enabled(LintRule::Crlf);
+    let trim = cfg.lint_enabled(LintRule::TrailingWhitespace);
+    let mut out = String::with_capacity(text.len() + 1);
+    for line in text.split_inclusive('\n') {
+        let (body, ending) = line.strip_suffix("\r\n").map_or_else(
+            || {
+                line.strip_suffix('\n')
+                    .map_or((line, ""), |body| (body, "\n"))
+            },
+            |body| (body, if crlf { "\n" } else { "\r\n" }),
+        );
+        out.push_str(if trim {
+            body.trim_end_matches([' ', '\t'])
+        } else {
+            body
+        });
+        out.push_str(ending);
+    }
+    if cfg.lint_enabled(LintRule::FinalNewline) && !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// Lint every markdown file in the library, or with `profile` only the
+/// files it resolves to.
+///
+/// With `fix`, files that are valid UTF-8 are first rewritten with
+/// [`fix_lint`]; the report then holds only what is left.
+///
+/// # Errors
+/// Returns an error if the profile does not resolve or a file cannot be
+/// read or written.
+pub fn lint_library(
+    cfg: &Config,
+    lib: &Path,
+    profile: Option<&str>,
+    fix: bool,
+) -> Result<LintReport, PrompterError> {
+    let files: Vec<PathBuf> = match profile {
+        Some(name) => resolve(cfg, lib, name)?,
+        None => library_files(lib)?
+            .into_iter()
+            .map(|file| lib.join(file))
+            .collect(),
+    };
+
+    let mut report = LintReport::default();
+    for file in files {
+        let shown = file.strip_prefix(lib).unwrap_or(&file).to_path_buf();
+        let bytes =
+            fs::read(&file).map_err(|e| format!("Failed to read {}: {e}", file.display()))?;
+        let mut text = String::from_utf8_lossy(&bytes).into_owned();
+        if fix && std::str::from_utf8(&bytes).is_ok() {
+            let fixed = fix_lint(cfg, &text);
+            if fixed != text {
+                fs::write(&file, &fixed)
+                    .map_err(|e| format!("Failed to write {}: {e}", file.display()))?;
+                report.fixed.push(shown.clone());
+                text = fixed;
+            }
+        }
+        report
+            .findings
+            .extend(lint_text(cfg, &text, &shown, &file, lib));
+    }
+    Ok(report)
+}
+
+/// Run `prompter lint`: print each fixed file and each remaining finding.
+///
+/// # Errors
+/// Returns an error if linting fails, or when findings remain after fixing.
+pub fn run_lint_stdout(
+    profile: Option<&str>,
+    fix: bool,
+    config_override: Option<&Path>,
+    strict_config: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    let report = lint_library(&cfg, &lib, profile, fix)?;
+    let mut out = io::stdout().lock();
+    for path in &report.fixed {
+        writeln!(out, "fixed {}", path.display())?;
+    }
+    for finding in &report.findings {
+        writeln!(out, "{finding}")?;
+    }
+    if report.findings.is_empty() {
+        return Ok(());
+    }
+    let files: HashSet<&Path> = report.findings.iter().map(|f| f.path.as_path()).collect();
+    let fixable = report.findings.iter().filter(|f| f.rule.fixable()).count();
+    let hint = if fixable > 0 && !fix {
+        format!("; {fixable} can be fixed with --fix")
+    } else {
+        String::new()
+    };
+    Err(format!(
+        "{} lint finding(s) in {} file(s){hint}",
+        report.findings.len(),
+        files.len()
+    )
+    .into())
+}
+
+/// Rough token count for `text`, at about four characters per token.
+///
+/// ```
+/// assert_eq!(prompter::estimate_tokens("12345678"), 2);
+/// assert_eq!(prompter::estimate_tokens("123456789"), 3);
+/// ```
+#[must_use]
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Format `n` with `,` between groups of three digits.
+fn with_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Aggregate figures for the whole library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryStats {
+    /// Every markdown file, relative to the library, with its size in bytes
+    pub files: Vec<(PathBuf, u64)>,
+    /// Sum of all file sizes
+    pub bytes: u64,
+    /// Estimated token count across all files
+    pub tokens: usize,
+    /// Files not referenced by any profile
+    pub unreferenced: usize,
+}
+
+impl LibraryStats {
+    /// The largest file, preferring the first in path order on ties.
+    #[must_use]
+    pub fn largest(&self) -> Option<&(PathBuf, u64)> {
+        self.files.iter().rev().max_by_key(|(_, bytes)| *bytes)
+    }
+
+    /// The smallest file, preferring the first in path order on ties.
+    #[must_use]
+    pub fn smallest(&self) -> Option<&(PathBuf, u64)> {
+        self.files.iter().min_by_key(|(_, bytes)| *bytes)
+    }
+}
+
+/// Gather [`LibraryStats`] for every markdown file in the library.
+///
+/// # Errors
+/// Returns an error if the library or one of its files cannot be read.
+pub fn library_stats(cfg: &Config, lib: &Path) -> Result<LibraryStats, String> {
+    let referenced = referenced_files(cfg, lib);
+    let mut stats = LibraryStats {
+        files: Vec::new(),
+        bytes: 0,
+        tokens: 0,
+        unreferenced: 0,
+    };
+    for file in library_files(lib).map_err(|e| e.to_string())? {
+        let full = lib.join(&file);
+        let bytes =
+            fs::read(&full).map_err(|e| format!("Failed to read {}: {e}", full.display()))?;
+        let size = bytes.len() as u64;
+        stats.bytes += size;
+        stats.tokens += estimate_tokens(&String::from_utf8_lossy(&bytes));
+        if !referenced.contains(&normalize_lexically(&full)) {
+            stats.unreferenced += 1;
+        }
+        stats.files.push((file, size));
+    }
+    Ok(stats)
+}
+
+/// Write library stats as aligned `label: value` lines.
+///
+/// # Errors
+/// Returns an error if writing fails.
+pub fn write_library_stats(stats: &LibraryStats, mut w: impl Write) -> io::Result<()> {
+    let file_size = |entry: Option<&(PathBuf, u64)>| {
+        entry.map_or_else(
+            || "-".to_string(),
+            |(path, bytes)| format!("{} ({} bytes)", path.display(), with_thousands(*bytes)),
+        )
+    };
+    let count = stats.files.len() as u64;
+    let average = stats.bytes.checked_div(count).unwrap_or(0);
+    let rows = [
+        ("Files", with_thousands(count)),
+        (
+            "Total size",
+            format!("{} bytes", with_thousands(stats.bytes)),
+        ),
+        ("Largest", file_size(stats.largest())),
+        ("Smallest", file_size(stats.smallest())),
+        ("Average size", format!("{} bytes", with_thousands(average))),
+        ("Est. tokens", with_thousands(stats.tokens as u64)),
+        ("Unreferenced", with_thousands(stats.unreferenced as u64)),
+    ];
+    for (label, value) in rows {
+        writeln!(w, "{:<14}{value}", format!("{label}:"))?;
+    }
+    Ok(())
+}
+
+/// Run a `library` subcommand against the configured library.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, or the
+/// library cannot be read.
+pub fn run_library_stdout(
+    command: &LibraryCommands,
+    config_override: Option<&Path>,
+    strict_config: bool,
+    styled: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    match command {
+        LibraryCommands::List { long } => Ok(list_library(&cfg, &lib, *long, io::stdout())?),
+        LibraryCommands::Search {
+            query,
+            case_sensitive,
+            regex,
+        } => {
+            let matches = search_library(&lib, query, *case_sensitive, *regex)?;
+            if matches.is_empty() {
+                return Err(format!("No matches for '{query}'").into());
+            }
+            Ok(write_search_matches(&matches, styled, io::stdout())?)
+        }
+        LibraryCommands::Check => {
+            let issues = check_library(&lib)?;
+            if issues.is_empty() {
+                println!("All library files OK");
+                return Ok(());
+            }
+            for issue in &issues {
+                println!("{issue}");
+            }
+            Err(format!("{} issue(s) found in library", issues.len()).into())
+        }
+        LibraryCommands::Stats => {
+            let stats = library_stats(&cfg, &lib)?;
+            Ok(write_library_stats(&stats, io::stdout())?)
+        }
+    }
+}
+
+/// Find the 1-based line of the `[profile]` section header in config text.
+#[must_use]
+pub fn profile_line(cfg_text: &str, profile: &str) -> Option<usize> {
+    logical_lines(cfg_text)
+        .into_iter()
+        .find(|(_, line)| {
+            strip_comments(line)
+                .trim()
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .is_some_and(|name| name.trim() == profile)
+        })
+        .map(|(line_no, _)| line_no)
+}
+
+/// Open the target of the `edit` subcommand in the user's editor.
+///
+/// With no target the config file opens at the top; profiles open it at
+/// their section header. After a config edit the result is validated.
+/// Files are resolved relative to the library and must already exist inside
+/// it.
+///
+/// # Errors
+/// Returns an error if the config is read from stdin, the profile or file
+/// does not exist, the file lies outside the library, the editor fails, or
+/// the edited config does not validate.
+pub fn run_edit(
+    target: Option<&EditTarget>,
+    config_override: Option<&Path>,
+    strict_config: bool,
+    styled: bool,
+) -> Result<(), PrompterError> {
+    let paths = ResolvedPaths::resolve(config_override)?;
+    if paths.config_is_stdin() {
+        return Err(PrompterError::Other(
+            "Cannot edit a config read from stdin".into(),
+        ));
+    }
+    match target {
+        None => {
+            open_in_editor(&paths.config, None)?;
+            validate_after_edit(&paths, strict_config, styled)
+        }
+        Some(EditTarget::Profile { name }) => {
+            let cfg_text = read_config_with_path(&paths.config)?;
+            let line =
+                profile_line(&cfg_text, name).ok_or_else(|| format!("Unknown profile: {name}"))?;
+            open_in_editor(&paths.config, Some(line))?;
+            validate_after_edit(&paths, strict_config, styled)
+        }
+        Some(EditTarget::File { path }) => {
+            let file = paths.library.join(path);
+            if !file.is_file() {
+                return Err(format!(
+                    "Snippet not found: {} (use 'prompter new {path}' to create it)",
+                    file.display()
+                )
+                .into());
+            }
+            if escapes(&paths.library, &file) {
+                return Err(format!("Path escapes the library: {}", file.display()).into());
+            }
+            Ok(open_in_editor(&file, None)?)
+        }
+    }
+}
+
+/// Re-read and validate the config after the editor exits.
+fn validate_after_edit(
+    paths: &ResolvedPaths,
+    strict: bool,
+    styled: bool,
+) -> Result<(), PrompterError> {
+    let cfg = Config::load(&paths.config, strict)?;
+    validate(&cfg, &paths.library).map_err(|errs| format!("Validation errors:\n{errs}"))?;
+    println!("{}", success_text("All profiles valid", styled));
+    Ok(())
+}
+
+/// Create a snippet in the configured library and print its path.
+///
+/// Convenience function used by the `new` subcommand; see [`new_snippet`].
+///
+/// # Errors
+/// Returns an error if the library location cannot be determined, the file
+/// cannot be created, or the editor fails.
+pub fn run_new_stdout(
+    path: &str,
+    content: Option<&str>,
+    edit: bool,
+    force: bool,
+    config_override: Option<&Path>,
+) -> Result<(), PrompterError> {
+    let lib = ResolvedPaths::resolve(config_override)?.library;
+    let created = new_snippet(&lib, path, content, force)?;
+    if edit {
+        open_in_editor(&created, None)?;
+    }
+    println!("{}", created.display());
+    Ok(())
+}
+
+/// How duplicate files are detected while rendering.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum DedupMode {
+    /// Skip files whose path was already included
+    #[default]
+    Path,
+    /// Additionally skip files whose content matches an already included file
+    Content,
+}
+
+/// The order in which resolved files are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum FileOrder {
+    /// Depth-first `depends_on` order, as resolved
+    #[default]
+    Config,
+    /// Alphabetical by path within the library
+    Alpha,
+    /// Least recently modified first
+    Mtime,
+}
+
+/// Whether the file at `path` was modified after `since`. Files whose time
+/// cannot be read are kept, so reading them reports the problem.
+fn modified_after(path: &Path, since: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .is_none_or(|modified| modified > since)
+}
+
+impl FileOrder {
+    /// Reorder resolved `files` in place; ties keep their resolved order.
+    fn apply(self, files: &mut [(PathBuf, Option<LineRange>)], lib: &Path) {
+        match self {
+            Self::Config => {}
+            Self::Alpha => {
+                files.sort_by(|(a, _), (b, _)| {
+                    a.strip_prefix(lib)
+                        .unwrap_or(a)
+                        .cmp(b.strip_prefix(lib).unwrap_or(b))
+                });
+            }
+            Self::Mtime => files
+                .sort_by_cached_key(|(path, _)| fs::metadata(path).and_then(|m| m.modified()).ok()),
+        }
+    }
+}
+
+/// Options controlling how a profile is rendered.
+///
+/// Build them with [`RenderOptions::new`] and the chainable setters, or with
+/// struct update syntax over [`RenderOptions::default`].
+///
+/// # Examples
+/// ```
+/// use prompter::{DedupMode, RenderOptions};
+///
+/// let opts = RenderOptions::new()
+///     .separator(Some("\n---\n"))
+///     .pre_prompt(None)
+///     .system_prefix(false)
+///     .dedup(DedupMode::Content);
+/// assert_eq!(opts.separator.as_deref(), Some("\n---\n"));
+/// assert!(opts.no_system_prefix);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RenderOptions {
+    /// Separator written after each file
+    pub separator: Option<String>,
+    /// Custom pre-prompt (defaults to LLM instructions)
+    pub pre_prompt: Option<String>,
+    /// Custom post-prompt (overrides the config's `post_prompt`)
+    pub post_prompt: Option<String>,
+    /// Values for `{{KEY}}` placeholders in snippet files (see
+    /// [`substitute_vars`])
+    pub vars: BTreeMap<String, String>,
+    /// How duplicate files are detected
+    pub dedup: DedupMode,
+    /// Order in which the resolved files are written
+    pub order: FileOrder,
+    /// Files or profiles appended to the profile's dependencies
+    pub add: Vec<String>,
+    /// Dependency entries left out wherever they appear
+    pub drop: Vec<String>,
+    /// Fail on undefined environment variables in paths and prompts
+    pub strict_env: bool,
+    /// Skip missing snippet files with a warning instead of failing
+    pub skip_missing: bool,
+    /// Drop leading frontmatter from each file (see [`strip_frontmatter`])
+    pub strip_frontmatter: bool,
+    /// Strip trailing whitespace from each line (see [`trim_trailing_whitespace`])
+    pub trim_trailing_whitespace: bool,
+    /// Convert `\r\n` to `\n` (see [`normalize_line_endings`])
+    pub normalize_line_endings: bool,
+    /// Write the separator between files only, leaving none after the last
+    pub between_only: bool,
+    /// Cap the newlines where sections meet at one blank line; blank lines
+    /// inside files and prompts are kept
+    pub collapse_blank_lines: bool,
+    /// Allow dependencies outside the library (see [`ResolveOptions`])
+    pub allow_external: bool,
+    /// Fail with the offending path when a file is not valid UTF-8
+    pub require_utf8: bool,
+    /// Fail once the output would exceed this many bytes, counting prompts,
+    /// files, and separators
+    pub limit_bytes: Option<u64>,
+    /// Leave out the date and platform line, e.g. for stable snapshots
+    pub no_system_prefix: bool,
+    /// Only include files modified after this time
+    pub since: Option<SystemTime>,
+    /// chrono format of the banner date (overrides the config's
+    /// `timestamp_format`)
+    pub timestamp_format: Option<String>,
+    /// Clock of the banner date (overrides the config's `timezone`)
+    pub timezone: Option<Timezone>,
+    /// Decorate the banner with colors and emoji; decided when the prompt
+    /// is written, so not part of the parsed mode
+    #[serde(skip)]
+    pub styled: bool,
+}
+
+impl RenderOptions {
+    /// Options for a default render: built-in prompts, no separator, and
+    /// path deduplication.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the separator written after each file.
+    #[must_use]
+    pub fn separator(mut self, separator: Option<&str>) -> Self {
+        self.separator = separator.map(str::to_string);
+        self
+    }
+
+    /// Set the pre-prompt; `None` uses the built-in one.
+    #[must_use]
+    pub fn pre_prompt(mut self, pre_prompt: Option<&str>) -> Self {
+        self.pre_prompt = pre_prompt.map(str::to_string);
+        self
+    }
+
+    /// Set the post-prompt; `None` uses the config's or the built-in one.
+    #[must_use]
+    pub fn post_prompt(mut self, post_prompt: Option<&str>) -> Self {
+        self.post_prompt = post_prompt.map(str::to_string);
+        self
+    }
+
+    /// Set how duplicate files are detected.
+    #[must_use]
+    pub const fn dedup(mut self, dedup: DedupMode) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set the order in which files are written.
+    #[must_use]
+    pub const fn order(mut self, order: FileOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Set template variables; a key set twice keeps the later value.
+    #[must_use]
+    pub fn vars<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.vars.extend(
+            vars.into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Append files or profiles to the profile's dependencies.
+    #[must_use]
+    pub fn add_dependencies<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.add.extend(entries.into_iter().map(Into::into));
+        self
+    }
+
+    /// Leave out dependency entries wherever they appear.
+    #[must_use]
+    pub fn drop_dependencies<I, S>(mut self, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.drop.extend(entries.into_iter().map(Into::into));
+        self
+    }
+
+    /// Fail on undefined environment variables in paths and prompts.
+    #[must_use]
+    pub const fn strict_env(mut self, strict_env: bool) -> Self {
+        self.strict_env = strict_env;
+        self
+    }
+
+    /// Skip missing snippet files with a warning instead of failing.
+    #[must_use]
+    pub const fn skip_missing(mut self, skip_missing: bool) -> Self {
+        self.skip_missing = skip_missing;
+        self
+    }
+
+    /// Drop leading frontmatter from each file.
+    #[must_use]
+    pub const fn strip_frontmatter(mut self, strip_frontmatter: bool) -> Self {
+        self.strip_frontmatter = strip_frontmatter;
+        self
+    }
+
+    /// Strip trailing whitespace from each line.
+    #[must_use]
+    pub const fn trim_trailing_whitespace(mut self, trim: bool) -> Self {
+        self.trim_trailing_whitespace = trim;
+        self
+    }
+
+    /// Convert `\r\n` line endings to `\n`.
+    #[must_use]
+    pub const fn normalize_line_endings(mut self, normalize: bool) -> Self {
+        self.normalize_line_endings = normalize;
+        self
+    }
+
+    /// Write the separator only between files.
+    #[must_use]
+    pub const fn between_only(mut self, between_only: bool) -> Self {
+        self.between_only = between_only;
+        self
+    }
+
+    /// Allow at most one blank line between sections.
+    #[must_use]
+    pub const fn collapse_blank_lines(mut self, collapse: bool) -> Self {
+        self.collapse_blank_lines = collapse;
+        self
+    }
+
+    /// Allow dependencies outside the library.
+    #[must_use]
+    pub const fn allow_external(mut self, allow_external: bool) -> Self {
+        self.allow_external = allow_external;
+        self
+    }
+
+    /// Fail when a file is not valid UTF-8.
+    #[must_use]
+    pub const fn require_utf8(mut self, require_utf8: bool) -> Self {
+        self.require_utf8 = require_utf8;
+        self
+    }
+
+    /// Cap the total output at this many bytes.
+    #[must_use]
+    pub const fn limit_bytes(mut self, limit_bytes: Option<u64>) -> Self {
+        self.limit_bytes = limit_bytes;
+        self
+    }
+
+    /// Only include files modified after `since`; `None` includes every file.
+    #[must_use]
+    pub const fn since(mut self, since: Option<SystemTime>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Set the chrono format of the banner date; `None` uses the config's or
+    /// [`DEFAULT_TIMESTAMP_FORMAT`].
+    #[must_use]
+    pub fn timestamp_format(mut self, timestamp_format: Option<&str>) -> Self {
+        self.timestamp_format = timestamp_format.map(str::to_string);
+        self
+    }
+
+    /// Set the clock of the banner date; `None` uses the config's `timezone`,
+    /// or local time.
+    #[must_use]
+    pub const fn timezone(mut self, timezone: Option<Timezone>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Write the date and platform line after the pre-prompt (on by default).
+    #[must_use]
+    pub const fn system_prefix(mut self, enabled: bool) -> Self {
+        self.no_system_prefix = !enabled;
+        self
+    }
+
+    /// Decorate the date and platform line with colors and emoji (off by
+    /// default).
+    #[must_use]
+    pub const fn styled(mut self, styled: bool) -> Self {
+        self.styled = styled;
+        self
+    }
+
+    /// The files a render of `profile` writes, in order, before
+    /// deduplication: resolved, reordered, and filtered by `since`.
+    fn files(
+        &self,
+        cfg: &Config,
+        lib: &Path,
+        profile: &str,
+    ) -> Result<Vec<(PathBuf, Option<LineRange>)>, PrompterError> {
+        let mut files = resolve_ranges(cfg, lib, profile, &self.resolve_options())?;
+        self.order.apply(&mut files, lib);
+        if let Some(since) = self.since {
+            files.retain(|(path, _)| modified_after(path, since));
+        }
+        Ok(files)
+    }
+
+    /// The resolver options implied by these render options.
+    fn resolve_options(&self) -> ResolveOptions {
+        ResolveOptions {
+            strict_env: self.strict_env,
+            skip_missing: self.skip_missing,
+            allow_external: self.allow_external,
+            add: self.add.clone(),
+            drop: self.drop.clone(),
+            ..ResolveOptions::default()
+        }
+    }
+
+    /// Whether any option rewrites file content, which rules out streaming.
+    const fn transforms_content(&self) -> bool {
+        self.strip_frontmatter || self.trim_trailing_whitespace || self.normalize_line_endings
+    }
+
+    /// The bytes to write for `path` (or its `range` of lines), checked and
+    /// transformed, or `None` when the file can be streamed as it is. `file`
+    /// holds the contents if they were already read.
+    fn snippet_bytes<'a>(
+        &self,
+        path: &Path,
+        range: Option<LineRange>,
+        file: Option<&'a SnippetFile>,
+    ) -> Result<Option<Cow<'a, [u8]>>, PrompterError> {
+        let bytes = match file {
+            Some(file) => file.snippet().map_err(PrompterError::Render)?,
+            None if self.transforms_content() || self.require_utf8 => {
+                Cow::Owned(read_snippet(path, range).map_err(PrompterError::Render)?)
+            }
+            None => return Ok(None),
+        };
+        if self.require_utf8 {
+            require_utf8(&bytes, path)?;
+        }
+        let bytes = if self.transforms_content() {
+            Cow::Owned(self.transform(&bytes))
+        } else {
+            bytes
+        };
+        if self.vars.is_empty() {
+            Ok(Some(bytes))
+        } else {
+            Ok(Some(Cow::Owned(substitute_vars(&bytes, &self.vars))))
+        }
+    }
+
+    /// Apply the content transforms to one file: frontmatter first, then line
+    /// endings, then trailing whitespace.
+    fn transform(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut content = if self.strip_frontmatter {
+            strip_frontmatter(bytes).to_vec()
+        } else {
+            bytes.to_vec()
+        };
+        if self.normalize_line_endings {
+            content = normalize_line_endings(&content);
+        }
+        if self.trim_trailing_whitespace {
+            content = trim_trailing_whitespace(&content);
+        }
+        content
+    }
+}
+
+/// Render a profile's content to a writer.
+///
+/// Resolves profile dependencies and writes the concatenated content
+/// to the provided writer, including pre-prompt, system info, file
+/// contents with optional separators, and post-prompt.
+///
+/// Deprecated shim over [`render`], which takes every option through
+/// [`RenderOptions`]; use [`render_to_string`] to get the prompt as a `String`.
+///
+/// # Arguments
+/// * `cfg` - Configuration containing profile definitions
+/// * `lib` - Library root directory for file resolution
+/// * `w` - Writer to output rendered content to
+/// * `profile` - Profile name to render
+/// * `separator` - Optional separator between files
+/// * `pre_prompt` - Optional custom pre-prompt (defaults to LLM instructions)
+/// * `post_prompt` - Optional custom post-prompt (defaults to @AGENTS/@CLAUDE instructions)
+///
+/// # Returns
+/// * `Ok(())` - Profile rendered successfully
+/// * `Err(PrompterError)` - Rendering failed
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile resolution fails (missing files, cycles, unknown profiles)
+/// - Writing to output fails
+/// - File reading fails
+#[deprecated(note = "use `render` with `RenderOptions`")]
+pub fn render_to_writer(
+    cfg: &Config,
+    lib: &Path,
+    w: impl Write,
+    profile: &str,
+    separator: Option<&str>,
+    pre_prompt: Option<&str>,
+    post_prompt: Option<&str>,
+) -> Result<(), PrompterError> {
+    let opts = RenderOptions::new()
+        .separator(separator)
+        .pre_prompt(pre_prompt)
+        .post_prompt(post_prompt);
+    render(cfg, lib, profile, &opts, w)
+}
+
+/// Fail with the offending path unless `bytes` is valid UTF-8.
+fn require_utf8(bytes: &[u8], path: &Path) -> Result<(), PrompterError> {
+    std::str::from_utf8(bytes).map(|_| ()).map_err(|e| {
+        PrompterError::Render(format!(
+            "File is not valid UTF-8: {} (invalid byte at offset {})",
+            path.display(),
+            e.valid_up_to()
+        ))
+    })
+}
+
+/// Writer that refuses writes which would take the total past `limit`.
+struct LimitedWriter<W> {
+    inner: W,
+    limit: Option<u64>,
+    written: u64,
+    exceeded: bool,
+}
+
+impl<W: Write> LimitedWriter<W> {
+    const fn new(inner: W, limit: Option<u64>) -> Self {
+        Self {
+            inner,
+            limit,
+            written: 0,
+            exceeded: false,
+        }
+    }
+
+    /// Replace a write error with the limit message if the limit caused it.
+    fn explain(&self, err: String, culprit: &str) -> PrompterError {
+        PrompterError::Render(match self.limit {
+            Some(limit) if self.exceeded => {
+                format!("Output exceeds --limit-bytes {limit} at {culprit}")
+            }
+            _ => err,
+        })
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.written + buf.len() as u64 > limit {
+                self.exceeded = true;
+                return Err(io::Error::other("output limit exceeded"));
+            }
+        }
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer that, when enabled, caps the run of newlines where two sections
+/// meet at two (one blank line).
+///
+/// Newlines are held back until the next other byte shows whether they end
+/// up at a section boundary. Runs that only touch content written since the
+/// last [`BlankLineWriter::boundary`] call are passed on unchanged.
+struct BlankLineWriter<W> {
+    inner: W,
+    enabled: bool,
+    pending: usize,
+    at_boundary: bool,
+}
+
+impl<W: Write> BlankLineWriter<W> {
+    const fn new(inner: W, enabled: bool) -> Self {
+        Self {
+            inner,
+            enabled,
+            pending: 0,
+            at_boundary: false,
+        }
+    }
+
+    /// Mark the start of layout output between two sections.
+    const fn boundary(&mut self) {
+        self.at_boundary = true;
+    }
+
+    /// Write the held-back newlines, capped when they span a boundary.
+    fn flush_pending(&mut self) -> io::Result<()> {
+        let count = if self.at_boundary {
+            self.pending.min(2)
+        } else {
+            self.pending
+        };
+        self.pending = 0;
+        self.inner.write_all(&b"\n".repeat(count))
+    }
+}
+
+impl<W: Write> BlankLineWriter<LimitedWriter<W>> {
+    fn explain(&self, err: String, culprit: &str) -> PrompterError {
+        self.inner.explain(err, culprit)
+    }
+}
+
+impl<W: Write> Write for BlankLineWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.enabled {
+            return self.inner.write(buf);
+        }
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let newlines = rest.iter().take_while(|&&b| b == b'\n').count();
+            self.pending += newlines;
+            rest = &rest[newlines..];
+            let text = rest.iter().take_while(|&&b| b != b'\n').count();
+            if text > 0 {
+                self.flush_pending()?;
+                self.at_boundary = false;
+                self.inner.write_all(&rest[..text])?;
+                rest = &rest[text..];
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_pending()?;
+        self.inner.flush()
+    }
+}
+
+/// The pre- and post-prompt for a render.
+///
+/// Each comes from the first of: the `RenderOptions` override, the config's
+/// `pre_prompt_file`/`post_prompt_file`, the inline `post_prompt`, and the
+/// default. Variables are expanded in overrides and inline prompts, never in
+/// prompt files, just as library files are copied verbatim.
+fn prompt_texts(
+    cfg: &Config,
+    lib: &Path,
+    opts: &RenderOptions,
+) -> Result<(String, String), PrompterError> {
+    let pre = match (&opts.pre_prompt, &cfg.pre_prompt_file) {
+        (None, Some(file)) => read_prompt_file(cfg, lib, "pre_prompt_file", file)?,
+        (pre, _) => expand_env(
+            pre.as_deref().unwrap_or(&default_pre_prompt()),
+            opts.strict_env,
+        )
+        .map_err(|e| PrompterError::Render(format!("{e} (in pre-prompt)")))?,
+    };
+    let post = match (&opts.post_prompt, &cfg.post_prompt_file) {
+        (None, Some(file)) => read_prompt_file(cfg, lib, "post_prompt_file", file)?,
+        (post, _) => expand_env(
+            post.as_deref()
+                .or(cfg.post_prompt.as_deref())
+                .unwrap_or(&default_post_prompt()),
+            opts.strict_env,
+        )
+        .map_err(|e| PrompterError::Render(format!("{e} (in post-prompt)")))?,
+    };
+    Ok((pre, post))
+}
+
+/// Where the prompt file `key = file` lives: the first of the library and
+/// its `library_roots` holding it.
+fn prompt_file_path(
+    cfg: &Config,
+    lib: &Path,
+    key: &str,
+    file: &str,
+) -> Result<PathBuf, PrompterError> {
+    let file = expand_env_lenient(file);
+    let roots = cfg.search_roots(lib);
+    let libs: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    find_in_roots(&libs, &file).map_or_else(
+        || {
+            Err(PrompterError::Render(format!(
+                "Missing file: {} ({key})",
+                dep_path(lib, &file).display()
+            )))
+        },
+        |(_, path)| Ok(path),
+    )
+}
+
+/// The text of the prompt file `key = file`, without a leading BOM.
+fn read_prompt_file(
+    cfg: &Config,
+    lib: &Path,
+    key: &str,
+    file: &str,
+) -> Result<String, PrompterError> {
+    let path = prompt_file_path(cfg, lib, key, file)?;
+    let bytes = read_snippet(&path, None).map_err(PrompterError::Render)?;
+    String::from_utf8(bytes).map_err(|_| {
+        PrompterError::Render(format!("{} is not valid UTF-8 ({key})", path.display()))
+    })
+}
+
+/// Render a profile's content to a writer using [`RenderOptions`].
+///
+/// Writes the pre-prompt, system info, each resolved file followed by the
+/// optional separator, and finally the post-prompt.
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile resolution fails (missing files, cycles, unknown profiles)
+/// - Writing to output fails
+/// - File reading fails
+/// - The output would exceed `opts.limit_bytes`
+pub fn render(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    opts: &RenderOptions,
+    w: impl Write,
+) -> Result<(), PrompterError> {
+    let files = opts.files(cfg, lib, profile)?;
+
+    let (pre_prompt_text, post_prompt_text) = prompt_texts(cfg, lib, opts)?;
+    let timestamp_format = opts
+        .timestamp_format
+        .as_deref()
+        .or_else(|| cfg.timestamp_format())
+        .unwrap_or(DEFAULT_TIMESTAMP_FORMAT);
+    if !opts.no_system_prefix {
+        check_timestamp_format(timestamp_format).map_err(PrompterError::Render)?;
+    }
+
+    let mut w = BlankLineWriter::new(
+        LimitedWriter::new(w, opts.limit_bytes),
+        opts.collapse_blank_lines,
+    );
+
+    // Write pre-prompt (defaults if not provided)
+    w.write_all(pre_prompt_text.as_bytes())
+        .map_err(|e| w.explain(format!("Write error: {e}"), "the pre-prompt"))?;
+
+    // Write system prefix with two newlines before
+    if !opts.no_system_prefix {
+        w.boundary();
+        w.write_all(b"\n")
+            .map_err(|e| w.explain(format!("Write error: {e}"), "the pre-prompt"))?;
+        let timezone = opts.timezone.or(cfg.timezone).unwrap_or_default();
+        let prefix = format_system_prefix(timestamp_format, timezone, opts.styled);
+        w.write_all(prefix.as_bytes())
+            .map_err(|e| w.explain(format!("Write error: {e}"), "the pre-prompt"))?;
+    }
+
+    if let Some(since) = opts.since.filter(|_| files.is_empty()) {
+        let since = DateTime::<Utc>::from(since).to_rfc3339_opts(SecondsFormat::Secs, true);
+        w.boundary();
+        w.write_all(format!("\n(No files changed since {since})\n").as_bytes())
+            .map_err(|e| w.explain(format!("Write error: {e}"), "the --since note"))?;
+    }
+
+    let sep = opts.separator.as_deref().unwrap_or("");
+    let mut seen_hashes = HashSet::new();
+    let mut written = 0;
+    // Large profiles are read ahead concurrently, then written in order
+    let mut prefetched = (files.len() >= PARALLEL_READ_MIN_FILES)
+        .then(|| read_snippets_parallel(&files).into_iter());
+    for (path, range) in files {
+        let file = prefetched
+            .as_mut()
+            .and_then(Iterator::next)
+            .transpose()
+            .map_err(PrompterError::Render)?;
+        if opts.dedup == DedupMode::Content {
+            let digest = file
+                .as_ref()
+                .map_or_else(|| snippet_digest(&path, range), SnippetFile::digest)
+                .map_err(PrompterError::Render)?;
+            if !seen_hashes.insert(digest) {
+                continue;
+            }
+        }
+
+        let culprit = labeled_path(&path, range).display().to_string();
+
+        // In between-only mode the separator leads every file but the first,
+        // so skipped duplicates never leave a dangling one
+        w.boundary();
+        if opts.between_only && written > 0 && !sep.is_empty() {
+            w.write_all(sep.as_bytes()).map_err(|e| {
+                w.explain(
+                    format!("Write error: {e}"),
+                    &format!("the separator before {culprit}"),
+                )
+            })?;
+        }
+        written += 1;
+
+        // Two newlines before each file
+        w.boundary();
+        w.write_all(b"\n")
+            .map_err(|e| w.explain(format!("Write error: {e}"), &culprit))?;
+        if let Some(bytes) = opts.snippet_bytes(&path, range, file.as_ref())? {
+            w.write_all(&bytes)
+                .map_err(|e| w.explain(format!("Write error: {e}"), &culprit))?;
+        } else {
+            copy_snippet(&path, range, &opts.vars, &mut w).map_err(|e| w.explain(e, &culprit))?;
+        }
+
+        // Write separator after each file if provided
+        if !opts.between_only && !sep.is_empty() {
+            w.boundary();
+            w.write_all(sep.as_bytes()).map_err(|e| {
+                w.explain(
+                    format!("Write error: {e}"),
+                    &format!("the separator after {culprit}"),
+                )
+            })?;
+        }
+    }
+
+    // Two newlines before post-prompt
+    w.boundary();
+    w.write_all(b"\n\n")
+        .map_err(|e| w.explain(format!("Write error: {e}"), "the post-prompt"))?;
+    w.write_all(post_prompt_text.as_bytes())
+        .and_then(|()| w.flush())
+        .map_err(|e| w.explain(format!("Write error: {e}"), "the post-prompt"))?;
+
+    Ok(())
+}
+
+/// Render a profile into a `String`, e.g. to send it to an API.
+///
+/// This is [`render`] into a buffer. Every library file must be valid UTF-8,
+/// as if `opts.require_utf8` were set: rather than converting lossily, an
+/// invalid file fails with [`PrompterError::Render`] naming its path.
+///
+/// # Examples
+/// ```
+/// use prompter::{Config, RenderOptions, render_to_string};
+///
+/// let lib = tempfile::tempdir().unwrap();
+/// std::fs::write(lib.path().join("style.md"), "Use snake_case.\n").unwrap();
+/// let mut cfg = Config::new();
+/// cfg.add_profile("python", ["style.md"]);
+///
+/// let opts = RenderOptions {
+///     pre_prompt: Some("Review this change.\n".into()),
+///     post_prompt: Some("Reply with a list of issues.".into()),
+///     ..RenderOptions::default()
+/// };
+/// let prompt = render_to_string(&cfg, lib.path(), "python", &opts).unwrap();
+/// assert!(prompt.starts_with("Review this change.\n"));
+/// assert!(prompt.contains("\nUse snake_case.\n"));
+/// assert!(prompt.ends_with("Reply with a list of issues."));
+/// ```
+///
+/// Invalid UTF-8 is reported with the offending file:
+/// ```
+/// use prompter::{Config, PrompterError, RenderOptions, render_to_string};
+///
+/// let lib = tempfile::tempdir().unwrap();
+/// std::fs::write(lib.path().join("latin1.md"), b"caf\xE9\n").unwrap();
+/// let mut cfg = Config::new();
+/// cfg.add_profile("p", ["latin1.md"]);
+///
+/// let err = render_to_string(&cfg, lib.path(), "p", &RenderOptions::default()).unwrap_err();
+/// assert!(matches!(err, PrompterError::Render(_)));
+/// assert!(err.to_string().contains("latin1.md"));
+/// ```
+///
+/// # Errors
+/// Returns the same errors as [`render`], plus one for a file that is not
+/// valid UTF-8.
+pub fn render_to_string(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    opts: &RenderOptions,
+) -> Result<String, PrompterError> {
+    let opts = RenderOptions {
+        require_utf8: true,
+        ..opts.clone()
+    };
+    let mut out = Vec::new();
+    render(cfg, lib, profile, &opts, &mut out)?;
+    // Prompts are strings and every file was checked, so this cannot fail
+    String::from_utf8(out).map_err(|e| PrompterError::Render(e.to_string()))
+}
+
+/// Render a profile to stdout.
+///
+/// Convenience function that reads configuration and renders the specified
+/// profile to standard output using the given [`RenderOptions`].
+///
+/// # Arguments
+/// * `profile` - Profile name to render; `None` renders the config's
+///   `default_profile`
+/// * `opts` - Rendering options (separator, prompts, dedup mode); template
+///   variables from [`VARS_ENV`] are added for keys it does not set
+/// * `config_override` - Optional configuration file override
+///
+/// # Returns
+/// * `Ok(())` - Profile rendered successfully
+/// * `Err(PrompterError)` - Rendering failed
+///
+/// # Errors
+/// Returns an error if:
+/// - Configuration file cannot be read or parsed
+/// - [`VARS_ENV`] holds an entry that is not `KEY=VALUE`
+/// - No profile is given and the config has no `default_profile`
+/// - Profile resolution fails
+/// - Writing to stdout fails
+pub fn run_render_stdout(
+    profile: Option<&str>,
+    opts: &RenderOptions,
+    config_override: Option<&Path>,
+    strict_config: bool,
+    manifest: Option<&Path>,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    let profile = profile_or_default(&cfg, profile)?;
+    let env_vars = env::var(VARS_ENV).ok();
+    let opts = &RenderOptions {
+        vars: template_vars(env_vars.as_deref(), &opts.vars)?,
+        ..opts.clone()
+    };
+    let stdout = io::stdout();
+    let handle = stdout.lock();
+    render(&cfg, &lib, profile, opts, handle)?;
+    if let Some(path) = manifest {
+        let json = render_manifest(&cfg, &lib, profile, opts)?;
+        let text = serde_json::to_string_pretty(&json).map_err(|e| e.to_string())?;
+        fs::write(path, text + "\n")
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// `profile` if given, else the config's `default_profile`.
+///
+/// # Errors
+/// Returns an error when neither is set.
+pub fn profile_or_default<'a>(
+    cfg: &'a Config,
+    profile: Option<&'a str>,
+) -> Result<&'a str, PrompterError> {
+    profile.or_else(|| cfg.default_profile()).ok_or_else(|| {
+        PrompterError::Other(
+            "No profile given and no default_profile set in config; \
+             run 'prompter run <PROFILE>' or add default_profile = \"<PROFILE>\""
+                .to_string(),
+        )
+    })
+}
+
+/// Describe the files a render of `profile` includes, for reproducibility.
+///
+/// The result is `{profile, files: [{path, bytes, sha256}], rendered_at}`.
+/// Files come from the same resolution as [`render`], in render order; paths
+/// inside the library are relative to it and keep any `#L..` fragment, and
+/// `bytes`/`sha256` cover the selected lines before content transforms.
+///
+/// # Errors
+/// Returns an error if the profile fails to resolve or a file cannot be read.
+pub fn render_manifest(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    opts: &RenderOptions,
+) -> Result<serde_json::Value, PrompterError> {
+    let files = opts.files(cfg, lib, profile)?;
+    let mut entries = Vec::with_capacity(files.len());
+    for (path, range) in files {
+        let bytes = read_snippet(&path, range).map_err(PrompterError::Render)?;
+        let shown = labeled_path(path.strip_prefix(lib).unwrap_or(&path), range);
+        entries.push(serde_json::json!({
+            "path": shown.display().to_string(),
+            "bytes": bytes.len(),
+            "sha256": hex::encode(Sha256::digest(&bytes)),
+        }));
+    }
+    Ok(serde_json::json!({
+        "profile": profile,
+        "files": entries,
+        "rendered_at": Local::now().to_rfc3339(),
+    }))
+}
+
+/// The result of comparing a profile's output with its snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotOutcome {
+    /// The output matches the snapshot
+    Matched,
+    /// The snapshot was written with the current output
+    Updated,
+    /// There is no snapshot for the profile yet
+    Missing,
+    /// The output differs; holds a [`line_diff`] from snapshot to output
+    Mismatch(String),
+}
+
+/// Path of the snapshot for `profile` inside `dir`.
+#[must_use]
+pub fn snapshot_path(dir: &Path, profile: &str) -> PathBuf {
+    dir.join(format!("{profile}.snap"))
+}
+
+/// Render `profile` and compare it with its snapshot in `dir`.
+///
+/// With `update`, a missing or differing snapshot is (re)written instead of
+/// reported. Profiles render with default options and without the system
+/// prefix, whose date would change the output every day.
+///
+/// # Errors
+/// Returns an error if the profile fails to render or the snapshot cannot be
+/// read or written.
+pub fn check_snapshot(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+    dir: &Path,
+    update: bool,
+) -> Result<SnapshotOutcome, PrompterError> {
+    let opts = RenderOptions::new().system_prefix(false);
+    let output = render_to_string(cfg, lib, profile, &opts)?;
+    let path = snapshot_path(dir, profile);
+    let stored = match fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display()).into()),
+    };
+    if stored.as_deref() == Some(output.as_str()) {
+        return Ok(SnapshotOutcome::Matched);
+    }
+    if update {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+        }
+        fs::write(&path, output).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        return Ok(SnapshotOutcome::Updated);
+    }
+    Ok(stored.map_or(SnapshotOutcome::Missing, |old| {
+        SnapshotOutcome::Mismatch(line_diff(&old, &output))
+    }))
+}
+
+/// A line diff from `old` to `new`.
+///
+/// Only changed lines are shown, as `-` and `+` lines under `@@ -a +b @@`
+/// headers giving the 1-based line where each hunk starts in either text.
+/// The result is empty when the texts are equal.
+///
+/// # Examples
+/// ```
+/// use prompter::line_diff;
+/// assert_eq!(line_diff("a\nb\nc", "a\nB\nc"), "@@ -2 +2 @@\n-b\n+B\n");
+/// ```
+#[must_use]
+pub fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    // Only the middle that differs needs the quadratic table
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+            in_hunk = false;
+            continue;
+        }
+        if !in_hunk {
+            out.push(format!("@@ -{} +{} @@", prefix + i + 1, prefix + j + 1));
+            in_hunk = true;
+        }
+        if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            out.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    let mut text = out.join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// Check profiles against their snapshots and print one line per profile,
+/// followed by the diff of each mismatch.
+///
+/// `profile` limits the check to one profile; otherwise every profile is
+/// checked in name order.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, or if any
+/// profile fails to render or does not match its snapshot.
+pub fn run_test_stdout(
+    profile: Option<&str>,
+    update: bool,
+    snapshot_dir: &Path,
+    config_override: Option<&Path>,
+    strict_config: bool,
+) -> Result<(), PrompterError> {
+    let (cfg, lib) = load_config(config_override, strict_config)?;
+    let mut names: Vec<&str> = match profile {
+        Some(name) => vec![name],
+        None => cfg.profiles.keys().map(String::as_str).collect(),
+    };
+    names.sort_unstable();
+
+    let mut out = io::stdout().lock();
+    let mut failed = 0;
+    for name in &names {
+        match check_snapshot(&cfg, &lib, name, snapshot_dir, update) {
+            Ok(SnapshotOutcome::Matched) => writeln!(out, "ok: {name}")?,
+            Ok(SnapshotOutcome::Updated) => writeln!(out, "updated: {name}")?,
+            Ok(SnapshotOutcome::Missing) => {
+                failed += 1;
+                writeln!(
+                    out,
+                    "missing: {name} (run with --update to create {})",
+                    snapshot_path(snapshot_dir, name).display()
+                )?;
+            }
+            Ok(SnapshotOutcome::Mismatch(diff)) => {
+                failed += 1;
+                writeln!(out, "FAILED: {name}")?;
+                out.write_all(diff.as_bytes())?;
+            }
+            Err(e) => {
+                failed += 1;
+                writeln!(out, "error: {name}: {e}")?;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{failed} of {} snapshots did not match", names.len()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_tmp(prefix: &str) -> PathBuf {
         let mut p = env::temp_dir();
         let unique = format!(
             "{}_{}_{}",
@@ -1045,268 +8483,3577 @@ mod tests {
             std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
-                .as_nanos()
+                .as_nanos()
+        );
+        p.push(unique);
+        p
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a\\nb\\t\\\"\\\\c"), "a\nb\t\"\\c");
+        assert_eq!(unescape("line1\\rline2"), "line1\rline2");
+        assert_eq!(unescape("noesc"), "noesc");
+    }
+
+    #[test]
+    fn test_template_vars_cli_overrides_env() {
+        let cli = BTreeMap::from([("team".to_string(), "web".to_string())]);
+        let vars = template_vars(Some("team=core, lang=rust,"), &cli).unwrap();
+        assert_eq!(
+            vars,
+            BTreeMap::from([
+                ("lang".to_string(), "rust".to_string()),
+                ("team".to_string(), "web".to_string()),
+            ])
+        );
+        assert_eq!(template_vars(None, &cli).unwrap(), cli);
+        // Values keep '=' after the first one
+        assert_eq!(template_vars(Some("q=a=b"), &cli).unwrap()["q"], "a=b");
+
+        let err = template_vars(Some("team=core,lang"), &cli).unwrap_err();
+        assert!(err.contains("Invalid PROMPTER_VARS"), "err={err}");
+        assert!(parse_var("=x").is_err());
+    }
+
+    #[test]
+    fn test_unescape_unicode() {
+        assert_eq!(unescape("caf\\u00e9 \\u00C9"), "café É");
+        assert_eq!(unescape("\\u2192 \\u4e2d"), "→ 中");
+        // Emoji outside the BMP take a surrogate pair
+        assert_eq!(unescape("\\ud83d\\ude00!"), "\u{1f600}!");
+        assert_eq!(unescape("\\uD83D\\uDE80"), "\u{1f680}");
+
+        // Anything else is kept as written
+        for malformed in [
+            "\\u",
+            "\\u12",
+            "\\u12g4",
+            "\\uzzzz",
+            "\\u00\u{e9}9",
+            "\\ud83d",
+            "\\ude00",
+        ] {
+            assert_eq!(unescape(malformed), malformed);
+        }
+        // Escapes after a malformed one still apply
+        assert_eq!(unescape("\\ud83d\\u0041"), "\\ud83dA");
+        assert_eq!(unescape("\\u12\\n"), "\\u12\n");
+        assert_eq!(unescape("\\\\u0041"), "\\u0041");
+    }
+
+    #[test]
+    fn test_escape_round_trips_through_unescape() {
+        assert_eq!(escape("a\nb\t\"\\c"), "a\\nb\\t\\\"\\\\c");
+        let inputs = [
+            "",
+            "plain",
+            "\n",
+            "\\",
+            "\\n",
+            "trailing backslash \\",
+            "\\\\\"\"",
+            "\r\n\t mixed \u{e9}\u{1f600} \\t",
+            "\n---\n",
+            "already \\\\n escaped",
+        ];
+        for input in inputs {
+            assert_eq!(unescape(&escape(input)), input, "input={input:?}");
+            assert!(!escape(input).contains('\n'), "input={input:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_truthy() {
+        assert!(is_truthy("1"));
+        assert!(is_truthy("yes"));
+        assert!(!is_truthy(""));
+        assert!(!is_truthy("0"));
+        assert!(!is_truthy("FALSE"));
+    }
+
+    #[test]
+    fn test_strip_comments_and_brackets_detection() {
+        let s = r"ab#cd";
+        assert_eq!(strip_comments(s), "ab");
+        let s = r#""ab#cd" # trailing"#;
+        assert_eq!(strip_comments(s), "\"ab#cd\" ");
+        assert!(contains_closing_bracket_outside_quotes("[\"not]here\"]]"));
+        assert!(!contains_closing_bracket_outside_quotes("[\"no]close\""));
+    }
+
+    #[test]
+    fn test_parse_array_items_escape_and_error() {
+        let s = r#"["a\"b", "c"]"#;
+        let items = parse_array_items(s).unwrap();
+        assert_eq!(items, vec!["a\"b", "c"]);
+        let err = parse_array_items("[\"unterminated").unwrap_err();
+        assert!(err.contains("Unterminated"));
+    }
+
+    #[test]
+    fn test_parse_array_accepts_trailing_commas_and_literal_strings() {
+        assert_eq!(
+            parse_array_items(r#"["a.md", "b.md",]"#).unwrap(),
+            ["a.md", "b.md"]
+        );
+        assert_eq!(
+            parse_array_items(r#"['C:\docs\a.md', 'say "hi"', "x]'#y"]"#).unwrap(),
+            [r"C:\docs\a.md", r#"say "hi""#, "x]'#y"]
+        );
+        let err = parse_array_items("['unterminated]").unwrap_err();
+        assert!(err.contains("Unterminated string"), "{err}");
+        assert!(contains_closing_bracket_outside_quotes("['not]here']"));
+        assert_eq!(strip_comments("'a#b' # note"), "'a#b' ");
+
+        let cfg = parse_config_toml(
+            "[p]\n\
+             depends_on = [\n\
+             \x20 'a.md',  # first\n\
+             \x20 { path = 'b.md', optional = true },\n\
+             \x20 \"c.md\",\n\
+             ]\n\
+             tags = ['x',]\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.profiles["p"], ["a.md", "b.md", "c.md"]);
+        assert_eq!(cfg.tags("p"), ["x"]);
+        assert!(cfg.is_optional("p", "b.md"));
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let err = parse_config_toml("[]\n").unwrap_err().to_string();
+        assert!(err.contains("Empty section name"));
+        let err = parse_config_toml("[p]\ndepends_on = \"x\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("must be an array"));
+        let err = parse_config_toml("depends_on = [\"a.md\"]\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("outside of a profile section"));
+    }
+
+    /// A small deterministic xorshift generator for round-trip tests.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            usize::try_from(self.0 % n as u64).unwrap()
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+
+        fn text(&mut self, alphabet: &[&str], max_len: usize) -> String {
+            (0..=self.below(max_len))
+                .map(|_| self.pick(alphabet))
+                .collect()
+        }
+    }
+
+    fn random_config(rng: &mut Rng) -> Config {
+        const NAME: &[&str] = &["a", "b", "x", "py", "_", "-", "9", "é"];
+        const DEP: &[&str] = &[
+            "a", "/", ".md", " ", "\"", "\\", "#", "]", "[", "{", "'", "ü",
+        ];
+        const PROMPT: &[&str] = &["Hi", " ", "\n", "\t", "\"", "\\", "#", "'''", "ß"];
+        let mut cfg = Config::new();
+        for _ in 0..rng.below(6) {
+            let segments: Vec<String> = (0..=rng.below(3)).map(|_| rng.text(NAME, 4)).collect();
+            let name = segments.join(".");
+            let deps: Vec<String> = (0..rng.below(4)).map(|_| rng.text(DEP, 8)).collect();
+            let mut meta = ProfileMeta::default();
+            for dep in &deps {
+                if rng.below(3) == 0 {
+                    meta.optional.insert(dep.clone());
+                }
+            }
+            meta.tags = (0..rng.below(3)).map(|_| rng.text(NAME, 5)).collect();
+            if rng.below(3) == 0 {
+                cfg.extends.insert(name.clone(), rng.text(NAME, 4));
+            }
+            cfg.meta.insert(name.clone(), meta);
+            cfg.add_profile(name, deps);
+        }
+        if rng.below(2) == 0 {
+            cfg.set_post_prompt(Some(rng.text(PROMPT, 10)));
+        }
+        if rng.below(2) == 0 {
+            cfg.set_default_profile(Some(rng.text(NAME, 6)));
+        }
+        if rng.below(3) == 0 {
+            cfg.set_timestamp_format(Some(rng.text(PROMPT, 6)));
+        }
+        if rng.below(3) == 0 {
+            cfg.set_timezone(Some(if rng.below(2) == 0 {
+                Timezone::Local
+            } else {
+                Timezone::Utc
+            }));
+        }
+        if rng.below(3) == 0 {
+            cfg.set_post_prompt_file(Some(rng.text(DEP, 6)));
+        }
+        for rule in LintRule::ALL {
+            if rng.below(4) == 0 {
+                cfg.set_lint_rule(rule, false);
+            }
+        }
+        cfg.library_roots = (0..rng.below(3)).map(|_| rng.text(DEP, 6)).collect();
+        cfg
+    }
+
+    #[test]
+    fn test_config_serialization_round_trips() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let cfg = random_config(&mut rng);
+            let text = cfg.to_toml_string();
+            assert_eq!(parse_config_toml(&text).unwrap(), cfg, "{text}");
+            assert_eq!(toml::from_str::<Config>(&text).unwrap(), cfg, "{text}");
+            assert_eq!(parse_config_toml_strict(&text).unwrap(), cfg, "{text}");
+
+            let via_serde = toml::to_string(&cfg).unwrap();
+            assert_eq!(
+                toml::from_str::<Config>(&via_serde).unwrap(),
+                cfg,
+                "{via_serde}"
+            );
+            assert_eq!(parse_config_toml(&via_serde).unwrap(), cfg, "{via_serde}");
+            let json = serde_json::to_string(&cfg).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Config>(&json).unwrap(),
+                cfg,
+                "{json}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_config_round_trips_and_is_idempotent() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..300 {
+            let cfg = random_config(&mut rng);
+            for text in [cfg.to_toml_string(), toml::to_string(&cfg).unwrap()] {
+                let parsed = parse_config_toml(&text).unwrap();
+                assert_eq!(parsed, cfg, "{text}");
+                let formatted = format_config(&text).unwrap();
+                assert_eq!(
+                    parse_config_toml(&formatted).unwrap(),
+                    parsed,
+                    "{formatted}"
+                );
+                assert_eq!(format_config(&formatted).unwrap(), formatted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_config_keeps_comments_and_wraps_long_arrays() {
+        let text = "# Team prompts\n\
+                    \n\
+                    post_prompt = \"Bye\"\n\
+                    \n\
+                    [zeta]\n\
+                    # core files\n\
+                    depends_on = [\n\
+                    \x20 \"a.md\", # first\n\
+                    \x20 \"b.md\"\n\
+                    ]\n\
+                    \n\
+                    # Python profiles\n\
+                    \n\
+                    [\"python.api\"]\n\
+                    depends_on = [\"python/very/long/path/one.md\", \"python/very/long/path/two.md\", \"zeta\"]\n\
+                    tags = [\"py\"]  # trailing comments are dropped\n";
+        let formatted = format_config(text).unwrap();
+        assert_eq!(
+            formatted,
+            "# Team prompts\n\
+             \n\
+             post_prompt = \"Bye\"\n\
+             \n\
+             # Python profiles\n\
+             [\"python.api\"]\n\
+             depends_on = [\n\
+             \x20 \"python/very/long/path/one.md\",\n\
+             \x20 \"python/very/long/path/two.md\",\n\
+             \x20 \"zeta\"\n\
+             ]\n\
+             tags = [\"py\"]\n\
+             \n\
+             # core files\n\
+             [zeta]\n\
+             depends_on = [\"a.md\", \"b.md\"]\n"
+        );
+        assert_eq!(
+            parse_config_toml(&formatted).unwrap(),
+            parse_config_toml(text).unwrap()
+        );
+    }
+
+    /// A `.tar.gz` bundle of `config.toml` (if given) and library `files`.
+    fn bundle_archive(config: Option<&str>, files: &[(&str, &str)]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        let mut tar = tar::Builder::new(gz);
+        let entries = config
+            .map(|text| (CONFIG_FILE_NAME.to_string(), text))
+            .into_iter()
+            .chain(
+                files
+                    .iter()
+                    .map(|(path, text)| (format!("library/{path}"), *text)),
+            );
+        for (path, text) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, path, text.as_bytes()).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_import_bundle_into_empty_library_then_again() {
+        let lib = mk_tmp("prompter_import_clean");
+        let archive = bundle_archive(
+            Some("[team]\ndepends_on = [\"team/a.md\"]\ntags = [\"t\"]\n"),
+            &[("team/a.md", "A\n")],
+        );
+        let bundle = Bundle::read(archive.as_slice()).unwrap();
+        assert_eq!(bundle.files[Path::new("team/a.md")], b"A\n");
+
+        let mut cfg = Config::new();
+        let plan = plan_import(&cfg, &lib, &bundle, false);
+        assert_eq!(
+            plan.files,
+            [(PathBuf::from("team/a.md"), ImportAction::Create)]
+        );
+        assert_eq!(plan.profiles, [("team".to_string(), ImportAction::Create)]);
+        apply_import(&mut cfg, &lib, &bundle, &plan).unwrap();
+        assert_eq!(fs::read_to_string(lib.join("team/a.md")).unwrap(), "A\n");
+        assert_eq!(cfg.tags("team"), ["t"]);
+        validate(&cfg, &lib).unwrap();
+
+        let plan = plan_import(&cfg, &lib, &bundle, false);
+        assert!(
+            plan.files
+                .iter()
+                .all(|(_, a)| *a == ImportAction::Unchanged)
+        );
+        assert!(
+            plan.profiles
+                .iter()
+                .all(|(_, a)| *a == ImportAction::Unchanged)
+        );
+
+        let err = Bundle::read(bundle_archive(None, &[("a.md", "A")]).as_slice()).unwrap_err();
+        assert_eq!(err, "Bundle has no config.toml");
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_cksum();
+        tar.append_data(&mut header, "notes.txt", &[][..]).unwrap();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar.into_inner().unwrap()).unwrap();
+        let err = Bundle::read(gz.finish().unwrap().as_slice()).unwrap_err();
+        assert_eq!(err, "Unexpected entry in bundle: notes.txt");
+    }
+
+    #[test]
+    fn test_import_reports_conflicts_before_writing() {
+        let lib = mk_tmp("prompter_import_conflicts");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "local\n").unwrap();
+        let mut cfg: Config = "[p]\ndepends_on = [\"a.md\"]\n[q]\ndepends_on = [\"b.md\"]\n"
+            .parse()
+            .unwrap();
+        let archive = bundle_archive(
+            Some("[p]\ndepends_on = [\"a.md\", \"b.md\"]\n[q]\ndepends_on = [\"b.md\"]\n"),
+            &[("a.md", "bundled\n"), ("b.md", "B\n")],
+        );
+        let bundle = Bundle::read(archive.as_slice()).unwrap();
+
+        let plan = plan_import(&cfg, &lib, &bundle, false);
+        assert_eq!(
+            plan.conflicts(),
+            [
+                "file a.md: differs from the library copy",
+                "profile p: already defined differently"
+            ]
+        );
+        assert_eq!(plan.profiles[1], ("q".to_string(), ImportAction::Unchanged));
+        let err = apply_import(&mut cfg, &lib, &bundle, &plan).unwrap_err();
+        assert!(err.starts_with("Import has conflicts:"));
+        assert!(!lib.join("b.md").exists(), "nothing is written on conflict");
+        assert_eq!(cfg.profiles["p"], ["a.md"]);
+
+        let plan = plan_import(&cfg, &lib, &bundle, true);
+        assert!(plan.conflicts().is_empty());
+        assert_eq!(
+            plan.files[0],
+            (PathBuf::from("a.md"), ImportAction::Overwrite)
+        );
+        apply_import(&mut cfg, &lib, &bundle, &plan).unwrap();
+        assert_eq!(fs::read_to_string(lib.join("a.md")).unwrap(), "bundled\n");
+        assert_eq!(cfg.profiles["p"], ["a.md", "b.md"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_import_refuses_to_write_through_symlinks() {
+        let lib = mk_tmp("prompter_import_symlinks");
+        let outside = mk_tmp("prompter_import_symlinks_outside");
+        fs::create_dir_all(&lib).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("a.md"), "outside\n").unwrap();
+        std::os::unix::fs::symlink(outside.join("a.md"), lib.join("a.md")).unwrap();
+        std::os::unix::fs::symlink(&outside, lib.join("dir")).unwrap();
+        let archive = bundle_archive(
+            Some("[p]\ndepends_on = [\"a.md\", \"dir/b.md\"]\n"),
+            &[("a.md", "bundled\n"), ("dir/b.md", "B\n")],
+        );
+        let bundle = Bundle::read(archive.as_slice()).unwrap();
+
+        let mut cfg = Config::new();
+        let plan = plan_import(&cfg, &lib, &bundle, true);
+        assert!(plan.conflicts().is_empty());
+        let err = apply_import(&mut cfg, &lib, &bundle, &plan).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Refusing to write through symlinks in the library:\n{}\n{}",
+                lib.join("a.md").display(),
+                lib.join("dir").display()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(outside.join("a.md")).unwrap(),
+            "outside\n"
+        );
+        assert!(!outside.join("b.md").exists());
+        assert!(cfg.profiles.is_empty());
+    }
+
+    #[test]
+    fn test_import_with_prefix_renames_profiles_and_files() {
+        let lib = mk_tmp("prompter_import_prefix");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "local\n").unwrap();
+        fs::write(lib.join("shared.md"), "shared\n").unwrap();
+        let mut cfg: Config = "[p]\ndepends_on = [\"a.md\"]\n".parse().unwrap();
+        let archive = bundle_archive(
+            Some(
+                "[base]\ndepends_on = [\"a.md#L1\", { path = \"opt.md\", optional = true }]\n\
+                 [p]\nextends = \"base\"\ndepends_on = [\"base\", \"shared.md\"]\n",
+            ),
+            &[("a.md", "bundled\n")],
+        );
+        let bundle = Bundle::read(archive.as_slice()).unwrap();
+        assert!(bundle.clone().with_prefix("..").is_err());
+        assert!(bundle.clone().with_prefix("a/b.").is_err());
+
+        let bundle = bundle.with_prefix("vendor.").unwrap();
+        assert_eq!(bundle.config.profile_names(), ["vendor.base", "vendor.p"]);
+        assert_eq!(
+            bundle.config.profiles["vendor.base"],
+            ["vendor/a.md#L1", "opt.md"]
+        );
+        assert_eq!(
+            bundle.config.profiles["vendor.p"],
+            ["vendor.base", "shared.md"]
+        );
+        assert_eq!(bundle.config.extends["vendor.p"], "vendor.base");
+        assert!(bundle.config.is_optional("vendor.base", "opt.md"));
+
+        let plan = plan_import(&cfg, &lib, &bundle, false);
+        assert!(plan.conflicts().is_empty());
+        apply_import(&mut cfg, &lib, &bundle, &plan).unwrap();
+        assert_eq!(
+            fs::read_to_string(lib.join("vendor/a.md")).unwrap(),
+            "bundled\n"
+        );
+        assert_eq!(fs::read_to_string(lib.join("a.md")).unwrap(), "local\n");
+        assert_eq!(cfg.profile_names(), ["p", "vendor.base", "vendor.p"]);
+        validate(&cfg, &lib).unwrap();
+    }
+
+    #[test]
+    fn test_diff_profiles_compares_files_and_content() {
+        let lib = mk_tmp("prompter_diff_profiles");
+        fs::create_dir_all(&lib).unwrap();
+        for name in ["a.md", "b.md", "c.md", "d.md"] {
+            fs::write(lib.join(name), format!("{name}\n")).unwrap();
+        }
+        let cfg: Config = "[old]\ndepends_on = [\"a.md\", \"b.md\", \"c.md\"]\n\
+                           [new]\ndepends_on = [\"b.md\", \"a.md\", \"d.md\"]\n\
+                           [same]\ndepends_on = [\"old\"]\n\
+                           [broken]\ndepends_on = [\"gone.md\"]\n"
+            .parse()
+            .unwrap();
+
+        let diff = diff_profiles(&cfg, &lib, "old", "same", true).unwrap();
+        assert!(diff.is_empty());
+
+        let diff = diff_profiles(&cfg, &lib, "old", "new", true).unwrap();
+        assert_eq!(diff.only_a, BTreeSet::from([lib.join("c.md")]));
+        assert_eq!(diff.only_b, BTreeSet::from([lib.join("d.md")]));
+        assert_eq!(
+            diff.shared,
+            BTreeSet::from([lib.join("a.md"), lib.join("b.md")])
+        );
+        let (order_a, order_b) = diff.reordered.clone().unwrap();
+        assert_eq!(order_a, [lib.join("a.md"), lib.join("b.md")]);
+        assert_eq!(order_b, [lib.join("b.md"), lib.join("a.md")]);
+        let content = diff.content.clone().unwrap();
+        assert!(content.starts_with("--- old\n+++ new\n"), "{content}");
+        assert!(content.contains("\n-c.md\n") && content.contains("\n+d.md\n"));
+
+        let mut out = Vec::new();
+        write_profile_diff(&diff, &lib, "old", "new", false, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(
+            "Only in old (1):\n  - c.md\nOnly in new (1):\n  + d.md\n\
+             Shared (2):\n    a.md\n    b.md\n\
+             Shared files in a different order:\n  old: a.md, b.md\n  new: b.md, a.md\n--- old"
+        ));
+
+        let err = diff_profiles(&cfg, &lib, "broken", "missing", false)
+            .unwrap_err()
+            .to_string();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 2, "{err}");
+        assert!(lines[0].starts_with("broken: Missing file:"));
+        assert_eq!(lines[1], "missing: Unknown profile: missing");
+    }
+
+    #[test]
+    fn test_profile_file_sets_partition_overlapping_profiles() {
+        let lib = mk_tmp("prompter_file_sets");
+        fs::create_dir_all(lib.join("shared")).unwrap();
+        for name in ["shared/x.md", "shared/y.md", "api.md", "cli.md"] {
+            fs::write(lib.join(name), name).unwrap();
+        }
+        let cfg: Config = "[base]\ndepends_on = [\"shared/y.md\", \"shared/x.md\"]\n\
+                           [api]\ndepends_on = [\"base\", \"api.md\"]\n\
+                           [cli]\ndepends_on = [\"cli.md\", \"shared/x.md\", \"base\"]\n"
+            .parse()
+            .unwrap();
+
+        let api = profile_file_sets(&cfg, &lib, "api").unwrap();
+        assert_eq!(
+            api.iter().collect::<Vec<_>>(),
+            [
+                &lib.join("api.md"),
+                &lib.join("shared/x.md"),
+                &lib.join("shared/y.md")
+            ],
+            "sorted, not in render order"
+        );
+        assert!(matches!(
+            profile_file_sets(&cfg, &lib, "nope"),
+            Err(ResolveError::UnknownProfile(_))
+        ));
+
+        let diff = diff_profiles(&cfg, &lib, "api", "cli", false).unwrap();
+        assert_eq!(diff.only_a, BTreeSet::from([lib.join("api.md")]));
+        assert_eq!(diff.only_b, BTreeSet::from([lib.join("cli.md")]));
+        assert_eq!(
+            diff.shared,
+            BTreeSet::from([lib.join("shared/x.md"), lib.join("shared/y.md")])
+        );
+        assert!(diff.reordered.is_some());
+        assert!(diff.content.is_none());
+    }
+
+    #[test]
+    fn test_lint_text_reports_each_rule() {
+        let lib = mk_tmp("prompter_lint_text");
+        fs::create_dir_all(lib.join("docs")).unwrap();
+        fs::write(lib.join("docs/there.md"), b"x\n").unwrap();
+        let file = lib.join("docs/a.md");
+        let text = "# Title\r\n\
+                    ##  \n\
+                    See [ok](there.md#part), [root](/docs/there.md) and [web](https://x.dev).\n\
+                    Also ![gone](img/missing.png \"title\")\t\n\
+                    ```\n\
+                    #\n\
+                    [not a link](nowhere.md)\n\
+                    ```\n\
+                    #hashtag";
+        let cfg = Config::new();
+        let found: Vec<(usize, LintRule)> =
+            lint_text(&cfg, text, Path::new("docs/a.md"), &file, &lib)
+                .iter()
+                .map(|f| (f.line, f.rule))
+                .collect();
+        assert_eq!(
+            found,
+            [
+                (1, LintRule::Crlf),
+                (2, LintRule::TrailingWhitespace),
+                (2, LintRule::EmptyHeadings),
+                (4, LintRule::TrailingWhitespace),
+                (4, LintRule::BrokenLinks),
+                (9, LintRule::FinalNewline),
+            ]
+        );
+
+        let mut quiet = Config::new();
+        quiet
+            .set_lint_rule(LintRule::TrailingWhitespace, false)
+            .set_lint_rule(LintRule::BrokenLinks, false);
+        let found = lint_text(&quiet, text, Path::new("docs/a.md"), &file, &lib);
+        assert_eq!(found.len(), 3);
+        assert_eq!(
+            fix_lint(&quiet, "a \r\nb"),
+            "a \nb\n",
+            "only enabled fixes apply"
+        );
+        assert_eq!(fix_lint(&cfg, "a \r\nb\t"), "a\nb\n");
+    }
+
+    #[test]
+    fn test_lint_library_fixes_and_filters_by_profile() {
+        let lib = mk_tmp("prompter_lint_library");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A  \r\n[x](gone.md)").unwrap();
+        fs::write(lib.join("b.md"), b"B \n").unwrap();
+        let cfg: Config = "[p]\ndepends_on = [\"a.md#L1-L2\"]\n".parse().unwrap();
+
+        let report = lint_library(&cfg, &lib, Some("p"), false).unwrap();
+        assert!(report.findings.iter().all(|f| f.path == Path::new("a.md")));
+        assert_eq!(report.findings.len(), 4);
+
+        let report = lint_library(&cfg, &lib, None, true).unwrap();
+        assert_eq!(report.fixed, [PathBuf::from("a.md"), PathBuf::from("b.md")]);
+        assert_eq!(
+            fs::read_to_string(lib.join("a.md")).unwrap(),
+            "A\n[x](gone.md)\n"
+        );
+        assert_eq!(
+            report
+                .findings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [format!(
+                "a.md:2: broken_links: link target not found: gone.md ({})",
+                lib.join("gone.md").display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_lint_table_parses_and_round_trips() {
+        let cfg: Config = "[lint]\ncrlf = false\nbroken_links = true\n\n[p]\ndepends_on = []\n"
+            .parse()
+            .unwrap();
+        assert!(!cfg.lint_enabled(LintRule::Crlf));
+        assert!(cfg.lint_enabled(LintRule::BrokenLinks));
+        assert_eq!(cfg.profile_names(), ["p"]);
+        let text = cfg.to_toml_string();
+        assert!(text.starts_with("[lint]\ncrlf = false\n\n[p]"), "{text}");
+        assert_eq!(text.parse::<Config>().unwrap(), cfg);
+        assert_eq!(toml::from_str::<Config>(&text).unwrap(), cfg);
+
+        let err = parse_config_toml("[lint]\ntabs = false\n").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown lint rule in [lint]: tabs")
+        );
+        let err = parse_config_toml("[lint]\ncrlf = \"no\"\n").unwrap_err();
+        assert!(err.to_string().contains("crlf must be true or false"));
+    }
+
+    #[test]
+    fn test_lint_table_with_profile_keys_is_a_profile() {
+        let text = "[lint]\ndepends_on = [\"a.md\"]\ntags = [\"ci\"]\n\n[p]\nextends = \"lint\"\n";
+        let cfg = parse_config_toml(text).unwrap();
+        assert_eq!(cfg.profile_names(), ["lint", "p"]);
+        assert_eq!(cfg.dependencies("lint"), Some(&["a.md".to_string()][..]));
+        assert!(LintRule::ALL.iter().all(|&rule| cfg.lint_enabled(rule)));
+        assert_eq!(parse_config_toml_strict(text).unwrap(), cfg);
+        assert_eq!(toml::from_str::<Config>(text).unwrap(), cfg);
+        let out = cfg.to_toml_string();
+        assert_eq!(out.parse::<Config>().unwrap(), cfg);
+        assert_eq!(toml::from_str::<Config>(&out).unwrap(), cfg);
+
+        // Inside a profile, rule names are ordinary unknown keys
+        let text = "[lint]\ncrlf = false\n[[lint.depends_on]]\npath = \"a.md\"\n";
+        let cfg = parse_config_toml(text).unwrap();
+        assert!(cfg.lint_enabled(LintRule::Crlf));
+        assert_eq!(cfg.dependencies("lint"), Some(&["a.md".to_string()][..]));
+        let err = parse_config_toml_strict(text).unwrap_err().to_string();
+        assert!(err.contains("Unknown key 'crlf' in [lint]"), "{err}");
+
+        // Rules are not written into the profile's table
+        let mut cfg = cfg;
+        cfg.set_lint_rule(LintRule::Crlf, false);
+        assert_eq!(cfg.to_toml_string().matches("[lint]").count(), 1);
+    }
+
+    #[test]
+    fn test_strict_parse_rejects_unknown_keys() {
+        let text = "post_prompt = \"Bye\"\ntimestamp_format = \"%Y\"\n\
+                    [lint]\ntrailing_whitespace = false\n\
+                    [p]\ndepends_on = [\"a.md\"]\ntags = [\"t\"]\ndescription = \"P\"\n\
+                    [q]\nextends = \"p\"\n";
+        assert_eq!(
+            parse_config_toml_strict(text).unwrap(),
+            parse_config_toml(text).unwrap()
+        );
+
+        let err = |text: &str| parse_config_toml_strict(text).unwrap_err().to_string();
+        // The lenient parser ignores each of these
+        let typo = "[p]\ndepnds_on = [\"a.md\"]\n";
+        assert!(parse_config_toml(typo).is_ok());
+        assert!(matches!(
+            parse_config_toml_strict(typo),
+            Err(PrompterError::ConfigParse { line: Some(2), .. })
+        ));
+        let e = err(typo);
+        assert!(e.contains("Unknown key 'depnds_on' in [p]"), "{e}");
+        let e = err("post_promt = \"x\"\n");
+        assert!(e.contains("Unknown top-level key 'post_promt'"), "{e}");
+        let e = err("[p]\npost_prompt = \"x\"\n");
+        assert!(
+            e.contains("post_prompt must come before the first profile section"),
+            "{e}"
+        );
+        let e = err("[lint]\nnope = true\n");
+        assert!(e.contains("Unknown lint rule in [lint]: nope"), "{e}");
+    }
+
+    #[test]
+    fn test_config_reads_toml_crate_output_and_nested_sections() {
+        let text = r#"
+post_prompt = "Bye"
+
+[base]
+depends_on = ["a.md", { path = "opt.md", optional = true }]
+tags = ["core"]
+
+[python.api]
+extends = "base"
+depends_on = ["b.md"]
+description = "ignored"
+"#;
+        let cfg = parse_config_toml(text).unwrap();
+        // TOML nests [python.api] under python; it still names one profile
+        assert_eq!(toml::from_str::<Config>(text).unwrap(), cfg);
+        assert_eq!(
+            parse_config_toml(&toml::to_string(&cfg).unwrap()).unwrap(),
+            cfg
+        );
+        assert!(
+            cfg.to_toml_string()
+                .contains("[\"python.api\"]\nextends = \"base\"\n")
+        );
+    }
+
+    #[test]
+    fn test_parse_config_multiline_strings_and_dependency_tables() {
+        let cfg = parse_config_toml(
+            "post_prompt = \"\"\"\n\
+             Line one # not a comment\n\
+             [not.a.section]\n\
+             say \"hi\"\"\"\"\n\
+             [p]\n\
+             extends = '''base'''\n\
+             depends_on = [\n\
+             \x20 \"\"\"a'\" ]\"\"\",\n\
+             \x20 '''\n\
+             b.md''',\n\
+             ]\n\
+             [[\"q.r\".depends_on]]\n\
+             path = 'c\"d.md'\n\
+             optional = true\n\
+             [[\"q.r\".depends_on]]\n\
+             path = \"e.md\" # plain\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.post_prompt(),
+            Some("Line one # not a comment\n[not.a.section]\nsay \"hi\"")
+        );
+        assert_eq!(cfg.extends["p"], "base");
+        assert_eq!(cfg.profiles["p"], vec!["a'\" ]", "b.md"]);
+        assert_eq!(cfg.profiles["q.r"], vec!["c\"d.md", "e.md"]);
+        assert_eq!(
+            cfg.meta["q.r"].optional,
+            HashSet::from(["c\"d.md".to_string()])
+        );
+        assert_eq!(profile_line("a = \"\"\"\n[p]\n\"\"\"\n[p]\n", "p"), Some(4));
+
+        let err =
+            parse_config_toml("[p]\ndepends_on = []\npost_prompt = \"\"\"\nopen\n").unwrap_err();
+        assert!(
+            matches!(err, PrompterError::ConfigParse { line: Some(3), .. }),
+            "{err}"
+        );
+        assert!(
+            err.to_string().contains("Unterminated multi-line string"),
+            "{err}"
+        );
+        let err = parse_config_toml("[[p.tags]]\nname = \"x\"\n").unwrap_err();
+        assert!(
+            err.to_string().contains("[[<profile>.depends_on]]"),
+            "{err}"
+        );
+        let err = parse_config_toml("[[p.depends_on]]\noptional = true\n").unwrap_err();
+        assert!(err.to_string().contains("Missing path"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_config_quoted_headers_and_escaped_quotes() {
+        let cfg = parse_config_toml(
+            "[\"python.api\"]\ndepends_on = [\"say \\\"#1]\\\".md\"] # comment\n['raw.name']\ndepends_on = []\n",
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.dependencies("python.api"),
+            Some(&["say \"#1]\".md".to_string()][..])
+        );
+        assert_eq!(cfg.dependencies("raw.name"), Some(&[][..]));
+    }
+
+    #[test]
+    fn test_config_profile_names_and_dependencies() {
+        let cfg: Config = r#"
+[base]
+depends_on = ["a.md"]
+
+[python.api]
+extends = "base"
+depends_on = ["b.md", "base"]
+"#
+        .parse()
+        .unwrap();
+        assert_eq!(cfg.profile_names(), ["base", "python.api"]);
+        // Only the profile's own entries; inherited files come from resolution
+        assert_eq!(
+            cfg.dependencies("python.api"),
+            Some(&["b.md".to_string(), "base".to_string()][..])
+        );
+        assert_eq!(cfg.dependencies("python"), None);
+        assert_eq!(cfg.post_prompt(), None);
+        assert!(Config::new().profile_names().is_empty());
+    }
+
+    #[test]
+    fn test_timestamp_format_appears_in_banner() {
+        let lib = mk_tmp("prompter_timestamp_format");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "A\n").unwrap();
+        let mut cfg: Config = "timestamp_format = \"year [%Y]\"\n[p]\ndepends_on = [\"a.md\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(cfg.timestamp_format(), Some("year [%Y]"));
+        assert_eq!(cfg.to_toml_string().parse::<Config>().unwrap(), cfg);
+
+        let banner = |cfg: &Config, opts: &RenderOptions| {
+            let mut out = Vec::new();
+            render(cfg, &lib, "p", opts, &mut out).map(|()| String::from_utf8(out).unwrap())
+        };
+        let year = Local::now().format("%Y").to_string();
+        let out = banner(&cfg, &RenderOptions::new()).unwrap();
+        assert!(out.contains(&format!("Today is year [{year}],")), "{out}");
+
+        // The command-line format wins over the config's
+        let opts = RenderOptions::new().timestamp_format(Some("%Y!"));
+        let out = banner(&cfg, &opts).unwrap();
+        assert!(out.contains(&format!("Today is {year}!,")), "{out}");
+
+        let err = banner(&cfg, &opts.timestamp_format(Some("%Q"))).unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid timestamp format '%Q'"),
+            "{err}"
+        );
+        // Without a banner the format is never used
+        let opts = RenderOptions::new()
+            .timestamp_format(Some("%Q"))
+            .system_prefix(false);
+        assert!(banner(&cfg, &opts).is_ok());
+
+        cfg.set_timestamp_format(Some("%".into()));
+        let errors = validate(&cfg, &lib).unwrap_err().to_string();
+        assert!(errors.contains("(timestamp_format)"), "{errors}");
+    }
+
+    #[test]
+    fn test_timezone_selects_banner_clock() {
+        let lib = mk_tmp("prompter_timezone");
+        fs::create_dir_all(&lib).unwrap();
+        let text = "timestamp_format = \"%Z %z\"\ntimezone = \"utc\"\n[p]\ndepends_on = []\n";
+        let cfg: Config = text.parse().unwrap();
+        assert_eq!(cfg.timezone(), Some(Timezone::Utc));
+        assert!(cfg.to_toml_string().contains("\ntimezone = \"utc\"\n"));
+        assert_eq!(cfg.to_toml_string().parse::<Config>().unwrap(), cfg);
+        assert_eq!(toml::from_str::<Config>(text).unwrap(), cfg);
+
+        let banner = |opts: &RenderOptions| {
+            let mut out = Vec::new();
+            render(&cfg, &lib, "p", opts, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let out = banner(&RenderOptions::new());
+        assert!(out.contains("Today is UTC +0000,"), "{out}");
+        // The command line wins over the config
+        let local = Local::now().format("%Z %z").to_string();
+        let out = banner(&RenderOptions::new().timezone(Some(Timezone::Local)));
+        assert!(out.contains(&format!("Today is {local},")), "{out}");
+
+        let utc = format_system_prefix("%Z", Timezone::Utc, false);
+        assert!(utc.starts_with("Today is UTC,"), "{utc}");
+
+        let err = "timezone = \"mars\"\n".parse::<Config>().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("timezone must be \"utc\" or \"local\""),
+            "{err}"
+        );
+        assert!(toml::from_str::<Config>("timezone = \"mars\"\n").is_err());
+    }
+
+    #[test]
+    fn test_default_profile_fallback() {
+        let lib = mk_tmp("prompter_default_profile");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        let cfg: Config = "default_profile = \"base\"\n[base]\ndepends_on = [\"a.md\"]\n\
+                           [other]\ndepends_on = []\n"
+            .parse()
+            .unwrap();
+        assert_eq!(cfg.default_profile(), Some("base"));
+        assert_eq!(profile_or_default(&cfg, None).unwrap(), "base");
+        // An explicit name always wins
+        assert_eq!(profile_or_default(&cfg, Some("other")).unwrap(), "other");
+        assert!(validate(&cfg, &lib).is_ok());
+
+        let mut missing = cfg;
+        missing.set_default_profile(Some("gone".into()));
+        let err = validate(&missing, &lib).unwrap_err().to_string();
+        assert!(
+            err.contains("Unknown profile: gone (default_profile)"),
+            "{err}"
+        );
+
+        let err = "default_profile = [\"base\"]\n"
+            .parse::<Config>()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("default_profile must be a string"), "{err}");
+    }
+
+    #[test]
+    fn test_default_profile_missing_is_an_error() {
+        let cfg: Config = "[base]\ndepends_on = []\n".parse().unwrap();
+        assert_eq!(cfg.default_profile(), None);
+        let err = profile_or_default(&cfg, None).unwrap_err().to_string();
+        assert!(err.contains("no default_profile set"), "{err}");
+        assert_eq!(profile_or_default(&cfg, Some("base")).unwrap(), "base");
+    }
+
+    #[test]
+    fn test_config_constructors_and_accessors() {
+        let lib = mk_tmp("prompter_config_builder");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+
+        let mut cfg = Config::new();
+        cfg.add_profile("child", ["b.md"])
+            .add_profile("root", vec!["a.md".to_string(), "child".to_string()])
+            .set_post_prompt(Some("Done.".into()));
+        assert_eq!(cfg.profiles().len(), 2);
+        assert_eq!(cfg.profile("child"), Some(&["b.md".to_string()][..]));
+        assert_eq!(cfg.profile("nope"), None);
+        assert_eq!(cfg.post_prompt(), Some("Done."));
+        assert!(validate(&cfg, &lib).is_ok());
+        assert_eq!(
+            resolve(&cfg, &lib, "root").unwrap(),
+            vec![lib.join("a.md"), lib.join("b.md")]
+        );
+
+        let parsed: Config = "[root]\ndepends_on = [\"a.md\"]\n".parse().unwrap();
+        assert_eq!(parsed.profile("root"), Some(&["a.md".to_string()][..]));
+        assert!(matches!(
+            "[]".parse::<Config>(),
+            Err(PrompterError::ConfigParse { line: Some(1), .. })
+        ));
+
+        let path = lib.join("config.toml");
+        fs::write(
+            &path,
+            "post_prompt = \"Bye\"\n[p]\ndepends_on = [\"a.md\"]\n",
+        )
+        .unwrap();
+        let loaded = Config::from_path(&path).unwrap();
+        assert_eq!(loaded.post_prompt(), Some("Bye"));
+        assert!(matches!(
+            Config::from_path(&lib.join("missing.toml")),
+            Err(PrompterError::ConfigRead { .. })
+        ));
+    }
+
+    #[test]
+    fn test_prompter_error_variants() {
+        let input =
+            "[a]\ndepends_on = [\"x.md\"]\n\n[b]\ndepends_on = [\n  \"y.md\",\n  {oops}\n]\n";
+        match parse_config_toml(input).unwrap_err() {
+            PrompterError::ConfigParse { line, message } => {
+                // Multi-line arrays report the line they start on
+                assert_eq!(line, Some(5));
+                assert!(message.starts_with("Invalid depends_on array for [b]"));
+            }
+            other => panic!("expected ConfigParse, got {other:?}"),
+        }
+
+        let err = read_config_with_path(Path::new("/nonexistent/prompter.toml")).unwrap_err();
+        assert!(matches!(err, PrompterError::ConfigRead { .. }));
+        assert!(
+            err.to_string()
+                .starts_with("Failed to read /nonexistent/prompter.toml: ")
+        );
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = PrompterError::from(ResolveError::UnknownProfile("x".into()));
+        assert_eq!(err.to_string(), "Unknown profile: x");
+        let err = PrompterError::from(io::Error::other("disk full"));
+        assert_eq!(err.to_string(), "disk full");
+        let err = PrompterError::Invalid(vec!["one".into(), "two".into()]);
+        assert_eq!(String::from(err), "one\ntwo");
+    }
+
+    #[test]
+    fn test_validate_success_and_unknowns() {
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("p1".into(), vec!["a.md".into()]),
+                ("p2".into(), vec!["p1".into(), "b.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_validate_ok");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A").unwrap();
+        fs::write(lib.join("b.md"), b"B").unwrap();
+        assert!(validate(&cfg, &lib).is_ok());
+        let cfg2 = Config {
+            profiles: HashMap::from([("root".into(), vec!["nope".into()])]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let err = validate(&cfg2, &lib).unwrap_err().to_string();
+        assert!(err.contains("Unknown profile"));
+    }
+
+    #[test]
+    fn test_missing_files_sorted_and_deduped() {
+        let lib = mk_tmp("prompter_missing_files");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("here.md"), b"H").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                (
+                    "a".into(),
+                    vec!["z.md".into(), "here.md".into(), "b".into()],
+                ),
+                ("b".into(), vec!["y/x.md".into(), "z.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        assert_eq!(
+            missing_files(&cfg, &lib),
+            vec![lib.join("y/x.md"), lib.join("z.md")]
+        );
+    }
+
+    #[test]
+    fn test_file_profiles() {
+        let lib = mk_tmp("prompter_file_profiles");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"1\n2\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        let cfg = parse_config_toml(
+            "[base]\ndepends_on = [\"a.md\"]\n\
+             [app]\ndepends_on = [\"base\", \"a.md#L2\", \"b.md\"]\n\
+             [broken]\ndepends_on = [\"b.md\", \"gone.md\"]\n",
+        )
+        .unwrap();
+        let usage = file_profiles(&cfg, &lib);
+        assert_eq!(
+            usage.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    lib.join("a.md"),
+                    vec!["app".to_string(), "base".to_string()]
+                ),
+                (lib.join("b.md"), vec!["app".to_string()]),
+            ]
+        );
+
+        // Prompt files are part of every render
+        let mut cfg = cfg;
+        cfg.set_pre_prompt_file(Some("b.md".into()));
+        let usage = file_profiles(&cfg, &lib);
+        assert_eq!(
+            usage.get(&lib.join("b.md")),
+            Some(&vec!["app".to_string(), "base".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_orphaned_files_normalizes_paths_and_counts_prompt_files() {
+        let lib = mk_tmp("prompter_orphaned_normalized");
+        fs::create_dir_all(lib.join("sub")).unwrap();
+        for file in ["b.md", "pre.md", "post.md", "dead.md"] {
+            fs::write(lib.join(file), b"x\n").unwrap();
+        }
+        let cfg = parse_config_toml(
+            "pre_prompt_file = \"pre.md\"\n\
+             post_prompt_file = \"./post.md\"\n\
+             [p]\ndepends_on = [\"sub/../b.md\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            orphaned_files(&cfg, &lib, false).unwrap(),
+            vec![PathBuf::from("dead.md")]
+        );
+        assert_eq!(
+            file_profiles(&cfg, &lib).into_keys().collect::<Vec<_>>(),
+            vec![lib.join("b.md"), lib.join("post.md"), lib.join("pre.md")]
+        );
+        assert_eq!(library_stats(&cfg, &lib).unwrap().unreferenced, 1);
+        assert!(unresolved_profiles(&cfg, &lib).is_empty());
+
+        let broken =
+            parse_config_toml("[p]\ndepends_on = [\"gone.md\"]\n[q]\ndepends_on = []\n").unwrap();
+        assert_eq!(unresolved_profiles(&broken, &lib), vec!["p".to_string()]);
+    }
+
+    #[test]
+    fn test_normalize_lexically() {
+        assert_eq!(
+            normalize_lexically(Path::new("/lib/sub/../a.md")),
+            PathBuf::from("/lib/a.md")
+        );
+        assert_eq!(
+            normalize_lexically(Path::new("./x/./y/../../z")),
+            PathBuf::from("z")
+        );
+        assert_eq!(
+            normalize_lexically(Path::new("../../a")),
+            PathBuf::from("../../a")
+        );
+        assert_eq!(normalize_lexically(Path::new("/../a")), PathBuf::from("/a"));
+    }
+
+    #[test]
+    fn test_orphaned_files_skips_hidden_dirs_and_symlinks() {
+        let lib = mk_tmp("prompter_orphaned_files");
+        fs::create_dir_all(lib.join("sub")).unwrap();
+        fs::create_dir_all(lib.join(".git")).unwrap();
+        fs::write(lib.join("used.md"), b"u\n").unwrap();
+        fs::write(lib.join("sub/dead.md"), b"d\n").unwrap();
+        fs::write(lib.join(".git/hidden.md"), b"h\n").unwrap();
+        fs::write(lib.join("notes.txt"), b"n\n").unwrap();
+        let cfg = parse_config_toml("[p]\ndepends_on = [\"used.md\"]\n").unwrap();
+
+        // A profile that fails to resolve still protects the files it names
+        let broken = parse_config_toml(
+            "[p]\ndepends_on = [\"used.md\"]\n[q]\ndepends_on = [\"sub/dead.md\", \"nope\"]\n",
+        )
+        .unwrap();
+        assert!(orphaned_files(&broken, &lib, false).unwrap().is_empty());
+
+        assert_eq!(
+            orphaned_files(&cfg, &lib, false).unwrap(),
+            vec![PathBuf::from("sub/dead.md")]
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(lib.join("sub"), lib.join("linked")).unwrap();
+            std::os::unix::fs::symlink(&lib, lib.join("sub/loop")).unwrap();
+            assert_eq!(
+                orphaned_files(&cfg, &lib, false).unwrap(),
+                vec![PathBuf::from("sub/dead.md")]
+            );
+            // Each directory is walked once, so the loop terminates.
+            assert_eq!(
+                orphaned_files(&cfg, &lib, true).unwrap(),
+                vec![PathBuf::from("sub/dead.md")]
+            );
+        }
+        assert!(
+            orphaned_files(&cfg, &lib.join("absent"), false)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_profile_lints() {
+        let cfg = parse_config_toml(
+            r#"
+[empty]
+depends_on = []
+
+[child]
+extends = "dup"
+
+[dup]
+depends_on = ["a.md", "b.md", "a.md", "a.md", "empty", "empty"]
+
+[selfish]
+depends_on = ["a.md", "selfish"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            profile_lints(&cfg),
+            vec![
+                ProfileLint::Duplicate {
+                    profile: "dup".into(),
+                    dep: "a.md".into()
+                },
+                ProfileLint::Duplicate {
+                    profile: "dup".into(),
+                    dep: "empty".into()
+                },
+                ProfileLint::Empty("empty".into()),
+                ProfileLint::SelfDependency("selfish".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_skip_missing() {
+        let lib = mk_tmp("prompter_resolve_skip_missing");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("child".into(), vec!["gone.md".into(), "b.md".into()]),
+                ("root".into(), vec!["a.md".into(), "child".into()]),
+            ]),
+            ..Config::default()
+        };
+
+        let err = resolve_with(&cfg, &lib, "root", &ResolveOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            ResolveError::MissingFile(lib.join("gone.md"), "child".into())
+        );
+
+        let lenient = ResolveOptions {
+            skip_missing: true,
+            ..ResolveOptions::default()
+        };
+        assert_eq!(
+            resolve_with(&cfg, &lib, "root", &lenient).unwrap(),
+            vec![lib.join("a.md"), lib.join("b.md")]
+        );
+        // Other errors are unaffected
+        assert_eq!(
+            resolve_with(&cfg, &lib, "nope", &lenient).unwrap_err(),
+            ResolveError::UnknownProfile("nope".into())
+        );
+    }
+
+    #[test]
+    fn test_resolve_errors_and_dedup() {
+        let cfg = Config {
+            profiles: HashMap::from([("root".into(), vec!["missing.md".into()])]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_resolve_errs");
+        fs::create_dir_all(&lib).unwrap();
+        let mut seen = HashSet::new();
+        let mut stack = Vec::new();
+        let mut out = Vec::new();
+        let err = resolve_profile(
+            "root",
+            &cfg,
+            &[lib.as_path()],
+            &mut seen,
+            &mut stack,
+            &mut out,
+        )
+        .unwrap_err();
+        match err {
+            ResolveError::MissingFile(_, p) => assert_eq!(p, "root"),
+            _ => panic!("expected missing file"),
+        }
+
+        let cfg2 = Config {
+            profiles: HashMap::from([
+                ("A".into(), vec!["a/b.md".into()]),
+                ("B".into(), vec!["A".into(), "a/b.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/b.md"), b"X").unwrap();
+        let mut seen = HashSet::new();
+        let mut stack = Vec::new();
+        let mut out = Vec::new();
+        resolve_profile(
+            "B",
+            &cfg2,
+            &[lib.as_path()],
+            &mut seen,
+            &mut stack,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(out.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_dedup_and_order() {
+        let lib = mk_tmp("prompter_resolve_api");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/b.md"), b"X").unwrap();
+        fs::write(lib.join("c.md"), b"Y").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("A".into(), vec!["a/b.md".into()]),
+                ("B".into(), vec!["A".into(), "c.md".into(), "a/b.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let files = resolve(&cfg, &lib, "B").unwrap();
+        assert_eq!(files, vec![lib.join("a/b.md"), lib.join("c.md")]);
+
+        let err = resolve(&cfg, &lib, "nope").unwrap_err();
+        assert_eq!(err, ResolveError::UnknownProfile("nope".into()));
+    }
+
+    #[test]
+    fn test_resolve_depth_exceeded_on_long_chain() {
+        let lib = mk_tmp("prompter_resolve_depth");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("leaf.md"), b"L").unwrap();
+        let mut profiles: HashMap<String, Vec<String>> = (0..DEFAULT_MAX_DEPTH + 5)
+            .map(|i| (format!("p{i}"), vec![format!("p{}", i + 1)]))
+            .collect();
+        profiles.insert(
+            format!("p{}", DEFAULT_MAX_DEPTH + 5),
+            vec!["leaf.md".into()],
+        );
+        let cfg = Config {
+            profiles,
+            post_prompt: None,
+            ..Config::default()
+        };
+        let err = resolve(&cfg, &lib, "p0").unwrap_err();
+        assert_eq!(err, ResolveError::DepthExceeded(DEFAULT_MAX_DEPTH));
+
+        // A custom limit that fits the chain resolves normally
+        let opts = ResolveOptions {
+            max_depth: 128,
+            ..ResolveOptions::default()
+        };
+        let files = resolve_with(&cfg, &lib, "p0", &opts).unwrap();
+        assert_eq!(files, vec![lib.join("leaf.md")]);
+
+        // validate surfaces the depth violation alongside other errors
+        let err = validate(&cfg, &lib).unwrap_err().to_string();
+        assert!(err.contains("exceeds maximum depth"), "err={err}");
+    }
+
+    #[test]
+    fn test_extends_multi_level_inheritance() {
+        let lib = mk_tmp("prompter_extends");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["base.md", "mid.md", "leaf.md", "extra.md"] {
+            fs::write(lib.join(f), f.as_bytes()).unwrap();
+        }
+        let cfg = parse_config_toml(
+            r#"
+[base]
+depends_on = ["base.md"]
+
+[mid]
+extends = "base"
+depends_on = ["mid.md"]
+
+[leaf]
+depends_on = ["leaf.md", "base.md"]
+extends = "mid"
+
+[bare]
+extends = "leaf"
+"#,
+        )
+        .unwrap();
+        let files = resolve(&cfg, &lib, "leaf").unwrap();
+        assert_eq!(
+            files,
+            vec![lib.join("base.md"), lib.join("mid.md"), lib.join("leaf.md")]
+        );
+        // A profile with only `extends` resolves to its parent's files
+        assert_eq!(resolve(&cfg, &lib, "bare").unwrap(), files);
+        assert!(validate(&cfg, &lib).is_ok());
+    }
+
+    #[test]
+    fn test_extends_cycle_and_unknown_parent() {
+        let lib = mk_tmp("prompter_extends_cycle");
+        fs::create_dir_all(&lib).unwrap();
+        let cfg =
+            parse_config_toml("[a]\nextends = \"b\"\n[b]\nextends = \"c\"\n[c]\nextends = \"a\"\n")
+                .unwrap();
+        match resolve(&cfg, &lib, "a").unwrap_err() {
+            ResolveError::Cycle(chain) => assert_eq!(chain, vec!["a", "b", "c", "a"]),
+            other => panic!("expected cycle, got {other:?}"),
+        }
+        assert!(
+            validate(&cfg, &lib)
+                .unwrap_err()
+                .to_string()
+                .contains("Cycle detected")
+        );
+
+        let cfg = parse_config_toml("[a]\nextends = \"ghost\"\n").unwrap();
+        let err = validate(&cfg, &lib).unwrap_err().to_string();
+        assert!(
+            err.contains("Unknown profile: ghost (extended by [a])"),
+            "err={err}"
+        );
+
+        let err = parse_config_toml("[a]\nextends = [\"b\"]\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("extends must be a string"));
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        // unknown flag
+        let args = vec!["prompter".into(), "--bogus".into()];
+        let err = parse_args_from(args).unwrap_err();
+        assert!(err.contains("unexpected argument"));
+        // missing separator value
+        let args = vec!["prompter".into(), "--separator".into()];
+        let err = parse_args_from(args).unwrap_err();
+        assert!(err.contains("value is required"));
+        // no action specified (should default to help)
+        let args = vec!["prompter".into()];
+        let mode = parse_args_from(args).unwrap().mode;
+        assert!(matches!(mode, AppMode::Help));
+    }
+
+    #[test]
+    fn test_emit_mode_json_round_trips() {
+        let args = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let mode = parse_args_from(args(&[
+            "prompter",
+            "run",
+            "python.api",
+            "--since",
+            "2024-01-02T03:04:05Z",
+            "--strict-config",
+            "--emit-mode-json",
+        ]))
+        .unwrap()
+        .mode;
+        let AppMode::EmitModeJson(inner) = mode else {
+            panic!("expected EmitModeJson, got {mode:?}");
+        };
+        let json = serde_json::to_value(&inner).unwrap();
+        assert_eq!(json["mode"]["Run"]["profile"], "python.api");
+        assert_eq!(json["strict_config"], true);
+        let back: Invocation = serde_json::from_value(json.clone()).unwrap();
+        assert!(matches!(&back.mode, AppMode::Run { opts, .. } if opts.since.is_some()));
+        assert!(back.strict_config);
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+
+        let color = |flags: &[&str]| {
+            let mut line = vec!["prompter", "list"];
+            line.extend_from_slice(flags);
+            parse_args_from(args(&line)).unwrap().color
+        };
+        assert_eq!(color(&[]), ColorChoice::Auto);
+        assert_eq!(color(&["--color", "always"]), ColorChoice::Always);
+        assert_eq!(color(&["--no-color"]), ColorChoice::Never);
+
+        for (source, name) in [
+            (PathSource::Env("XDG_DATA_HOME"), "XDG_DATA_HOME"),
+            (PathSource::Flag("--config-dir"), "--config-dir"),
+            (PathSource::WorkingDirectory, "WorkingDirectory"),
+        ] {
+            let json = serde_json::to_string(&source).unwrap();
+            assert!(json.contains(name), "{json}");
+            assert_eq!(serde_json::from_str::<PathSource>(&json).unwrap(), source);
+        }
+        assert!(serde_json::from_str::<PathSource>(r#"{"Env":"OTHER"}"#).is_err());
+
+        let cli = Cli::try_parse_from(["prompter", "diff", "a", "b", "--content"]).unwrap();
+        let json = serde_json::to_string(&cli).unwrap();
+        let back: Cli = serde_json::from_str(&json).unwrap();
+        assert!(matches!(
+            back.command,
+            Some(Commands::Diff { content: true, .. })
+        ));
+    }
+
+    #[test]
+    fn test_list_profiles_order() {
+        let cfg = Config {
+            profiles: HashMap::from([("b".into(), vec![]), ("a".into(), vec![])]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn test_list_profiles_long_counts() {
+        let lib = mk_tmp("prompter_list_long");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["a.md", "b.md", "c.md"] {
+            fs::write(lib.join(f), f).unwrap();
+        }
+        let cfg = parse_config_toml(
+            "[base]\ndepends_on = [\"a.md\", \"b.md\"]\n\
+             [app.server]\ndepends_on = [\"base\", \"a.md\", \"c.md\"]\ntags = [\"web\"]\n\
+             [broken]\ndepends_on = [\"gone.md\"]\n",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        list_profiles_long(&cfg, &lib, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PROFILE     DEPS  FILES  TAGS\n\
+             app.server     3      3  web\n\
+             base           2      2\n\
+             broken         1      ?\n"
+        );
+    }
+
+    #[test]
+    fn test_list_profiles_tags() {
+        let cfg = parse_config_toml(
+            r#"
+[py.test]
+tags = ["python", "testing"]
+depends_on = ["a.md"]
+
+[py.style]
+depends_on = ["b.md"]
+tags = [
+  "python",
+  "style",
+]
+
+[plain]
+depends_on = ["c.md"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.tags("py.test"), ["python", "testing"]);
+        assert!(cfg.tags("plain").is_empty());
+
+        let list = |opts: &ListOptions| {
+            let mut out = Vec::new();
+            list_profiles_with(&cfg, Path::new("/nonexistent"), opts, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let by_tags = |tags: &[&str]| ListOptions {
+            tags: tags.iter().map(ToString::to_string).collect(),
+            ..ListOptions::default()
+        };
+        assert_eq!(list(&by_tags(&["python"])), "py.style\npy.test\n");
+        assert_eq!(list(&by_tags(&["python", "testing"])), "py.test\n");
+        assert_eq!(list(&by_tags(&["rust"])), "");
+        assert_eq!(
+            list(&ListOptions {
+                all_tags: true,
+                ..ListOptions::default()
+            }),
+            "python\nstyle\ntesting\n"
+        );
+        assert_eq!(
+            list(&ListOptions {
+                long: true,
+                ..ListOptions::default()
+            }),
+            "PROFILE   DEPS  FILES  TAGS\n\
+             plain        1      ?\n\
+             py.style     1      ?  python, style\n\
+             py.test      1      ?  python, testing\n"
+        );
+
+        let err = parse_config_toml("[a]\ntags = \"python\"\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("tags must be an array"));
+    }
+
+    #[test]
+    fn test_list_profiles_verbose() {
+        let lib = mk_tmp("prompter_list_verbose");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "a".repeat(2000)).unwrap();
+        fs::write(lib.join("b.md"), "bb\n").unwrap();
+        let cfg = parse_config_toml(
+            "[zeta]\ndepends_on = [\"b.md\"]\n\
+             [app]\nextends = \"zeta\"\ndepends_on = [\"a.md\", \"zeta\"]\n\
+             [broken]\ndepends_on = [\"gone.md\"]\n",
+        )
+        .unwrap();
+        let opts = ListOptions {
+            verbose: true,
+            ..ListOptions::default()
+        };
+        let mut out = Vec::new();
+        list_profiles_with(&cfg, &lib, &opts, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let missing = lib.join("gone.md");
+        assert_eq!(
+            text,
+            format!(
+                "app (resolves to 2 files, 2.0 KiB)\n  extends zeta\n  a.md\n  zeta\n\
+                 broken (error: Missing file: {} (referenced by [broken]))\n  gone.md\n\
+                 zeta (resolves to 1 file, 3 B)\n  b.md\n",
+                missing.display()
+            )
+        );
+
+        // Without a library only the entries are listed, followed by a hint
+        let mut out = Vec::new();
+        list_profiles_verbose(&cfg, &lib.join("none"), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("app\n  extends zeta\n"), "{text}");
+        assert!(text.ends_with("not found; run 'prompter init' to create it\n"));
+
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(12_595), "12.3 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_list_profiles_matching_substring_glob_and_none() {
+        let cfg = parse_config_toml(
+            "[python.api]\ndepends_on = []\n\
+             [python.cli]\ndepends_on = []\n\
+             [rust.api]\ndepends_on = []\n\
+             [meta.python]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let list = |pattern: &str| {
+            let mut out = Vec::new();
+            let count = list_profiles_matching(&cfg, pattern, &mut out).unwrap();
+            (count, String::from_utf8(out).unwrap())
+        };
+
+        // Substring, case-insensitive
+        assert_eq!(
+            list("PYTHON"),
+            (3, "meta.python\npython.api\npython.cli\n".to_string())
+        );
+        assert_eq!(list(".api"), (2, "python.api\nrust.api\n".to_string()));
+
+        // Globs must match the whole name
+        assert_eq!(
+            list("python.*"),
+            (2, "python.api\npython.cli\n".to_string())
+        );
+        assert_eq!(list("*.AP?"), (2, "python.api\nrust.api\n".to_string()));
+        assert_eq!(list("*py*n"), (1, "meta.python\n".to_string()));
+
+        // Nothing matches
+        assert_eq!(list("go"), (0, String::new()));
+        assert_eq!(list("python?"), (0, String::new()));
+
+        assert!(profile_matches("anything", ""));
+        assert!(profile_matches("a.b.c", "a*c"));
+        assert!(!profile_matches("a.b.c", "a*b"));
+    }
+
+    fn tree_text(cfg: &Config, files: bool, unicode: bool) -> String {
+        let mut out = Vec::new();
+        write_tree(&profile_tree(cfg, files), unicode, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_profile_tree_diamond_shares_subprofiles() {
+        let cfg = parse_config_toml(
+            "[app]\ndepends_on = [\"left\", \"right\", \"app.md\"]\n\
+             [left]\ndepends_on = [\"base\"]\n\
+             [right]\nextends = \"base\"\ndepends_on = [\"r.md\"]\n\
+             [base]\ndepends_on = [\"base.md\"]\n\
+             [solo]\ndepends_on = [\"ghost\"]\n",
+        )
+        .unwrap();
+
+        let roots = profile_tree(&cfg, false);
+        let names: Vec<&str> = roots.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, ["app", "solo"]);
+        assert_eq!(roots[1].children[0].kind, TreeNodeKind::Unknown);
+
+        assert_eq!(
+            tree_text(&cfg, false, true),
+            "app\n├── left\n│   └── base\n└── right\n    └── base\n\
+             solo\n└── ghost (unknown profile)\n"
+        );
+        assert_eq!(
+            tree_text(&cfg, true, false),
+            "app\n|-- left\n|   `-- base\n|       `-- base.md\n\
+             |-- right\n|   |-- base\n|   |   `-- base.md\n|   `-- r.md\n\
+             `-- app.md\n\
+             solo\n`-- ghost (unknown profile)\n"
+        );
+    }
+
+    #[test]
+    fn test_profile_tree_marks_cycles() {
+        let cfg = parse_config_toml(
+            "[top]\ndepends_on = [\"a\"]\n\
+             [a]\ndepends_on = [\"b\"]\n\
+             [b]\ndepends_on = [\"a\", \"b.md\"]\n\
+             [x]\ndepends_on = [\"y\"]\n\
+             [y]\ndepends_on = [\"x\"]\n\
+             [me]\ndepends_on = [\"me\"]\n",
+        )
+        .unwrap();
+
+        let roots = profile_tree(&cfg, false);
+        let cycle =
+            &roots.iter().find(|n| n.name == "top").unwrap().children[0].children[0].children[0];
+        assert_eq!(
+            (cycle.name.as_str(), cycle.kind),
+            ("a", TreeNodeKind::Cycle)
+        );
+        assert!(cycle.children.is_empty());
+
+        // Profiles only reachable through a cycle still get a root
+        assert_eq!(
+            tree_text(&cfg, false, true),
+            "me\n└── me … (cycle)\n\
+             top\n└── a\n    └── b\n        └── a … (cycle)\n\
+             x\n└── y\n    └── x … (cycle)\n"
+        );
+        assert!(tree_text(&cfg, false, false).contains("a ... (cycle)"));
+    }
+
+    #[test]
+    fn test_list_tree_roots_follow_filters() {
+        let cfg = parse_config_toml(
+            "[python]\ndepends_on = [\"python.api\"]\n\
+             [python.api]\ndepends_on = [\"base\"]\n\
+             [base]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let opts = ListOptions {
+            tree: true,
+            pattern: Some("api".into()),
+            ..ListOptions::default()
+        };
+        let mut out = Vec::new();
+        list_profiles_with(&cfg, Path::new(""), &opts, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "python.api\n`-- base\n");
+    }
+
+    #[test]
+    fn test_list_profiles_json_round_trips() {
+        let cfg = parse_config_toml(
+            "[zeta]\ndepends_on = [\"b.md\", \"c.md:1-5\"]\n\
+             [app]\ndepends_on = [\"zeta\"]\n\
+             [empty]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        list_profiles_json(&cfg, &mut out).unwrap();
+        let parsed: Vec<serde_json::Value> = serde_json::from_slice(&out).unwrap();
+        let names: Vec<&str> = parsed.iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["app", "empty", "zeta"]);
+        for entry in &parsed {
+            let deps: Vec<String> = serde_json::from_value(entry["depends_on"].clone()).unwrap();
+            assert_eq!(
+                Some(deps.as_slice()),
+                cfg.dependencies(entry["name"].as_str().unwrap())
+            );
+        }
+
+        // The --json option routes through the same writer
+        let opts = ListOptions {
+            json: true,
+            ..ListOptions::default()
+        };
+        let mut via_opts = Vec::new();
+        list_profiles_with(&cfg, Path::new(""), &opts, &mut via_opts).unwrap();
+        assert_eq!(via_opts, out);
+    }
+
+    #[test]
+    fn test_list_profiles_grouped() {
+        let cfg = parse_config_toml(
+            "[python.cli]\ndepends_on = []\n\
+             [rust.api]\ndepends_on = []\n\
+             [python.api]\ndepends_on = []\n\
+             [python]\ndepends_on = []\n\
+             [general.testing.unit]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let opts = ListOptions {
+            grouped: true,
+            ..ListOptions::default()
+        };
+        let mut out = Vec::new();
+        list_profiles_with(&cfg, Path::new("/nonexistent"), &opts, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "general:\n  general.testing.unit\n\n\
+             python:\n  python\n  python.api\n  python.cli\n\n\
+             rust:\n  rust.api\n"
+        );
+
+        // The default flat listing is unchanged
+        let mut out = Vec::new();
+        list_profiles(&cfg, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "general.testing.unit\npython\npython.api\npython.cli\nrust.api\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_cycle_detected() {
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("A".into(), vec!["B".into()]),
+                ("B".into(), vec!["A".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_cycle");
+        fs::create_dir_all(&lib).unwrap();
+        let err = validate(&cfg, &lib).unwrap_err().to_string();
+        assert!(err.contains("Cycle detected"));
+    }
+
+    #[test]
+    fn test_parse_config_multiline_long() {
+        let cfg = r#"
+[profile.x]
+depends_on = [
+  "a/b.md",
+  "c/d.md",
+  "e/f.md",
+]
+"#;
+        let parsed = parse_config_toml(cfg).unwrap();
+        assert_eq!(parsed.profiles.get("profile.x").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_render_basic() {
+        // library and files
+        let lib = mk_tmp("prompter_render_to_writer");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::create_dir_all(lib.join("f")).unwrap();
+        fs::write(lib.join("a/x.md"), b"AX\n").unwrap();
+        fs::write(lib.join("f/y.md"), b"FY\n").unwrap();
+        // config with nested profile and duplicate file reference
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("child".into(), vec!["a/x.md".into()]),
+                (
+                    "root".into(),
+                    vec!["child".into(), "f/y.md".into(), "a/x.md".into()],
+                ),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let opts = RenderOptions::new().separator(Some("\n--\n"));
+        render(&cfg, &lib, "root", &opts, &mut out).unwrap();
+
+        let output_str = String::from_utf8(out).unwrap();
+        // Should start with default pre-prompt
+        assert!(output_str.starts_with("You are an LLM coding agent."));
+        // Should contain system prefix
+        assert!(output_str.contains("Today is "));
+        assert!(output_str.contains(", and you are running on a "));
+        assert!(output_str.contains(" system.\n\n"));
+        // Should contain the file contents with separator
+        assert!(output_str.contains("AX\n"));
+        assert!(output_str.contains("\n--\n"));
+        assert!(output_str.contains("FY\n"));
+        // Should end with default post-prompt
+        assert!(output_str.ends_with(
+            "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist."
+        ));
+    }
+
+    #[test]
+    fn test_render_reads_large_profiles_in_parallel_and_in_order() {
+        let lib = mk_tmp("prompter_render_parallel");
+        fs::create_dir_all(&lib).unwrap();
+        let count = 300;
+        let content = |i: usize| {
+            if i % 10 == 0 {
+                "same\n".to_string()
+            } else {
+                format!("file {i}\nline 2\n")
+            }
+        };
+        for i in 0..count {
+            let bom: &[u8] = if i % 7 == 0 { UTF8_BOM } else { b"" };
+            fs::write(
+                lib.join(format!("f{i}.md")),
+                [bom, content(i).as_bytes()].concat(),
+            )
+            .unwrap();
+        }
+        // A fixed permutation, so output order differs from file creation order
+        let order: Vec<usize> = (0..count).map(|i| i * 37 % count).collect();
+        let mut deps: Vec<String> = order.iter().map(|i| format!("f{i}.md")).collect();
+        deps.push("f1.md#L2-L2".into());
+        let mut cfg = Config::new();
+        cfg.add_profile("big", deps);
+        assert!(order.len() >= PARALLEL_READ_MIN_FILES);
+
+        let render_big = |opts: RenderOptions| {
+            let opts = opts
+                .pre_prompt(Some("PRE"))
+                .post_prompt(Some("POST"))
+                .separator(Some("|"))
+                .system_prefix(false);
+            let mut out = Vec::new();
+            render(&cfg, &lib, "big", &opts, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let expected = |dedup: bool| {
+            let mut seen = HashSet::new();
+            let mut text = "PRE".to_string();
+            for &i in &order {
+                // Byte order marks count toward content identity
+                if dedup && !seen.insert((content(i), i % 7 == 0)) {
+                    continue;
+                }
+                text.push('\n');
+                text += &content(i);
+                text.push('|');
+            }
+            text + "\nline 2\n|\n\nPOST"
+        };
+        assert_eq!(render_big(RenderOptions::new()), expected(false));
+        assert_eq!(
+            render_big(RenderOptions::new().dedup(DedupMode::Content)),
+            expected(true)
+        );
+
+        // A file that cannot be read fails the same way read ahead or not
+        fs::create_dir_all(lib.join("dir.md")).unwrap();
+        for len in [1, count] {
+            let mut deps: Vec<String> = (0..len).map(|i| format!("f{i}.md")).collect();
+            deps.push("dir.md".into());
+            cfg.add_profile("broken", deps);
+            let err = render(&cfg, &lib, "broken", &RenderOptions::new(), io::sink())
+                .unwrap_err()
+                .to_string();
+            let prefix = format!("Failed to read {}: ", lib.join("dir.md").display());
+            assert!(err.starts_with(&prefix), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_render_streams_large_files() {
+        let lib = mk_tmp("prompter_render_stream");
+        fs::create_dir_all(&lib).unwrap();
+        // Larger than any internal buffer, and not a multiple of its size
+        let big: Vec<u8> = (0..5_000_003u32).map(|i| b'a' + (i % 26) as u8).collect();
+        fs::write(lib.join("big.md"), &big).unwrap();
+        fs::write(lib.join("small.md"), b"tail\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([(
+                "p".into(),
+                vec!["big.md".into(), "small.md".into(), "big.md#L1".into()],
+            )]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            separator: Some("\n--\n".into()),
+            pre_prompt: Some("PRE".into()),
+            post_prompt: Some("POST".into()),
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+
+        let prefix = format!(
+            "PRE\n{}",
+            format_system_prefix(DEFAULT_TIMESTAMP_FORMAT, Timezone::Local, false)
+        );
+        let mut expected = prefix.into_bytes();
+        for part in [&big[..], b"tail\n", &big[..]] {
+            expected.extend_from_slice(b"\n");
+            expected.extend_from_slice(part);
+            expected.extend_from_slice(b"\n--\n");
+        }
+        expected.extend_from_slice(b"\n\nPOST");
+        assert_eq!(out.len(), expected.len());
+        assert!(out == expected);
+    }
+
+    #[test]
+    fn test_strip_frontmatter() {
+        assert_eq!(
+            strip_frontmatter(b"---\ntitle: Style\ntags: [a]\n---\n# Style\n"),
+            b"# Style\n"
+        );
+        assert_eq!(
+            strip_frontmatter(b"+++\ntitle = \"Style\"\n+++\r\nBody"),
+            b"Body"
+        );
+        assert_eq!(
+            strip_frontmatter(b"---\r\na: 1\r\n---\r\nBody\r\n"),
+            b"Body\r\n"
+        );
+        // No frontmatter
+        assert_eq!(strip_frontmatter(b"# Title\nBody\n"), b"# Title\nBody\n");
+        assert_eq!(strip_frontmatter(b""), b"");
+        // `---` only in the body is a horizontal rule, not frontmatter
+        assert_eq!(
+            strip_frontmatter(b"Intro\n---\nmore\n---\n"),
+            b"Intro\n---\nmore\n---\n"
+        );
+        // Mismatched or unterminated delimiters are left alone
+        assert_eq!(
+            strip_frontmatter(b"---\na: 1\n+++\nBody\n"),
+            b"---\na: 1\n+++\nBody\n"
+        );
+        assert_eq!(
+            strip_frontmatter(b"----\nBody\n----\n"),
+            b"----\nBody\n----\n"
+        );
+    }
+
+    #[test]
+    fn test_whitespace_transforms() {
+        let mixed = b"one  \r\ntwo\t\nthree \r\n\r\nlast \t";
+        assert_eq!(
+            trim_trailing_whitespace(mixed),
+            b"one\r\ntwo\nthree\r\n\r\nlast"
+        );
+        assert_eq!(
+            normalize_line_endings(mixed),
+            b"one  \ntwo\t\nthree \n\nlast \t"
+        );
+        assert_eq!(
+            trim_trailing_whitespace(&normalize_line_endings(mixed)),
+            b"one\ntwo\nthree\n\nlast"
+        );
+        // Leading and inner whitespace is untouched
+        assert_eq!(trim_trailing_whitespace(b"  a  b  \n"), b"  a  b\n");
+        assert_eq!(trim_trailing_whitespace(b""), b"");
+    }
+
+    #[test]
+    fn test_render_whitespace_flags_compose() {
+        let lib = mk_tmp("prompter_render_whitespace");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"a  \r\nb\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let render_body = |opts: RenderOptions| {
+            let opts = RenderOptions {
+                pre_prompt: Some(String::new()),
+                post_prompt: Some(String::new()),
+                ..opts
+            };
+            let mut out = Vec::new();
+            render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+            let prefix = format!(
+                "\n{}\n",
+                format_system_prefix(DEFAULT_TIMESTAMP_FORMAT, Timezone::Local, false)
+            );
+            out[prefix.len()..out.len() - 2].to_vec()
+        };
+
+        assert_eq!(render_body(RenderOptions::default()), b"a  \r\nb\n");
+        assert_eq!(
+            render_body(RenderOptions {
+                trim_trailing_whitespace: true,
+                ..RenderOptions::default()
+            }),
+            b"a\r\nb\n"
+        );
+        assert_eq!(
+            render_body(RenderOptions {
+                normalize_line_endings: true,
+                ..RenderOptions::default()
+            }),
+            b"a  \nb\n"
+        );
+        assert_eq!(
+            render_body(RenderOptions {
+                trim_trailing_whitespace: true,
+                normalize_line_endings: true,
+                ..RenderOptions::default()
+            }),
+            b"a\nb\n"
+        );
+    }
+
+    #[test]
+    fn test_render_strip_frontmatter() {
+        let lib = mk_tmp("prompter_render_frontmatter");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"---\ntitle: A\n---\nA body\n").unwrap();
+        fs::write(lib.join("b.md"), b"B body\n---\nB more\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into(), "b.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            pre_prompt: Some(String::new()),
+            post_prompt: Some(String::new()),
+            ..RenderOptions::default()
+        };
+
+        let mut out = Vec::new();
+        render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("title: A"));
+
+        let stripped = RenderOptions {
+            strip_frontmatter: true,
+            ..opts
+        };
+        let mut out = Vec::new();
+        render(&cfg, &lib, "p", &stripped, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("title: A"));
+        assert!(out.ends_with("\nA body\n\nB body\n---\nB more\n\n\n"));
+    }
+
+    #[test]
+    fn test_render_to_string_matches_render() {
+        let lib = mk_tmp("prompter_render_to_string");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "Grüße, {{team}}\n").unwrap();
+        let mut cfg = Config::new();
+        cfg.add_profile("p", ["a.md"]);
+        let opts = RenderOptions {
+            separator: Some("--".into()),
+            vars: BTreeMap::from([("team".to_string(), "core".to_string())]),
+            ..RenderOptions::default()
+        };
+
+        let mut bytes = Vec::new();
+        render(&cfg, &lib, "p", &opts, &mut bytes).unwrap();
+        let text = render_to_string(&cfg, &lib, "p", &opts).unwrap();
+        assert_eq!(text.as_bytes(), bytes);
+        assert!(text.contains("Grüße, core\n"));
+        assert!(matches!(
+            render_to_string(&cfg, &lib, "missing", &opts),
+            Err(PrompterError::Resolve(ResolveError::UnknownProfile(_)))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_add_file_and_drop_nested_profile() {
+        let lib = mk_tmp("prompter_add_drop");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["a.md", "b.md", "c.md", "extra.md"] {
+            fs::write(lib.join(f), f).unwrap();
+        }
+        let mut cfg = Config::new();
+        cfg.add_profile("inner", ["b.md"])
+            .add_profile("mid", ["inner", "c.md"])
+            .add_profile("app", ["a.md", "mid"]);
+
+        let opts = ResolveOptions {
+            add: vec!["extra.md".into()],
+            drop: vec!["inner".into()],
+            ..ResolveOptions::default()
+        };
+        assert_eq!(
+            resolve_with(&cfg, &lib, "app", &opts).unwrap(),
+            vec![lib.join("a.md"), lib.join("c.md"), lib.join("extra.md")]
+        );
+        // Dropping a file works the same way, and added profiles recurse
+        let opts = ResolveOptions {
+            add: vec!["inner".into()],
+            drop: vec!["a.md".into()],
+            ..ResolveOptions::default()
+        };
+        assert_eq!(
+            resolve_with(&cfg, &lib, "app", &opts).unwrap(),
+            vec![lib.join("b.md"), lib.join("c.md")]
+        );
+        let opts = ResolveOptions {
+            add: vec!["nope.md".into()],
+            ..ResolveOptions::default()
+        };
+        assert!(matches!(
+            resolve_with(&cfg, &lib, "app", &opts),
+            Err(ResolveError::MissingFile(..))
+        ));
+    }
+
+    #[test]
+    fn test_render_order_alpha_and_mtime() {
+        let lib = mk_tmp("prompter_render_order");
+        fs::create_dir_all(&lib).unwrap();
+        let now = std::time::SystemTime::now();
+        for (name, age_secs) in [("c.md", 20), ("a.md", 10), ("b.md", 30)] {
+            fs::write(lib.join(name), format!("<{name}>")).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(lib.join(name))
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age_secs))
+                .unwrap();
+        }
+        let mut cfg = Config::new();
+        cfg.add_profile("p", ["c.md", "a.md", "b.md"]);
+
+        let order_of = |order| {
+            let opts = RenderOptions {
+                order,
+                ..RenderOptions::default()
+            };
+            let text = render_to_string(&cfg, &lib, "p", &opts).unwrap();
+            let mut names = vec!["a.md", "b.md", "c.md"];
+            names.sort_by_key(|name| text.find(&format!("<{name}>")).unwrap());
+            names
+        };
+        assert_eq!(order_of(FileOrder::Config), ["c.md", "a.md", "b.md"]);
+        assert_eq!(order_of(FileOrder::Alpha), ["a.md", "b.md", "c.md"]);
+        assert_eq!(order_of(FileOrder::Mtime), ["b.md", "c.md", "a.md"]);
+    }
+
+    #[test]
+    fn test_line_diff_shows_changed_lines_in_hunks() {
+        assert_eq!(line_diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            line_diff("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n"),
+            "@@ -2 +2 @@\n-b\n+x\n@@ -5 +5 @@\n+e\n"
+        );
+        assert_eq!(line_diff("a\n", "a"), "@@ -2 +2 @@\n-\n");
+    }
+
+    #[test]
+    fn test_check_snapshot_missing_update_and_mismatch() {
+        let lib = mk_tmp("prompter_snapshot");
+        let dir = lib.join("snapshots");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "one\n").unwrap();
+        let mut cfg = Config::new();
+        cfg.add_profile("p", ["a.md"]);
+
+        let check = |update| check_snapshot(&cfg, &lib, "p", &dir, update).unwrap();
+        assert_eq!(check(false), SnapshotOutcome::Missing);
+        assert_eq!(check(true), SnapshotOutcome::Updated);
+        let snap = fs::read_to_string(snapshot_path(&dir, "p")).unwrap();
+        assert!(snap.contains("one\n"));
+        assert!(!snap.contains("Today is"));
+        assert_eq!(check(false), SnapshotOutcome::Matched);
+
+        fs::write(lib.join("a.md"), "two\n").unwrap();
+        assert_eq!(
+            check(false),
+            SnapshotOutcome::Mismatch(line_diff(&snap, &snap.replace("one\n", "two\n")))
+        );
+        assert_eq!(check(true), SnapshotOutcome::Updated);
+        assert_eq!(check(false), SnapshotOutcome::Matched);
+    }
+
+    #[test]
+    fn test_render_strips_bom_and_can_require_utf8() {
+        let lib = mk_tmp("prompter_render_bom");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("bom.md"), b"\xEF\xBB\xBFWith BOM\n").unwrap();
+        fs::write(lib.join("latin1.md"), b"caf\xE9\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("bom".into(), vec!["a.md".into(), "bom.md".into()]),
+                ("latin1".into(), vec!["latin1.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        let opts = RenderOptions {
+            pre_prompt: Some(String::new()),
+            post_prompt: Some(String::new()),
+            separator: Some("--\n".into()),
+            ..RenderOptions::default()
+        };
+        let render_str = |opts: &RenderOptions, profile: &str| {
+            let mut out = Vec::new();
+            render(&cfg, &lib, profile, opts, &mut out).map(|()| out)
+        };
+
+        // Both the streaming and the buffered path drop the BOM
+        for opts in [
+            opts.clone(),
+            RenderOptions {
+                require_utf8: true,
+                ..opts.clone()
+            },
+        ] {
+            let out = render_str(&opts, "bom").unwrap();
+            assert!(!out.windows(3).any(|w| w == UTF8_BOM));
+            assert!(
+                String::from_utf8(out)
+                    .unwrap()
+                    .ends_with("\nA\n--\n\nWith BOM\n--\n\n\n")
+            );
+        }
+
+        // Invalid UTF-8 is copied as is unless it is required
+        let out = render_str(&opts, "latin1").unwrap();
+        assert!(out.ends_with(b"\ncaf\xE9\n--\n\n\n"));
+        let strict = RenderOptions {
+            require_utf8: true,
+            ..opts
+        };
+        let err = render_str(&strict, "latin1").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "File is not valid UTF-8: {} (invalid byte at offset 3)",
+                lib.join("latin1.md").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_library_roots_are_searched_in_order() {
+        let base = mk_tmp("prompter_library_roots");
+        let lib = base.join("library");
+        let shared = base.join("shared");
+        let personal = base.join("personal");
+        for dir in [&lib, &shared, &personal] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(lib.join("own.md"), b"own\n").unwrap();
+        fs::write(shared.join("team.md"), b"shared team\n").unwrap();
+        fs::write(personal.join("team.md"), b"personal team\n").unwrap();
+        fs::write(personal.join("mine.md"), b"mine\n").unwrap();
+
+        let cfg = parse_config_toml(&format!(
+            "library_roots = [\n  \"{}\",\n  \"../personal\",\n]\n\
+             [p]\ndepends_on = [\"own.md\", \"team.md\", \"mine.md\"]\n",
+            shared.display()
+        ))
+        .unwrap();
+        assert_eq!(cfg.library_roots().len(), 2);
+        assert_eq!(
+            cfg.search_roots(&lib),
+            vec![lib.clone(), shared.clone(), lib.join("../personal")]
+        );
+
+        // The first root holding a file wins
+        let files = resolve(&cfg, &lib, "p").unwrap();
+        assert_eq!(
+            files,
+            vec![
+                lib.join("own.md"),
+                shared.join("team.md"),
+                lib.join("../personal/mine.md")
+            ]
+        );
+        assert!(validate(&cfg, &lib).is_ok());
+        assert!(missing_files(&cfg, &lib).is_empty());
+        assert_eq!(
+            file_roots(&cfg, &lib),
+            BTreeMap::from([
+                ("mine.md".to_string(), lib.join("../personal")),
+                ("own.md".to_string(), lib.clone()),
+                ("team.md".to_string(), shared),
+            ])
+        );
+
+        // Without the roots only the primary library is searched
+        let single = parse_config_toml("[p]\ndepends_on = [\"team.md\"]\n").unwrap();
+        assert!(matches!(
+            resolve(&single, &lib, "p"),
+            Err(ResolveError::MissingFile(..))
+        ));
+
+        let err = parse_config_toml("[p]\nlibrary_roots = [\"x\"]\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "library_roots must come before the first profile section"
+        );
+    }
+
+    #[test]
+    fn test_resolve_external_paths_are_gated() {
+        let lib = mk_tmp("prompter_resolve_external_lib");
+        let shared = mk_tmp("prompter_resolve_external_shared");
+        fs::create_dir_all(&lib).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(shared.join("ext.md"), b"E\n").unwrap();
+        let abs = shared.join("ext.md").display().to_string();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("abs".into(), vec!["a.md".into(), format!("{abs}#L1")]),
+                ("tilde".into(), vec!["~/notes/x.md".into()]),
+            ]),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            resolve(&cfg, &lib, "abs").unwrap_err(),
+            ResolveError::ExternalPath(format!("{abs}#L1"), "abs".into())
+        );
+        assert_eq!(
+            resolve(&cfg, &lib, "tilde").unwrap_err(),
+            ResolveError::ExternalPath("~/notes/x.md".into(), "tilde".into())
+        );
+        let opts = ResolveOptions {
+            allow_external: true,
+            ..ResolveOptions::default()
+        };
+        assert_eq!(
+            resolve_with(&cfg, &lib, "abs", &opts).unwrap(),
+            vec![lib.join("a.md"), shared.join("ext.md")]
+        );
+        assert!(is_external_dep("~/x.md"));
+        assert!(!is_external_dep("~x.md"));
+        assert!(!is_external_dep("a/b.md"));
+    }
+
+    #[test]
+    fn test_resolve_rejects_paths_escaping_library() {
+        let root = mk_tmp("prompter_resolve_escape");
+        let lib = root.join("lib");
+        fs::create_dir_all(lib.join("inner")).unwrap();
+        fs::write(root.join("secret.md"), b"S\n").unwrap();
+        fs::write(lib.join("ok.md"), b"O\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("dots".into(), vec!["inner/../../secret.md".into()]),
+                ("inside".into(), vec!["inner/../ok.md".into()]),
+            ]),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            resolve(&cfg, &lib, "dots").unwrap_err(),
+            ResolveError::OutsideLibrary(lib.join("inner/../../secret.md"))
+        );
+        assert_eq!(
+            resolve(&cfg, &lib, "inside").unwrap(),
+            vec![lib.join("inner/../ok.md")]
+        );
+        let opts = ResolveOptions {
+            allow_external: true,
+            ..ResolveOptions::default()
+        };
+        assert!(resolve_with(&cfg, &lib, "dots", &opts).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_rejects_symlink_escaping_library() {
+        let root = mk_tmp("prompter_resolve_symlink_escape");
+        let lib = root.join("lib");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(root.join("secret.md"), b"S\n").unwrap();
+        std::os::unix::fs::symlink(root.join("secret.md"), lib.join("link.md")).unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["link.md".into()])]),
+            ..Config::default()
+        };
+        assert_eq!(
+            resolve(&cfg, &lib, "p").unwrap_err(),
+            ResolveError::OutsideLibrary(lib.join("link.md"))
+        );
+    }
+
+    #[test]
+    fn test_templates_resolve_every_profile() {
+        for template in TEMPLATES {
+            let lib = mk_tmp(&format!("prompter_template_{}", template.name));
+            for (path, contents) in template_files(template, &lib.join("config.toml"), &lib) {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            let cfg = parse_config_toml(template.config).unwrap();
+            assert!(!cfg.profiles.is_empty(), "{}", template.name);
+            validate(&cfg, &lib).unwrap_or_else(|e| panic!("{}: {e}", template.name));
+            for profile in cfg.profiles.keys() {
+                let files = resolve(&cfg, &lib, profile)
+                    .unwrap_or_else(|e| panic!("{}/{profile}: {e}", template.name));
+                assert!(!files.is_empty(), "{}/{profile}", template.name);
+            }
+            // Every bundled file is used by some profile
+            assert!(orphaned_files(&cfg, &lib, false).unwrap().is_empty());
+        }
+        assert!(find_template("coding-agent").is_ok());
+        assert!(
+            find_template("nope")
+                .unwrap_err()
+                .contains("coding-agent, docs")
+        );
+    }
+
+    #[test]
+    fn test_write_default_backs_up_only_when_forced() {
+        let dir = mk_tmp("prompter_write_default");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "mine").unwrap();
+
+        assert_eq!(
+            write_default(&path, "default", false, "S").unwrap(),
+            DefaultWrite::Kept
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "mine");
+
+        let backup = dir.join("config.toml.bak-S");
+        assert_eq!(
+            write_default(&path, "default", true, "S").unwrap(),
+            DefaultWrite::Replaced {
+                backup: backup.clone()
+            }
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "default");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "mine");
+
+        // Unchanged defaults are not backed up again
+        assert_eq!(
+            write_default(&path, "default", true, "S").unwrap(),
+            DefaultWrite::Kept
+        );
+
+        // A second backup with the same stamp gets its own name
+        fs::write(&path, "mine again").unwrap();
+        let second = dir.join("config.toml.bak-S-1");
+        assert_eq!(
+            write_default(&path, "default", true, "S").unwrap(),
+            DefaultWrite::Replaced {
+                backup: second.clone()
+            }
+        );
+        assert_eq!(
+            write_default(&dir.join("new.md"), "x", false, "S").unwrap(),
+            DefaultWrite::Created
+        );
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "mine");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "mine again");
+    }
+
+    #[test]
+    fn test_render_limit_bytes_names_the_file_over_the_limit() {
+        let lib = mk_tmp("prompter_render_limit_bytes");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("big.md"), vec![b'x'; 100]).unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into(), "big.md".into()])]),
+            ..Config::default()
+        };
+        let header =
+            1 + format_system_prefix(DEFAULT_TIMESTAMP_FORMAT, Timezone::Local, false).len() as u64;
+        let render_with = |limit: u64| {
+            let opts = RenderOptions {
+                pre_prompt: Some(String::new()),
+                post_prompt: Some("POST".into()),
+                limit_bytes: Some(header + limit),
+                ..RenderOptions::default()
+            };
+            render(&cfg, &lib, "p", &opts, &mut Vec::new())
+        };
+
+        // The header, "\nA\n", and "\n" fit; big.md does not
+        let err = render_with(50).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "Output exceeds --limit-bytes {} at {}",
+                header + 50,
+                lib.join("big.md").display()
+            )
+        );
+        // Everything but the post-prompt fits
+        let err = render_with(3 + 101 + 2).unwrap_err().to_string();
+        assert!(err.ends_with("at the post-prompt"), "{err}");
+        assert!(render_with(3 + 101 + 2 + 4).is_ok());
+    }
+
+    #[test]
+    fn test_render_manifest_lists_files_and_hashes() {
+        let lib = mk_tmp("prompter_render_manifest");
+        fs::create_dir_all(lib.join("sub")).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("sub/b.md"), b"one\ntwo\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([(
+                "p".into(),
+                vec!["a.md".into(), "sub/b.md#L2".into(), "a.md".into()],
+            )]),
+            ..Config::default()
+        };
+
+        let json = render_manifest(&cfg, &lib, "p", &RenderOptions::default()).unwrap();
+        assert_eq!(json["profile"], "p");
+        assert!(json["rendered_at"].as_str().is_some());
+        assert_eq!(
+            json["files"],
+            serde_json::json!([
+                {
+                    "path": "a.md",
+                    "bytes": 2,
+                    "sha256": hex::encode(Sha256::digest(b"A\n")),
+                },
+                {
+                    "path": "sub/b.md#L2",
+                    "bytes": 4,
+                    "sha256": hex::encode(Sha256::digest(b"two\n")),
+                },
+            ])
         );
-        p.push(unique);
-        p
     }
 
     #[test]
-    fn test_unescape() {
-        assert_eq!(unescape("a\\nb\\t\\\"\\\\c"), "a\nb\t\"\\c");
-        assert_eq!(unescape("line1\\rline2"), "line1\rline2");
-        assert_eq!(unescape("noesc"), "noesc");
+    fn test_render_separator_between_only() {
+        let lib = mk_tmp("prompter_render_between_only");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        fs::write(lib.join("b2.md"), b"B\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([(
+                "p".into(),
+                vec!["a.md".into(), "b.md".into(), "b2.md".into()],
+            )]),
+            ..Config::default()
+        };
+        let body = |opts: RenderOptions| {
+            let opts = RenderOptions {
+                separator: Some("--\n".into()),
+                pre_prompt: Some(String::new()),
+                post_prompt: Some("POST".into()),
+                ..opts
+            };
+            let mut out = Vec::new();
+            render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            out[format!(
+                "\n{}",
+                format_system_prefix(DEFAULT_TIMESTAMP_FORMAT, Timezone::Local, false)
+            )
+            .len()..]
+                .to_string()
+        };
+
+        // Default keeps the trailing separator
+        assert_eq!(
+            body(RenderOptions::default()),
+            "\nA\n--\n\nB\n--\n\nB\n--\n\n\nPOST"
+        );
+        assert_eq!(
+            body(RenderOptions {
+                between_only: true,
+                ..RenderOptions::default()
+            }),
+            "\nA\n--\n\nB\n--\n\nB\n\n\nPOST"
+        );
+        // The last file written may not be the last resolved one
+        assert_eq!(
+            body(RenderOptions {
+                between_only: true,
+                dedup: DedupMode::Content,
+                ..RenderOptions::default()
+            }),
+            "\nA\n--\n\nB\n\n\nPOST"
+        );
     }
 
     #[test]
-    fn test_strip_comments_and_brackets_detection() {
-        let s = r"ab#cd";
-        assert_eq!(strip_comments(s), "ab");
-        let s = r#""ab#cd" # trailing"#;
-        assert_eq!(strip_comments(s), "\"ab#cd\" ");
-        assert!(contains_closing_bracket_outside_quotes("[\"not]here\"]]"));
-        assert!(!contains_closing_bracket_outside_quotes("[\"no]close\""));
+    fn test_render_collapse_blank_lines() {
+        let lib = mk_tmp("prompter_render_collapse");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"\nA\n\n\nA2\n\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        let mut cfg = Config::new();
+        cfg.add_profile("p", ["a.md", "b.md"]);
+        let body = |opts: RenderOptions| {
+            let opts = opts
+                .pre_prompt(Some("PRE\n"))
+                .post_prompt(Some("POST"))
+                .system_prefix(false);
+            let mut out = Vec::new();
+            render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // The default layout is unchanged
+        assert_eq!(
+            body(RenderOptions::new()),
+            "PRE\n\n\nA\n\n\nA2\n\n\nB\n\n\nPOST"
+        );
+        // Boundaries shrink to one blank line; the file's own run is kept
+        assert_eq!(
+            body(RenderOptions::new().collapse_blank_lines(true)),
+            "PRE\n\nA\n\n\nA2\n\nB\n\nPOST"
+        );
+        assert_eq!(
+            body(
+                RenderOptions::new()
+                    .separator(Some("\n---\n"))
+                    .collapse_blank_lines(true)
+            ),
+            "PRE\n\nA\n\n\nA2\n\n---\n\nB\n\n---\n\nPOST"
+        );
     }
 
     #[test]
-    fn test_parse_array_items_escape_and_error() {
-        let s = r#"["a\"b", "c"]"#;
-        let items = parse_array_items(s).unwrap();
-        assert_eq!(items, vec!["a\"b", "c"]);
-        let err = parse_array_items("[\"unterminated").unwrap_err();
-        assert!(err.contains("Unterminated"));
+    #[allow(deprecated)]
+    fn test_render_to_writer_shim_matches_render() {
+        let lib = mk_tmp("prompter_render_to_writer_shim");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        let mut cfg = Config::new();
+        cfg.add_profile("p", ["a.md", "b.md"]);
+
+        let mut shim = Vec::new();
+        super::render_to_writer(
+            &cfg,
+            &lib,
+            &mut shim,
+            "p",
+            Some("--"),
+            Some("PRE"),
+            Some("POST"),
+        )
+        .unwrap();
+        let opts = RenderOptions::new()
+            .separator(Some("--"))
+            .pre_prompt(Some("PRE"))
+            .post_prompt(Some("POST"));
+        let mut out = Vec::new();
+        render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+        assert_eq!(shim, out);
     }
 
     #[test]
-    fn test_parse_config_errors() {
-        let err = parse_config_toml("[]\n").unwrap_err();
-        assert!(err.contains("Empty section name"));
-        let err = parse_config_toml("[p]\ndepends_on = \"x\"\n").unwrap_err();
-        assert!(err.contains("must be an array"));
-        let err = parse_config_toml("depends_on = [\"a.md\"]\n").unwrap_err();
-        assert!(err.contains("outside of a profile section"));
+    fn test_render_options_builder_sets_every_field() {
+        let opts = RenderOptions::new()
+            .separator(Some("--"))
+            .pre_prompt(Some("PRE"))
+            .post_prompt(None)
+            .vars([("team", "core"), ("team", "web")])
+            .dedup(DedupMode::Content)
+            .order(FileOrder::Alpha)
+            .add_dependencies(["extra.md"])
+            .drop_dependencies(vec!["base".to_string()])
+            .strict_env(true)
+            .skip_missing(true)
+            .strip_frontmatter(true)
+            .trim_trailing_whitespace(true)
+            .normalize_line_endings(true)
+            .between_only(true)
+            .collapse_blank_lines(true)
+            .allow_external(true)
+            .require_utf8(true)
+            .limit_bytes(Some(10))
+            .since(Some(SystemTime::UNIX_EPOCH))
+            .timestamp_format(Some("%H:%M"))
+            .timezone(Some(Timezone::Utc))
+            .system_prefix(false)
+            .styled(true);
+        assert_eq!(
+            opts,
+            RenderOptions {
+                separator: Some("--".into()),
+                pre_prompt: Some("PRE".into()),
+                post_prompt: None,
+                vars: BTreeMap::from([("team".into(), "web".into())]),
+                dedup: DedupMode::Content,
+                order: FileOrder::Alpha,
+                add: vec!["extra.md".into()],
+                drop: vec!["base".into()],
+                strict_env: true,
+                skip_missing: true,
+                strip_frontmatter: true,
+                trim_trailing_whitespace: true,
+                normalize_line_endings: true,
+                between_only: true,
+                collapse_blank_lines: true,
+                allow_external: true,
+                require_utf8: true,
+                limit_bytes: Some(10),
+                no_system_prefix: true,
+                since: Some(SystemTime::UNIX_EPOCH),
+                timestamp_format: Some("%H:%M".into()),
+                timezone: Some(Timezone::Utc),
+                styled: true,
+            }
+        );
+        assert!(!RenderOptions::new().system_prefix(true).no_system_prefix);
     }
 
     #[test]
-    fn test_validate_success_and_unknowns() {
+    fn test_render_since_keeps_newer_files() {
+        use std::time::Duration;
+        let lib = mk_tmp("prompter_render_since");
+        fs::create_dir_all(&lib).unwrap();
+        let epoch = SystemTime::UNIX_EPOCH;
+        for (name, secs) in [("old.md", 1_000), ("new.md", 3_000)] {
+            fs::write(lib.join(name), format!("{name}\n")).unwrap();
+            let file = fs::File::options()
+                .write(true)
+                .open(lib.join(name))
+                .unwrap();
+            file.set_modified(epoch + Duration::from_secs(secs))
+                .unwrap();
+        }
+        let cfg: Config = "[p]\ndepends_on = [\"old.md\", \"new.md\"]\n"
+            .parse()
+            .unwrap();
+        let opts = RenderOptions::new()
+            .pre_prompt(Some("PRE\n"))
+            .post_prompt(Some("POST"))
+            .system_prefix(false);
+
+        let since = opts.clone().since(Some(epoch + Duration::from_secs(2_000)));
+        assert_eq!(
+            render_to_string(&cfg, &lib, "p", &since).unwrap(),
+            "PRE\n\nnew.md\n\n\nPOST"
+        );
+
+        let none = opts.since(Some(epoch + Duration::from_secs(5_000)));
+        assert_eq!(
+            render_to_string(&cfg, &lib, "p", &none).unwrap(),
+            "PRE\n\n(No files changed since 1970-01-01T01:23:20Z)\n\n\nPOST"
+        );
+        assert!(parse_since("2025-06-01T12:00:00+02:00").is_ok());
+        assert!(parse_since("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_render_custom_pre_prompt() {
+        // library and files
+        let lib = mk_tmp("prompter_render_custom_pre");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/x.md"), b"Content\n").unwrap();
+        // config
         let cfg = Config {
-            profiles: HashMap::from([
-                ("p1".into(), vec!["a.md".into()]),
-                ("p2".into(), vec!["p1".into(), "b.md".into()]),
-            ]),
+            profiles: HashMap::from([("test".into(), vec!["a/x.md".into()])]),
             post_prompt: None,
+            ..Config::default()
         };
-        let lib = mk_tmp("prompter_validate_ok");
-        fs::create_dir_all(&lib).unwrap();
-        fs::write(lib.join("a.md"), b"A").unwrap();
-        fs::write(lib.join("b.md"), b"B").unwrap();
+        let mut out = Vec::new();
+        let opts = RenderOptions::new().pre_prompt(Some("Custom pre-prompt\n\n"));
+        render(&cfg, &lib, "test", &opts, &mut out).unwrap();
+
+        let output_str = String::from_utf8(out).unwrap();
+        // Should start with custom pre-prompt
+        assert!(output_str.starts_with("Custom pre-prompt\n\n"));
+        // Should contain system prefix
+        assert!(output_str.contains("Today is "));
+        // Should contain file content
+        assert!(output_str.contains("Content\n"));
+        // Should end with default post-prompt
+        assert!(output_str.ends_with(
+            "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist."
+        ));
+    }
+
+    #[test]
+    fn test_render_custom_post_prompt() {
+        // library and files
+        let lib = mk_tmp("prompter_render_custom_post");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/x.md"), b"Content\n").unwrap();
+        // config with custom post_prompt
+        let cfg = Config {
+            profiles: HashMap::from([("test".into(), vec!["a/x.md".into()])]),
+            post_prompt: Some("Custom config post-prompt".to_string()),
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        render(&cfg, &lib, "test", &RenderOptions::new(), &mut out).unwrap();
+
+        let output_str = String::from_utf8(out).unwrap();
+        // Should end with config post-prompt
+        assert!(output_str.ends_with("Custom config post-prompt"));
+
+        // Test CLI post-prompt overriding config
+        let mut out2 = Vec::new();
+        let opts = RenderOptions::new().post_prompt(Some("CLI post-prompt"));
+        render(&cfg, &lib, "test", &opts, &mut out2).unwrap();
+
+        let output_str2 = String::from_utf8(out2).unwrap();
+        // Should end with CLI post-prompt
+        assert!(output_str2.ends_with("CLI post-prompt"));
+    }
+
+    #[test]
+    fn test_render_prompt_files_and_precedence() {
+        let lib = mk_tmp("prompter_render_prompt_files");
+        fs::create_dir_all(lib.join("prompts")).unwrap();
+        fs::write(lib.join("x.md"), b"Content\n").unwrap();
+        fs::write(lib.join("prompts/pre.md"), b"File pre $HOME\n").unwrap();
+        fs::write(lib.join("prompts/post.md"), b"File post\n").unwrap();
+        let cfg: Config = "post_prompt = \"Inline post\"\n\
+                           pre_prompt_file = \"prompts/pre.md\"\n\
+                           post_prompt_file = \"prompts/post.md\"\n\
+                           [p]\ndepends_on = [\"x.md\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(cfg.pre_prompt_file(), Some("prompts/pre.md"));
         assert!(validate(&cfg, &lib).is_ok());
-        let cfg2 = Config {
-            profiles: HashMap::from([("root".into(), vec!["nope".into()])]),
+
+        // The file beats the inline post_prompt; its text is not expanded
+        let out = render_to_string(&cfg, &lib, "p", &RenderOptions::new()).unwrap();
+        assert!(out.starts_with("File pre $HOME\n"));
+        assert!(out.ends_with("\n\nFile post\n"));
+
+        // CLI values beat both
+        let opts = RenderOptions::new()
+            .pre_prompt(Some("CLI pre\n"))
+            .post_prompt(Some("CLI post"));
+        let out = render_to_string(&cfg, &lib, "p", &opts).unwrap();
+        assert!(out.starts_with("CLI pre\n"));
+        assert!(out.ends_with("CLI post"));
+
+        // A missing prompt file fails the render and validation
+        let mut missing = cfg.clone();
+        missing.set_post_prompt_file(Some("prompts/gone.md".into()));
+        let err = render_to_string(&missing, &lib, "p", &RenderOptions::new())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("gone.md (post_prompt_file)"), "{err}");
+        let err = validate(&missing, &lib).unwrap_err().to_string();
+        assert!(err.contains("gone.md (post_prompt_file)"), "{err}");
+
+        // Round-trips through the canonical text
+        assert_eq!(cfg.to_toml_string().parse::<Config>().unwrap(), cfg);
+    }
+
+    #[test]
+    fn test_render_dedup_by_content() {
+        let lib = mk_tmp("prompter_render_dedup_content");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/one.md"), b"SAME\n").unwrap();
+        fs::write(lib.join("a/two.md"), b"SAME\n").unwrap();
+        fs::write(lib.join("a/three.md"), b"OTHER\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([(
+                "p".into(),
+                vec!["a/one.md".into(), "a/two.md".into(), "a/three.md".into()],
+            )]),
             post_prompt: None,
+            ..Config::default()
         };
-        let err = validate(&cfg2, &lib).unwrap_err();
-        assert!(err.contains("Unknown profile"));
+
+        let mut out = Vec::new();
+        let opts = RenderOptions {
+            separator: Some("--\n".into()),
+            ..RenderOptions::default()
+        };
+        render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+        let by_path = String::from_utf8(out).unwrap();
+        assert_eq!(by_path.matches("SAME\n").count(), 2);
+
+        let mut out = Vec::new();
+        let opts = RenderOptions {
+            dedup: DedupMode::Content,
+            ..opts
+        };
+        render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+        let by_content = String::from_utf8(out).unwrap();
+        assert_eq!(by_content.matches("SAME\n").count(), 1);
+        assert_eq!(by_content.matches("--\n").count(), 2);
+        // First occurrence wins and order is preserved
+        assert!(by_content.find("SAME").unwrap() < by_content.find("OTHER").unwrap());
+    }
+
+    #[test]
+    fn test_split_line_range() {
+        assert_eq!(split_line_range("a/x.md").unwrap(), ("a/x.md", None));
+        assert_eq!(
+            split_line_range("a/x.md#L10-L40").unwrap(),
+            ("a/x.md", Some(LineRange { start: 10, end: 40 }))
+        );
+        assert_eq!(
+            split_line_range("x.md#L7").unwrap(),
+            ("x.md", Some(LineRange { start: 7, end: 7 }))
+        );
+        // A '#' that is not a fragment on a markdown path is part of the name
+        assert_eq!(split_line_range("c#.md").unwrap(), ("c#.md", None));
+        for bad in [
+            "x.md#L0",
+            "x.md#L5-L2",
+            "x.md#10-20",
+            "x.md#Lx",
+            "x.md#",
+            "x.md#L1-",
+        ] {
+            assert!(split_line_range(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_render_line_ranges() {
+        let lib = mk_tmp("prompter_line_ranges");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("big.md"), b"one\ntwo\nthree\nfour\nfive\n").unwrap();
+        let cfg = parse_config_toml(
+            "[range]\ndepends_on = [\"big.md#L2-L4\"]\n[single]\ndepends_on = [\"big.md#L5\"]\n[past]\ndepends_on = [\"big.md#L4-L9\"]\n[bad]\ndepends_on = [\"big.md#L3-L1\"]\n",
+        )
+        .unwrap();
+
+        let render_str = |profile: &str| {
+            let mut out = Vec::new();
+            render(&cfg, &lib, profile, &RenderOptions::default(), &mut out)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+
+        let out = render_str("range").unwrap();
+        assert!(out.contains("\ntwo\nthree\nfour\n"));
+        assert!(!out.contains("one") && !out.contains("five"));
+
+        let out = render_str("single").unwrap();
+        assert!(out.contains("\nfive\n") && !out.contains("four"));
+
+        let err = render_str("past").unwrap_err().to_string();
+        assert!(err.contains("Line range L4-L9 out of range"), "err={err}");
+        assert!(err.contains("(5 lines)"));
+
+        assert_eq!(
+            resolve(&cfg, &lib, "bad").unwrap_err(),
+            ResolveError::InvalidLineRange("big.md#L3-L1".into(), "bad".into())
+        );
+        let opts = ResolveOptions::default();
+        assert_eq!(resolve(&cfg, &lib, "range").unwrap(), [lib.join("big.md")]);
+        assert_eq!(
+            resolve_ranges(&cfg, &lib, "range", &opts).unwrap(),
+            [(lib.join("big.md"), Some(LineRange { start: 2, end: 4 }))]
+        );
+        assert_eq!(
+            resolve_ranges(&cfg, &lib, "bad", &opts).unwrap_err(),
+            ResolveError::InvalidLineRange("big.md#L3-L1".into(), "bad".into())
+        );
+        let err = validate(&cfg, &lib).unwrap_err().to_string();
+        assert!(err.contains("Invalid line range '#L3-L1'"), "err={err}");
+        // Existence is checked against the base path, without the fragment
+        assert!(!err.contains("Missing file"));
+    }
+
+    #[test]
+    fn test_new_snippet() {
+        let lib = mk_tmp("prompter_new_snippet");
+        let created = new_snippet(&lib, "python/style.md", Some("# Style\n"), false).unwrap();
+        assert_eq!(
+            created,
+            fs::canonicalize(lib.join("python/style.md")).unwrap()
+        );
+        assert_eq!(fs::read_to_string(&created).unwrap(), "# Style\n");
+
+        let err = new_snippet(&lib, "python/style.md", None, false).unwrap_err();
+        assert!(err.contains("already exists"), "err={err}");
+        assert_eq!(fs::read_to_string(&created).unwrap(), "# Style\n");
+
+        new_snippet(&lib, "python/style.md", None, true).unwrap();
+        assert_eq!(fs::read_to_string(&created).unwrap(), "");
+
+        for bad in ["", "/etc/passwd", "../escape.md", "a/../../b.md"] {
+            assert!(new_snippet(&lib, bad, None, false).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/me".to_string()),
+            "PROJECT" => Some("demo".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            expand_vars("$HOME/${PROJECT}.md", lookup),
+            ("/home/me/demo.md".to_string(), None)
+        );
+        assert_eq!(
+            expand_vars("${MISSING}/x and $ALSO", lookup),
+            ("${MISSING}/x and $ALSO".to_string(), Some("MISSING".into()))
+        );
+        // Lone or malformed references are kept as written
+        assert_eq!(
+            expand_vars("$ 5$ ${ $1 ${} ${A-B}", lookup),
+            ("$ 5$ ${ $1 ${} ${A-B}".to_string(), None)
+        );
+        assert_eq!(
+            expand_vars("${PROJECT}_x $PROJECT_x", lookup),
+            ("demo_x $PROJECT_x".to_string(), Some("PROJECT_x".into()))
+        );
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_render_expands_env_in_paths_and_prompts_only() {
+        let lib = mk_tmp("prompter_env_expand");
+        fs::create_dir_all(lib.join("sub")).unwrap();
+        fs::write(lib.join("sub/a.md"), b"content keeps $PROMPTER_TEST_DIR\n").unwrap();
+        unsafe {
+            env::set_var("PROMPTER_TEST_DIR", "sub");
+        }
+        let cfg = parse_config_toml(
+            "[p]\ndepends_on = [\"${PROMPTER_TEST_DIR}/a.md\"]\n[undef]\ndepends_on = [\"$PROMPTER_TEST_UNSET/a.md\"]\n",
+        )
+        .unwrap();
+        assert!(
+            validate(&cfg, &lib)
+                .unwrap_err()
+                .to_string()
+                .contains("$PROMPTER_TEST_UNSET/a.md")
+        );
+
+        let opts = RenderOptions {
+            pre_prompt: Some("dir=$PROMPTER_TEST_DIR".into()),
+            post_prompt: Some("left ${PROMPTER_TEST_UNSET}".into()),
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        render(&cfg, &lib, "p", &opts, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("dir=sub"));
+        assert!(out.contains("content keeps $PROMPTER_TEST_DIR\n"));
+        assert!(out.ends_with("left ${PROMPTER_TEST_UNSET}"));
+
+        let strict = RenderOptions {
+            strict_env: true,
+            ..opts
+        };
+        let err = render(&cfg, &lib, "p", &strict, Vec::new())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("PROMPTER_TEST_UNSET (in post-prompt)"),
+            "err={err}"
+        );
+        let err = render(&cfg, &lib, "undef", &strict, Vec::new())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(
+                "Undefined environment variable: PROMPTER_TEST_UNSET (referenced by [undef])"
+            ),
+            "err={err}"
+        );
+    }
+
+    #[test]
+    fn test_profile_stats() {
+        let lib = mk_tmp("prompter_stats");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/one.md"), b"1\n2\n3\n").unwrap();
+        fs::write(lib.join("a/big.md"), b"a much longer line\nand another\n").unwrap();
+        fs::write(lib.join("a/copy.md"), b"1\n2\n3\n").unwrap();
+        let cfg = parse_config_toml(
+            "[base]\ndepends_on = [\"a/one.md\", \"a/big.md\"]\n[p]\ndepends_on = [\"base\", \"a/copy.md\", \"a/one.md\"]\n",
+        )
+        .unwrap();
+
+        let stats = profile_stats(&cfg, &lib, "p", DedupMode::Path).unwrap();
+        assert_eq!(stats.files.len(), 3);
+        assert_eq!(stats.bytes, 6 + 31 + 6);
+        assert_eq!(stats.lines, 3 + 2 + 3);
+        assert_eq!(stats.largest().unwrap().path, lib.join("a/big.md"));
+
+        let stats = profile_stats(&cfg, &lib, "p", DedupMode::Content).unwrap();
+        assert_eq!(stats.files.len(), 2);
+        assert_eq!(stats.bytes, 37);
+
+        let mut out = Vec::new();
+        write_stats(&stats, &lib, false, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("File"), "out={out}");
+        assert!(out.contains("a/big.md"));
+        assert!(out.contains("Total (2 files)"));
+        assert!(out.ends_with("Largest: a/big.md (31 bytes)\n"));
+
+        assert!(profile_stats(&cfg, &lib, "nope", DedupMode::Path).is_err());
+    }
+
+    #[test]
+    fn test_resolved_paths_for_overrides() {
+        let dir = mk_tmp("prompter_resolved_paths");
+        let cfg = dir.join("proj/config.toml");
+        let paths = ResolvedPaths::resolve_as(Some(&cfg), PathSource::LocalConfig).unwrap();
+        assert_eq!(
+            paths,
+            ResolvedPaths {
+                config: cfg.clone(),
+                config_source: PathSource::LocalConfig,
+                library: dir.join("proj/library"),
+                library_source: PathSource::NextToConfig,
+            }
+        );
+        assert_eq!(
+            ResolvedPaths::resolve(Some(&cfg)).unwrap().config_source,
+            PathSource::Flag("--config")
+        );
+
+        let stdin = ResolvedPaths::resolve(Some(Path::new("-"))).unwrap();
+        assert!(stdin.config_is_stdin());
+        assert_eq!(stdin.library, env::current_dir().unwrap().join("library"));
+        assert_eq!(stdin.library_source, PathSource::WorkingDirectory);
+
+        let cli = Cli::try_parse_from(["prompter", "--config-dir", "proj", "which"]).unwrap();
+        assert_eq!(cli.config_source(), PathSource::Flag("--config-dir"));
+    }
+
+    #[test]
+    fn test_write_which_reports_existence_and_sources() {
+        let dir = mk_tmp("prompter_which");
+        fs::create_dir_all(dir.join("library")).unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "library_roots = [\"../shared\"]\n[a]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let paths = ResolvedPaths::resolve(Some(&dir.join("config.toml"))).unwrap();
+
+        let mut out = Vec::new();
+        write_which(&paths, false, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "config:  {} (exists; --config flag)\n\
+                 library: {} (exists; next to config)\n\
+                 root:    {} (missing; config key library_roots)\n",
+                dir.join("config.toml").display(),
+                dir.join("library").display(),
+                dir.join("library/../shared").display(),
+            )
+        );
+
+        let mut out = Vec::new();
+        write_which(&paths, true, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["config"]["exists"], true);
+        assert_eq!(json["library"]["source"], "next to config");
+        assert_eq!(json["library_roots"][0]["exists"], false);
+
+        // A missing config is reported, not an error
+        let paths = ResolvedPaths::resolve(Some(&dir.join("nope/config.toml"))).unwrap();
+        let mut out = Vec::new();
+        write_which(&paths, false, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("(missing; --config flag)"), "{text}");
+        assert_eq!(text.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_config_override_from_config_or_dir() {
+        let cli = Cli::try_parse_from(["prompter", "--config-dir", "proj", "list"]).unwrap();
+        assert_eq!(
+            cli.config_override(),
+            Some(PathBuf::from("proj/config.toml"))
+        );
+
+        let cli = Cli::try_parse_from(["prompter", "-c", "other.toml", "list"]).unwrap();
+        assert_eq!(cli.config_override(), Some(PathBuf::from("other.toml")));
+
+        let err = Cli::try_parse_from(["prompter", "--config-dir", "proj", "-c", "other.toml"])
+            .unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+
+        let cli = Cli::try_parse_from(["prompter", "list"]).unwrap();
+        assert_eq!(cli.config_override(), None);
     }
 
     #[test]
-    fn test_resolve_errors_and_dedup() {
-        let cfg = Config {
-            profiles: HashMap::from([("root".into(), vec!["missing.md".into()])]),
-            post_prompt: None,
-        };
-        let lib = mk_tmp("prompter_resolve_errs");
-        fs::create_dir_all(&lib).unwrap();
-        let mut seen = HashSet::new();
-        let mut stack = Vec::new();
-        let mut out = Vec::new();
-        let err = resolve_profile("root", &cfg, &lib, &mut seen, &mut stack, &mut out).unwrap_err();
-        match err {
-            ResolveError::MissingFile(_, p) => assert_eq!(p, "root"),
-            _ => panic!("expected missing file"),
-        }
+    fn test_find_local_config_walks_up() {
+        let root = mk_tmp("prompter_local_config");
+        let nested = root.join("project/src/deep");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_local_config(&nested), None);
 
-        let cfg2 = Config {
-            profiles: HashMap::from([
-                ("A".into(), vec!["a/b.md".into()]),
-                ("B".into(), vec!["A".into(), "a/b.md".into()]),
-            ]),
-            post_prompt: None,
-        };
-        fs::create_dir_all(lib.join("a")).unwrap();
-        fs::write(lib.join("a/b.md"), b"X").unwrap();
-        let mut seen = HashSet::new();
-        let mut stack = Vec::new();
-        let mut out = Vec::new();
-        resolve_profile("B", &cfg2, &lib, &mut seen, &mut stack, &mut out).unwrap();
-        assert_eq!(out.len(), 1);
+        fs::write(root.join("prompter.toml"), "").unwrap();
+        assert_eq!(find_local_config(&nested), Some(root.join("prompter.toml")));
+
+        // The nearest directory wins, and .prompterrc beats prompter.toml
+        fs::write(root.join("project/prompter.toml"), "").unwrap();
+        fs::write(root.join("project/.prompterrc"), "").unwrap();
+        assert_eq!(
+            find_local_config(&nested),
+            Some(root.join("project/.prompterrc"))
+        );
     }
 
     #[test]
-    fn test_parse_args_errors() {
-        // unknown flag
-        let args = vec!["prompter".into(), "--bogus".into()];
-        let err = parse_args_from(args).unwrap_err();
-        assert!(err.contains("unexpected argument"));
-        // missing separator value
-        let args = vec!["prompter".into(), "--separator".into()];
-        let err = parse_args_from(args).unwrap_err();
-        assert!(err.contains("value is required"));
-        // no action specified (should default to help)
-        let args = vec!["prompter".into()];
-        let mode = parse_args_from(args).unwrap();
-        assert!(matches!(mode, AppMode::Help));
+    fn test_xdg_base_ignores_relative_values() {
+        let home = || Ok(PathBuf::from("/home/me"));
+        assert_eq!(
+            xdg_base_from(Some(PathBuf::from("/xdg/data")), home, ".local/share"),
+            Ok(PathBuf::from("/xdg/data"))
+        );
+        assert_eq!(
+            xdg_base_from(Some(PathBuf::from("rel/data")), home, ".local/share"),
+            Ok(PathBuf::from("/home/me/.local/share"))
+        );
+        assert_eq!(
+            xdg_base_from(None, home, ".config"),
+            Ok(PathBuf::from("/home/me/.config"))
+        );
+        assert!(xdg_base_from(None, || Err("no home".into()), ".config").is_err());
     }
 
     #[test]
-    fn test_list_profiles_order() {
-        let cfg = Config {
-            profiles: HashMap::from([("b".into(), vec![]), ("a".into(), vec![])]),
-            post_prompt: None,
-        };
-        let mut out = Vec::new();
-        super::list_profiles(&cfg, &mut out).unwrap();
-        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+    fn test_styling_enabled() {
+        use ColorChoice::{Always, Auto, Never};
+        for tty in [true, false] {
+            for no_color in [None, Some(""), Some("1")] {
+                let auto = tty && no_color != Some("1");
+                assert_eq!(styling_enabled(Auto, no_color, tty), auto);
+                assert!(styling_enabled(Always, no_color, tty));
+                assert!(!styling_enabled(Never, no_color, tty));
+            }
+        }
     }
 
     #[test]
-    fn test_validate_cycle_detected() {
-        let cfg = Config {
-            profiles: HashMap::from([
-                ("A".into(), vec!["B".into()]),
-                ("B".into(), vec!["A".into()]),
-            ]),
-            post_prompt: None,
-        };
-        let lib = mk_tmp("prompter_cycle");
-        fs::create_dir_all(&lib).unwrap();
-        let err = validate(&cfg, &lib).unwrap_err();
-        assert!(err.contains("Cycle detected"));
+    fn test_color_never_gives_plain_prefix() {
+        assert!(!styling_enabled(ColorChoice::Never, None, true));
+        let prefix = format_system_prefix(DEFAULT_TIMESTAMP_FORMAT, Timezone::Local, false);
+        assert!(prefix.starts_with("Today is "), "prefix={prefix}");
+        assert!(!prefix.contains('\u{1b}'));
+        assert_eq!(warning_text("careful", false), "Warning: careful");
     }
 
     #[test]
-    fn test_parse_config_multiline_long() {
-        let cfg = r#"
-[profile.x]
-depends_on = [
-  "a/b.md",
-  "c/d.md",
-  "e/f.md",
-]
-"#;
-        let parsed = parse_config_toml(cfg).unwrap();
-        assert_eq!(parsed.profiles.get("profile.x").unwrap().len(), 3);
+    fn test_no_color_env_gives_plain_prefix() {
+        assert!(!styling_enabled(ColorChoice::Auto, Some("1"), true));
+        assert!(styling_enabled(ColorChoice::Auto, Some(""), true));
+        assert!(styling_enabled(ColorChoice::Auto, None, true));
+        assert!(!styling_enabled(ColorChoice::Auto, None, false));
+        let prefix = format_system_prefix(DEFAULT_TIMESTAMP_FORMAT, Timezone::Local, false);
+        assert!(prefix.starts_with("Today is "), "prefix={prefix}");
+        assert!(!prefix.contains('\u{1b}'));
+        assert_eq!(success_text("done", false), "done");
+        assert_eq!(info_text("note", false), "note");
     }
 
     #[test]
-    fn test_render_to_writer_basic() {
-        // library and files
-        let lib = mk_tmp("prompter_render_to_writer");
-        fs::create_dir_all(lib.join("a")).unwrap();
-        fs::create_dir_all(lib.join("f")).unwrap();
-        fs::write(lib.join("a/x.md"), b"AX\n").unwrap();
-        fs::write(lib.join("f/y.md"), b"FY\n").unwrap();
-        // config with nested profile and duplicate file reference
-        let cfg = Config {
-            profiles: HashMap::from([
-                ("child".into(), vec!["a/x.md".into()]),
-                (
-                    "root".into(),
-                    vec!["child".into(), "f/y.md".into(), "a/x.md".into()],
-                ),
-            ]),
-            post_prompt: None,
-        };
+    fn test_library_files_and_list() {
+        let lib = mk_tmp("prompter_library_list");
+        fs::create_dir_all(lib.join("b/nested")).unwrap();
+        fs::write(lib.join("z.md"), b"Z").unwrap();
+        fs::write(lib.join("b/nested/deep.MD"), b"deep").unwrap();
+        fs::write(lib.join("b/a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b/notes.txt"), b"ignored").unwrap();
+
+        let files = library_files(&lib).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("b/a.md"),
+                PathBuf::from("b/nested/deep.MD"),
+                PathBuf::from("z.md")
+            ]
+        );
+        assert!(library_files(&lib.join("missing")).unwrap().is_empty());
+
+        let cfg = parse_config_toml("[p]\ndepends_on = [\"b/a.md#L1\"]\n").unwrap();
         let mut out = Vec::new();
-        super::render_to_writer(&cfg, &lib, &mut out, "root", Some("\n--\n"), None, None).unwrap();
+        list_library(&cfg, &lib, false, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "b/a.md\nb/nested/deep.MD\nz.md\n"
+        );
 
-        let output_str = String::from_utf8(out).unwrap();
-        // Should start with default pre-prompt
-        assert!(output_str.starts_with("You are an LLM coding agent."));
-        // Should contain system prefix
-        assert!(output_str.contains("Today is "));
-        assert!(output_str.contains(", and you are running on a "));
-        assert!(output_str.contains(" system.\n\n"));
-        // Should contain the file contents with separator
-        assert!(output_str.contains("AX\n"));
-        assert!(output_str.contains("\n--\n"));
-        assert!(output_str.contains("FY\n"));
-        // Should end with default post-prompt
-        assert!(output_str.ends_with(
-            "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist."
-        ));
+        let mut out = Vec::new();
+        list_library(&cfg, &lib, true, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("b/a.md") && lines[0].ends_with("  referenced"));
+        assert!(lines[0].contains("       2  "));
+        assert!(lines[2].starts_with("z.md") && lines[2].ends_with("unreferenced"));
     }
 
     #[test]
-    fn test_render_to_writer_custom_pre_prompt() {
-        // library and files
-        let lib = mk_tmp("prompter_render_custom_pre");
-        fs::create_dir_all(lib.join("a")).unwrap();
-        fs::write(lib.join("a/x.md"), b"Content\n").unwrap();
-        // config
-        let cfg = Config {
-            profiles: HashMap::from([("test".into(), vec!["a/x.md".into()])]),
-            post_prompt: None,
-        };
-        let mut out = Vec::new();
-        super::render_to_writer(
-            &cfg,
-            &lib,
-            &mut out,
-            "test",
-            None,
-            Some("Custom pre-prompt\n\n"),
-            None,
+    fn test_search_library() {
+        let lib = mk_tmp("prompter_library_search");
+        fs::create_dir_all(lib.join("b")).unwrap();
+        fs::write(
+            lib.join("a.md"),
+            b"Use Rust.\nnothing here\nrust and RUST\n",
         )
         .unwrap();
+        fs::write(lib.join("b/c.md"), b"trust me\n").unwrap();
 
-        let output_str = String::from_utf8(out).unwrap();
-        // Should start with custom pre-prompt
-        assert!(output_str.starts_with("Custom pre-prompt\n\n"));
-        // Should contain system prefix
-        assert!(output_str.contains("Today is "));
-        // Should contain file content
-        assert!(output_str.contains("Content\n"));
-        // Should end with default post-prompt
-        assert!(output_str.ends_with(
-            "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist."
-        ));
+        let found = search_library(&lib, "rust", false, false).unwrap();
+        let located: Vec<_> = found
+            .iter()
+            .map(|m| (m.path.display().to_string(), m.line))
+            .collect();
+        assert_eq!(
+            located,
+            vec![("a.md".into(), 1), ("a.md".into(), 3), ("b/c.md".into(), 1)]
+        );
+        assert_eq!(found[1].spans, vec![(0, 4), (9, 13)]);
+
+        let found = search_library(&lib, "rust", true, false).unwrap();
+        assert_eq!(found.len(), 2);
+
+        let found = search_library(&lib, r"^\w+ust\b", false, true).unwrap();
+        assert_eq!(found.len(), 2);
+        // Without --regex, metacharacters are literal
+        assert!(
+            search_library(&lib, "R.st", false, false)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(search_library(&lib, "(", false, true).is_err());
+
+        let mut out = Vec::new();
+        write_search_matches(&found, false, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a.md:3: rust and RUST\nb/c.md:1: trust me\n"
+        );
     }
 
     #[test]
-    fn test_render_to_writer_custom_post_prompt() {
-        // library and files
-        let lib = mk_tmp("prompter_render_custom_post");
-        fs::create_dir_all(lib.join("a")).unwrap();
-        fs::write(lib.join("a/x.md"), b"Content\n").unwrap();
-        // config with custom post_prompt
-        let cfg = Config {
-            profiles: HashMap::from([("test".into(), vec!["a/x.md".into()])]),
-            post_prompt: Some("Custom config post-prompt".to_string()),
-        };
+    fn test_check_library() {
+        let lib = mk_tmp("prompter_library_check");
+        fs::create_dir_all(lib.join("b")).unwrap();
+        fs::write(lib.join("ok.md"), b"fine\n").unwrap();
+        fs::write(lib.join("empty.md"), b"").unwrap();
+        fs::write(lib.join("b/latin1.md"), b"caf\xe9\n").unwrap();
+        fs::write(lib.join("pasted.md"), b"no newline").unwrap();
+
+        let issues = check_library(&lib).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                LibraryIssue {
+                    path: PathBuf::from("b/latin1.md"),
+                    issue: FileIssue::InvalidUtf8 { offset: 3 },
+                },
+                LibraryIssue {
+                    path: PathBuf::from("pasted.md"),
+                    issue: FileIssue::MissingTrailingNewline,
+                },
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "b/latin1.md: invalid UTF-8 at byte 3"
+        );
+        assert_eq!(issues[1].to_string(), "pasted.md: no trailing newline");
+        assert!(check_library(&lib.join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_with_thousands() {
+        assert_eq!(with_thousands(0), "0");
+        assert_eq!(with_thousands(999), "999");
+        assert_eq!(with_thousands(1000), "1,000");
+        assert_eq!(with_thousands(123_456), "123,456");
+        assert_eq!(with_thousands(1_234_567), "1,234,567");
+    }
+
+    #[test]
+    fn test_library_stats() {
+        let lib = mk_tmp("prompter_library_stats");
+        fs::create_dir_all(lib.join("b")).unwrap();
+        fs::write(lib.join("a.md"), "x".repeat(2000)).unwrap();
+        fs::write(lib.join("b/c.md"), b"abcd\n").unwrap();
+        fs::write(lib.join("z.md"), b"abcd\n").unwrap();
+
+        let cfg = parse_config_toml("[p]\ndepends_on = [\"a.md\"]\n").unwrap();
+        let stats = library_stats(&cfg, &lib).unwrap();
+        assert_eq!(stats.bytes, 2010);
+        assert_eq!(stats.tokens, 500 + 2 + 2);
+        assert_eq!(stats.unreferenced, 2);
+        assert_eq!(stats.largest().unwrap().0, PathBuf::from("a.md"));
+        assert_eq!(stats.smallest().unwrap().0, PathBuf::from("b/c.md"));
+
         let mut out = Vec::new();
-        super::render_to_writer(&cfg, &lib, &mut out, "test", None, None, None).unwrap();
+        write_library_stats(&stats, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Files:        3\n\
+             Total size:   2,010 bytes\n\
+             Largest:      a.md (2,000 bytes)\n\
+             Smallest:     b/c.md (5 bytes)\n\
+             Average size: 670 bytes\n\
+             Est. tokens:  504\n\
+             Unreferenced: 2\n"
+        );
 
-        let output_str = String::from_utf8(out).unwrap();
-        // Should end with config post-prompt
-        assert!(output_str.ends_with("Custom config post-prompt"));
+        let empty = library_stats(&cfg, &lib.join("missing")).unwrap();
+        let mut out = Vec::new();
+        write_library_stats(&empty, &mut out).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("Largest:      -\n")
+        );
+    }
 
-        // Test CLI post-prompt overriding config
-        let mut out2 = Vec::new();
-        super::render_to_writer(
-            &cfg,
-            &lib,
-            &mut out2,
-            "test",
-            None,
-            None,
-            Some("CLI post-prompt"),
-        )
-        .unwrap();
+    #[test]
+    fn test_profile_line() {
+        let text = "# [a] in a comment\n[a]\ndepends_on = []\n\n  [ b.c ]  # trailing\ndepends_on = [\"[a]\"]\n";
+        assert_eq!(profile_line(text, "a"), Some(2));
+        assert_eq!(profile_line(text, "b.c"), Some(5));
+        assert_eq!(profile_line(text, "missing"), None);
+    }
 
-        let output_str2 = String::from_utf8(out2).unwrap();
-        // Should end with CLI post-prompt
-        assert!(output_str2.ends_with("CLI post-prompt"));
+    #[test]
+    fn test_editor_args_jump_to_line_only_where_supported() {
+        let path = Path::new("/cfg/config.toml");
+        let args = |program: &str, line: Option<usize>| -> Vec<String> {
+            editor_args(program, path, line)
+                .into_iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(args("vim", Some(4)), ["+4", "/cfg/config.toml"]);
+        assert_eq!(args("/usr/bin/nano", Some(4)), ["+4", "/cfg/config.toml"]);
+        assert_eq!(args("code", Some(4)), ["-g", "/cfg/config.toml:4"]);
+        assert_eq!(args("subl", Some(4)), ["/cfg/config.toml"]);
+        assert_eq!(args("notepad", Some(4)), ["/cfg/config.toml"]);
+        assert_eq!(args("notepad.exe", Some(4)), ["/cfg/config.toml"]);
+        assert_eq!(args("Code.exe", Some(4)), ["-g", "/cfg/config.toml:4"]);
+        assert_eq!(args("nvim.exe", Some(4)), ["+4", "/cfg/config.toml"]);
+        assert_eq!(
+            args(FALLBACK_EDITOR, Some(4)).len(),
+            if cfg!(windows) { 1 } else { 2 }
+        );
+        assert_eq!(args("vim", None), ["/cfg/config.toml"]);
+        assert_eq!(args("code", None), ["/cfg/config.toml"]);
     }
 
     #[test]
@@ -1325,6 +12072,107 @@ depends_on = ["file.md"]
         assert_eq!(parsed.profiles.get("profile").unwrap().len(), 1);
     }
 
+    #[test]
+    fn test_parse_config_literal_string_values() {
+        let cfg = parse_config_toml(
+            "post_prompt = 'plain'\n\
+             timestamp_format = '%H:%M \\n'\n\
+             [p]\nextends = 'base'  # parent\n\
+             [base]\ndepends_on = []\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.post_prompt(), Some("plain"));
+        assert_eq!(cfg.timestamp_format(), Some("%H:%M \\n"));
+        assert_eq!(cfg.extends.get("p").map(String::as_str), Some("base"));
+
+        let err = parse_config_toml("post_prompt = 'it's'\n").unwrap_err();
+        assert!(
+            err.to_string().contains("post_prompt must be a string"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_parse_depends_on_inline_tables() {
+        let cfg = parse_config_toml(
+            r#"
+[p]
+depends_on = [
+  "a.md",
+  { path = "drafts/b.md", optional = true },
+  { path = "c.md" },
+  { optional = false, path = "d.md" }, # key order does not matter
+  "q",
+]
+tags = ["x"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.profiles["p"],
+            vec!["a.md", "drafts/b.md", "c.md", "d.md", "q"]
+        );
+        assert_eq!(
+            cfg.meta["p"].optional,
+            HashSet::from(["drafts/b.md".to_string()])
+        );
+        assert!(cfg.is_optional("p", "drafts/b.md"));
+        assert!(!cfg.is_optional("p", "c.md"));
+
+        for (array, msg) in [
+            (r"depends_on = [{ optional = true }]", "Missing path"),
+            (
+                r#"depends_on = [{ path = "a.md", opt = true }]"#,
+                "Unknown key 'opt'",
+            ),
+            (
+                r#"depends_on = [{ path = "a.md", optional = "yes" }]"#,
+                "optional must be a boolean",
+            ),
+            (
+                r#"depends_on = [{ path = "a.md"]"#,
+                "Unterminated inline table",
+            ),
+            (
+                r#"tags = [{ path = "a.md" }]"#,
+                "inline tables are not allowed",
+            ),
+        ] {
+            let err = parse_config_toml(&format!("[p]\n{array}\n"))
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains(msg), "{array}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_optional_dependencies_skip_when_missing() {
+        let lib = mk_tmp("prompter_optional_deps");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("c.md"), b"C\n").unwrap();
+        let cfg = parse_config_toml(
+            "[p]\ndepends_on = [\"a.md\", { path = \"b.md\", optional = true }, { path = \"c.md\", optional = true }]\n\
+             [q]\ndepends_on = [{ path = \"b.md\" }]\n",
+        )
+        .unwrap();
+
+        // Missing optional files are skipped; present ones are still included
+        assert_eq!(
+            resolve(&cfg, &lib, "p").unwrap(),
+            vec![lib.join("a.md"), lib.join("c.md")]
+        );
+        // Without optional = true a missing file still fails
+        assert_eq!(
+            resolve(&cfg, &lib, "q").unwrap_err(),
+            ResolveError::MissingFile(lib.join("b.md"), "q".into())
+        );
+        assert_eq!(missing_files(&cfg, &lib), vec![lib.join("b.md")]);
+        let err = validate(&cfg, &lib).unwrap_err().to_string();
+        assert_eq!(err.lines().count(), 1, "{err}");
+        assert!(err.contains("(referenced by [q])"));
+    }
+
     #[test]
     fn test_array_items_escaped_backslash() {
         let s = r#"["a\\"]"#; // a single backslash in content
@@ -1333,6 +12181,7 @@ depends_on = ["file.md"]
     }
 
     #[test]
+    #[allow(clippy::too_many_lines)] // one check per subcommand
     fn test_parse_args_from() {
         let args = vec![
             "prompter".into(),
@@ -1340,18 +12189,15 @@ depends_on = ["file.md"]
             "\\n--\\n".into(),
             "profile".into(),
         ];
-        match parse_args_from(args).unwrap() {
+        match parse_args_from(args).unwrap().mode {
             AppMode::Run {
                 profile,
-                separator,
-                pre_prompt,
-                post_prompt,
+                opts,
                 config,
+                ..
             } => {
-                assert_eq!(profile, "profile");
-                assert_eq!(separator, Some("\n--\n".into()));
-                assert_eq!(pre_prompt, None);
-                assert_eq!(post_prompt, None);
+                assert_eq!(profile.as_deref(), Some("profile"));
+                assert_eq!(opts, RenderOptions::new().separator(Some("\n--\n")));
                 assert!(config.is_none());
             }
             _ => panic!("expected run"),
@@ -1363,37 +12209,55 @@ depends_on = ["file.md"]
             "Custom pre-prompt".into(),
             "profile".into(),
         ];
-        match parse_args_from(args).unwrap() {
+        match parse_args_from(args).unwrap().mode {
             AppMode::Run {
                 profile,
-                separator,
-                pre_prompt,
-                post_prompt,
+                opts,
                 config,
+                ..
             } => {
-                assert_eq!(profile, "profile");
-                assert_eq!(separator, None);
-                assert_eq!(pre_prompt, Some("Custom pre-prompt".into()));
-                assert_eq!(post_prompt, None);
+                assert_eq!(profile.as_deref(), Some("profile"));
+                assert_eq!(
+                    opts,
+                    RenderOptions::new().pre_prompt(Some("Custom pre-prompt"))
+                );
                 assert!(config.is_none());
             }
             _ => panic!("expected run"),
         }
 
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "--dedup".into(),
+            "content".into(),
+            "profile".into(),
+        ];
+        assert!(matches!(
+            parse_args_from(args).unwrap().mode,
+            AppMode::Run { opts, .. } if opts.dedup == DedupMode::Content
+        ));
+
         let args = vec!["prompter".into(), "list".into()];
         assert!(matches!(
-            parse_args_from(args).unwrap(),
-            AppMode::List { config: None }
+            parse_args_from(args).unwrap().mode,
+            AppMode::List { config: None, .. }
         ));
         let args = vec!["prompter".into(), "validate".into()];
         assert!(matches!(
-            parse_args_from(args).unwrap(),
+            parse_args_from(args).unwrap().mode,
             AppMode::Validate { config: None }
         ));
         let args = vec!["prompter".into(), "init".into()];
-        assert!(matches!(parse_args_from(args).unwrap(), AppMode::Init));
+        assert!(matches!(
+            parse_args_from(args).unwrap().mode,
+            AppMode::Init { .. }
+        ));
         let args = vec!["prompter".into(), "version".into()];
-        assert!(matches!(parse_args_from(args).unwrap(), AppMode::Version));
+        assert!(matches!(
+            parse_args_from(args).unwrap().mode,
+            AppMode::Version
+        ));
 
         let args = vec![
             "prompter".into(),
@@ -1401,8 +12265,8 @@ depends_on = ["file.md"]
             "custom/config.toml".into(),
             "list".into(),
         ];
-        match parse_args_from(args).unwrap() {
-            AppMode::List { config } => {
+        match parse_args_from(args).unwrap().mode {
+            AppMode::List { config, .. } => {
                 assert_eq!(config, Some(PathBuf::from("custom/config.toml")));
             }
             other => panic!("unexpected mode: {other:?}"),
@@ -1415,7 +12279,7 @@ depends_on = ["file.md"]
             "custom/config.toml".into(),
             "profile".into(),
         ];
-        match parse_args_from(args).unwrap() {
+        match parse_args_from(args).unwrap().mode {
             AppMode::Run { config, .. } => {
                 assert_eq!(config, Some(PathBuf::from("custom/config.toml")));
             }
@@ -1443,7 +12307,7 @@ depends_on = ["file.md"]
     }
 
     #[test]
-    fn test_render_to_writer_write_error_on_separator() {
+    fn test_render_write_error_on_separator() {
         let lib = mk_tmp("prompter_write_err_sep");
         fs::create_dir_all(lib.join("a")).unwrap();
         fs::write(lib.join("a/x.md"), b"AX").unwrap();
@@ -1451,31 +12315,37 @@ depends_on = ["file.md"]
         let cfg = Config {
             profiles: HashMap::from([("p".into(), vec!["a/x.md".into(), "a/y.md".into()])]),
             post_prompt: None,
+            ..Config::default()
         };
         let mut w = FailAfterN {
             writes_done: 0,
             fail_on: 3,
         }; // pre-prompt ok, system prefix ok, fail on separator
-        let err =
-            super::render_to_writer(&cfg, &lib, &mut w, "p", Some("--"), None, None).unwrap_err();
+        let opts = RenderOptions::new().separator(Some("--"));
+        let err = render(&cfg, &lib, "p", &opts, &mut w)
+            .unwrap_err()
+            .to_string();
         assert!(err.contains("Write error"), "err={err}");
     }
 
     #[test]
-    fn test_render_to_writer_write_error_on_file() {
+    fn test_render_write_error_on_file() {
         let lib = mk_tmp("prompter_write_err_file");
         fs::create_dir_all(lib.join("a")).unwrap();
         fs::write(lib.join("a/x.md"), b"AX").unwrap();
         let cfg = Config {
             profiles: HashMap::from([("p".into(), vec!["a/x.md".into()])]),
             post_prompt: None,
+            ..Config::default()
         };
         let mut w = FailAfterN {
             writes_done: 0,
             fail_on: 1,
         }; // fail on first write (pre-prompt)
-        let err =
-            super::render_to_writer(&cfg, &lib, &mut w, "p", Some("--"), None, None).unwrap_err();
+        let opts = RenderOptions::new().separator(Some("--"));
+        let err = render(&cfg, &lib, "p", &opts, &mut w)
+            .unwrap_err()
+            .to_string();
         assert!(err.contains("Write error"), "err={err}");
     }
 
@@ -1485,7 +12355,7 @@ depends_on = ["file.md"]
     fn test_run_list_and_validate_with_home_injection() {
         let home = mk_tmp("prompter_home_unit_ok");
         let cfg_dir = home.join(".config/prompter");
-        let lib_dir = home.join(".local/prompter/library");
+        let lib_dir = home.join(".local/share/prompter/library");
         fs::create_dir_all(&cfg_dir).unwrap();
         fs::create_dir_all(lib_dir.join("a")).unwrap();
         fs::create_dir_all(lib_dir.join("f")).unwrap();
@@ -1503,8 +12373,8 @@ depends_on = ["child", "f/y.md"]
         unsafe {
             env::set_var("HOME", &home);
         }
-        assert!(super::run_validate_stdout(None).is_ok());
-        assert!(super::run_list_stdout(None).is_ok());
+        assert!(super::run_validate_stdout(None, false).is_ok());
+        assert!(super::run_list_stdout(&ListOptions::default(), None, false).is_ok());
         if let Some(prev) = prev_home {
             unsafe {
                 env::set_var("HOME", prev);
@@ -1521,7 +12391,7 @@ depends_on = ["child", "f/y.md"]
     fn test_run_validate_with_home_injection_failure() {
         let home = mk_tmp("prompter_home_unit_bad");
         let cfg_dir = home.join(".config/prompter");
-        let lib_dir = home.join(".local/prompter/library");
+        let lib_dir = home.join(".local/share/prompter/library");
         fs::create_dir_all(&cfg_dir).unwrap();
         fs::create_dir_all(&lib_dir).unwrap();
         let cfg = r#"
@@ -1533,7 +12403,9 @@ depends_on = ["missing.md", "unknown_profile"]
         unsafe {
             env::set_var("HOME", &home);
         }
-        let err = super::run_validate_stdout(None).unwrap_err();
+        let err = super::run_validate_stdout(None, false)
+            .unwrap_err()
+            .to_string();
         assert!(
             err.contains("Missing file") && err.contains("Unknown profile"),
             "err={err}"
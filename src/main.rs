@@ -5,64 +5,282 @@
 use std::env;
 
 use clap::Parser;
+use is_terminal::IsTerminal;
 use prompter::{
-    AppMode, Cli, init_scaffold, parse_args_from, run_list_stdout, run_render_stdout,
-    run_validate_stdout,
+    AppMode, Cli, InitOptions, Invocation, find_template, init_paths, init_scaffold,
+    parse_args_from, run_diff_stdout, run_edit, run_escape_stdout, run_fmt_stdout,
+    run_import_stdout, run_library_stdout, run_lint_stdout, run_list_stdout, run_new_stdout,
+    run_render_stdout, run_stats_stdout, run_test_stdout, run_validate_stdout, run_which_stdout,
+    write_templates,
 };
 
 mod completions;
 mod doctor;
+mod mark;
+mod release;
 mod update;
 
-fn parse_args() -> Result<AppMode, String> {
+fn parse_args() -> Result<Invocation, String> {
     let args: Vec<String> = env::args().collect();
     parse_args_from(args)
 }
 
+/// Print the error and exit with status 1 if `result` failed.
+fn exit_on_error(result: Result<(), impl std::fmt::Display>) {
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+#[allow(clippy::too_many_lines)] // one arm per subcommand
 fn main() {
-    let mode = match parse_args() {
-        Ok(m) => m,
+    update::cleanup_old_binary();
+
+    let Invocation {
+        mode,
+        strict_config,
+        color,
+    } = match parse_args() {
+        Ok(invocation) => invocation,
         Err(e) => {
             eprintln!("{e}");
             std::process::exit(2);
         }
     };
+    let styled = prompter::use_styling(color);
+    // colored checks the terminal on its own; styled output must get its codes
+    colored::control::set_override(styled);
 
     match mode {
         AppMode::Help => {
             Cli::parse_from(["prompter", "--help"]);
         }
+        AppMode::EmitModeJson(mode) => {
+            exit_on_error(serde_json::to_string_pretty(&mode).map(|json| println!("{json}")));
+        }
         AppMode::Version => {
             println!("prompter {}", env!("CARGO_PKG_VERSION"));
         }
-        AppMode::Completions { shell } => {
-            completions::generate_completions(shell);
+        AppMode::Completions {
+            shell,
+            install,
+            write_to,
+            force,
+        } => {
+            if let Some(path) = write_to {
+                exit_on_error(completions::write_completions_to(shell, &path, force));
+            } else if install {
+                exit_on_error(completions::install_completions(shell, force, styled));
+            } else {
+                completions::generate_completions(shell);
+            }
         }
-        AppMode::Doctor => {
-            let exit_code = doctor::run_doctor();
+        AppMode::Doctor {
+            offline,
+            fix,
+            yes,
+            force,
+            strict,
+            deep,
+            max_file_size_kb,
+            follow_symlinks,
+            config,
+        } => {
+            let exit_code = doctor::run_doctor(&doctor::DoctorOptions {
+                offline: offline || prompter::offline_from_env(),
+                fix,
+                yes,
+                force,
+                strict,
+                deep,
+                max_file_size_kb,
+                follow_symlinks,
+                config,
+                strict_config,
+                styled,
+            });
             std::process::exit(exit_code);
         }
         AppMode::Update {
             version,
             force,
             install_dir,
+            rollback,
+            check,
+            json,
+            channel,
+            skip_checksum,
+            skip_verify,
+            backup_path,
+            dry_run,
+            quiet,
+            list_versions,
+            limit,
         } => {
-            let exit_code = update::run_update(version.as_deref(), force, install_dir.as_deref());
+            let exit_code = if list_versions {
+                update::run_list_versions(limit, styled)
+            } else if rollback {
+                update::run_rollback(install_dir.as_deref(), backup_path.as_deref(), styled)
+            } else if check {
+                update::run_check(json, channel, styled)
+            } else {
+                update::run_update(update::UpdateOptions {
+                    version: version.as_deref(),
+                    force,
+                    install_dir: install_dir.as_deref(),
+                    channel,
+                    skip_checksum,
+                    skip_verify,
+                    backup_path: backup_path.as_deref(),
+                    dry_run,
+                    quiet: quiet || !std::io::stdout().is_terminal(),
+                    styled,
+                })
+            };
             std::process::exit(exit_code);
         }
-        AppMode::Init => {
-            if let Err(e) = init_scaffold() {
+        AppMode::Init {
+            force,
+            minimal,
+            template,
+            quiet,
+            config,
+            library,
+        } => {
+            if template.as_deref() == Some("list") {
+                exit_on_error(write_templates(std::io::stdout()).map_err(|e| e.to_string()));
+                return;
+            }
+            let template = match template.as_deref().map(find_template).transpose() {
+                Ok(template) => template,
+                Err(e) => {
+                    eprintln!("Init failed: {e}");
+                    std::process::exit(1);
+                }
+            };
+            let opts = InitOptions {
+                force,
+                minimal,
+                template,
+                quiet: quiet || !std::io::stdout().is_terminal(),
+                styled,
+            };
+            let paths = init_paths(config.as_deref(), library.as_deref());
+            if let Err(e) = paths.and_then(|(cfg, lib)| init_scaffold(&cfg, &lib, opts)) {
                 eprintln!("Init failed: {e}");
                 std::process::exit(1);
             }
         }
-        AppMode::List { config } => {
-            if let Err(e) = run_list_stdout(config.as_deref()) {
-                eprintln!("{e}");
-                std::process::exit(1);
-            }
+        AppMode::Stats {
+            profile,
+            dedup,
+            config,
+        } => {
+            exit_on_error(run_stats_stdout(
+                &profile,
+                dedup,
+                config.as_deref(),
+                strict_config,
+                styled,
+            ));
+        }
+        AppMode::Import {
+            bundle,
+            force,
+            prefix,
+            config,
+        } => exit_on_error(run_import_stdout(
+            &bundle,
+            force,
+            prefix.as_deref(),
+            config.as_deref(),
+            strict_config,
+            styled,
+        )),
+        AppMode::Diff {
+            a,
+            b,
+            content,
+            config,
+        } => exit_on_error(run_diff_stdout(
+            &a,
+            &b,
+            content,
+            config.as_deref(),
+            strict_config,
+            styled,
+        )),
+        AppMode::Test {
+            profile,
+            update,
+            snapshot_dir,
+            config,
+        } => {
+            exit_on_error(run_test_stdout(
+                profile.as_deref(),
+                update,
+                &snapshot_dir,
+                config.as_deref(),
+                strict_config,
+            ));
+        }
+        AppMode::Library { command, config } => {
+            exit_on_error(run_library_stdout(
+                &command,
+                config.as_deref(),
+                strict_config,
+                styled,
+            ));
+        }
+        AppMode::Escape => exit_on_error(run_escape_stdout()),
+        AppMode::Which {
+            config,
+            config_source,
+            json,
+        } => exit_on_error(run_which_stdout(config.as_deref(), config_source, json)),
+        AppMode::Edit { target, config } => {
+            exit_on_error(run_edit(
+                target.as_ref(),
+                config.as_deref(),
+                strict_config,
+                styled,
+            ));
         }
-        AppMode::Validate { config } => match run_validate_stdout(config.as_deref()) {
+        AppMode::New {
+            path,
+            content,
+            edit,
+            force,
+            config,
+        } => {
+            exit_on_error(run_new_stdout(
+                &path,
+                content.as_deref(),
+                edit,
+                force,
+                config.as_deref(),
+            ));
+        }
+        AppMode::List { config, mut opts } => {
+            opts.styled = styled;
+            exit_on_error(run_list_stdout(&opts, config.as_deref(), strict_config));
+        }
+        AppMode::Lint {
+            profile,
+            fix,
+            config,
+        } => exit_on_error(run_lint_stdout(
+            profile.as_deref(),
+            fix,
+            config.as_deref(),
+            strict_config,
+        )),
+        AppMode::Fmt { check, config } => {
+            exit_on_error(run_fmt_stdout(check, config.as_deref(), strict_config));
+        }
+        AppMode::Validate { config } => match run_validate_stdout(config.as_deref(), strict_config)
+        {
             Ok(()) => println!("All profiles valid"),
             Err(errs) => {
                 eprintln!("Validation errors:\n{errs}");
@@ -71,21 +289,18 @@ fn main() {
         },
         AppMode::Run {
             profile,
-            separator,
-            pre_prompt,
-            post_prompt,
+            mut opts,
             config,
+            manifest,
         } => {
-            if let Err(e) = run_render_stdout(
-                &profile,
-                separator.as_deref(),
-                pre_prompt.as_deref(),
-                post_prompt.as_deref(),
+            opts.styled = styled;
+            exit_on_error(run_render_stdout(
+                profile.as_deref(),
+                &opts,
                 config.as_deref(),
-            ) {
-                eprintln!("{e}");
-                std::process::exit(1);
-            }
+                strict_config,
+                manifest.as_deref(),
+            ));
         }
     }
 }
@@ -0,0 +1,59 @@
+//! Line markers shared by `doctor`, `update`, and `completions` output.
+
+/// The marker that starts a line of status output.
+///
+/// Shown as an emoji when output is styled (see [`prompter::use_styling`]).
+/// Plain output labels errors, warnings, and hints like the library's
+/// messages do and drops the rest; either form ends in the space before the
+/// message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    Ok,
+    Error,
+    Warning,
+    Info,
+    Hint,
+    Highlight,
+    Progress,
+    Install,
+    Fixed,
+    Deleted,
+    Health,
+}
+
+impl Mark {
+    /// The marker text, as an emoji when `styled`.
+    pub const fn text(self, styled: bool) -> &'static str {
+        match (self, styled) {
+            (Self::Ok, true) => "✅ ",
+            (Self::Error, true) => "❌ ",
+            (Self::Error, false) => "Error: ",
+            (Self::Warning, true) => "⚠️  ",
+            (Self::Warning, false) => "Warning: ",
+            (Self::Info, true) => "ℹ️  ",
+            (Self::Hint, true) => "💡 ",
+            (Self::Hint, false) => "Hint: ",
+            (Self::Highlight, true) => "✨ ",
+            (Self::Progress, true) => "🔄 ",
+            (Self::Install, true) => "📦 ",
+            (Self::Fixed, true) => "🔧 ",
+            (Self::Deleted, true) => "🗑️  ",
+            (Self::Health, true) => "🏥 ",
+            (_, false) => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_marks_keep_only_labels() {
+        assert_eq!(Mark::Warning.text(true), "⚠️  ");
+        assert_eq!(Mark::Warning.text(false), "Warning: ");
+        assert_eq!(Mark::Error.text(false), "Error: ");
+        assert_eq!(Mark::Ok.text(false), "");
+        assert_eq!(Mark::Install.text(false), "");
+    }
+}
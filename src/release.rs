@@ -0,0 +1,531 @@
+//! GitHub release metadata shared by `update` and `doctor`.
+
+use prompter::UpdateChannel;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use semver::Version;
+use std::time::Duration;
+
+/// GitHub API endpoint describing the latest published release.
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/workhelix/prompter/releases/latest";
+
+/// GitHub API endpoint listing recent releases, including pre-releases.
+const RELEASES_URL: &str = "https://api.github.com/repos/workhelix/prompter/releases?per_page=100";
+
+/// GitHub API endpoint listing releases, newest first, one page at a time.
+const RELEASES_PAGE_URL: &str = "https://api.github.com/repos/workhelix/prompter/releases";
+
+/// Releases requested per page when paginating.
+const RELEASES_PER_PAGE: usize = 100;
+
+/// GitHub API endpoint describing the release with a given tag.
+const RELEASE_BY_TAG_URL: &str = "https://api.github.com/repos/workhelix/prompter/releases/tags";
+
+/// Tag prefix used for prompter releases.
+const TAG_PREFIX: &str = "prompter-v";
+
+/// The parts of a GitHub release `update` cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// Git tag, e.g. `prompter-v1.2.3`
+    pub tag: String,
+    /// Names of the downloadable assets
+    pub assets: Vec<String>,
+}
+
+impl Release {
+    /// Parse a release object from the GitHub API.
+    fn from_json(json: &serde_json::Value) -> Option<Self> {
+        let tag = json["tag_name"].as_str()?.to_string();
+        let assets = json["assets"]
+            .as_array()
+            .map(|assets| {
+                assets
+                    .iter()
+                    .filter_map(|a| a["name"].as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Self { tag, assets })
+    }
+
+    /// Version number with the tag prefix stripped.
+    pub fn version(&self) -> &str {
+        version_from_tag(&self.tag)
+    }
+}
+
+/// One published release as shown by `update --list-versions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseSummary {
+    /// Version number with the tag prefix stripped
+    pub version: String,
+    /// Publication date as `YYYY-MM-DD`, when GitHub reports one
+    pub published: Option<String>,
+    /// Whether GitHub marks the release as a pre-release
+    pub prerelease: bool,
+}
+
+impl ReleaseSummary {
+    /// Parse a release object from the GitHub API, skipping drafts.
+    fn from_json(json: &serde_json::Value) -> Option<Self> {
+        if json["draft"].as_bool().unwrap_or(false) {
+            return None;
+        }
+        let version = version_from_tag(json["tag_name"].as_str()?).to_string();
+        let published = json["published_at"]
+            .as_str()
+            .and_then(|date| date.get(..10))
+            .map(str::to_string);
+        Some(Self {
+            version,
+            published,
+            prerelease: json["prerelease"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// Fetch up to `limit` published releases, newest first, following the
+/// GitHub API's pagination.
+pub fn list_releases(
+    user_agent: &str,
+    timeout: Duration,
+    limit: usize,
+) -> Result<Vec<ReleaseSummary>, String> {
+    let client = http_client(user_agent, timeout)?;
+    let mut releases = Vec::new();
+    for page in 1.. {
+        if releases.len() >= limit {
+            break;
+        }
+        let url = format!("{RELEASES_PAGE_URL}?per_page={RELEASES_PER_PAGE}&page={page}");
+        let response = api_get(&client, &url).send().map_err(|e| e.to_string())?;
+        let json: serde_json::Value = check_api_response(response)?
+            .json()
+            .map_err(|e| e.to_string())?;
+        let entries = json
+            .as_array()
+            .ok_or_else(|| "Unexpected response listing releases".to_string())?;
+        releases.extend(entries.iter().filter_map(ReleaseSummary::from_json));
+        if entries.len() < RELEASES_PER_PAGE {
+            break;
+        }
+    }
+    releases.truncate(limit);
+    Ok(releases)
+}
+
+/// The newest stable version among `releases`, by semver.
+pub fn newest_stable(releases: &[ReleaseSummary]) -> Option<&str> {
+    releases
+        .iter()
+        .filter(|r| !r.prerelease)
+        .filter_map(|r| Some((Version::parse(&r.version).ok()?, r.version.as_str())))
+        .filter(|(version, _)| version.pre.is_empty())
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, version)| version)
+}
+
+/// Tag under which `version` is released.
+pub fn release_tag(version: &str) -> String {
+    format!("{TAG_PREFIX}{version}")
+}
+
+/// Fetch the release tagged for `version`, or `None` if there is no such release.
+pub fn fetch_release(client: &Client, version: &str) -> Result<Option<Release>, String> {
+    let url = format!("{RELEASE_BY_TAG_URL}/{}", release_tag(version));
+    let response = api_get(client, &url).send().map_err(|e| e.to_string())?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let json: serde_json::Value = check_api_response(response)?
+        .json()
+        .map_err(|e| e.to_string())?;
+    Release::from_json(&json)
+        .map(Some)
+        .ok_or_else(|| "No tag_name in response".to_string())
+}
+
+/// Check that `release` exists and ships `asset`, explaining what is
+/// available otherwise.
+///
+/// `latest` is only used to point at a real version when the release is
+/// missing.
+pub fn require_asset(
+    release: Option<&Release>,
+    version: &str,
+    asset: &str,
+    platform: &str,
+    latest: Option<&str>,
+) -> Result<(), String> {
+    let Some(release) = release else {
+        let hint = latest
+            .map(|l| format!("; latest is {l}"))
+            .unwrap_or_default();
+        return Err(format!(
+            "no release tagged {} found{hint}",
+            release_tag(version)
+        ));
+    };
+    if release.assets.iter().any(|a| a == asset) {
+        return Ok(());
+    }
+    let available = if release.assets.is_empty() {
+        "none".to_string()
+    } else {
+        release.assets.join(", ")
+    };
+    Err(format!(
+        "release {} exists but has no asset for {platform} (available: {available})",
+        release.tag
+    ))
+}
+
+/// Environment variables checked, in order, for a GitHub API token.
+const TOKEN_ENV_VARS: [&str; 2] = ["PROMPTER_GITHUB_TOKEN", "GITHUB_TOKEN"];
+
+/// Build the HTTP client used for all release traffic.
+///
+/// Proxies come from `HTTPS_PROXY`/`HTTP_PROXY`/`ALL_PROXY`, with hosts in
+/// `NO_PROXY` bypassed; reqwest reads these itself as long as proxy
+/// detection is left enabled, so nothing here may call `no_proxy()`.
+pub fn http_client(user_agent: &str, timeout: Duration) -> Result<Client, String> {
+    Client::builder()
+        .user_agent(user_agent)
+        .timeout(timeout)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// GET a GitHub API URL, authenticating with a token from the environment
+/// when one is set.
+fn api_get(client: &Client, url: &str) -> RequestBuilder {
+    let request = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+    match github_token(|name| std::env::var(name).ok()) {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// First non-empty token among [`TOKEN_ENV_VARS`].
+fn github_token(lookup: impl Fn(&str) -> Option<String>) -> Option<String> {
+    TOKEN_ENV_VARS
+        .iter()
+        .filter_map(|name| lookup(name))
+        .map(|token| token.trim().to_string())
+        .find(|token| !token.is_empty())
+}
+
+/// Turn a GitHub API response into an error unless it succeeded.
+fn check_api_response(response: Response) -> Result<Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    Err(api_error(
+        status,
+        header("x-ratelimit-remaining").as_deref(),
+        header("x-ratelimit-reset").as_deref(),
+    ))
+}
+
+/// Describe a failed GitHub API request, calling out rate limiting.
+fn api_error(status: StatusCode, remaining: Option<&str>, reset: Option<&str>) -> String {
+    let rate_limited = matches!(
+        status,
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    ) && remaining == Some("0");
+    if !rate_limited {
+        return format!("GitHub API request failed: HTTP {status}");
+    }
+    let resets = reset
+        .and_then(|r| r.parse::<i64>().ok())
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|t| {
+            format!(
+                " (resets at {})",
+                t.with_timezone(&chrono::Local).format("%H:%M")
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "GitHub API rate limit exceeded{resets}; set GITHUB_TOKEN or PROMPTER_GITHUB_TOKEN to raise the limit"
+    )
+}
+
+/// Fetch the version number of the latest stable release.
+///
+/// The `prompter-v`/`v` tag prefix is stripped, so `prompter-v1.2.3` yields
+/// `1.2.3`.
+pub fn latest_version(user_agent: &str, timeout: Duration) -> Result<String, String> {
+    latest_version_for(UpdateChannel::Stable, user_agent, timeout)
+}
+
+/// Fetch the newest version available on `channel`.
+///
+/// Stable uses GitHub's "latest release"; prerelease scans the release list
+/// and picks the highest semver version, release candidates included.
+pub fn latest_version_for(
+    channel: UpdateChannel,
+    user_agent: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    let client = http_client(user_agent, timeout)?;
+
+    let url = match channel {
+        UpdateChannel::Stable => LATEST_RELEASE_URL,
+        UpdateChannel::Prerelease => RELEASES_URL,
+    };
+    let response = api_get(&client, url).send().map_err(|e| e.to_string())?;
+    let response: serde_json::Value = check_api_response(response)?
+        .json()
+        .map_err(|e| e.to_string())?;
+
+    match channel {
+        UpdateChannel::Stable => Release::from_json(&response)
+            .map(|r| r.version().to_string())
+            .ok_or_else(|| "No tag_name in response".to_string()),
+        UpdateChannel::Prerelease => newest_release(&response, true)
+            .map(|v| v.to_string())
+            .ok_or_else(|| "No releases found".to_string()),
+    }
+}
+
+/// Pick the highest version from a GitHub release list.
+///
+/// Drafts and tags that are not valid semver are ignored, as are
+/// pre-releases unless `include_prerelease` is set.
+fn newest_release(releases: &serde_json::Value, include_prerelease: bool) -> Option<Version> {
+    releases
+        .as_array()?
+        .iter()
+        .filter(|r| !r["draft"].as_bool().unwrap_or(false))
+        .filter_map(|r| {
+            let version = Version::parse(version_from_tag(r["tag_name"].as_str()?)).ok()?;
+            let prerelease = r["prerelease"].as_bool().unwrap_or(false) || !version.pre.is_empty();
+            (include_prerelease || !prerelease).then_some(version)
+        })
+        .max()
+}
+
+fn version_from_tag(tag: &str) -> &str {
+    tag.trim_start_matches(TAG_PREFIX).trim_start_matches('v')
+}
+
+/// Whether `latest` is a newer release than `current`.
+///
+/// Versions are compared with semver ordering, so `1.0.0-rc.1 < 1.0.0` and a
+/// local build newer than the latest release is not "updated" backwards.
+/// Versions that are not valid semver count as newer whenever they differ.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    match (Version::parse(latest), Version::parse(current)) {
+        (Ok(l), Ok(c)) => l > c,
+        _ => latest != current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSET: &str = "prompter-aarch64-apple-darwin.tar.gz";
+
+    fn release_fixture() -> Release {
+        let json = serde_json::json!({
+            "tag_name": "prompter-v1.2.3",
+            "assets": [
+                { "name": "prompter-x86_64-unknown-linux-gnu.tar.gz" },
+                { "name": "prompter-x86_64-unknown-linux-gnu.tar.gz.sha256" },
+            ],
+        });
+        Release::from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn test_release_from_json() {
+        let release = release_fixture();
+        assert_eq!(release.tag, "prompter-v1.2.3");
+        assert_eq!(release.version(), "1.2.3");
+        assert_eq!(release.assets.len(), 2);
+
+        let bare = Release::from_json(&serde_json::json!({ "tag_name": "v2.0.0" })).unwrap();
+        assert_eq!(bare.version(), "2.0.0");
+        assert!(bare.assets.is_empty());
+        assert_eq!(
+            Release::from_json(&serde_json::json!({ "message": "Not Found" })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_require_asset() {
+        let release = release_fixture();
+        assert_eq!(
+            require_asset(
+                Some(&release),
+                "1.2.3",
+                "prompter-x86_64-unknown-linux-gnu.tar.gz",
+                "x86_64-unknown-linux-gnu",
+                None
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            require_asset(Some(&release), "1.2.3", ASSET, "aarch64-apple-darwin", None),
+            Err(
+                "release prompter-v1.2.3 exists but has no asset for aarch64-apple-darwin \
+                 (available: prompter-x86_64-unknown-linux-gnu.tar.gz, \
+                 prompter-x86_64-unknown-linux-gnu.tar.gz.sha256)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            require_asset(None, "9.9.9", ASSET, "aarch64-apple-darwin", Some("1.4.0")),
+            Err("no release tagged prompter-v9.9.9 found; latest is 1.4.0".to_string())
+        );
+        assert_eq!(
+            require_asset(None, "9.9.9", ASSET, "aarch64-apple-darwin", None),
+            Err("no release tagged prompter-v9.9.9 found".to_string())
+        );
+    }
+
+    #[test]
+    fn test_github_token_precedence() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| (*v).to_string())
+            }
+        };
+        assert_eq!(github_token(env(&[])), None);
+        assert_eq!(
+            github_token(env(&[("GITHUB_TOKEN", "gh")])),
+            Some("gh".into())
+        );
+        assert_eq!(
+            github_token(env(&[
+                ("GITHUB_TOKEN", "gh"),
+                ("PROMPTER_GITHUB_TOKEN", "pr")
+            ])),
+            Some("pr".into())
+        );
+        assert_eq!(
+            github_token(env(&[
+                ("GITHUB_TOKEN", "gh"),
+                ("PROMPTER_GITHUB_TOKEN", " ")
+            ])),
+            Some("gh".into())
+        );
+    }
+
+    #[test]
+    fn test_api_error_detects_rate_limit() {
+        let limited = api_error(StatusCode::FORBIDDEN, Some("0"), Some("1700000000"));
+        assert!(limited.starts_with("GitHub API rate limit exceeded (resets at "));
+        assert!(limited.ends_with("set GITHUB_TOKEN or PROMPTER_GITHUB_TOKEN to raise the limit"));
+        assert!(
+            api_error(StatusCode::TOO_MANY_REQUESTS, Some("0"), None)
+                .starts_with("GitHub API rate limit exceeded;")
+        );
+        // A 403 with quota left is a permissions problem, not rate limiting
+        assert_eq!(
+            api_error(StatusCode::FORBIDDEN, Some("12"), None),
+            "GitHub API request failed: HTTP 403 Forbidden"
+        );
+        assert_eq!(
+            api_error(StatusCode::NOT_FOUND, None, None),
+            "GitHub API request failed: HTTP 404 Not Found"
+        );
+    }
+
+    #[test]
+    fn test_version_from_tag() {
+        assert_eq!(version_from_tag("prompter-v1.5.5"), "1.5.5");
+        assert_eq!(version_from_tag("v2.0.0"), "2.0.0");
+        assert_eq!(version_from_tag("3.1.0"), "3.1.0");
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("1.5.6", "1.5.5"));
+        assert!(is_newer("1.10.0", "1.9.9"));
+        assert!(!is_newer("1.5.5", "1.5.5"));
+        assert!(!is_newer("1.5.4", "1.5.5"));
+        assert!(is_newer("2.0.0-rc.1", "1.5.5"));
+        assert!(!is_newer("2.0.0-rc.1", "2.0.0-rc.1"));
+        assert!(is_newer("2.0.0", "2.0.0-rc.1"));
+        assert!(is_newer("2.0.0-rc.10", "2.0.0-rc.2"));
+        assert!(!is_newer("2.0.0-rc.1", "2.0.0"));
+        assert!(is_newer("next", "1.0.0"));
+    }
+
+    #[test]
+    fn test_release_summaries_skip_drafts_and_pick_newest_stable() {
+        let page = serde_json::json!([
+            { "tag_name": "prompter-v2.0.0-rc.1", "prerelease": true,
+              "published_at": "2025-03-01T10:00:00Z" },
+            { "tag_name": "prompter-v1.10.0", "published_at": "2025-02-01T10:00:00Z" },
+            { "tag_name": "prompter-v3.0.0", "draft": true },
+            { "tag_name": "v1.9.0" },
+        ]);
+        let releases: Vec<ReleaseSummary> = page
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(ReleaseSummary::from_json)
+            .collect();
+        assert_eq!(
+            releases,
+            [
+                ReleaseSummary {
+                    version: "2.0.0-rc.1".into(),
+                    published: Some("2025-03-01".into()),
+                    prerelease: true,
+                },
+                ReleaseSummary {
+                    version: "1.10.0".into(),
+                    published: Some("2025-02-01".into()),
+                    prerelease: false,
+                },
+                ReleaseSummary {
+                    version: "1.9.0".into(),
+                    published: None,
+                    prerelease: false,
+                },
+            ]
+        );
+        assert_eq!(newest_stable(&releases), Some("1.10.0"));
+        assert_eq!(newest_stable(&releases[..1]), None);
+    }
+
+    #[test]
+    fn test_newest_release_by_channel() {
+        let releases = serde_json::json!([
+            { "tag_name": "prompter-v1.5.5", "prerelease": false },
+            { "tag_name": "prompter-v1.10.0", "prerelease": false },
+            { "tag_name": "prompter-v2.0.0-rc.1", "prerelease": true },
+            { "tag_name": "prompter-v3.0.0", "draft": true },
+            { "tag_name": "nightly", "prerelease": true },
+        ]);
+        assert_eq!(
+            newest_release(&releases, false),
+            Some(Version::new(1, 10, 0))
+        );
+        assert_eq!(
+            newest_release(&releases, true),
+            Some(Version::parse("2.0.0-rc.1").unwrap())
+        );
+        assert_eq!(newest_release(&serde_json::json!([]), true), None);
+        assert_eq!(newest_release(&serde_json::json!({}), true), None);
+    }
+}
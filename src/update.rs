@@ -1,133 +1,572 @@
 //! Self-update module.
 
+use crate::mark::Mark;
+use indicatif::{ProgressBar, ProgressStyle};
+use prompter::UpdateChannel;
 use sha2::{Digest, Sha256};
-use std::path::Path;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Options controlling an update run.
+#[derive(Debug, Default, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct UpdateOptions<'a> {
+    /// Install this version instead of the newest one on `channel`
+    pub version: Option<&'a str>,
+    /// Skip the up-to-date check and the confirmation prompt
+    pub force: bool,
+    /// Install into this directory instead of replacing the running binary
+    pub install_dir: Option<&'a Path>,
+    /// Release channel to look for the newest version on
+    pub channel: UpdateChannel,
+    /// Install even when the release checksum is missing or unreachable
+    pub skip_checksum: bool,
+    /// Do not fetch or check any checksum
+    pub skip_verify: bool,
+    /// Save the previous binary here instead of `<install path>.bak`
+    pub backup_path: Option<&'a Path>,
+    /// Print the steps an update would take and stop before downloading
+    pub dry_run: bool,
+    /// Do not show a progress bar while downloading
+    pub quiet: bool,
+    /// Print emoji markers instead of plain labels
+    pub styled: bool,
+}
 
 /// Run update command to install latest or specified version.
 ///
 /// Returns exit code: 0 if successful, 1 on error, 2 if already up-to-date.
 #[allow(clippy::unused_async)]
-pub fn run_update(version: Option<&str>, force: bool, install_dir: Option<&Path>) -> i32 {
+pub fn run_update(opts: UpdateOptions) -> i32 {
+    let UpdateOptions {
+        version,
+        force,
+        install_dir,
+        channel,
+        skip_checksum,
+        skip_verify,
+        backup_path,
+        dry_run,
+        quiet: _,
+        styled,
+    } = opts;
     let current_version = env!("CARGO_PKG_VERSION");
 
-    println!("🔄 Checking for updates...");
+    if prompter::offline_from_env() {
+        eprintln!(
+            "{}Cannot update while offline ({} is set)",
+            Mark::Error.text(styled),
+            prompter::OFFLINE_ENV
+        );
+        return 1;
+    }
+
+    println!("{}Checking for updates...", Mark::Progress.text(styled));
 
     // Get target version
     let target_version = if let Some(v) = version {
         v.to_string()
     } else {
-        match get_latest_version() {
+        match get_latest_version(channel) {
             Ok(v) => v,
             Err(e) => {
-                eprintln!("❌ Failed to check for updates: {e}");
+                eprintln!(
+                    "{}Failed to check for updates: {e}",
+                    Mark::Error.text(styled)
+                );
                 return 1;
             }
         }
     };
 
-    // Check if already up-to-date
-    if target_version == current_version && !force {
-        println!("✅ Already running latest version (v{current_version})");
+    // Check if already up-to-date; an explicit --version may downgrade
+    let up_to_date = if version.is_some() {
+        target_version == current_version
+    } else {
+        !crate::release::is_newer(&target_version, current_version)
+    };
+    if up_to_date && !force {
+        println!(
+            "{}Already running latest version (v{current_version})",
+            Mark::Ok.text(styled)
+        );
         return 2;
     }
 
-    println!("✨ Update available: v{target_version} (current: v{current_version})");
+    println!(
+        "{}Update available: v{target_version} (current: v{current_version})",
+        Mark::Highlight.text(styled)
+    );
 
     // Detect current binary location
-    let install_path = if let Some(dir) = install_dir {
-        dir.join("prompter")
-    } else {
-        match std::env::current_exe() {
-            Ok(path) => path,
-            Err(e) => {
-                eprintln!("❌ Failed to determine binary location: {e}");
-                return 1;
-            }
+    let install_path = match install_path(install_dir) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}{e}", Mark::Error.text(styled));
+            return 1;
         }
     };
 
-    println!("📍 Install location: {}", install_path.display());
-    println!();
-
-    // Confirm unless forced
-    if !force {
-        use std::io::{self, Write};
-        print!("Continue with update? [y/N]: ");
-        io::stdout().flush().unwrap();
+    let backup = backup_path.map_or_else(|| default_backup_path(&install_path), Path::to_path_buf);
 
-        let mut response = String::new();
-        io::stdin().read_line(&mut response).unwrap();
+    print_install_location(&install_path, install_dir);
 
-        if !matches!(response.trim().to_lowercase().as_str(), "y" | "yes") {
-            println!("Update cancelled.");
-            return 0;
+    if dry_run {
+        if let Err(e) = check_install_path(&install_path) {
+            eprintln!("{}{e}", Mark::Error.text(styled));
+            return 1;
         }
+        print!(
+            "{}",
+            dry_run_plan(
+                &target_version,
+                &install_path,
+                &backup,
+                skip_checksum,
+                skip_verify
+            )
+        );
+        return 0;
+    }
+
+    // Confirm unless forced
+    if !force && !confirm_update() {
+        println!("Update cancelled.");
+        return 0;
     }
 
     // Perform update
-    match perform_update(&target_version, &install_path) {
+    match perform_update(&target_version, &install_path, &backup, opts) {
         Ok(()) => {
-            println!("✅ Successfully updated to v{target_version}");
+            println!(
+                "{}Successfully updated to v{target_version}",
+                Mark::Ok.text(styled)
+            );
             println!();
             println!("Run 'prompter --version' to verify the installation.");
             0
         }
         Err(e) => {
-            eprintln!("❌ Update failed: {e}");
+            eprintln!("{}Update failed: {e}", Mark::Error.text(styled));
             1
         }
     }
 }
 
-fn get_latest_version() -> Result<String, String> {
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("prompter-updater")
-        .timeout(std::time::Duration::from_secs(10))
-        .build()
-        .map_err(|e| e.to_string())?;
+/// Ask whether to go ahead with the update, defaulting to no.
+fn confirm_update() -> bool {
+    use std::io::{self, Write};
+    print!("Continue with update? [y/N]: ");
+    io::stdout().flush().unwrap();
 
-    let url = "https://api.github.com/repos/workhelix/prompter/releases/latest";
-    let response: serde_json::Value = client
-        .get(url)
-        .send()
-        .map_err(|e| e.to_string())?
-        .json()
-        .map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    io::stdin().read_line(&mut response).unwrap();
+    matches!(response.trim().to_lowercase().as_str(), "y" | "yes")
+}
 
-    let tag_name = response["tag_name"]
-        .as_str()
-        .ok_or_else(|| "No tag_name in response".to_string())?;
+/// Restore the binary saved by the last update from `backup_path`, or from
+/// `<install path>.bak` when it is not given.
+///
+/// Returns exit code: 0 if the backup was restored, 1 on error or when there
+/// is no backup.
+pub fn run_rollback(install_dir: Option<&Path>, backup_path: Option<&Path>, styled: bool) -> i32 {
+    let install_path = match install_path(install_dir) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}{e}", Mark::Error.text(styled));
+            return 1;
+        }
+    };
+    let backup = backup_path.map_or_else(|| default_backup_path(&install_path), Path::to_path_buf);
+    if !backup.exists() {
+        eprintln!(
+            "{}No backup found at {}; nothing to roll back",
+            Mark::Warning.text(styled),
+            backup.display()
+        );
+        return 1;
+    }
 
-    let version = tag_name
-        .trim_start_matches("prompter-v")
-        .trim_start_matches('v');
-    Ok(version.to_string())
+    println!("⏪ Rolling back {}...", install_path.display());
+    match restore_backup(&install_path, &backup).and_then(|()| binary_version(&install_path)) {
+        Ok(version) => {
+            println!("{}Restored {version}", Mark::Ok.text(styled));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}Rollback failed: {e}", Mark::Error.text(styled));
+            1
+        }
+    }
 }
 
-fn perform_update(version: &str, install_path: &Path) -> Result<(), String> {
-    // Detect platform
-    let platform = get_platform_string();
-    let archive_ext = if cfg!(target_os = "windows") {
-        "zip"
+/// The binary to replace: inside `install_dir` if given, else the running
+/// one, unless its directory is not writable (e.g. a system directory that
+/// needs sudo) and [`find_writable_install_dir`] finds one that is.
+fn install_path(install_dir: Option<&Path>) -> Result<PathBuf, String> {
+    if let Some(dir) = install_dir {
+        return Ok(dir.join(binary_name()));
+    }
+    let exe =
+        std::env::current_exe().map_err(|e| format!("Failed to determine binary location: {e}"))?;
+    if exe.parent().is_some_and(is_writable_dir) {
+        return Ok(exe);
+    }
+    Ok(find_writable_install_dir().map_or(exe, |dir| dir.join(binary_name())))
+}
+
+/// Print where the update goes, and why when it is not the running binary.
+fn print_install_location(install_path: &Path, install_dir: Option<&Path>) {
+    println!("📍 Install location: {}", install_path.display());
+    if install_dir.is_none() && std::env::current_exe().ok().as_deref() != Some(install_path) {
+        println!(
+            "   (the running binary's directory is not writable; using the first one on $PATH)"
+        );
+    }
+    println!();
+}
+
+/// The first directory on `$PATH` the current user can write to, skipping
+/// `/usr/bin` and `/bin`.
+fn find_writable_install_dir() -> Option<PathBuf> {
+    first_writable_dir(&std::env::var_os("PATH")?)
+}
+
+fn first_writable_dir(path_var: &std::ffi::OsStr) -> Option<PathBuf> {
+    // Relative entries such as `.` depend on where update happens to run
+    std::env::split_paths(path_var).find(|dir| {
+        dir.is_absolute()
+            && dir != Path::new("/usr/bin")
+            && dir != Path::new("/bin")
+            && is_writable_dir(dir)
+    })
+}
+
+/// Whether a file can be created in `dir`; permission bits alone miss
+/// ownership, ACLs and read-only mounts.
+fn is_writable_dir(dir: &Path) -> bool {
+    dir.is_dir() && tempfile::tempfile_in(dir).is_ok()
+}
+
+fn get_latest_version(channel: UpdateChannel) -> Result<String, String> {
+    crate::release::latest_version_for(channel, "prompter-updater", Duration::from_secs(10))
+}
+
+/// Exit code from `update --check` when a newer release exists.
+pub const UPDATE_AVAILABLE_EXIT_CODE: i32 = 10;
+
+/// Report whether a newer release exists without downloading or prompting.
+///
+/// Returns exit code: 0 if up to date, [`UPDATE_AVAILABLE_EXIT_CODE`] if an
+/// update is available, 1 if the check failed.
+pub fn run_check(json: bool, channel: UpdateChannel, styled: bool) -> i32 {
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = if prompter::offline_from_env() {
+        Err(format!(
+            "cannot check while offline ({} is set)",
+            prompter::OFFLINE_ENV
+        ))
     } else {
-        "tar.gz"
+        get_latest_version(channel)
+    };
+    let latest = match latest {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!(
+                "{}Failed to check for updates: {e}",
+                Mark::Error.text(styled)
+            );
+            return 1;
+        }
+    };
+
+    let update_available = crate::release::is_newer(&latest, current);
+    if json {
+        println!("{}", check_json(current, &latest, update_available));
+    } else {
+        println!("Current version: v{current}");
+        println!("Latest version:  v{latest}");
+        if update_available {
+            println!(
+                "{}Update available: run 'prompter update' to install it",
+                Mark::Highlight.text(styled)
+            );
+        } else {
+            println!("{}Up to date", Mark::Ok.text(styled));
+        }
+    }
+
+    if update_available {
+        UPDATE_AVAILABLE_EXIT_CODE
+    } else {
+        0
+    }
+}
+
+/// Digests a release may publish checksum files for, in the order they are
+/// tried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChecksumAlgorithm {
+    /// `<asset>.sha256`
+    Sha256,
+    /// `<asset>.b3`, the fallback when there is no `.sha256`
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    const ALL: [Self; 2] = [Self::Sha256, Self::Blake3];
+
+    /// Extension of the checksum file next to the asset.
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "b3",
+        }
+    }
+
+    /// Lowercase hex digest of `bytes`.
+    fn hex_digest(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Sha256 => hex::encode(Sha256::digest(bytes)),
+            Self::Blake3 => blake3::hash(bytes).to_hex().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "SHA-256",
+            Self::Blake3 => "BLAKE3",
+        })
+    }
+}
+
+/// GET `url` and return the body, or why it could not be fetched.
+fn fetch_text(client: &reqwest::blocking::Client, url: &str) -> Result<String, String> {
+    let response = client.get(url).send().map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        response.text().map_err(|e| e.to_string())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+/// Check `bytes` downloaded from `url` against the first checksum file
+/// `fetch` can get: `<url>.sha256`, then `<url>.b3`.
+///
+/// Returns the algorithm that verified the download. When neither file can
+/// be fetched this is an error unless `skip_missing` is set, in which case it
+/// returns `Ok(None)`. A checksum file that exists but does not match is
+/// always an error; the next one is not tried.
+fn verify_checksum(
+    bytes: &[u8],
+    url: &str,
+    fetch: impl Fn(&str) -> Result<String, String>,
+    skip_missing: bool,
+) -> Result<Option<ChecksumAlgorithm>, String> {
+    let filename = url.rsplit('/').next().unwrap_or(url);
+    let mut missing = Vec::new();
+    for algorithm in ChecksumAlgorithm::ALL {
+        match fetch(&format!("{url}.{}", algorithm.extension())) {
+            Ok(text) => {
+                return check_checksum(bytes, filename, algorithm, &text).map(|()| Some(algorithm));
+            }
+            Err(e) => missing.push(format!(".{}: {e}", algorithm.extension())),
+        }
+    }
+    if skip_missing {
+        return Ok(None);
+    }
+    Err(format!(
+        "Checksum for {filename} not available ({}); rerun with --skip-checksum to install without verification",
+        missing.join(", ")
+    ))
+}
+
+/// Check `bytes` against the text of a checksum file.
+///
+/// When the file names an artifact (`<hash>  <name>`), that name must match
+/// `filename`.
+fn check_checksum(
+    bytes: &[u8],
+    filename: &str,
+    algorithm: ChecksumAlgorithm,
+    checksum: &str,
+) -> Result<(), String> {
+    let mut fields = checksum.split_whitespace();
+    let expected_hash = fields
+        .next()
+        .ok_or_else(|| "Invalid checksum format".to_string())?;
+    if let Some(named) = fields.next() {
+        // sha256sum marks binary-mode entries with a leading '*'
+        let named = named.trim_start_matches('*');
+        let named = Path::new(named)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(named);
+        if named != filename {
+            return Err(format!(
+                "Checksum file is for {named}, but the download is {filename}"
+            ));
+        }
+    }
+
+    let actual_hash = algorithm.hex_digest(bytes);
+    if !actual_hash.eq_ignore_ascii_case(expected_hash) {
+        return Err(format!(
+            "Checksum verification failed! ({algorithm})\nExpected: {expected_hash}\nActual:   {actual_hash}"
+        ));
+    }
+    Ok(())
+}
+
+/// Print up to `limit` published releases with their dates, marking the
+/// running version and the newest stable one.
+///
+/// Returns exit code: 0 on success, 1 if the releases could not be fetched.
+pub fn run_list_versions(limit: usize, styled: bool) -> i32 {
+    let releases = if prompter::offline_from_env() {
+        Err(format!(
+            "cannot list versions while offline ({} is set)",
+            prompter::OFFLINE_ENV
+        ))
+    } else {
+        crate::release::list_releases("prompter-updater", Duration::from_secs(10), limit)
     };
+    match releases {
+        Ok(releases) if releases.is_empty() => {
+            println!("No releases found");
+            0
+        }
+        Ok(releases) => {
+            let latest = crate::release::newest_stable(&releases);
+            print!(
+                "{}",
+                versions_table(&releases, env!("CARGO_PKG_VERSION"), latest)
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("{}Failed to list versions: {e}", Mark::Error.text(styled));
+            1
+        }
+    }
+}
+
+/// Lay out `releases` as a VERSION / RELEASED / NOTE table, noting the
+/// `current` and `latest` versions.
+fn versions_table(
+    releases: &[crate::release::ReleaseSummary],
+    current: &str,
+    latest: Option<&str>,
+) -> String {
+    let rows: Vec<(String, &str, String)> = releases
+        .iter()
+        .map(|release| {
+            let mut notes = Vec::new();
+            if latest == Some(release.version.as_str()) {
+                notes.push("latest");
+            }
+            if release.version == current {
+                notes.push("current");
+            }
+            if release.prerelease {
+                notes.push("pre-release");
+            }
+            (
+                format!("v{}", release.version),
+                release.published.as_deref().unwrap_or("-"),
+                notes.join(", "),
+            )
+        })
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(version, _, _)| version.len())
+        .max()
+        .unwrap_or(0)
+        .max("VERSION".len());
 
-    let filename = format!("prompter-{platform}.{archive_ext}");
-    let download_url = format!(
-        "https://github.com/workhelix/prompter/releases/download/prompter-v{version}/{filename}"
+    let mut table = format!("{:<width$}  {:<10}  NOTE\n", "VERSION", "RELEASED");
+    for (version, date, note) in rows {
+        let line = format!("{version:<width$}  {date:<10}  {note}");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// A bar of bytes downloaded out of `total`, or a byte-counting spinner when
+/// the server sent no `Content-Length`; hidden when `quiet`.
+fn download_progress(total: Option<u64>, quiet: bool) -> ProgressBar {
+    if quiet {
+        return ProgressBar::hidden();
+    }
+    let pb = total.map_or_else(ProgressBar::new_spinner, ProgressBar::new);
+    let template = if total.is_some() {
+        "{spinner:.green} [{bar:30.cyan/blue}] {bytes}/{total_bytes} ({eta})"
+    } else {
+        "{spinner:.green} {bytes} downloaded"
+    };
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+            .template(template)
+            .unwrap()
+            .progress_chars("=> "),
     );
+    pb.enable_steady_tick(Duration::from_millis(120));
+    pb
+}
+
+fn check_json(current: &str, latest: &str, update_available: bool) -> serde_json::Value {
+    serde_json::json!({
+        "current": current,
+        "latest": latest,
+        "update_available": update_available,
+    })
+}
+
+fn perform_update(
+    version: &str,
+    install_path: &Path,
+    backup: &Path,
+    opts: UpdateOptions,
+) -> Result<(), String> {
+    let UpdateOptions {
+        skip_checksum,
+        skip_verify,
+        quiet,
+        styled,
+        ..
+    } = opts;
+    let platform = get_platform_string();
+    let (filename, download_url) = release_asset(version);
+
+    let client = crate::release::http_client("prompter-updater", Duration::from_secs(300))?;
+
+    // Fail early with a useful message if the release or asset is missing
+    let found = crate::release::fetch_release(&client, version)?;
+    let latest = if found.is_none() {
+        get_latest_version(UpdateChannel::Stable).ok()
+    } else {
+        None
+    };
+    crate::release::require_asset(
+        found.as_ref(),
+        version,
+        &filename,
+        platform,
+        latest.as_deref(),
+    )?;
 
     println!("📥 Downloading {filename}...");
 
     // Download file
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("prompter-updater")
-        .timeout(std::time::Duration::from_secs(300))
-        .build()
-        .map_err(|e| e.to_string())?;
 
-    let response = client
+    let mut response = client
         .get(&download_url)
         .send()
         .map_err(|e| e.to_string())?;
@@ -136,63 +575,45 @@ fn perform_update(version: &str, install_path: &Path) -> Result<(), String> {
         return Err(format!("Download failed: HTTP {}", response.status()));
     }
 
-    let bytes = response.bytes().map_err(|e| e.to_string())?;
+    let pb = download_progress(response.content_length(), quiet);
+    let mut bytes = Vec::new();
+    std::io::copy(&mut response, &mut pb.wrap_write(&mut bytes)).map_err(|e| e.to_string())?;
+    pb.finish_and_clear();
 
-    // Download checksum
-    let checksum_url = format!("{download_url}.sha256");
-    let checksum_response = client
-        .get(&checksum_url)
-        .send()
-        .map_err(|e| e.to_string())?;
-
-    if checksum_response.status().is_success() {
+    if skip_verify {
+        eprintln!(
+            "{}Skipping checksum verification (--skip-verify); the download is not verified",
+            Mark::Warning.text(styled)
+        );
+    } else {
         println!("🔐 Verifying checksum...");
-        let expected_checksum = checksum_response.text().map_err(|e| e.to_string())?;
-        let expected_hash = expected_checksum
-            .split_whitespace()
-            .next()
-            .ok_or_else(|| "Invalid checksum format".to_string())?;
-
-        // Calculate actual checksum
-        let mut hasher = Sha256::new();
-        hasher.update(&bytes);
-        let actual_hash = hex::encode(hasher.finalize());
-
-        if actual_hash != expected_hash {
-            return Err(format!(
-                "Checksum verification failed!\nExpected: {expected_hash}\nActual:   {actual_hash}"
-            ));
+        let fetch = |url: &str| fetch_text(&client, url);
+        match verify_checksum(&bytes, &download_url, fetch, skip_checksum)? {
+            Some(algorithm) => println!("{}Checksum verified ({algorithm})", Mark::Ok.text(styled)),
+            None => eprintln!(
+                "{}Checksum file not available, skipping verification (--skip-checksum)",
+                Mark::Warning.text(styled)
+            ),
         }
-
-        println!("✅ Checksum verified");
-    } else {
-        eprintln!("⚠️  Checksum file not available, skipping verification");
     }
 
     // Extract and install
-    println!("📦 Installing...");
+    println!("{}Installing...", Mark::Install.text(styled));
 
     // Create temp directory
     let temp_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
 
     // Extract archive
-    if cfg!(target_os = "windows") {
-        // Extract zip (would need zip crate)
-        return Err("Windows update not yet implemented".to_string());
-    }
-    // Extract tar.gz
-    let tar_gz = flate2::read::GzDecoder::new(&bytes[..]);
-    let mut archive = tar::Archive::new(tar_gz);
-    archive.unpack(temp_dir.path()).map_err(|e| e.to_string())?;
-
-    // Find binary in temp dir
-    let binary_name = if cfg!(target_os = "windows") {
-        "prompter.exe"
+    let binary_name = binary_name();
+    let temp_binary = if cfg!(target_os = "windows") {
+        extract_zip(&bytes, temp_dir.path(), binary_name)?
     } else {
-        "prompter"
+        let tar_gz = flate2::read::GzDecoder::new(&bytes[..]);
+        let mut archive = tar::Archive::new(tar_gz);
+        archive.unpack(temp_dir.path()).map_err(|e| e.to_string())?;
+        temp_dir.path().join(binary_name)
     };
 
-    let temp_binary = temp_dir.path().join(binary_name);
     if !temp_binary.exists() {
         return Err(format!("Binary not found in archive: {binary_name}"));
     }
@@ -208,18 +629,254 @@ fn perform_update(version: &str, install_path: &Path) -> Result<(), String> {
         std::fs::set_permissions(&temp_binary, perms).map_err(|e| e.to_string())?;
     }
 
-    // Replace binary
-    std::fs::copy(&temp_binary, install_path).map_err(|e| {
-        if e.kind() == std::io::ErrorKind::PermissionDenied {
-            format!("Permission denied. Try running with sudo or use --install-dir to specify a writable location:\n  {e}")
-        } else {
-            e.to_string()
+    let installed = install_verified(&temp_binary, install_path, backup)?;
+    println!(
+        "{}Installed binary reports: {installed}",
+        Mark::Ok.text(styled)
+    );
+
+    Ok(())
+}
+
+/// Archive name for this platform and the URL it is downloaded from.
+fn release_asset(version: &str) -> (String, String) {
+    let archive_ext = if cfg!(target_os = "windows") {
+        "zip"
+    } else {
+        "tar.gz"
+    };
+    let filename = format!("prompter-{}.{archive_ext}", get_platform_string());
+    let url = format!(
+        "https://github.com/workhelix/prompter/releases/download/{}/{filename}",
+        crate::release::release_tag(version)
+    );
+    (filename, url)
+}
+
+/// Check that an update could write `install_path`: its directory must
+/// exist and an existing binary must not be read-only.
+fn check_install_path(install_path: &Path) -> Result<(), String> {
+    let dir = install_path.parent().unwrap_or_else(|| Path::new("."));
+    if !dir.is_dir() {
+        return Err(format!("Install directory not found: {}", dir.display()));
+    }
+    match std::fs::metadata(install_path) {
+        Ok(meta) if meta.permissions().readonly() => Err(format!(
+            "Current binary is read-only: {}",
+            install_path.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The steps `run_update` would take after resolving the version, as printed
+/// by `update --dry-run`.
+fn dry_run_plan(
+    version: &str,
+    install_path: &Path,
+    backup: &Path,
+    skip_checksum: bool,
+    skip_verify: bool,
+) -> String {
+    let (filename, url) = release_asset(version);
+    let checksum = if skip_verify {
+        "Skip checksum verification (--skip-verify)".to_string()
+    } else if skip_checksum {
+        format!("Verify against {url}.sha256 (or .b3) if published (--skip-checksum)")
+    } else {
+        format!("Verify against {url}.sha256 (or .b3)")
+    };
+    let backup_step = if install_path.exists() {
+        format!("Back up {} to {}", install_path.display(), backup.display())
+    } else {
+        format!(
+            "Nothing to back up ({} does not exist)",
+            install_path.display()
+        )
+    };
+    let steps = [
+        format!("Download {filename} from {url}"),
+        checksum,
+        format!("Extract {}", binary_name()),
+        backup_step,
+        format!("Install to {}", install_path.display()),
+        "Run the new binary with --version (restore the backup if it fails)".to_string(),
+    ];
+    let mut plan = String::from("Dry run; these steps would be taken:\n");
+    for (i, step) in steps.iter().enumerate() {
+        let _ = writeln!(plan, "  {}. {step}", i + 1);
+    }
+    plan
+}
+
+/// Replace `install_path` with `new_binary`, keeping a backup of the old one.
+///
+/// The previous binary is saved to `backup`, replacing any backup from an
+/// earlier update. The new binary must answer `--version`; if it does not,
+/// the backup is restored and an error is returned.
+///
+/// Returns the new binary's `--version` output.
+fn install_verified(
+    new_binary: &Path,
+    install_path: &Path,
+    backup: &Path,
+) -> Result<String, String> {
+    let backed_up = install_path.exists();
+    if backed_up {
+        if let Some(parent) = backup.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
         }
-    })?;
+        std::fs::copy(install_path, backup).map_err(|e| {
+            format!(
+                "Failed to back up current binary to {}: {e}",
+                backup.display()
+            )
+        })?;
+    }
+
+    replace_binary(new_binary, install_path).map_err(|e| install_error(&e))?;
+
+    match binary_version(install_path) {
+        Ok(version) => Ok(version),
+        Err(e) if backed_up => {
+            restore_backup(install_path, backup)?;
+            Err(format!(
+                "New binary failed its sanity check ({e}); rolled back to the previous version"
+            ))
+        }
+        Err(e) => Err(format!("New binary failed its sanity check: {e}")),
+    }
+}
+
+/// Path the previous binary is saved to before an update unless
+/// `--backup-path` says otherwise.
+fn default_backup_path(install_path: &Path) -> PathBuf {
+    let mut name = install_path.as_os_str().to_os_string();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// Move the `backup` binary back into place.
+fn restore_backup(install_path: &Path, backup: &Path) -> Result<(), String> {
+    if !backup.exists() {
+        return Err(format!("No backup found at {}", backup.display()));
+    }
+    replace_binary(backup, install_path).map_err(|e| install_error(&e))?;
+    std::fs::remove_file(backup).map_err(|e| format!("Failed to remove {}: {e}", backup.display()))
+}
 
+/// Run `binary --version` and return its trimmed output.
+fn binary_version(binary: &Path) -> Result<String, String> {
+    let output = std::process::Command::new(binary)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("failed to run {}: {e}", binary.display()))?;
+    if !output.status.success() {
+        return Err(format!("`--version` exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn replace_binary(new_binary: &Path, install_path: &Path) -> std::io::Result<()> {
+    if cfg!(target_os = "windows") {
+        replace_running_binary(new_binary, install_path)
+    } else {
+        std::fs::copy(new_binary, install_path).map(|_| ())
+    }
+}
+
+fn install_error(e: &std::io::Error) -> String {
+    if e.kind() == std::io::ErrorKind::PermissionDenied {
+        format!(
+            "Permission denied. Try running with sudo or use --install-dir to specify a writable location:\n  {e}"
+        )
+    } else {
+        e.to_string()
+    }
+}
+
+const fn binary_name() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "prompter.exe"
+    } else {
+        "prompter"
+    }
+}
+
+/// Extract `binary_name` from a zip archive into `dest`, returning its path.
+///
+/// The binary may sit at the archive root or inside a directory.
+fn extract_zip(bytes: &[u8], dest: &Path, binary_name: &str) -> Result<PathBuf, String> {
+    let mut archive =
+        zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+        let is_binary = entry
+            .enclosed_name()
+            .is_some_and(|name| name.file_name().is_some_and(|n| n == binary_name));
+        if entry.is_file() && is_binary {
+            let target = dest.join(binary_name);
+            let mut out = std::fs::File::create(&target).map_err(|e| e.to_string())?;
+            std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+            return Ok(target);
+        }
+    }
+    Err(format!("Binary not found in archive: {binary_name}"))
+}
+
+/// Path the previous binary is moved to while it is being replaced.
+fn old_binary_path(install_path: &Path) -> PathBuf {
+    let mut name = install_path.as_os_str().to_os_string();
+    name.push(".old");
+    PathBuf::from(name)
+}
+
+/// Replace a binary that may currently be running.
+///
+/// Windows refuses to overwrite a running executable but does allow renaming
+/// it, so the old binary is moved aside to `<name>.old` first and restored if
+/// the copy fails. The leftover is removed by [`cleanup_old_binary`].
+fn replace_running_binary(new_binary: &Path, install_path: &Path) -> std::io::Result<()> {
+    let old = old_binary_path(install_path);
+    let moved = install_path.exists();
+    if moved {
+        // A stale .old from an earlier update would block the rename
+        if old.exists() {
+            std::fs::remove_file(&old)?;
+        }
+        std::fs::rename(install_path, &old)?;
+    }
+    if let Err(e) = std::fs::copy(new_binary, install_path) {
+        if moved {
+            std::fs::rename(&old, install_path).ok();
+        }
+        return Err(e);
+    }
     Ok(())
 }
 
+/// Remove the `<name>.old` binary left behind by a previous Windows update.
+///
+/// Called at startup; it is a no-op on other platforms and when there is
+/// nothing to clean up.
+pub fn cleanup_old_binary() {
+    if !cfg!(target_os = "windows") {
+        return;
+    }
+    if let Ok(exe) = std::env::current_exe() {
+        remove_old_binary(&exe);
+    }
+}
+
+fn remove_old_binary(install_path: &Path) {
+    let old = old_binary_path(install_path);
+    if old.exists() {
+        // Still locked if the old process has not exited yet; retry next run
+        std::fs::remove_file(old).ok();
+    }
+}
+
 fn get_platform_string() -> &'static str {
     match (std::env::consts::OS, std::env::consts::ARCH) {
         ("macos", "x86_64") => "x86_64-apple-darwin",
@@ -227,6 +884,7 @@ fn get_platform_string() -> &'static str {
         ("linux", "x86_64") => "x86_64-unknown-linux-gnu",
         ("linux", "aarch64") => "aarch64-unknown-linux-gnu",
         ("windows", "x86_64") => "x86_64-pc-windows-msvc",
+        ("windows", "aarch64") => "aarch64-pc-windows-msvc",
         _ => "unknown",
     }
 }
@@ -236,6 +894,136 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    const ARTIFACT: &str = "prompter-x86_64-unknown-linux-gnu.tar.gz";
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    #[test]
+    fn test_versions_table_marks_current_and_latest() {
+        use crate::release::ReleaseSummary;
+        let release = |version: &str, published: Option<&str>, prerelease| ReleaseSummary {
+            version: version.into(),
+            published: published.map(str::to_string),
+            prerelease,
+        };
+        let releases = [
+            release("2.0.0-rc.1", Some("2025-03-01"), true),
+            release("1.10.0", Some("2025-02-01"), false),
+            release("1.9.0", None, false),
+        ];
+        assert_eq!(
+            versions_table(&releases, "1.9.0", Some("1.10.0")),
+            "VERSION      RELEASED    NOTE\n\
+             v2.0.0-rc.1  2025-03-01  pre-release\n\
+             v1.10.0      2025-02-01  latest\n\
+             v1.9.0       -           current\n"
+        );
+        assert!(
+            versions_table(&releases[1..2], "1.10.0", Some("1.10.0"))
+                .ends_with("v1.10.0  2025-02-01  latest, current\n")
+        );
+    }
+
+    /// A fetcher that serves `files` by URL and 404s everything else.
+    fn served(files: &[(&str, String)]) -> impl Fn(&str) -> Result<String, String> {
+        let files: Vec<(String, String)> = files
+            .iter()
+            .map(|(url, text)| ((*url).to_string(), text.clone()))
+            .collect();
+        move |url: &str| {
+            files
+                .iter()
+                .find(|(served, _)| served == url)
+                .map(|(_, text)| text.clone())
+                .ok_or_else(|| "HTTP 404".to_string())
+        }
+    }
+
+    #[test]
+    fn test_verify_checksum_accepts_matching_hash() {
+        let hash = sha256_hex(b"archive");
+        let sha = format!("{ARTIFACT}.sha256");
+        assert_eq!(
+            verify_checksum(
+                b"archive",
+                ARTIFACT,
+                served(&[(&sha, format!("{hash}\n"))]),
+                false
+            ),
+            Ok(Some(ChecksumAlgorithm::Sha256))
+        );
+        assert_eq!(
+            verify_checksum(
+                b"archive",
+                ARTIFACT,
+                served(&[(&sha, format!("{}  *dist/{ARTIFACT}\n", hash.to_uppercase()))]),
+                false
+            ),
+            Ok(Some(ChecksumAlgorithm::Sha256))
+        );
+    }
+
+    #[test]
+    fn test_verify_checksum_falls_back_to_blake3() {
+        let url = format!("https://example.com/v1/{ARTIFACT}");
+        let b3 = format!("{url}.b3");
+        let hash = blake3::hash(b"archive").to_hex().to_string();
+        assert_eq!(
+            verify_checksum(
+                b"archive",
+                &url,
+                served(&[(&b3, format!("{hash}  {ARTIFACT}\n"))]),
+                false
+            ),
+            Ok(Some(ChecksumAlgorithm::Blake3))
+        );
+
+        // A mismatching .sha256 fails outright instead of falling back
+        let sha = format!("{url}.sha256");
+        let files = [(sha.as_str(), sha256_hex(b"other")), (&b3, hash)];
+        let err = verify_checksum(b"archive", &url, served(&files), false).unwrap_err();
+        assert!(err.starts_with("Checksum verification failed! (SHA-256)"));
+    }
+
+    #[test]
+    fn test_verify_checksum_rejects_bad_hash() {
+        let b3 = format!("{ARTIFACT}.b3");
+        let hash = blake3::hash(b"other").to_hex().to_string();
+        let err = verify_checksum(b"archive", ARTIFACT, served(&[(&b3, hash)]), true).unwrap_err();
+        assert!(err.starts_with("Checksum verification failed! (BLAKE3)"));
+    }
+
+    #[test]
+    fn test_verify_checksum_missing() {
+        let err = verify_checksum(b"archive", ARTIFACT, served(&[]), false).unwrap_err();
+        assert!(err.contains("not available (.sha256: HTTP 404, .b3: HTTP 404)"));
+        assert!(err.contains("--skip-checksum"));
+        assert_eq!(
+            verify_checksum(b"archive", ARTIFACT, served(&[]), true),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_verify_checksum_rejects_mismatched_filename() {
+        let hash = sha256_hex(b"archive");
+        let err = check_checksum(
+            b"archive",
+            ARTIFACT,
+            ChecksumAlgorithm::Sha256,
+            &format!("{hash}  prompter-aarch64-apple-darwin.tar.gz"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Checksum file is for prompter-aarch64-apple-darwin.tar.gz, but the download is {ARTIFACT}"
+            )
+        );
+    }
+
     #[test]
     fn test_get_platform_string() {
         let platform = get_platform_string();
@@ -246,6 +1034,7 @@ mod tests {
                 || platform == "x86_64-unknown-linux-gnu"
                 || platform == "aarch64-unknown-linux-gnu"
                 || platform == "x86_64-pc-windows-msvc"
+                || platform == "aarch64-pc-windows-msvc"
                 || platform == "unknown"
         );
     }
@@ -253,17 +1042,30 @@ mod tests {
     #[test]
     fn test_get_latest_version_handles_errors() {
         // Test that get_latest_version returns a Result
-        let result = get_latest_version();
+        let result = get_latest_version(UpdateChannel::Stable);
         // Either Ok or Err is acceptable since we're testing structure
         assert!(result.is_ok() || result.is_err());
     }
 
+    #[test]
+    fn test_check_json_shape() {
+        let value = check_json("1.0.0", "1.1.0", true);
+        assert_eq!(
+            value.to_string(),
+            r#"{"current":"1.0.0","latest":"1.1.0","update_available":true}"#
+        );
+    }
+
     #[test]
     fn test_run_update_with_current_version() {
         // Test update when already at current version
         let current = env!("CARGO_PKG_VERSION");
         let temp_dir = TempDir::new().unwrap();
-        let exit_code = run_update(Some(current), false, Some(temp_dir.path()));
+        let exit_code = run_update(UpdateOptions {
+            version: Some(current),
+            install_dir: Some(temp_dir.path()),
+            ..UpdateOptions::default()
+        });
         // Should return 2 for "already up-to-date"
         assert_eq!(exit_code, 2);
     }
@@ -271,7 +1073,12 @@ mod tests {
     #[test]
     fn test_run_update_rejects_invalid_path() {
         // Test with an invalid/non-writable path
-        let exit_code = run_update(Some("99.99.99"), true, Some(Path::new("/nonexistent")));
+        let exit_code = run_update(UpdateOptions {
+            version: Some("99.99.99"),
+            force: true,
+            install_dir: Some(Path::new("/nonexistent")),
+            ..UpdateOptions::default()
+        });
         // Should fail with exit code 1
         assert_eq!(exit_code, 1);
     }
@@ -281,9 +1088,220 @@ mod tests {
         // Test force flag bypasses up-to-date check
         let current = env!("CARGO_PKG_VERSION");
         let temp_dir = TempDir::new().unwrap();
-        let exit_code = run_update(Some(current), true, Some(temp_dir.path()));
+        let exit_code = run_update(UpdateOptions {
+            version: Some(current),
+            force: true,
+            install_dir: Some(temp_dir.path()),
+            ..UpdateOptions::default()
+        });
         // With force=true, it tries to download current version and may succeed or fail
         // depending on whether release exists
         assert!(exit_code == 0 || exit_code == 1);
     }
+
+    fn zip_with(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        use std::io::Write;
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, data) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_extract_zip_finds_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let bytes = zip_with(&[
+            ("README.md", b"docs"),
+            ("prompter-x86_64-pc-windows-msvc/prompter.exe", b"MZ binary"),
+        ]);
+        let extracted = extract_zip(&bytes, temp_dir.path(), "prompter.exe").unwrap();
+        assert_eq!(extracted, temp_dir.path().join("prompter.exe"));
+        assert_eq!(std::fs::read(&extracted).unwrap(), b"MZ binary");
+
+        let bytes = zip_with(&[("README.md", b"docs")]);
+        let err = extract_zip(&bytes, temp_dir.path(), "prompter.exe").unwrap_err();
+        assert!(err.contains("Binary not found in archive"));
+        assert!(extract_zip(b"not a zip", temp_dir.path(), "prompter.exe").is_err());
+    }
+
+    #[test]
+    fn test_replace_running_binary_renames_old_then_cleans_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let install = temp_dir.path().join("prompter.exe");
+        let new_binary = temp_dir.path().join("new.exe");
+        std::fs::write(&install, b"old").unwrap();
+        std::fs::write(&new_binary, b"new").unwrap();
+        // Stale leftover from an earlier update must not block the rename
+        std::fs::write(old_binary_path(&install), b"older").unwrap();
+
+        replace_running_binary(&new_binary, &install).unwrap();
+        let old = temp_dir.path().join("prompter.exe.old");
+        assert_eq!(std::fs::read(&install).unwrap(), b"new");
+        assert_eq!(std::fs::read(&old).unwrap(), b"old");
+
+        remove_old_binary(&install);
+        assert!(!old.exists());
+        assert!(install.exists());
+    }
+
+    #[test]
+    fn test_replace_running_binary_restores_on_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let install = temp_dir.path().join("prompter.exe");
+        std::fs::write(&install, b"old").unwrap();
+
+        let missing = temp_dir.path().join("missing.exe");
+        assert!(replace_running_binary(&missing, &install).is_err());
+        assert_eq!(std::fs::read(&install).unwrap(), b"old");
+        assert!(!old_binary_path(&install).exists());
+    }
+
+    #[cfg(unix)]
+    fn fake_binary(path: &Path, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::write(path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_verified_keeps_backup_and_rolls_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let install = temp_dir.path().join("prompter");
+        let good = temp_dir.path().join("good");
+        let broken = temp_dir.path().join("broken");
+        fake_binary(&install, "echo prompter 1.0.0");
+        fake_binary(&good, "echo prompter 2.0.0");
+        fake_binary(&broken, "exit 1");
+
+        let backup = default_backup_path(&install);
+        assert_eq!(
+            install_verified(&good, &install, &backup).unwrap(),
+            "prompter 2.0.0"
+        );
+        assert_eq!(binary_version(&backup).unwrap(), "prompter 1.0.0");
+
+        // A broken download is rolled back automatically
+        let err = install_verified(&broken, &install, &backup).unwrap_err();
+        assert!(err.contains("rolled back"), "err={err}");
+        assert_eq!(binary_version(&install).unwrap(), "prompter 2.0.0");
+        assert!(!backup.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rollback_restores_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let install = temp_dir.path().join("prompter");
+        let newer = temp_dir.path().join("newer");
+        fake_binary(&install, "echo prompter 1.0.0");
+        fake_binary(&newer, "echo prompter 2.0.0");
+
+        let backup = default_backup_path(&install);
+        install_verified(&newer, &install, &backup).unwrap();
+        // A subsequent successful update replaces the older backup
+        install_verified(&newer, &install, &backup).unwrap();
+        assert_eq!(binary_version(&backup).unwrap(), "prompter 2.0.0");
+
+        assert_eq!(run_rollback(Some(temp_dir.path()), None, false), 0);
+        assert_eq!(binary_version(&install).unwrap(), "prompter 2.0.0");
+        assert!(!backup.exists());
+
+        // Nothing left to roll back to
+        assert_eq!(run_rollback(Some(temp_dir.path()), None, false), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_backup_path_override_is_used_for_update_and_rollback() {
+        let temp_dir = TempDir::new().unwrap();
+        let install = temp_dir.path().join("prompter");
+        let newer = temp_dir.path().join("newer");
+        let custom = temp_dir.path().join("backups/prompter-previous");
+        fake_binary(&install, "echo prompter 1.0.0");
+        fake_binary(&newer, "echo prompter 2.0.0");
+
+        install_verified(&newer, &install, &custom).unwrap();
+        assert_eq!(binary_version(&custom).unwrap(), "prompter 1.0.0");
+        assert!(!default_backup_path(&install).exists());
+
+        // The default location is empty, so only the override can restore
+        assert_eq!(run_rollback(Some(temp_dir.path()), None, false), 1);
+        assert_eq!(run_rollback(Some(temp_dir.path()), Some(&custom), false), 0);
+        assert_eq!(binary_version(&install).unwrap(), "prompter 1.0.0");
+        assert!(!custom.exists());
+    }
+
+    #[test]
+    fn test_dry_run_plan_lists_steps_without_touching_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let install = temp_dir.path().join("prompter");
+        let backup = default_backup_path(&install);
+        let (filename, url) = release_asset("1.2.3");
+        assert!(url.ends_with(&format!(
+            "/{}/{filename}",
+            crate::release::release_tag("1.2.3")
+        )));
+
+        let plan = dry_run_plan("1.2.3", &install, &backup, false, false);
+        assert!(plan.starts_with("Dry run; these steps would be taken:\n"));
+        assert!(plan.contains(&format!("  1. Download {filename} from {url}\n")));
+        assert!(plan.contains(&format!("  2. Verify against {url}.sha256 (or .b3)\n")));
+        assert!(plan.contains("Nothing to back up"));
+        check_install_path(&install).unwrap();
+
+        fake_binary(&install, "echo prompter 1.0.0");
+        let plan = dry_run_plan("1.2.3", &install, &backup, true, false);
+        assert!(plan.contains("(--skip-checksum)"));
+        let plan = dry_run_plan("1.2.3", &install, &backup, false, true);
+        assert!(plan.contains("  2. Skip checksum verification (--skip-verify)\n"));
+        assert!(plan.contains(&format!(
+            "Back up {} to {}",
+            install.display(),
+            backup.display()
+        )));
+        assert!(!backup.exists());
+
+        let missing = temp_dir.path().join("nope/prompter");
+        assert!(
+            check_install_path(&missing)
+                .unwrap_err()
+                .starts_with("Install directory not found")
+        );
+    }
+
+    #[test]
+    fn test_first_writable_dir_skips_missing_relative_and_system_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let writable = temp_dir.path().join("bin");
+        std::fs::create_dir(&writable).unwrap();
+        let path_var = std::env::join_paths([
+            temp_dir.path().join("missing"),
+            PathBuf::from("relative"),
+            PathBuf::from("/usr/bin"),
+            PathBuf::from("/bin"),
+            writable.clone(),
+            temp_dir.path().to_path_buf(),
+        ])
+        .unwrap();
+        assert_eq!(first_writable_dir(&path_var), Some(writable));
+        assert_eq!(first_writable_dir(std::ffi::OsStr::new("/usr/bin")), None);
+        assert!(!is_writable_dir(&temp_dir.path().join("missing")));
+    }
+
+    #[test]
+    fn test_download_progress_sizes_bar_from_content_length() {
+        assert_eq!(download_progress(Some(2048), false).length(), Some(2048));
+        assert_eq!(download_progress(None, false).length(), None);
+        assert!(download_progress(Some(2048), true).is_hidden());
+
+        let pb = download_progress(Some(5), true);
+        let mut bytes = Vec::new();
+        std::io::copy(&mut &b"hello"[..], &mut pb.wrap_write(&mut bytes)).unwrap();
+        assert_eq!(bytes, b"hello");
+        assert_eq!(pb.position(), 5);
+    }
 }
@@ -5,9 +5,9 @@
 
 use std::env;
 use std::fs;
-use std::io::Read;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 fn tmp_home(prefix: &str) -> PathBuf {
     let mut p = env::temp_dir();
@@ -96,7 +96,7 @@ fn test_init_list_validate_run() {
     assert!(output_str.contains(", and you are running on a "));
     assert!(output_str.contains(" system.\n\n"));
     // Should contain the library file contents
-    let lib = home.join(".local/prompter/library");
+    let lib = home.join(".local/share/prompter/library");
     let c_bytes = read_all(&lib.join("a/b/c.md"));
     let h_bytes = read_all(&lib.join("f/g/h.md"));
     let c_content = String::from_utf8_lossy(&c_bytes);
@@ -113,7 +113,7 @@ fn test_init_list_validate_run() {
 fn test_missing_file_and_unknown_profile_fail() {
     let home = tmp_home("prompter_it_missing");
     let cfg_path = home.join(".config/prompter");
-    let lib_path = home.join(".local/prompter/library");
+    let lib_path = home.join(".local/share/prompter/library");
     fs::create_dir_all(&cfg_path).unwrap();
     fs::create_dir_all(&lib_path).unwrap();
 
@@ -143,18 +143,234 @@ depends_on = ["does.not.exist.md", "unknown_profile"]
     assert!(!out.status.success());
 }
 
+#[test]
+fn test_run_skip_missing_warns_and_continues() {
+    let home = tmp_home("prompter_it_skip_missing");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("here.md"), b"HERE\n").unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "[root]\ndepends_on = [\"gone.md\", \"here.md\"]\n",
+    )
+    .unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["run", "root"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Missing file:"));
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["run", "root", "--skip-missing"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("HERE\n"));
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("Warning: skipping missing file"), "{err}");
+    assert!(err.contains("gone.md (referenced by [root])"), "{err}");
+}
+
+#[test]
+fn test_run_timestamp_format_sets_banner_date() {
+    let home = tmp_home("prompter_it_timestamp_format");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("a.md"), b"A\n").unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "timestamp_format = \"cfg-%Y\"\n[root]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(["run", "root"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&[]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Today is cfg-"));
+
+    let out = run(&["--timestamp-format", "cli-%Y-%m-%d %H:%M"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Today is cli-"));
+
+    let out = run(&["--timestamp-format", "%Q"]);
+    assert_eq!(out.status.code(), Some(2));
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("Invalid timestamp format '%Q'"), "{err}");
+}
+
+#[test]
+fn test_run_utc_banner_ignores_local_time_zone() {
+    let home = tmp_home("prompter_it_utc");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("a.md"), b"A\n").unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "timestamp_format = \"%z\"\n[root]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let out = Command::new(bin_path())
+            .env("HOME", &home)
+            .env("TZ", "IST-5:30")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(["run", "root"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+
+    assert!(run(&[]).contains("Today is +0530,"));
+    assert!(run(&["--utc"]).contains("Today is +0000,"));
+}
+
+#[test]
+fn test_strict_config_rejects_misspelled_keys() {
+    let home = tmp_home("prompter_it_strict_config");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("a.md"), b"A\n").unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "[root]\ndepends_on = [\"a.md\"]\ndepnds_on = [\"b.md\"]\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert!(run(&["run", "root"]).status.success());
+    for args in [
+        &["--strict-config", "run", "root"][..],
+        &["validate", "--strict-config"],
+        &["fmt", "--check", "--strict-config"],
+    ] {
+        let out = run(args);
+        assert_eq!(out.status.code(), Some(1), "{args:?}");
+        let err = String::from_utf8_lossy(&out.stderr);
+        assert!(err.contains("Unknown key 'depnds_on' in [root]"), "{err}");
+    }
+
+    // doctor loads the config like every other command
+    let out = run(&["--strict-config", "doctor", "--offline"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("Config does not load: Unknown key 'depnds_on' in [root]"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_run_strips_bom_and_require_utf8_rejects_invalid_files() {
+    let home = tmp_home("prompter_it_utf8");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(lib_path.join("bom.md"), b"\xEF\xBB\xBFBOM\n").unwrap();
+    fs::write(lib_path.join("bad.md"), b"\xFF\n").unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "[bom]\ndepends_on = [\"bom.md\"]\n[bad]\ndepends_on = [\"bad.md\"]\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .args(["run", "--separator", "|"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["bom"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\nBOM\n|"));
+
+    assert!(run(&["bad"]).status.success());
+    let out = run(&["bad", "--require-utf8"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("File is not valid UTF-8:"), "{err}");
+    assert!(err.contains("bad.md"), "{err}");
+}
+
+#[test]
+fn test_run_allow_external_expands_tilde() {
+    let home = tmp_home("prompter_it_allow_external");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::create_dir_all(home.join("shared")).unwrap();
+    fs::write(home.join("shared/team.md"), b"TEAM\n").unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "[root]\ndepends_on = [\"~/shared/team.md\"]\n",
+    )
+    .unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["run", "root"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("--allow-external"), "{err}");
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["run", "root", "--allow-external"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("TEAM\n"));
+}
+
 #[test]
 fn test_recursive_resolution_and_separator() {
     let home = tmp_home("prompter_it_recursive");
     let cfg_path = home.join(".config/prompter");
-    let lib_path = home.join(".local/prompter/library");
+    let lib_path = home.join(".local/share/prompter/library");
     fs::create_dir_all(&cfg_path).unwrap();
     fs::create_dir_all(lib_path.join("a")).unwrap();
     fs::create_dir_all(lib_path.join("f")).unwrap();
 
     // files
-    fs::write(home.join(".local/prompter/library/a/x.md"), b"AX\n").unwrap();
-    fs::write(home.join(".local/prompter/library/f/y.md"), b"FY\n").unwrap();
+    fs::write(home.join(".local/share/prompter/library/a/x.md"), b"AX\n").unwrap();
+    fs::write(home.join(".local/share/prompter/library/f/y.md"), b"FY\n").unwrap();
 
     // config with recursive profile dep
     let cfg = r#"
@@ -200,7 +416,7 @@ depends_on = ["child", "f/y.md", "a/x.md"]
 fn test_cycle_detection_in_validate() {
     let home = tmp_home("prompter_it_cycle");
     let cfg_path = home.join(".config/prompter");
-    let lib_path = home.join(".local/prompter/library");
+    let lib_path = home.join(".local/share/prompter/library");
     fs::create_dir_all(&cfg_path).unwrap();
     fs::create_dir_all(&lib_path).unwrap();
 
@@ -265,6 +481,80 @@ fn test_completions_fish() {
     assert!(stdout.contains("prompter"));
 }
 
+#[test]
+fn test_completions_nushell() {
+    for shell in ["nushell", "nu"] {
+        let out = Command::new(bin_path())
+            .args(["completions", shell])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.starts_with("# Shell completion for prompter"));
+        assert!(stdout.contains("export extern prompter ["), "{stdout}");
+    }
+
+    let out = Command::new(bin_path())
+        .args(["completions", "--help"])
+        .output()
+        .unwrap();
+    let help = String::from_utf8_lossy(&out.stdout) + String::from_utf8_lossy(&out.stderr);
+    assert!(help.contains("nushell"), "{help}");
+}
+
+#[test]
+fn test_completions_write_and_write_to() {
+    let home = tmp_home("prompter_it_completions_write");
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["completions", "fish", "--write"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let fish = home.join(".config/fish/completions/prompter.fish");
+    assert!(String::from_utf8_lossy(&out.stdout).contains(&fish.display().to_string()));
+    assert!(fs::read_to_string(&fish).unwrap().contains("prompter"));
+
+    let dest = home.join("custom/dir/_prompter");
+    let out = Command::new(bin_path())
+        .args(["completions", "zsh", "--write-to"])
+        .arg(&dest)
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout).trim(),
+        dest.display().to_string()
+    );
+    assert!(
+        fs::read_to_string(&dest)
+            .unwrap()
+            .contains("#compdef prompter")
+    );
+
+    // A different existing file is kept unless --force is given
+    let out = Command::new(bin_path())
+        .args(["completions", "bash", "--write-to"])
+        .arg(&dest)
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--force"));
+    let out = Command::new(bin_path())
+        .args(["completions", "bash", "--force", "--write-to"])
+        .arg(&dest)
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    let out = Command::new(bin_path())
+        .args(["completions", "bash", "--install", "--write-to"])
+        .arg(&dest)
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+}
+
 #[test]
 fn test_doctor_command() {
     let out = Command::new(bin_path()).arg("doctor").output().unwrap();
@@ -274,76 +564,1815 @@ fn test_doctor_command() {
 }
 
 #[test]
-fn test_help_flag() {
-    let out = Command::new(bin_path()).arg("--help").output().unwrap();
-    // Help output may go to stdout or stderr depending on exit code
+fn test_doctor_offline_env_skips_update_check() {
+    let started = std::time::Instant::now();
+    let out = Command::new(bin_path())
+        .env("PROMPTER_OFFLINE", "1")
+        .arg("doctor")
+        .output()
+        .unwrap();
     let stdout = String::from_utf8_lossy(&out.stdout);
-    let stderr = String::from_utf8_lossy(&out.stderr);
-    let combined = format!("{stdout}{stderr}");
-    assert!(combined.contains("prompter") || combined.contains("Usage:"));
+    assert!(stdout.contains("Update check skipped (offline)"));
+    assert!(!stdout.contains("Failed to check for updates"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
 }
 
 #[test]
-fn test_run_with_separator() {
-    let home = tmp_home("prompter_it_sep");
-    fs::create_dir_all(&home).unwrap();
+fn test_doctor_exit_codes() {
+    let home = tmp_home("prompter_it_doctor_exit");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[a]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    fs::write(lib.join("a.md"), "A\n").unwrap();
 
-    // Init first
-    Command::new(bin_path())
+    let doctor = |args: &[&str]| {
+        let out = Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("SHELL")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(["doctor", "--offline"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        (out.status.code(), stdout)
+    };
+
+    let (code, stdout) = doctor(&[]);
+    assert_eq!(code, Some(0), "{stdout}");
+    assert!(stdout.contains("Everything looks healthy!"), "{stdout}");
+
+    // An orphaned file is only a warning, unless --strict
+    fs::write(lib.join("orphan.md"), "O\n").unwrap();
+    let (code, stdout) = doctor(&[]);
+    assert_eq!(code, Some(0), "{stdout}");
+    assert!(stdout.contains("1 warning found"), "{stdout}");
+    let (code, stdout) = doctor(&["--strict"]);
+    assert_eq!(code, Some(1), "{stdout}");
+
+    fs::remove_file(lib.join("a.md")).unwrap();
+    let (code, stdout) = doctor(&[]);
+    assert_eq!(code, Some(1), "{stdout}");
+    assert!(stdout.contains("1 error"), "{stdout}");
+}
+
+#[test]
+fn test_doctor_fix_creates_library_and_stubs() {
+    let home = tmp_home("prompter_it_doctor_fix");
+    let cfg_dir = home.join(".config/prompter");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[root]\ndepends_on = [\"notes/missing.md\"]\n",
+    )
+    .unwrap();
+
+    let out = Command::new(bin_path())
         .env("HOME", &home)
-        .arg("init")
+        .args(["doctor", "--offline", "--fix"])
         .output()
         .unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "stdout: {stdout}");
+    assert!(stdout.contains("Created library directory"));
+    assert!(stdout.contains("Created stub"));
+    assert!(stdout.contains("Fixed 2 of 2 issue(s), 0 remaining"));
 
-    // Run with custom separator
+    let stub = home.join(".local/share/prompter/library/notes/missing.md");
+    assert!(fs::read_to_string(&stub).unwrap().contains("prompter stub"));
+
+    // The config now validates
     let out = Command::new(bin_path())
         .env("HOME", &home)
-        .args(["--separator", "\\n---\\n", "python.api"])
+        .arg("validate")
         .output()
         .unwrap();
     assert!(out.status.success());
-    let stdout = String::from_utf8_lossy(&out.stdout);
-    assert!(stdout.contains("\n---\n"));
 }
 
 #[test]
-fn test_run_with_custom_pre_prompt() {
-    let home = tmp_home("prompter_it_pre");
-    fs::create_dir_all(&home).unwrap();
+fn test_doctor_checks_and_fixes_the_config_it_is_given() {
+    let home = tmp_home("prompter_it_doctor_config");
+    let dir = home.join("project");
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    fs::write(&config, "[p]\ndepends_on = [\"notes/missing.md\"]\n").unwrap();
+    let doctor = |config: &std::path::Path, args: &[&str]| {
+        let out = Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("SHELL")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .arg("--config")
+            .arg(config)
+            .args(["doctor", "--offline"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        (out.status.code(), stdout)
+    };
 
-    Command::new(bin_path())
+    let (code, stdout) = doctor(&config, &[]);
+    assert_eq!(code, Some(1), "{stdout}");
+    assert!(
+        stdout.contains(&format!("Config file: {}", config.display())),
+        "{stdout}"
+    );
+
+    let (code, stdout) = doctor(&config, &["--fix"]);
+    assert_eq!(code, Some(0), "{stdout}");
+    let stub = dir.join("library/notes/missing.md");
+    assert!(fs::read_to_string(&stub).unwrap().contains("prompter stub"));
+    assert!(!home.join(".config/prompter").exists());
+    assert!(!home.join(".local/share/prompter").exists());
+
+    // Orphans are looked for in the library next to that config
+    fs::write(dir.join("library/orphan.md"), "O\n").unwrap();
+    let (code, stdout) = doctor(&config, &["--fix", "--force"]);
+    assert_eq!(code, Some(0), "{stdout}");
+    assert!(!dir.join("library/orphan.md").exists());
+    assert!(stub.exists());
+
+    // A missing config is scaffolded where it was asked for
+    let other = home.join("other/config.toml");
+    let (code, stdout) = doctor(&other, &["--fix"]);
+    assert_eq!(code, Some(0), "{stdout}");
+    assert!(other.is_file());
+    assert!(home.join("other/library").is_dir());
+    assert!(!home.join(".config/prompter").exists());
+}
+
+#[test]
+fn test_xdg_dirs_override_defaults() {
+    let home = tmp_home("prompter_it_xdg");
+    let config_home = home.join("xdg-config");
+    let data_home = home.join("xdg-data");
+
+    let out = Command::new(bin_path())
         .env("HOME", &home)
-        .arg("init")
+        .env("XDG_CONFIG_HOME", &config_home)
+        .env("XDG_DATA_HOME", &data_home)
+        .args(["init", "--quiet"])
         .output()
         .unwrap();
+    assert!(out.status.success());
+    assert!(config_home.join("prompter/config.toml").is_file());
+    assert!(data_home.join("prompter/library").is_dir());
+    assert!(!home.join(".config/prompter").exists());
+}
+
+#[test]
+fn test_doctor_hints_at_legacy_library() {
+    let home = tmp_home("prompter_it_doctor_legacy");
+    let cfg_dir = home.join(".config/prompter");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::write(cfg_dir.join("config.toml"), "[a]\ndepends_on = []\n").unwrap();
+    fs::create_dir_all(home.join(".local/prompter/library")).unwrap();
+
+    let doctor = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env("NO_COLOR", "1")
+            .env_remove("XDG_DATA_HOME")
+            .args(["doctor", "--offline"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = doctor(&[]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!out.status.success());
+    assert!(stdout.contains("found the old location"), "{stdout}");
+    assert!(stdout.contains("  Hint: Move it: mkdir -p"), "{stdout}");
+    assert!(!stdout.contains('💡'), "{stdout}");
+
+    // --color always overrides both NO_COLOR and the missing terminal
+    let out = doctor(&["--color", "always"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("💡 Move it: mkdir -p"), "{stdout}");
+}
+
+#[test]
+fn test_doctor_checks_completions_for_current_shell() {
+    let home = tmp_home("prompter_it_doctor_completions");
+    let doctor = || {
+        let out = Command::new(bin_path())
+            .env("HOME", &home)
+            .env("SHELL", "/usr/bin/fish")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(["doctor", "--offline"])
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+
+    let stdout = doctor();
+    assert!(
+        stdout.contains("fish completions not installed"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("prompter completions fish --write'"),
+        "{stdout}"
+    );
+
+    let script = home.join(".config/fish/completions/prompter.fish");
+    fs::create_dir_all(script.parent().unwrap()).unwrap();
+    fs::write(&script, "# old\n").unwrap();
+    let stdout = doctor();
+    assert!(
+        stdout.contains("fish completions are out of date"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("--write --force"), "{stdout}");
 
     let out = Command::new(bin_path())
         .env("HOME", &home)
-        .args(["--pre-prompt", "Custom prefix", "python.api"])
+        .args(["completions", "fish", "--write", "--force"])
         .output()
         .unwrap();
     assert!(out.status.success());
+    let stdout = doctor();
+    assert!(stdout.contains("fish completions up to date"), "{stdout}");
+}
+
+#[test]
+fn test_doctor_fix_force_deletes_orphans() {
+    let home = tmp_home("prompter_it_doctor_orphans");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("used.md"), b"U\n").unwrap();
+    fs::write(lib.join("old.md"), b"O\n").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[root]\ndepends_on = [\"used.md\"]\n",
+    )
+    .unwrap();
+
+    // Without --force nothing is deleted when there is no terminal to ask on
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["doctor", "--offline", "--fix"])
+        .output()
+        .unwrap();
     let stdout = String::from_utf8_lossy(&out.stdout);
-    assert!(stdout.starts_with("Custom prefix"));
+    assert!(
+        stdout.contains("Not used by any profile: old.md"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("pass --force"), "{stdout}");
+    assert!(lib.join("old.md").exists());
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["doctor", "--offline", "--fix", "--force"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("Deleted 1 of 1 orphaned file(s)"),
+        "{stdout}"
+    );
+    assert!(!lib.join("old.md").exists());
+    assert!(lib.join("used.md").exists());
 }
 
 #[test]
-fn test_run_with_custom_post_prompt() {
-    let home = tmp_home("prompter_it_post");
+fn test_help_flag() {
+    let out = Command::new(bin_path()).arg("--help").output().unwrap();
+    // Help output may go to stdout or stderr depending on exit code
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let combined = format!("{stdout}{stderr}");
+    assert!(combined.contains("prompter") || combined.contains("Usage:"));
+}
+
+#[test]
+fn test_init_force_backs_up_and_resets() {
+    let home = tmp_home("prompter_it_init_force");
     fs::create_dir_all(&home).unwrap();
+    let init = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .arg("init")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    assert!(init(&[]).status.success());
 
-    Command::new(bin_path())
+    let cfg = home.join(".config/prompter/config.toml");
+    let lib = home.join(".local/share/prompter/library");
+    let default_cfg = fs::read_to_string(&cfg).unwrap();
+    fs::write(&cfg, "[broken\n").unwrap();
+    fs::write(lib.join("a/b.md"), "my notes\n").unwrap();
+    fs::write(lib.join("mine.md"), "keep me\n").unwrap();
+
+    // Without --force nothing changes
+    let out = init(&[]);
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("Backed up"));
+    assert_eq!(fs::read_to_string(&cfg).unwrap(), "[broken\n");
+
+    let out = init(&["--force"]);
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Overwriting 2 existing file(s)"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("config.toml"), "{stderr}");
+    assert_eq!(stderr.matches("Backed up").count(), 2, "{stderr}");
+    assert_eq!(fs::read_to_string(&cfg).unwrap(), default_cfg);
+    assert_eq!(
+        fs::read_to_string(lib.join("mine.md")).unwrap(),
+        "keep me\n"
+    );
+
+    let backups: Vec<String> = fs::read_dir(cfg.parent().unwrap())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("config.toml.bak-"))
+        .collect();
+    assert_eq!(backups.len(), 1);
+    let backup = cfg.with_file_name(&backups[0]);
+    assert_eq!(fs::read_to_string(backup).unwrap(), "[broken\n");
+    let notes: Vec<_> = fs::read_dir(lib.join("a"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("b.md.bak-"))
+        .collect();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(
+        fs::read_to_string(lib.join("a").join(&notes[0])).unwrap(),
+        "my notes\n"
+    );
+}
+
+#[test]
+fn test_init_quiet_reports_first_run() {
+    let home = tmp_home("prompter_it_init_quiet");
+    fs::create_dir_all(&home).unwrap();
+    let cfg = home.join(".config/prompter/config.toml");
+    let init = || {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .args(["init", "--quiet"])
+            .output()
+            .unwrap()
+    };
+
+    let out = init();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!("initialized {}\n", cfg.display())
+    );
+
+    let out = init();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!("already initialized {}\n", cfg.display())
+    );
+
+    // A missing sample file counts as work to do again
+    fs::remove_file(home.join(".local/share/prompter/library/a/b.md")).unwrap();
+    let out = Command::new(bin_path())
         .env("HOME", &home)
         .arg("init")
         .output()
         .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!("initialized {}\n", cfg.display())
+    );
+}
 
+#[test]
+fn test_init_minimal_skips_samples() {
+    let home = tmp_home("prompter_it_init_minimal");
+    fs::create_dir_all(&home).unwrap();
     let out = Command::new(bin_path())
         .env("HOME", &home)
-        .args(["--post-prompt", "Custom suffix", "python.api"])
+        .args(["init", "--minimal"])
         .output()
         .unwrap();
     assert!(out.status.success());
-    let stdout = String::from_utf8_lossy(&out.stdout);
-    assert!(stdout.ends_with("Custom suffix"));
+
+    let lib = home.join(".local/share/prompter/library");
+    assert!(lib.is_dir());
+    assert_eq!(fs::read_dir(&lib).unwrap().count(), 0);
+    let cfg = fs::read_to_string(home.join(".config/prompter/config.toml")).unwrap();
+    assert!(cfg.contains("[example]\ndepends_on = []\n"), "{cfg}");
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .arg("list")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "example");
+}
+
+#[test]
+fn test_init_template() {
+    let project = tmp_home("prompter_it_init_template");
+    fs::create_dir_all(&project).unwrap();
+    let out = Command::new(bin_path())
+        .args(["init", "--template", "list"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("coding-agent"));
+
+    let init = || {
+        Command::new(bin_path())
+            .current_dir(&project)
+            .args([
+                "init",
+                "--template",
+                "coding-agent",
+                "--config",
+                "p/config.toml",
+            ])
+            .output()
+            .unwrap()
+    };
+    assert!(init().status.success());
+    let conduct = project.join("p/library/core/conduct.md");
+    fs::write(&conduct, "my rules\n").unwrap();
+    // Non-destructive without --force
+    assert!(init().status.success());
+    assert_eq!(fs::read_to_string(&conduct).unwrap(), "my rules\n");
+
+    let out = Command::new(bin_path())
+        .current_dir(&project)
+        .args(["--config", "p/config.toml", "run", "review"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("my rules\n") && stdout.contains("# Task: review"));
+
+    let out = Command::new(bin_path())
+        .current_dir(&project)
+        .args(["init", "--template", "nope", "--config", "q/config.toml"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Unknown template 'nope'"));
+    assert!(!project.join("q").exists());
+}
+
+#[test]
+fn test_init_into_project_dir() {
+    let project = tmp_home("prompter_it_init_project");
+    fs::create_dir_all(&project).unwrap();
+    let out = Command::new(bin_path())
+        .current_dir(&project)
+        .args(["init", "--config", "prompts/config.toml"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "init failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(project.join("prompts/config.toml").is_file());
+    assert!(project.join("prompts/library/a/b/c.md").is_file());
+
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .current_dir(&project)
+            .args(["--config", "prompts/config.toml"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let out = prompter(&["list"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("python.api"));
+    assert!(prompter(&["validate"]).status.success());
+    let out = prompter(&["run", "general.testing"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("General testing snippet."));
+
+    // An explicit --library wins over the one next to the config
+    let out = Command::new(bin_path())
+        .current_dir(&project)
+        .args([
+            "init",
+            "--config",
+            "other/config.toml",
+            "--library",
+            "shared",
+        ])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(project.join("shared/f/g/h.md").is_file());
+    assert!(!project.join("other/library").exists());
+    let cfg = fs::read_to_string(project.join("other/config.toml")).unwrap();
+    assert!(cfg.contains(&project.join("shared").display().to_string()));
+}
+
+#[test]
+fn test_local_config_discovered_from_subdir() {
+    let home = tmp_home("prompter_it_local_config");
+    let project = home.join("project");
+    let nested = project.join("src/deep");
+    fs::create_dir_all(&nested).unwrap();
+    fs::create_dir_all(project.join("library")).unwrap();
+    fs::write(project.join("library/rules.md"), "Local rules.\n").unwrap();
+    fs::write(
+        project.join(".prompterrc"),
+        "[local]\ndepends_on = [\"rules.md\"]\n",
+    )
+    .unwrap();
+    let global_cfg = home.join(".config/prompter");
+    fs::create_dir_all(&global_cfg).unwrap();
+    fs::write(
+        global_cfg.join("config.toml"),
+        "[global]\ndepends_on = []\n",
+    )
+    .unwrap();
+
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .current_dir(&nested)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let out = prompter(&["list"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("local"), "{stdout}");
+    assert!(!stdout.contains("global"), "{stdout}");
+
+    let out = prompter(&["run", "local"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Local rules."));
+
+    let out = prompter(&["doctor", "--offline"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains(&project.join(".prompterrc").display().to_string()),
+        "{stdout}"
+    );
+
+    let out = prompter(&["--no-local-config", "list"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("global"), "{stdout}");
+    assert!(!stdout.contains("local"), "{stdout}");
+}
+
+#[test]
+fn test_run_with_separator() {
+    let home = tmp_home("prompter_it_sep");
+    fs::create_dir_all(&home).unwrap();
+
+    // Init first
+    Command::new(bin_path())
+        .env("HOME", &home)
+        .arg("init")
+        .output()
+        .unwrap();
+
+    // Run with custom separator
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["--separator", "\\n---\\n", "python.api"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("\n---\n"));
+}
+
+#[test]
+fn test_run_with_custom_pre_prompt() {
+    let home = tmp_home("prompter_it_pre");
+    fs::create_dir_all(&home).unwrap();
+
+    Command::new(bin_path())
+        .env("HOME", &home)
+        .arg("init")
+        .output()
+        .unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["--pre-prompt", "Custom prefix", "python.api"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("Custom prefix"));
+}
+
+#[test]
+fn test_run_with_custom_post_prompt() {
+    let home = tmp_home("prompter_it_post");
+    fs::create_dir_all(&home).unwrap();
+
+    Command::new(bin_path())
+        .env("HOME", &home)
+        .arg("init")
+        .output()
+        .unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["--post-prompt", "Custom suffix", "python.api"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.ends_with("Custom suffix"));
+}
+
+#[test]
+fn test_run_substitutes_vars_over_env_defaults() {
+    let home = tmp_home("prompter_it_vars");
+    let cfg_path = home.join(".config/prompter");
+    let lib_path = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_path).unwrap();
+    fs::create_dir_all(&lib_path).unwrap();
+    fs::write(
+        lib_path.join("team.md"),
+        b"Team {{team}} writes {{lang}}.\nUnset: {{owner}}\n",
+    )
+    .unwrap();
+    fs::write(
+        cfg_path.join("config.toml"),
+        "[team]\ndepends_on = [\"team.md\"]\n",
+    )
+    .unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env("PROMPTER_VARS", "team=core,lang=rust")
+        .args(["run", "team", "--var", "team=web"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "run failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("Team web writes rust.\nUnset: {{owner}}\n"));
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env("PROMPTER_VARS", "team")
+        .arg("team")
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Invalid PROMPTER_VARS"));
+}
+
+#[test]
+fn test_escape_filter_output_works_as_separator() {
+    let mut child = Command::new(bin_path())
+        .arg("escape")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"\n--- \"next\" ---\n")
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let escaped = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(escaped, "\\n--- \\\"next\\\" ---\\n\n");
+
+    // The escaped form feeds straight back into --separator
+    let home = tmp_home("prompter_it_escape");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), b"A").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[p]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("XDG_DATA_HOME")
+        .args(["run", "p", "--separator", escaped.trim_end()])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("A\n--- \"next\" ---\n"));
+}
+
+#[test]
+fn test_snapshot_test_update_then_detect_changes() {
+    let home = tmp_home("prompter_it_snapshots");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), "alpha\n").unwrap();
+    fs::write(lib.join("b.md"), "beta\n").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[one]\ndepends_on = [\"a.md\"]\n[two]\ndepends_on = [\"b.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .current_dir(&home)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["test"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("missing: one"));
+
+    let out = prompter(&["test", "--update"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "updated: one\nupdated: two\n"
+    );
+    assert!(home.join("snapshots/one.snap").exists());
+
+    let out = prompter(&["test"]);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "ok: one\nok: two\n");
+
+    fs::write(lib.join("b.md"), "BETA\n").unwrap();
+    let out = prompter(&["test"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("ok: one\nFAILED: two\n"));
+    assert!(stdout.contains("-beta\n+BETA\n"));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("1 of 2 snapshots did not match"));
+
+    // A single profile and a custom directory
+    let out = prompter(&["test", "one", "--snapshot-dir", "other", "--update"]);
+    assert!(out.status.success());
+    assert!(home.join("other/one.snap").exists());
+    assert!(!home.join("other/two.snap").exists());
+}
+
+#[test]
+fn test_run_add_and_drop_entries() {
+    let home = tmp_home("prompter_it_add_drop");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    for f in ["a.md", "b.md", "extra.md"] {
+        fs::write(lib.join(f), format!("<{f}>\n")).unwrap();
+    }
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[inner]\ndepends_on = [\"b.md\"]\n[app]\ndepends_on = [\"a.md\", \"inner\"]\n",
+    )
+    .unwrap();
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("XDG_DATA_HOME")
+        .args(["run", "app", "--add", "extra.md", "--drop", "inner"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let (a, extra) = (stdout.find("<a.md>"), stdout.find("<extra.md>"));
+    assert!(a.is_some() && a < extra, "{stdout}");
+    assert!(!stdout.contains("<b.md>"));
+
+    // Added files may select a line range
+    fs::write(lib.join("lines.md"), "one\ntwo\nthree\n").unwrap();
+    let run_add = |entry: &str| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(["run", "app", "--add", entry])
+            .output()
+            .unwrap()
+    };
+    let out = run_add("lines.md#L2");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("\ntwo\n"), "{stdout}");
+    assert!(
+        !stdout.contains("one") && !stdout.contains("three"),
+        "{stdout}"
+    );
+    let out = run_add("lines.md#L2-L9");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Line range L2-L9 out of range"));
+}
+
+#[test]
+fn test_run_without_profile_uses_default_profile() {
+    let home = tmp_home("prompter_it_default_profile");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), "<a.md>\n").unwrap();
+    let run = |config: &str| {
+        fs::write(cfg_dir.join("config.toml"), config).unwrap();
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .current_dir(&home)
+            .arg("run")
+            .output()
+            .unwrap()
+    };
+
+    let out = run("default_profile = \"app\"\n[app]\ndepends_on = [\"a.md\"]\n");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("<a.md>"));
+
+    let out = run("[app]\ndepends_on = [\"a.md\"]\n");
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("no default_profile set"), "{stderr}");
+}
+
+#[test]
+fn test_which_reports_sources() {
+    let home = tmp_home("prompter_it_which");
+    let project = home.join("project");
+    fs::create_dir_all(project.join("library")).unwrap();
+    let xdg = home.join("xdg-config");
+    let which = |args: &[&str], dir: &Path| {
+        let out = Command::new(bin_path())
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", &xdg)
+            .env_remove("XDG_DATA_HOME")
+            .current_dir(dir)
+            .arg("which")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout).unwrap()
+    };
+
+    let text = which(&[], &home);
+    assert!(
+        text.contains(&format!(
+            "config:  {} (missing; $XDG_CONFIG_HOME)",
+            xdg.join("prompter/config.toml").display()
+        )),
+        "{text}"
+    );
+    assert!(
+        text.contains("prompter/library (missing; default)"),
+        "{text}"
+    );
+
+    fs::write(project.join("prompter.toml"), "[a]\ndepends_on = []\n").unwrap();
+    let text = which(&[], &project);
+    assert!(text.contains("(exists; local config)"), "{text}");
+    assert!(text.contains("(exists; next to config)"), "{text}");
+
+    let json: serde_json::Value =
+        serde_json::from_str(&which(&["--config", "other.toml", "--json"], &project)).unwrap();
+    assert_eq!(json["config"]["source"], "--config flag");
+    assert_eq!(json["config"]["exists"], false);
+}
+
+#[test]
+fn test_config_from_stdin() {
+    let cfg = r#"
+[stdin.profile]
+depends_on = ["a.md"]
+"#;
+    let mut child = Command::new(bin_path())
+        .args(["--config", "-", "list"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(cfg.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(stdout.trim(), "stdin.profile");
+}
+
+#[test]
+fn test_config_from_stdin_uses_cwd_library() {
+    let project = tmp_home("prompter_it_stdin");
+    fs::create_dir_all(project.join("library")).unwrap();
+    fs::write(project.join("library/a.md"), b"FROM STDIN LIB\n").unwrap();
+
+    let mut child = Command::new(bin_path())
+        .current_dir(&project)
+        .args(["--config", "-", "run", "root"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"[root]\ndepends_on = [\"a.md\"]\n")
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(
+        out.status.success(),
+        "run failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("FROM STDIN LIB\n"));
+}
+
+#[test]
+fn test_list_filters_by_tag() {
+    let home = tmp_home("prompter_it_tags");
+    let cfg_dir = home.join(".config/prompter");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        r#"
+[py.test]
+tags = ["python", "testing"]
+depends_on = []
+
+[rust.test]
+tags = ["rust", "testing"]
+depends_on = []
+"#,
+    )
+    .unwrap();
+
+    let list = |args: &[&str]| {
+        let out = Command::new(bin_path())
+            .env("HOME", &home)
+            .arg("list")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "list failed: {}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout).unwrap()
+    };
+    assert_eq!(list(&["--tag", "testing"]), "py.test\nrust.test\n");
+    assert_eq!(list(&["--tag", "testing", "--tag", "rust"]), "rust.test\n");
+    assert_eq!(list(&["--tags"]), "python\nrust\ntesting\n");
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["list", "--tags", "--tag", "rust"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+}
+
+#[test]
+fn test_list_pattern_filters_and_fails_without_matches() {
+    let home = tmp_home("prompter_it_list_pattern");
+    let cfg_dir = home.join(".config/prompter");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[python.api]\ndepends_on = [\"a.md\"]\n\
+         [python.cli]\ndepends_on = []\n\
+         [rust.api]\ndepends_on = []\n",
+    )
+    .unwrap();
+
+    let list = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .current_dir(&home)
+            .arg("list")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = list(&["python.*"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "python.api\npython.cli\n"
+    );
+
+    let out = list(&["API", "--json"]);
+    assert!(out.status.success());
+    let parsed: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(
+        parsed,
+        serde_json::json!([
+            {"name": "python.api", "depends_on": ["a.md"]},
+            {"name": "rust.api", "depends_on": []},
+        ])
+    );
+
+    let out = list(&["go"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("No profiles match 'go'"));
+}
+
+#[test]
+fn test_new_creates_snippet_and_refuses_overwrite() {
+    let home = tmp_home("prompter_it_new");
+    fs::create_dir_all(&home).unwrap();
+    let lib = home.join(".local/share/prompter/library");
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env("EDITOR", "true")
+        .args(["new", "notes/todo.md", "--content", "- item\\n", "--edit"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "new failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let printed = String::from_utf8_lossy(&out.stdout);
+    let created = fs::canonicalize(lib.join("notes/todo.md")).unwrap();
+    assert_eq!(printed.trim(), created.display().to_string());
+    assert_eq!(fs::read_to_string(&created).unwrap(), "- item\n");
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["new", "notes/todo.md"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("already exists"));
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["new", "notes/todo.md", "--force", "--content", "replaced"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(fs::read_to_string(&created).unwrap(), "replaced");
+}
+
+#[cfg(unix)]
+fn stub_editor(dir: &std::path::Path, name: &str, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+    let path = dir.join(name);
+    fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edit_profile_and_file_use_editor() {
+    let home = tmp_home("prompter_it_edit");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(lib.join("a")).unwrap();
+    fs::write(lib.join("a/x.md"), b"X\n").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        "[first]\ndepends_on = []\n\n[second]\ndepends_on = [\"a/x.md\"]\n",
+    )
+    .unwrap();
+    let log = home.join("editor.log");
+    let script = format!("echo \"$@\" >> {}", log.display());
+    let vim = stub_editor(&home, "vim", &script);
+    let code = stub_editor(&home, "code", &script);
+    let other = stub_editor(&home, "stub-editor", &script);
+
+    let edit = |editor: &PathBuf, args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env("EDITOR", editor)
+            .arg("edit")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    assert!(edit(&vim, &["profile", "second"]).status.success());
+    assert!(edit(&vim, &["file", "a/x.md"]).status.success());
+    // Only editors known to take a line get one, each in its own syntax
+    assert!(edit(&code, &["profile", "second"]).status.success());
+    assert!(edit(&other, &["profile", "second"]).status.success());
+    let config = cfg_dir.join("config.toml");
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        format!(
+            "+4 {0}\n{1}\n-g {0}:4\n{0}\n",
+            config.display(),
+            lib.join("a/x.md").display()
+        )
+    );
+
+    let out = edit(&vim, &["profile", "nope"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Unknown profile: nope"));
+
+    // Files must stay inside the library
+    fs::write(home.join("secret.md"), b"S\n").unwrap();
+    let out = edit(&vim, &["file", "../../../../secret.md"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Path escapes the library"));
+
+    // No $EDITOR/$VISUAL and no vi on PATH
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env_remove("EDITOR")
+        .env_remove("VISUAL")
+        .env("PATH", "")
+        .args(["edit", "file", "a/x.md"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("No editor found"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edit_config_validates_after_editor_exits() {
+    let home = tmp_home("prompter_it_edit_config");
+    let cfg_path = home.join("custom.toml");
+    fs::create_dir_all(home.join("library")).unwrap();
+    fs::write(home.join("library/x.md"), b"X\n").unwrap();
+    fs::write(&cfg_path, "[first]\ndepends_on = [\"x.md\"]\n").unwrap();
+    let log = home.join("editor.log");
+
+    let edit = |script: &str, args: &[&str]| {
+        let editor = stub_editor(&home, "vi", script);
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env("EDITOR", &editor)
+            .args(["--config", cfg_path.to_str().unwrap(), "edit"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // The editor receives the --config file; appending a valid profile passes
+    let out = edit(
+        &format!(
+            "echo \"$@\" >> {}\nprintf '[second]\\ndepends_on = [\"first\"]\\n' >> \"$1\"",
+            log.display()
+        ),
+        &[],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("All profiles valid"));
+    assert!(fs::read_to_string(&cfg_path).unwrap().contains("[second]"));
+
+    // `edit <profile>` jumps to the section, then validation reports the breakage
+    let out = edit(
+        &format!(
+            "echo \"$@\" >> {}\nprintf '[broken]\\ndepends_on = [\"gone.md\"]\\n' >> \"$2\"",
+            log.display()
+        ),
+        &["second"],
+    );
+    assert_eq!(out.status.code(), Some(1));
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("Validation errors:"), "{err}");
+    assert!(err.contains("gone.md (referenced by [broken])"), "{err}");
+
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        format!("{0}\n+3 {0}\n", cfg_path.display())
+    );
+}
+
+#[test]
+fn test_update_check_never_downloads_offline() {
+    let out = Command::new(bin_path())
+        .env("PROMPTER_OFFLINE", "1")
+        .args(["update", "--check", "--json"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("offline"));
+
+    // --json only makes sense together with --check
+    let out = Command::new(bin_path())
+        .args(["update", "--json"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_update_list_versions_fails_offline() {
+    let out = Command::new(bin_path())
+        .env("PROMPTER_OFFLINE", "1")
+        .args(["update", "--list-versions", "--limit", "3"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Failed to list versions"));
+
+    // --limit only applies to --list-versions
+    let out = Command::new(bin_path())
+        .args(["update", "--limit", "3"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_update_dry_run_fails_offline_and_conflicts_with_rollback() {
+    let out = Command::new(bin_path())
+        .env("PROMPTER_OFFLINE", "1")
+        .args(["update", "--dry-run"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("offline"));
+
+    let out = Command::new(bin_path())
+        .args(["update", "--dry-run", "--rollback"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_library_roots_fallback_and_validate_report() {
+    let project = tmp_home("prompter_it_library_roots");
+    fs::create_dir_all(project.join("library")).unwrap();
+    fs::create_dir_all(project.join("shared")).unwrap();
+    fs::write(project.join("library/own.md"), b"OWN\n").unwrap();
+    fs::write(project.join("shared/team.md"), b"TEAM\n").unwrap();
+    fs::write(
+        project.join("config.toml"),
+        "library_roots = [\"../shared\"]\n[p]\ndepends_on = [\"own.md\", \"team.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .arg("--config-dir")
+            .arg(&project)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["run", "p", "-s", "|"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("OWN\n|\nTEAM\n|"));
+
+    let out = prompter(&["validate"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let lib = project.join("library");
+    assert!(
+        stdout.contains(&format!("own.md: {}\n", lib.display())),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!("team.md: {}\n", lib.join("../shared").display())),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_config_dir_renders_project_layout() {
+    let project = tmp_home("prompter_it_config_dir");
+    fs::create_dir_all(project.join("library/a")).unwrap();
+    fs::write(project.join("library/a/x.md"), b"FROM CONFIG DIR\n").unwrap();
+    fs::write(
+        project.join("config.toml"),
+        "[proj]\ndepends_on = [\"a/x.md\"]\n",
+    )
+    .unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", tmp_home("prompter_it_config_dir_home"))
+        .arg("--config-dir")
+        .arg(&project)
+        .args(["run", "proj"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "run failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("FROM CONFIG DIR\n"));
+
+    let out = Command::new(bin_path())
+        .args(["list", "--config-dir"])
+        .arg(&project)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "proj\n");
+}
+
+#[test]
+fn test_config_dir_init_and_conflict_with_config() {
+    let root = tmp_home("prompter_it_config_dir_init");
+    let project = root.join("teams/web");
+    fs::create_dir_all(&root).unwrap();
+
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", root.join("home"))
+            .current_dir(&root)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // A relative directory is resolved against the working directory
+    let out = prompter(&["--config-dir", "teams/web", "init", "--quiet"]);
+    assert!(
+        out.status.success(),
+        "init failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(project.join("config.toml").is_file());
+    assert!(project.join("library/a/b/c.md").is_file());
+    assert!(!root.join("home/.config").exists());
+
+    let out = prompter(&["--config-dir", "teams/web", "validate"]);
+    assert!(out.status.success());
+    let out = prompter(&["--config-dir", "teams/web", "python.api"]);
+    assert!(out.status.success());
+
+    let out = prompter(&[
+        "--config-dir",
+        "teams/web",
+        "--config",
+        "teams/web/config.toml",
+        "list",
+    ]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cannot be used with"));
+}
+
+#[test]
+fn test_library_search_exit_codes() {
+    let home = tmp_home("prompter_it_search");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(home.join(".config/prompter")).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(home.join(".config/prompter/config.toml"), "").unwrap();
+    fs::write(lib.join("notes.md"), b"alpha\nBeta\n").unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["library", "search", "beta"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "notes.md:2: Beta\n");
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["library", "search", "--case-sensitive", "beta"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn test_library_check_reports_issues() {
+    let home = tmp_home("prompter_it_check");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(home.join(".config/prompter")).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(home.join(".config/prompter/config.toml"), "").unwrap();
+    fs::write(lib.join("ok.md"), b"fine\n").unwrap();
+
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["library", "check"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    fs::write(lib.join("bad.md"), b"ab\xfe\n").unwrap();
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .args(["library", "check"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "bad.md: invalid UTF-8 at byte 2\n"
+    );
+}
+
+#[test]
+fn test_fmt_check_then_rewrite_in_place() {
+    let project = tmp_home("prompter_it_fmt");
+    fs::create_dir_all(&project).unwrap();
+    let cfg_path = project.join("config.toml");
+    fs::write(
+        &cfg_path,
+        "[b]\ndepends_on=[ \"b.md\" ]\n\n# About a\n[a]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .arg("--config")
+            .arg(&cfg_path)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["fmt", "--check"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("+depends_on = [\"b.md\"]\n"));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is not formatted"));
+
+    let out = prompter(&["fmt"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!("formatted {}\n", cfg_path.display())
+    );
+    assert_eq!(
+        fs::read_to_string(&cfg_path).unwrap(),
+        "# About a\n[a]\ndepends_on = [\"a.md\"]\n\n[b]\ndepends_on = [\"b.md\"]\n"
+    );
+
+    let out = prompter(&["fmt", "--check"]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn test_run_since_filters_old_files_and_rejects_bad_times() {
+    let home = tmp_home("prompter_it_since");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), b"FRESH\n").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        b"[p]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |since: &str| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(["run", "p", "--since", since])
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter("2000-01-01T00:00:00Z");
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("FRESH"));
+
+    let out = prompter("2999-01-01T00:00:00Z");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!stdout.contains("FRESH"));
+    assert!(stdout.contains("(No files changed since 2999-01-01T00:00:00Z)"));
+
+    let out = prompter("last week");
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("RFC 3339"));
+}
+
+#[test]
+fn test_lint_reports_then_fixes_mechanical_findings() {
+    let home = tmp_home("prompter_it_lint");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), b"# A \r\n[b](b.md)").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        b"[p]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["lint"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("a.md:1: trailing_whitespace:"), "{stdout}");
+    assert!(stdout.contains("a.md:2: broken_links:"), "{stdout}");
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains("4 lint finding(s) in 1 file(s); 3 can be fixed with --fix")
+    );
+
+    let out = prompter(&["lint", "p", "--fix"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("fixed a.md"), "{stdout}");
+    assert!(!stdout.contains("trailing_whitespace"), "{stdout}");
+    assert_eq!(
+        fs::read_to_string(lib.join("a.md")).unwrap(),
+        "# A\n[b](b.md)\n"
+    );
+
+    fs::write(
+        cfg_dir.join("config.toml"),
+        b"[lint]\nbroken_links = false\n\n[p]\ndepends_on = [\"a.md\"]\n",
+    )
+    .unwrap();
+    let out = prompter(&["lint"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[test]
+fn test_profile_named_lint_still_loads() {
+    let dir = tmp_home("prompter_it_lint_profile");
+    fs::create_dir_all(dir.join("library")).unwrap();
+    fs::write(dir.join("library/a.md"), "A\n").unwrap();
+    let config = dir.join("config.toml");
+    fs::write(&config, "[lint]\ndepends_on = [\"a.md\"]\n").unwrap();
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .arg("--config")
+            .arg(&config)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["run", "lint"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("A\n"));
+    assert_eq!(
+        String::from_utf8_lossy(&prompter(&["list"]).stdout),
+        "lint\n"
+    );
+    assert!(prompter(&["validate"]).status.success());
+    assert!(prompter(&["lint", "lint"]).status.success());
+}
+
+#[test]
+fn test_diff_exit_codes_and_resolve_errors() {
+    let home = tmp_home("prompter_it_diff");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), b"A\n").unwrap();
+    fs::write(lib.join("b.md"), b"B\n").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        b"[old]\ndepends_on = [\"a.md\"]\n[copy]\ndepends_on = [\"a.md\"]\n\
+          [new]\ndepends_on = [\"a.md\", \"b.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["diff", "old", "copy", "--content"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "old and copy are identical\n"
+    );
+
+    let out = prompter(&["diff", "old", "new", "--content"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.starts_with("Only in new (1):\n  + b.md\nShared (1):\n    a.md\n--- old\n+++ new\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("\n+B\n"), "{stdout}");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("old and new differ"));
+
+    let out = prompter(&["diff", "old", "nope"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("nope: Unknown profile: nope"));
+}
+
+#[test]
+fn test_paths_alias_prints_home_paths() {
+    let home = tmp_home("prompter_it_paths");
+    let cfg_dir = home.join(".config/prompter");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::write(cfg_dir.join("config.toml"), b"").unwrap();
+    let out = Command::new(bin_path())
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("XDG_DATA_HOME")
+        .current_dir(&home)
+        .arg("paths")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        text.contains(&format!(
+            "config:  {} (exists;",
+            cfg_dir.join("config.toml").display()
+        )),
+        "{text}"
+    );
+    assert!(
+        text.contains(&format!(
+            "library: {} (missing;",
+            home.join(".local/share/prompter/library").display()
+        )),
+        "{text}"
+    );
+}
+
+#[test]
+fn test_emit_mode_json_prints_mode_without_running_it() {
+    let out = Command::new(bin_path())
+        .args([
+            "--emit-mode-json",
+            "--strict-config",
+            "init",
+            "--config",
+            "/nonexistent/config.toml",
+        ])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(json["mode"]["Init"]["config"], "/nonexistent/config.toml");
+    assert_eq!(json["strict_config"], true);
+    assert!(!Path::new("/nonexistent").exists());
+}
+
+#[test]
+fn test_import_bundle_conflicts_force_and_prefix() {
+    let home = tmp_home("prompter_it_import");
+    let cfg_dir = home.join(".config/prompter");
+    let lib = home.join(".local/share/prompter/library");
+    fs::create_dir_all(&cfg_dir).unwrap();
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.md"), b"LOCAL\n").unwrap();
+    fs::write(
+        cfg_dir.join("config.toml"),
+        b"# Mine\n[mine]\ndepends_on = [ \"a.md\" ]  # keep\n",
+    )
+    .unwrap();
+
+    let bundle = home.join("bundle.tar.gz");
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        fs::File::create(&bundle).unwrap(),
+        flate2::Compression::fast(),
+    ));
+    for (path, text) in [
+        ("config.toml", "[mine]\ndepends_on = [\"a.md\", \"b.md\"]\n"),
+        ("library/a.md", "BUNDLED\n"),
+        ("library/b.md", "B\n"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(text.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, path, text.as_bytes()).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+
+    let prompter = |args: &[&str]| {
+        Command::new(bin_path())
+            .env("HOME", &home)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .arg("import")
+            .arg(&bundle)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&[]);
+    assert_eq!(out.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("file a.md: differs from the library copy"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("profile mine: already defined differently"),
+        "{stdout}"
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("2 conflict(s); nothing was imported"));
+    assert!(!lib.join("b.md").exists());
+
+    let out = prompter(&["--prefix", "team."]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("added team/a.md\n"), "{stdout}");
+    assert!(stdout.contains("added profile team.mine\n"), "{stdout}");
+    assert!(stdout.contains("configuration is valid"), "{stdout}");
+    assert_eq!(fs::read_to_string(lib.join("a.md")).unwrap(), "LOCAL\n");
+    // New profiles are appended; the existing text is left as written
+    assert!(String::from_utf8_lossy(&out.stderr).is_empty());
+    assert_eq!(
+        fs::read_to_string(cfg_dir.join("config.toml")).unwrap(),
+        "# Mine\n[mine]\ndepends_on = [ \"a.md\" ]  # keep\n\n\
+         [\"team.mine\"]\ndepends_on = [\"team/a.md\", \"team/b.md\"]\n"
+    );
+
+    let out = prompter(&["--force"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("replaced a.md\nadded b.md\nreplaced profile mine\n"),
+        "{stdout}"
+    );
+    // Replacing a profile rewrites the config, with a warning
+    assert!(String::from_utf8_lossy(&out.stderr).contains("canonical form"));
+    assert_eq!(fs::read_to_string(lib.join("a.md")).unwrap(), "BUNDLED\n");
 }